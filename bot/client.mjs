@@ -10,7 +10,12 @@ import pino from "pino";
 import NodeCache from "@cacheable/node-cache";
 import { createClient } from "redis";
 import { loadPlugins } from "./plugins";
-import serialize, { socketOut, handleEvent, handleCommand } from "./utility";
+import serialize, {
+  socketOut,
+  handleEvent,
+  handleCommand,
+  serveCommands,
+} from "./utility";
 import {
   getMessage,
   saveMessage,
@@ -106,6 +111,7 @@ const Client = async (phone = process.argv?.[2]) => {
       return cachedGroupMetadata(phone, jid);
     },
   });
+  serveCommands(sock);
 
   if (!sock.authState?.creds?.registered) {
     await delay(5000);
@@ -165,12 +171,22 @@ const Client = async (phone = process.argv?.[2]) => {
     }
 
     if (events["messages.upsert"]) {
-      const { messages } = events["messages.upsert"];
+      const { messages, type } = events["messages.upsert"];
       for (const msg of messages) {
         await saveMessage(msg, phone);
 
         const msgCopy = structuredClone(msg);
         const m = await serialize({ ...msgCopy, session: phone }, sock);
+
+        // Status updates and protocol messages aren't conversation messages
+        if (
+          msg.message &&
+          m.chat !== "status@broadcast" &&
+          m.mtype !== "protocolMessage"
+        ) {
+          if (m.key.fromMe) socketOut("MESSAGE_SENT", m);
+          else if (type === "notify") socketOut("MESSAGE_RECEIVED", m);
+        }
         await Promise.allSettled([
           handleCommand(m),
           handleEvent(m),
@@ -185,6 +201,23 @@ const Client = async (phone = process.argv?.[2]) => {
       }
     }
 
+    if (events["message-receipt.update"]) {
+      // Read receipts on the account's own statuses are status views
+      for (const { key, receipt } of events["message-receipt.update"]) {
+        if (
+          key.remoteJid === "status@broadcast" &&
+          key.fromMe &&
+          receipt.readTimestamp
+        ) {
+          socketOut("STATUS_VIEWED", {
+            id: key.id,
+            viewer: receipt.userJid,
+            timestamp: receipt.readTimestamp,
+          });
+        }
+      }
+    }
+
     if (events["group-participants.update"]) {
       const { id, participants, action } = events["group-participants.update"];
       const firstParticipant = participants[0];
@@ -71,6 +71,278 @@ export const whatsaly = $root.whatsaly = (() => {
         return ConnectionUpdate;
     })();
 
+    whatsaly.CommandResult = (function () {
+
+        function CommandResult(properties) {
+            if (properties)
+                for (let keys = Object.keys(properties), i = 0; i < keys.length; ++i)
+                    if (properties[keys[i]] != null)
+                        this[keys[i]] = properties[keys[i]];
+        }
+
+        CommandResult.prototype.requestId = "";
+        CommandResult.prototype.success = false;
+        CommandResult.prototype.error = "";
+        CommandResult.prototype.data = "";
+
+        CommandResult.create = function create(properties) {
+            return new CommandResult(properties);
+        };
+
+        CommandResult.encode = function encode(message, writer) {
+            if (!writer)
+                writer = $Writer.create();
+            if (message.requestId != null && Object.hasOwnProperty.call(message, "requestId"))
+                writer.uint32(10).string(message.requestId);
+            if (message.success != null && Object.hasOwnProperty.call(message, "success"))
+                writer.uint32(16).bool(message.success);
+            if (message.error != null && Object.hasOwnProperty.call(message, "error"))
+                writer.uint32(26).string(message.error);
+            if (message.data != null && Object.hasOwnProperty.call(message, "data"))
+                writer.uint32(34).string(message.data);
+            return writer;
+        };
+
+        CommandResult.decode = function decode(reader, length) {
+            if (!(reader instanceof $Reader))
+                reader = $Reader.create(reader);
+            let end = length === undefined ? reader.len : reader.pos + length, message = new CommandResult();
+            while (reader.pos < end) {
+                let tag = reader.uint32();
+                switch (tag >>> 3) {
+                    case 1:
+                        message.requestId = reader.string();
+                        break;
+                    case 2:
+                        message.success = reader.bool();
+                        break;
+                    case 3:
+                        message.error = reader.string();
+                        break;
+                    case 4:
+                        message.data = reader.string();
+                        break;
+                    default:
+                        reader.skipType(tag & 7);
+                        break;
+                }
+            }
+            return message;
+        };
+
+        return CommandResult;
+    })();
+
+    whatsaly.QuotedMessage = (function () {
+
+        function QuotedMessage(properties) {
+            if (properties)
+                for (let keys = Object.keys(properties), i = 0; i < keys.length; ++i)
+                    if (properties[keys[i]] != null)
+                        this[keys[i]] = properties[keys[i]];
+        }
+
+        QuotedMessage.prototype.id = "";
+        QuotedMessage.prototype.sender = "";
+        QuotedMessage.prototype.mtype = "";
+        QuotedMessage.prototype.text = "";
+
+        QuotedMessage.create = function create(properties) {
+            return new QuotedMessage(properties);
+        };
+
+        QuotedMessage.encode = function encode(message, writer) {
+            if (!writer)
+                writer = $Writer.create();
+            if (message.id != null && Object.hasOwnProperty.call(message, "id"))
+                writer.uint32(10).string(message.id);
+            if (message.sender != null && Object.hasOwnProperty.call(message, "sender"))
+                writer.uint32(18).string(message.sender);
+            if (message.mtype != null && Object.hasOwnProperty.call(message, "mtype"))
+                writer.uint32(26).string(message.mtype);
+            if (message.text != null && Object.hasOwnProperty.call(message, "text"))
+                writer.uint32(34).string(message.text);
+            return writer;
+        };
+
+        QuotedMessage.decode = function decode(reader, length) {
+            if (!(reader instanceof $Reader))
+                reader = $Reader.create(reader);
+            let end = length === undefined ? reader.len : reader.pos + length, message = new QuotedMessage();
+            while (reader.pos < end) {
+                let tag = reader.uint32();
+                switch (tag >>> 3) {
+                    case 1:
+                        message.id = reader.string();
+                        break;
+                    case 2:
+                        message.sender = reader.string();
+                        break;
+                    case 3:
+                        message.mtype = reader.string();
+                        break;
+                    case 4:
+                        message.text = reader.string();
+                        break;
+                    default:
+                        reader.skipType(tag & 7);
+                        break;
+                }
+            }
+            return message;
+        };
+
+        return QuotedMessage;
+    })();
+
+    whatsaly.MessageEvent = (function () {
+
+        function MessageEvent(properties) {
+            if (properties)
+                for (let keys = Object.keys(properties), i = 0; i < keys.length; ++i)
+                    if (properties[keys[i]] != null)
+                        this[keys[i]] = properties[keys[i]];
+        }
+
+        MessageEvent.prototype.id = "";
+        MessageEvent.prototype.chat = "";
+        MessageEvent.prototype.sender = "";
+        MessageEvent.prototype.device = "";
+        MessageEvent.prototype.mtype = "";
+        MessageEvent.prototype.text = "";
+        MessageEvent.prototype.isGroup = false;
+        MessageEvent.prototype.quoted = null;
+        MessageEvent.prototype.timestamp = $util.Long ? $util.Long.fromBits(0,0,false) : 0;
+
+        MessageEvent.create = function create(properties) {
+            return new MessageEvent(properties);
+        };
+
+        MessageEvent.encode = function encode(message, writer) {
+            if (!writer)
+                writer = $Writer.create();
+            if (message.id != null && Object.hasOwnProperty.call(message, "id"))
+                writer.uint32(10).string(message.id);
+            if (message.chat != null && Object.hasOwnProperty.call(message, "chat"))
+                writer.uint32(18).string(message.chat);
+            if (message.sender != null && Object.hasOwnProperty.call(message, "sender"))
+                writer.uint32(26).string(message.sender);
+            if (message.device != null && Object.hasOwnProperty.call(message, "device"))
+                writer.uint32(34).string(message.device);
+            if (message.mtype != null && Object.hasOwnProperty.call(message, "mtype"))
+                writer.uint32(42).string(message.mtype);
+            if (message.text != null && Object.hasOwnProperty.call(message, "text"))
+                writer.uint32(50).string(message.text);
+            if (message.isGroup != null && Object.hasOwnProperty.call(message, "isGroup"))
+                writer.uint32(56).bool(message.isGroup);
+            if (message.quoted != null && Object.hasOwnProperty.call(message, "quoted"))
+                whatsaly.QuotedMessage.encode(message.quoted, writer.uint32(66).fork()).ldelim();
+            if (message.timestamp != null && Object.hasOwnProperty.call(message, "timestamp"))
+                writer.uint32(72).int64(message.timestamp);
+            return writer;
+        };
+
+        MessageEvent.decode = function decode(reader, length) {
+            if (!(reader instanceof $Reader))
+                reader = $Reader.create(reader);
+            let end = length === undefined ? reader.len : reader.pos + length, message = new MessageEvent();
+            while (reader.pos < end) {
+                let tag = reader.uint32();
+                switch (tag >>> 3) {
+                    case 1:
+                        message.id = reader.string();
+                        break;
+                    case 2:
+                        message.chat = reader.string();
+                        break;
+                    case 3:
+                        message.sender = reader.string();
+                        break;
+                    case 4:
+                        message.device = reader.string();
+                        break;
+                    case 5:
+                        message.mtype = reader.string();
+                        break;
+                    case 6:
+                        message.text = reader.string();
+                        break;
+                    case 7:
+                        message.isGroup = reader.bool();
+                        break;
+                    case 8:
+                        message.quoted = whatsaly.QuotedMessage.decode(reader, reader.uint32());
+                        break;
+                    case 9:
+                        message.timestamp = reader.int64();
+                        break;
+                    default:
+                        reader.skipType(tag & 7);
+                        break;
+                }
+            }
+            return message;
+        };
+
+        return MessageEvent;
+    })();
+
+    whatsaly.StatusView = (function () {
+
+        function StatusView(properties) {
+            if (properties)
+                for (let keys = Object.keys(properties), i = 0; i < keys.length; ++i)
+                    if (properties[keys[i]] != null)
+                        this[keys[i]] = properties[keys[i]];
+        }
+
+        StatusView.prototype.id = "";
+        StatusView.prototype.viewer = "";
+        StatusView.prototype.timestamp = $util.Long ? $util.Long.fromBits(0,0,false) : 0;
+
+        StatusView.create = function create(properties) {
+            return new StatusView(properties);
+        };
+
+        StatusView.encode = function encode(message, writer) {
+            if (!writer)
+                writer = $Writer.create();
+            if (message.id != null && Object.hasOwnProperty.call(message, "id"))
+                writer.uint32(10).string(message.id);
+            if (message.viewer != null && Object.hasOwnProperty.call(message, "viewer"))
+                writer.uint32(18).string(message.viewer);
+            if (message.timestamp != null && Object.hasOwnProperty.call(message, "timestamp"))
+                writer.uint32(24).int64(message.timestamp);
+            return writer;
+        };
+
+        StatusView.decode = function decode(reader, length) {
+            if (!(reader instanceof $Reader))
+                reader = $Reader.create(reader);
+            let end = length === undefined ? reader.len : reader.pos + length, message = new StatusView();
+            while (reader.pos < end) {
+                let tag = reader.uint32();
+                switch (tag >>> 3) {
+                    case 1:
+                        message.id = reader.string();
+                        break;
+                    case 2:
+                        message.viewer = reader.string();
+                        break;
+                    case 3:
+                        message.timestamp = reader.int64();
+                        break;
+                    default:
+                        reader.skipType(tag & 7);
+                        break;
+                }
+            }
+            return message;
+        };
+
+        return StatusView;
+    })();
+
     whatsaly.WorkerEvent = (function () {
 
         function WorkerEvent(properties) {
@@ -82,11 +354,15 @@ export const whatsaly = $root.whatsaly = (() => {
 
         WorkerEvent.prototype.connection = null;
         WorkerEvent.prototype.rawLog = "";
+        WorkerEvent.prototype.commandResult = null;
+        WorkerEvent.prototype.messageReceived = null;
+        WorkerEvent.prototype.messageSent = null;
+        WorkerEvent.prototype.statusViewed = null;
 
         let $oneOfFields;
 
         Object.defineProperty(WorkerEvent.prototype, "event", {
-            get: $util.oneOfGetter($oneOfFields = ["connection", "rawLog"]),
+            get: $util.oneOfGetter($oneOfFields = ["connection", "rawLog", "commandResult", "messageReceived", "messageSent", "statusViewed"]),
             set: $util.oneOfSetter($oneOfFields)
         });
 
@@ -101,6 +377,14 @@ export const whatsaly = $root.whatsaly = (() => {
                 whatsaly.ConnectionUpdate.encode(message.connection, writer.uint32(10).fork()).ldelim();
             if (message.rawLog != null && Object.hasOwnProperty.call(message, "rawLog"))
                 writer.uint32(18).string(message.rawLog);
+            if (message.commandResult != null && Object.hasOwnProperty.call(message, "commandResult"))
+                whatsaly.CommandResult.encode(message.commandResult, writer.uint32(26).fork()).ldelim();
+            if (message.messageReceived != null && Object.hasOwnProperty.call(message, "messageReceived"))
+                whatsaly.MessageEvent.encode(message.messageReceived, writer.uint32(34).fork()).ldelim();
+            if (message.messageSent != null && Object.hasOwnProperty.call(message, "messageSent"))
+                whatsaly.MessageEvent.encode(message.messageSent, writer.uint32(42).fork()).ldelim();
+            if (message.statusViewed != null && Object.hasOwnProperty.call(message, "statusViewed"))
+                whatsaly.StatusView.encode(message.statusViewed, writer.uint32(50).fork()).ldelim();
             return writer;
         };
 
@@ -117,6 +401,18 @@ export const whatsaly = $root.whatsaly = (() => {
                     case 2:
                         message.rawLog = reader.string();
                         break;
+                    case 3:
+                        message.commandResult = whatsaly.CommandResult.decode(reader, reader.uint32());
+                        break;
+                    case 4:
+                        message.messageReceived = whatsaly.MessageEvent.decode(reader, reader.uint32());
+                        break;
+                    case 5:
+                        message.messageSent = whatsaly.MessageEvent.decode(reader, reader.uint32());
+                        break;
+                    case 6:
+                        message.statusViewed = whatsaly.StatusView.decode(reader, reader.uint32());
+                        break;
                     default:
                         reader.skipType(tag & 7);
                         break;
@@ -128,6 +424,409 @@ export const whatsaly = $root.whatsaly = (() => {
         return WorkerEvent;
     })();
 
+    whatsaly.SendMessage = (function () {
+
+        function SendMessage(properties) {
+            if (properties)
+                for (let keys = Object.keys(properties), i = 0; i < keys.length; ++i)
+                    if (properties[keys[i]] != null)
+                        this[keys[i]] = properties[keys[i]];
+        }
+
+        SendMessage.prototype.jid = "";
+        SendMessage.prototype.text = "";
+        SendMessage.prototype.mediaUrl = "";
+        SendMessage.prototype.mediaType = "";
+        SendMessage.prototype.fileName = "";
+
+        SendMessage.create = function create(properties) {
+            return new SendMessage(properties);
+        };
+
+        SendMessage.encode = function encode(message, writer) {
+            if (!writer)
+                writer = $Writer.create();
+            if (message.jid != null && Object.hasOwnProperty.call(message, "jid"))
+                writer.uint32(10).string(message.jid);
+            if (message.text != null && Object.hasOwnProperty.call(message, "text"))
+                writer.uint32(18).string(message.text);
+            if (message.mediaUrl != null && Object.hasOwnProperty.call(message, "mediaUrl"))
+                writer.uint32(26).string(message.mediaUrl);
+            if (message.mediaType != null && Object.hasOwnProperty.call(message, "mediaType"))
+                writer.uint32(34).string(message.mediaType);
+            if (message.fileName != null && Object.hasOwnProperty.call(message, "fileName"))
+                writer.uint32(42).string(message.fileName);
+            return writer;
+        };
+
+        SendMessage.decode = function decode(reader, length) {
+            if (!(reader instanceof $Reader))
+                reader = $Reader.create(reader);
+            let end = length === undefined ? reader.len : reader.pos + length, message = new SendMessage();
+            while (reader.pos < end) {
+                let tag = reader.uint32();
+                switch (tag >>> 3) {
+                    case 1:
+                        message.jid = reader.string();
+                        break;
+                    case 2:
+                        message.text = reader.string();
+                        break;
+                    case 3:
+                        message.mediaUrl = reader.string();
+                        break;
+                    case 4:
+                        message.mediaType = reader.string();
+                        break;
+                    case 5:
+                        message.fileName = reader.string();
+                        break;
+                    default:
+                        reader.skipType(tag & 7);
+                        break;
+                }
+            }
+            return message;
+        };
+
+        return SendMessage;
+    })();
+
+    whatsaly.GroupAction = (function () {
+
+        function GroupAction(properties) {
+            this.participants = [];
+            if (properties)
+                for (let keys = Object.keys(properties), i = 0; i < keys.length; ++i)
+                    if (properties[keys[i]] != null)
+                        this[keys[i]] = properties[keys[i]];
+        }
+
+        GroupAction.prototype.action = "";
+        GroupAction.prototype.jid = "";
+        GroupAction.prototype.participants = $util.emptyArray;
+        GroupAction.prototype.value = "";
+
+        GroupAction.create = function create(properties) {
+            return new GroupAction(properties);
+        };
+
+        GroupAction.encode = function encode(message, writer) {
+            if (!writer)
+                writer = $Writer.create();
+            if (message.action != null && Object.hasOwnProperty.call(message, "action"))
+                writer.uint32(10).string(message.action);
+            if (message.jid != null && Object.hasOwnProperty.call(message, "jid"))
+                writer.uint32(18).string(message.jid);
+            if (message.participants != null && message.participants.length)
+                for (let i = 0; i < message.participants.length; ++i)
+                    writer.uint32(26).string(message.participants[i]);
+            if (message.value != null && Object.hasOwnProperty.call(message, "value"))
+                writer.uint32(34).string(message.value);
+            return writer;
+        };
+
+        GroupAction.decode = function decode(reader, length) {
+            if (!(reader instanceof $Reader))
+                reader = $Reader.create(reader);
+            let end = length === undefined ? reader.len : reader.pos + length, message = new GroupAction();
+            while (reader.pos < end) {
+                let tag = reader.uint32();
+                switch (tag >>> 3) {
+                    case 1:
+                        message.action = reader.string();
+                        break;
+                    case 2:
+                        message.jid = reader.string();
+                        break;
+                    case 3:
+                        if (!(message.participants && message.participants.length))
+                            message.participants = [];
+                        message.participants.push(reader.string());
+                        break;
+                    case 4:
+                        message.value = reader.string();
+                        break;
+                    default:
+                        reader.skipType(tag & 7);
+                        break;
+                }
+            }
+            return message;
+        };
+
+        return GroupAction;
+    })();
+
+    whatsaly.CheckNumbers = (function () {
+
+        function CheckNumbers(properties) {
+            this.jids = [];
+            if (properties)
+                for (let keys = Object.keys(properties), i = 0; i < keys.length; ++i)
+                    if (properties[keys[i]] != null)
+                        this[keys[i]] = properties[keys[i]];
+        }
+
+        CheckNumbers.prototype.jids = $util.emptyArray;
+
+        CheckNumbers.create = function create(properties) {
+            return new CheckNumbers(properties);
+        };
+
+        CheckNumbers.encode = function encode(message, writer) {
+            if (!writer)
+                writer = $Writer.create();
+            if (message.jids != null && message.jids.length)
+                for (let i = 0; i < message.jids.length; ++i)
+                    writer.uint32(10).string(message.jids[i]);
+            return writer;
+        };
+
+        CheckNumbers.decode = function decode(reader, length) {
+            if (!(reader instanceof $Reader))
+                reader = $Reader.create(reader);
+            let end = length === undefined ? reader.len : reader.pos + length, message = new CheckNumbers();
+            while (reader.pos < end) {
+                let tag = reader.uint32();
+                switch (tag >>> 3) {
+                    case 1:
+                        if (!(message.jids && message.jids.length))
+                            message.jids = [];
+                        message.jids.push(reader.string());
+                        break;
+                    default:
+                        reader.skipType(tag & 7);
+                        break;
+                }
+            }
+            return message;
+        };
+
+        return CheckNumbers;
+    })();
+
+    whatsaly.ProfileAction = (function () {
+
+        function ProfileAction(properties) {
+            if (properties)
+                for (let keys = Object.keys(properties), i = 0; i < keys.length; ++i)
+                    if (properties[keys[i]] != null)
+                        this[keys[i]] = properties[keys[i]];
+        }
+
+        ProfileAction.prototype.action = "";
+        ProfileAction.prototype.value = "";
+        ProfileAction.prototype.setting = "";
+        ProfileAction.prototype.image = $util.newBuffer([]);
+
+        ProfileAction.create = function create(properties) {
+            return new ProfileAction(properties);
+        };
+
+        ProfileAction.encode = function encode(message, writer) {
+            if (!writer)
+                writer = $Writer.create();
+            if (message.action != null && Object.hasOwnProperty.call(message, "action"))
+                writer.uint32(10).string(message.action);
+            if (message.value != null && Object.hasOwnProperty.call(message, "value"))
+                writer.uint32(18).string(message.value);
+            if (message.setting != null && Object.hasOwnProperty.call(message, "setting"))
+                writer.uint32(26).string(message.setting);
+            if (message.image != null && Object.hasOwnProperty.call(message, "image"))
+                writer.uint32(34).bytes(message.image);
+            return writer;
+        };
+
+        ProfileAction.decode = function decode(reader, length) {
+            if (!(reader instanceof $Reader))
+                reader = $Reader.create(reader);
+            let end = length === undefined ? reader.len : reader.pos + length, message = new ProfileAction();
+            while (reader.pos < end) {
+                let tag = reader.uint32();
+                switch (tag >>> 3) {
+                    case 1:
+                        message.action = reader.string();
+                        break;
+                    case 2:
+                        message.value = reader.string();
+                        break;
+                    case 3:
+                        message.setting = reader.string();
+                        break;
+                    case 4:
+                        message.image = reader.bytes();
+                        break;
+                    default:
+                        reader.skipType(tag & 7);
+                        break;
+                }
+            }
+            return message;
+        };
+
+        return ProfileAction;
+    })();
+
+    whatsaly.PostStatus = (function () {
+
+        function PostStatus(properties) {
+            this.audience = [];
+            if (properties)
+                for (let keys = Object.keys(properties), i = 0; i < keys.length; ++i)
+                    if (properties[keys[i]] != null)
+                        this[keys[i]] = properties[keys[i]];
+        }
+
+        PostStatus.prototype.kind = "";
+        PostStatus.prototype.text = "";
+        PostStatus.prototype.mediaUrl = "";
+        PostStatus.prototype.backgroundColor = "";
+        PostStatus.prototype.font = 0;
+        PostStatus.prototype.audience = $util.emptyArray;
+
+        PostStatus.create = function create(properties) {
+            return new PostStatus(properties);
+        };
+
+        PostStatus.encode = function encode(message, writer) {
+            if (!writer)
+                writer = $Writer.create();
+            if (message.kind != null && Object.hasOwnProperty.call(message, "kind"))
+                writer.uint32(10).string(message.kind);
+            if (message.text != null && Object.hasOwnProperty.call(message, "text"))
+                writer.uint32(18).string(message.text);
+            if (message.mediaUrl != null && Object.hasOwnProperty.call(message, "mediaUrl"))
+                writer.uint32(26).string(message.mediaUrl);
+            if (message.backgroundColor != null && Object.hasOwnProperty.call(message, "backgroundColor"))
+                writer.uint32(34).string(message.backgroundColor);
+            if (message.font != null && Object.hasOwnProperty.call(message, "font"))
+                writer.uint32(40).int32(message.font);
+            if (message.audience != null && message.audience.length)
+                for (let i = 0; i < message.audience.length; ++i)
+                    writer.uint32(50).string(message.audience[i]);
+            return writer;
+        };
+
+        PostStatus.decode = function decode(reader, length) {
+            if (!(reader instanceof $Reader))
+                reader = $Reader.create(reader);
+            let end = length === undefined ? reader.len : reader.pos + length, message = new PostStatus();
+            while (reader.pos < end) {
+                let tag = reader.uint32();
+                switch (tag >>> 3) {
+                    case 1:
+                        message.kind = reader.string();
+                        break;
+                    case 2:
+                        message.text = reader.string();
+                        break;
+                    case 3:
+                        message.mediaUrl = reader.string();
+                        break;
+                    case 4:
+                        message.backgroundColor = reader.string();
+                        break;
+                    case 5:
+                        message.font = reader.int32();
+                        break;
+                    case 6:
+                        if (!(message.audience && message.audience.length))
+                            message.audience = [];
+                        message.audience.push(reader.string());
+                        break;
+                    default:
+                        reader.skipType(tag & 7);
+                        break;
+                }
+            }
+            return message;
+        };
+
+        return PostStatus;
+    })();
+
+    whatsaly.WorkerCommand = (function () {
+
+        function WorkerCommand(properties) {
+            if (properties)
+                for (let keys = Object.keys(properties), i = 0; i < keys.length; ++i)
+                    if (properties[keys[i]] != null)
+                        this[keys[i]] = properties[keys[i]];
+        }
+
+        WorkerCommand.prototype.requestId = "";
+        WorkerCommand.prototype.sendMessage = null;
+        WorkerCommand.prototype.groupAction = null;
+        WorkerCommand.prototype.checkNumbers = null;
+        WorkerCommand.prototype.profileAction = null;
+        WorkerCommand.prototype.postStatus = null;
+
+        let $oneOfFields;
+
+        Object.defineProperty(WorkerCommand.prototype, "command", {
+            get: $util.oneOfGetter($oneOfFields = ["sendMessage", "groupAction", "checkNumbers", "profileAction", "postStatus"]),
+            set: $util.oneOfSetter($oneOfFields)
+        });
+
+        WorkerCommand.create = function create(properties) {
+            return new WorkerCommand(properties);
+        };
+
+        WorkerCommand.encode = function encode(message, writer) {
+            if (!writer)
+                writer = $Writer.create();
+            if (message.requestId != null && Object.hasOwnProperty.call(message, "requestId"))
+                writer.uint32(10).string(message.requestId);
+            if (message.sendMessage != null && Object.hasOwnProperty.call(message, "sendMessage"))
+                whatsaly.SendMessage.encode(message.sendMessage, writer.uint32(18).fork()).ldelim();
+            if (message.groupAction != null && Object.hasOwnProperty.call(message, "groupAction"))
+                whatsaly.GroupAction.encode(message.groupAction, writer.uint32(26).fork()).ldelim();
+            if (message.checkNumbers != null && Object.hasOwnProperty.call(message, "checkNumbers"))
+                whatsaly.CheckNumbers.encode(message.checkNumbers, writer.uint32(34).fork()).ldelim();
+            if (message.profileAction != null && Object.hasOwnProperty.call(message, "profileAction"))
+                whatsaly.ProfileAction.encode(message.profileAction, writer.uint32(42).fork()).ldelim();
+            if (message.postStatus != null && Object.hasOwnProperty.call(message, "postStatus"))
+                whatsaly.PostStatus.encode(message.postStatus, writer.uint32(50).fork()).ldelim();
+            return writer;
+        };
+
+        WorkerCommand.decode = function decode(reader, length) {
+            if (!(reader instanceof $Reader))
+                reader = $Reader.create(reader);
+            let end = length === undefined ? reader.len : reader.pos + length, message = new WorkerCommand();
+            while (reader.pos < end) {
+                let tag = reader.uint32();
+                switch (tag >>> 3) {
+                    case 1:
+                        message.requestId = reader.string();
+                        break;
+                    case 2:
+                        message.sendMessage = whatsaly.SendMessage.decode(reader, reader.uint32());
+                        break;
+                    case 3:
+                        message.groupAction = whatsaly.GroupAction.decode(reader, reader.uint32());
+                        break;
+                    case 4:
+                        message.checkNumbers = whatsaly.CheckNumbers.decode(reader, reader.uint32());
+                        break;
+                    case 5:
+                        message.profileAction = whatsaly.ProfileAction.decode(reader, reader.uint32());
+                        break;
+                    case 6:
+                        message.postStatus = whatsaly.PostStatus.decode(reader, reader.uint32());
+                        break;
+                    default:
+                        reader.skipType(tag & 7);
+                        break;
+                }
+            }
+            return message;
+        };
+
+        return WorkerCommand;
+    })();
+
     return whatsaly;
 })();
 
@@ -1,4 +1,9 @@
 import { whatsaly } from './events.mjs';
 
 export const ConnectionUpdate = whatsaly.ConnectionUpdate;
+export const CommandResult = whatsaly.CommandResult;
+export const QuotedMessage = whatsaly.QuotedMessage;
+export const MessageEvent = whatsaly.MessageEvent;
+export const StatusView = whatsaly.StatusView;
 export const WorkerEvent = whatsaly.WorkerEvent;
+export const WorkerCommand = whatsaly.WorkerCommand;
@@ -73,8 +73,8 @@ SessionContact.init(
 export class SessionMessage extends Model {}
 SessionMessage.init(
   {
-    sessionId: { type: DataTypes.TEXT, allowNull: false },
-    messageId: { type: DataTypes.TEXT, primaryKey: true },
+    sessionId: { type: DataTypes.TEXT, allowNull: false, primaryKey: true },
+    messageId: { type: DataTypes.TEXT, allowNull: false, primaryKey: true },
     messageContent: { type: DataTypes.TEXT },
     createdAt: { type: DataTypes.DATE, defaultValue: DataTypes.NOW },
   },
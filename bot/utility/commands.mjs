@@ -0,0 +1,164 @@
+import { jidNormalizedUser } from "baileys";
+
+const MEDIA_MIMETYPES = {
+  audio: "audio/mp4",
+  document: "application/octet-stream",
+};
+
+/** Baileys privacy updaters, keyed by the service's setting names */
+const privacyUpdaters = (client) => ({
+  last_seen: (v) => client.updateLastSeenPrivacy(v),
+  online: (v) => client.updateOnlinePrivacy(v),
+  profile_picture: (v) => client.updateProfilePicturePrivacy(v),
+  status: (v) => client.updateStatusPrivacy(v),
+  read_receipts: (v) => client.updateReadReceiptsPrivacy(v),
+  groups_add: (v) => client.updateGroupsAddPrivacy(v),
+  calls_add: (v) => client.updateCallPrivacy(v),
+});
+
+const sendMessage = async (client, { jid, text, mediaUrl, mediaType, fileName }) => {
+  let content;
+  if (!mediaUrl) {
+    content = { text };
+  } else if (["image", "video", "audio", "document"].includes(mediaType)) {
+    content = { [mediaType]: { url: mediaUrl } };
+    if (text && mediaType !== "audio") content.caption = text;
+    if (MEDIA_MIMETYPES[mediaType]) content.mimetype = MEDIA_MIMETYPES[mediaType];
+    if (mediaType === "document") content.fileName = fileName || "file";
+  } else {
+    throw new Error(`Unsupported media type '${mediaType}'`);
+  }
+
+  const sent = await client.sendMessage(jid, content);
+  return { id: sent?.key?.id, jid };
+};
+
+const groupAction = async (client, { action, jid, participants, value }) => {
+  switch (action) {
+    case "create":
+      return await client.groupCreate(value, participants);
+    case "add":
+    case "remove":
+    case "promote":
+    case "demote": {
+      const results = await client.groupParticipantsUpdate(jid, participants, action);
+      return results.map(({ jid, status }) => ({ jid, status }));
+    }
+    case "subject":
+      await client.groupUpdateSubject(jid, value);
+      return {};
+    case "description":
+      await client.groupUpdateDescription(jid, value);
+      return {};
+    case "settings":
+      await client.groupSettingUpdate(jid, value);
+      return {};
+    case "invite_code":
+      return { code: await client.groupInviteCode(jid) };
+    case "revoke_invite":
+      return { code: await client.groupRevokeInvite(jid) };
+    default:
+      throw new Error(`Unknown group action '${action}'`);
+  }
+};
+
+const checkNumbers = async (client, { jids }) => {
+  if (!jids.length) return [];
+  const found = await client.onWhatsApp(...jids);
+  return (found || []).map(({ jid, exists, lid }) => ({
+    jid,
+    exists: !!exists,
+    lid: lid || null,
+  }));
+};
+
+const photoUrl = (client, jid) =>
+  client.profilePictureUrl(jid, "image").catch(() => null);
+
+const profileAction = async (client, { action, value, setting, image }) => {
+  const me = jidNormalizedUser(client.user?.id);
+  switch (action) {
+    case "get": {
+      const [status, url, privacy] = await Promise.all([
+        client.fetchStatus(me).catch(() => null),
+        photoUrl(client, me),
+        client.fetchPrivacySettings(true).catch(() => ({})),
+      ]);
+      // Newer Baileys returns a list of {id, status}
+      const about = Array.isArray(status) ? status[0]?.status : status;
+      return {
+        name: client.user?.name || null,
+        about: about?.status ?? null,
+        photoUrl: url,
+        privacy: {
+          last_seen: privacy.last,
+          online: privacy.online,
+          profile_picture: privacy.profile,
+          status: privacy.status,
+          read_receipts: privacy.readreceipts,
+          groups_add: privacy.groupadd,
+          calls_add: privacy.calladd,
+        },
+      };
+    }
+    case "name":
+      await client.updateProfileName(value);
+      return { name: value };
+    case "about":
+      await client.updateProfileStatus(value);
+      return { about: value };
+    case "photo":
+      await client.updateProfilePicture(me, Buffer.from(image));
+      return { photoUrl: await photoUrl(client, me) };
+    case "remove_photo":
+      await client.removeProfilePicture(me);
+      return { photoUrl: null };
+    case "privacy": {
+      const update = privacyUpdaters(client)[setting];
+      if (!update) throw new Error(`Unknown privacy setting '${setting}'`);
+      await update(value);
+      return { [setting]: value };
+    }
+    default:
+      throw new Error(`Unknown profile action '${action}'`);
+  }
+};
+
+const postStatus = async (client, { kind, text, mediaUrl, backgroundColor, font, audience }) => {
+  let content;
+  if (kind === "text") {
+    content = { text };
+  } else if (kind === "image" || kind === "video") {
+    content = { [kind]: { url: mediaUrl } };
+    if (text) content.caption = text;
+  } else {
+    throw new Error(`Unknown status kind '${kind}'`);
+  }
+
+  const sent = await client.sendMessage("status@broadcast", content, {
+    statusJidList: audience,
+    backgroundColor: backgroundColor || undefined,
+    font,
+  });
+  return { id: sent?.key?.id };
+};
+
+/** Run a command from the service, returning the JSON-able result data */
+export const runCommand = async (client, command) => {
+  if (!client) throw new Error("Client is not ready");
+
+  switch (command.command) {
+    case "sendMessage":
+      return await sendMessage(client, command.sendMessage);
+    case "groupAction":
+      return await groupAction(client, command.groupAction);
+    case "checkNumbers":
+      return await checkNumbers(client, command.checkNumbers);
+    case "profileAction":
+      return await profileAction(client, command.profileAction);
+    case "postStatus":
+      return await postStatus(client, command.postStatus);
+    default:
+      throw new Error("Unknown command");
+  }
+};
@@ -3,10 +3,21 @@ import { getAlternateId, Configuration } from "../sql";
 import { to_small_caps, parse_command } from "../pkg/util";
 
 import * as net from "net";
-import { WorkerEvent, ConnectionUpdate } from "../proto";
+import {
+  WorkerEvent,
+  WorkerCommand,
+  CommandResult,
+  MessageEvent,
+  QuotedMessage,
+  StatusView,
+  ConnectionUpdate,
+} from "../proto";
+import { runCommand } from "./commands";
 
 const port = parseInt(process.argv[3]);
 let socket = null;
+let client = null;
+let pending = Buffer.alloc(0);
 
 const log = (...args) => {
   if (process.env.LOGS === "true") {
@@ -21,8 +32,62 @@ if (port) {
   socket.on("connect", () =>
     log("[SOCKET] Connected to service on port", port),
   );
+  socket.on("data", (chunk) => {
+    // Commands arrive as a 4-byte big-endian length followed by the message
+    pending = Buffer.concat([pending, chunk]);
+    while (pending.length >= 4) {
+      const length = pending.readUInt32BE(0);
+      if (pending.length < 4 + length) break;
+      const frame = pending.subarray(4, 4 + length);
+      pending = pending.subarray(4 + length);
+      onCommand(frame);
+    }
+  });
 }
 
+const writeEvent = (event) => {
+  if (!socket || socket.destroyed) return;
+
+  const bytes = WorkerEvent.encode(event).finish();
+  const header = Buffer.alloc(4);
+  header.writeUInt32BE(bytes.length, 0);
+
+  socket.write(Buffer.concat([header, bytes]));
+};
+
+const onCommand = async (frame) => {
+  let command;
+  try {
+    command = WorkerCommand.decode(frame);
+  } catch (e) {
+    log("[SOCKET] Bad command frame:", e.message);
+    return;
+  }
+
+  let result;
+  try {
+    const data = await runCommand(client, command);
+    result = CommandResult.create({
+      requestId: command.requestId,
+      success: true,
+      data: JSON.stringify(data ?? null),
+    });
+  } catch (e) {
+    log("[COMMAND]", command.command, "failed:", e.message);
+    result = CommandResult.create({
+      requestId: command.requestId,
+      success: false,
+      error: e.message || String(e),
+    });
+  }
+  writeEvent(WorkerEvent.create({ commandResult: result }));
+};
+
+/** Use this socket for commands from the service, replaced on reconnect */
+export const serveCommands = (sock) => {
+  client = sock;
+};
+
 export const socketOut = (tag, data) => {
   if (!socket || socket.destroyed) return;
 
@@ -40,17 +105,46 @@ export const socketOut = (tag, data) => {
     });
     event = WorkerEvent.create({ connection: conn });
     log("[EVENT]", tag, data.status || tag);
+  } else if (tag === "MESSAGE_RECEIVED" || tag === "MESSAGE_SENT") {
+    const field = tag === "MESSAGE_SENT" ? "messageSent" : "messageReceived";
+    event = WorkerEvent.create({ [field]: toMessageEvent(data) });
+  } else if (tag === "STATUS_VIEWED") {
+    const view = StatusView.create({
+      ...data,
+      timestamp: toSeconds(data.timestamp),
+    });
+    event = WorkerEvent.create({ statusViewed: view });
   } else {
     event = WorkerEvent.create({ rawLog: JSON.stringify(data) });
   }
 
-  const bytes = WorkerEvent.encode(event).finish();
-  const header = Buffer.alloc(4);
-  header.writeUInt32BE(bytes.length, 0);
-
-  socket.write(Buffer.concat([header, bytes]));
+  writeEvent(event);
 };
 
+const toSeconds = (ts) =>
+  typeof ts === "object" && ts !== null ? ts.toNumber() : Number(ts) || 0;
+
+/** Normalized message for the service, from a serialized message */
+const toMessageEvent = (msg) =>
+  MessageEvent.create({
+    id: msg.key.id,
+    chat: msg.chat,
+    sender: msg.sender,
+    device: msg.device,
+    mtype: msg.mtype,
+    text: msg.text,
+    isGroup: msg.isGroup,
+    quoted: msg.quoted
+      ? QuotedMessage.create({
+          id: msg.quoted.stanzaId,
+          sender: msg.quoted.sender,
+          mtype: msg.quoted.mtype,
+          text: msg.quoted.text,
+        })
+      : null,
+    timestamp: toSeconds(msg.messageTimestamp),
+  });
+
 export const handleCommand = async (msg) => {
   if (!msg?.text) return;
 
@@ -0,0 +1,81 @@
+//! Local webhook receiver for trying out subscriptions.
+//!
+//! ```sh
+//! WEBHOOK_SECRET=whsec_... cargo run -p whatsaly-api --example webhook_receiver
+//! ```
+//!
+//! Point a webhook at `http://127.0.0.1:9000/hook`. Every delivery is printed
+//! along with whether its signature checks out. Set `FAIL_STATUS=500` to make
+//! the receiver reject deliveries and watch the retries and dead letters.
+
+use axum::{Router, body::Bytes, http::HeaderMap, http::StatusCode, routing::post};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+fn verify(secret: &str, headers: &HeaderMap, body: &[u8]) -> bool {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    let (Some(timestamp), Some(signature)) = (
+        header("x-whatsaly-timestamp"),
+        header("x-whatsaly-signature").and_then(|s| s.strip_prefix("sha256=")),
+    ) else {
+        return false;
+    };
+    let Ok(signature) = hex::decode(signature) else {
+        return false;
+    };
+
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC can take key of any size");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+#[tokio::main]
+async fn main() {
+    let port: u16 = std::env::var("PORT")
+        .ok()
+        .and_then(|p| p.parse().ok())
+        .unwrap_or(9000);
+    let secret = std::env::var("WEBHOOK_SECRET").unwrap_or_default();
+    let fail_status = std::env::var("FAIL_STATUS")
+        .ok()
+        .and_then(|s| s.parse::<u16>().ok())
+        .and_then(|s| StatusCode::from_u16(s).ok());
+
+    let app = Router::new().route(
+        "/hook",
+        post(move |headers: HeaderMap, body: Bytes| async move {
+            let event = headers
+                .get("x-whatsaly-event")
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or("?");
+            let delivery = headers
+                .get("x-whatsaly-delivery")
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or("?");
+            let valid = !secret.is_empty() && verify(&secret, &headers, &body);
+
+            println!(
+                "[{}] {} ({}) signature {}",
+                chrono::Utc::now().format("%H:%M:%S"),
+                event,
+                delivery,
+                if valid { "ok" } else { "INVALID" }
+            );
+            println!("{}\n", String::from_utf8_lossy(&body));
+
+            match fail_status {
+                Some(status) => status,
+                None if valid => StatusCode::NO_CONTENT,
+                None => StatusCode::UNAUTHORIZED,
+            }
+        }),
+    );
+
+    let addr = std::net::SocketAddr::from(([127, 0, 0, 1], port));
+    println!("Listening for webhooks on http://{}/hook", addr);
+    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+    axum::serve(listener, app).await.unwrap();
+}
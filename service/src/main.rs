@@ -1,8 +1,14 @@
 mod logger;
 mod manager;
+mod ratelimit;
+mod rbac;
 mod routes;
 mod security;
 mod sql;
+#[cfg(test)]
+mod testing;
+mod totp;
+mod webauthn;
 
 use crate::sql::Session;
 use axum::middleware;
@@ -30,6 +36,7 @@ async fn main() {
 
     logger::debug("INIT", "Connecting to SQLite database...");
     let pool = sql::sync_db().await;
+    routes::auth::bootstrap_admin(&pool).await;
 
     logger::debug("INIT", "Connecting to Redis...");
     let redis_client = redis::Client::open("redis://127.0.0.1/").unwrap();
@@ -104,11 +111,15 @@ async fn main() {
 
     let (tx, _rx) = tokio::sync::broadcast::channel::<String>(1024);
     let (log_tx, _) = tokio::sync::broadcast::channel::<String>(256);
+    let (events_tx, _) = tokio::sync::broadcast::channel::<manager::InstanceEvent>(1024);
     logger::set_broadcast(log_tx.clone());
 
     let manager = manager::SessionManager {
         workers: Arc::new(tokio::sync::RwLock::new(std::collections::HashMap::new())),
         tx: tx.clone(),
+        events: events_tx,
+        links: Arc::new(tokio::sync::RwLock::new(std::collections::HashMap::new())),
+        pending: Arc::new(tokio::sync::Mutex::new(std::collections::HashMap::new())),
     };
 
     let state = Arc::new(AppState {
@@ -118,6 +129,11 @@ async fn main() {
         log_tx,
     });
 
+    tokio::spawn(manager::webhooks::run(state.clone()));
+    tokio::spawn(manager::statuses::run(state.clone()));
+    manager::broadcasts::interrupt_running(&pool).await;
+    manager::number_checks::interrupt_running(&pool).await;
+
     logger::debug("INIT", "Loading existing sessions...");
     let sessions: Vec<Session> = sqlx::query_as::<_, Session>("SELECT * FROM sessions")
         .fetch_all(&pool)
@@ -154,9 +170,19 @@ async fn main() {
 
     let static_service = ServeDir::new("ui/build");
     let app = routes::create_routes()
-        .layer(middleware::from_fn(security::jwt_auth_middleware))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            ratelimit::user_rate_limit_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            security::jwt_auth_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            ratelimit::ip_rate_limit_middleware,
+        ))
         .layer(middleware::from_fn(security::origin_validation_middleware))
-        .layer(middleware::from_fn(security::api_key_middleware))
         .layer(CorsLayer::permissive())
         .with_state(state)
         .fallback_service(static_service);
@@ -165,5 +191,10 @@ async fn main() {
 
     let addr = std::net::SocketAddr::from(([0, 0, 0, 0], port));
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+    )
+    .await
+    .unwrap();
 }
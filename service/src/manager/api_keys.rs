@@ -0,0 +1,304 @@
+use crate::AppState;
+use crate::ratelimit::client_ip;
+use crate::security::{Claims, create_secure_response, response_codes};
+use crate::sql::{ApiKey, User};
+use axum::{
+    Json,
+    http::{HeaderMap, Method, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use sha2::{Digest, Sha256};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+/// What a key may do
+pub const SCOPES: &[&str] = &["instances:read", "messages:send", "settings:manage"];
+
+/// Every key starts with this, which tells them apart from JWTs
+pub const KEY_PREFIX: &str = "wsk_";
+/// Characters of a key kept in clear, so users can tell their keys apart
+const SHOWN_PREFIX_LEN: usize = 12;
+/// Last-used time is written at most this often per key
+const LAST_USED_RESOLUTION_SECS: i64 = 60;
+
+/// Instance sections whose writes send something out
+const SENDING: &[&str] = &["messages", "broadcasts", "statuses"];
+/// Account sections that need a real sign-in
+const SIGNED_IN_ONLY: &[&str] = &["api-keys", "2fa", "logins"];
+
+/// A new key and the parts of it that are stored
+pub struct NewKey {
+    pub key: String,
+    pub prefix: String,
+    pub hash: String,
+}
+
+pub fn generate_key() -> NewKey {
+    let key = format!("{}{}", KEY_PREFIX, hex::encode(rand::random::<[u8; 32]>()));
+    NewKey {
+        prefix: key[..SHOWN_PREFIX_LEN].to_string(),
+        hash: hash_key(&key),
+        key,
+    }
+}
+
+fn hash_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+/// An allowlist entry, a single address or a CIDR range
+pub fn is_valid_ip_rule(rule: &str) -> bool {
+    parse_ip_rule(rule).is_some()
+}
+
+fn parse_ip_rule(rule: &str) -> Option<(IpAddr, u32)> {
+    let (addr, bits) = match rule.split_once('/') {
+        Some((addr, bits)) => (addr.parse::<IpAddr>().ok()?, bits.parse::<u32>().ok()?),
+        None => {
+            let addr = rule.parse::<IpAddr>().ok()?;
+            (addr, if addr.is_ipv4() { 32 } else { 128 })
+        }
+    };
+    let max = if addr.is_ipv4() { 32 } else { 128 };
+    (bits <= max).then_some((addr, bits))
+}
+
+fn ip_matches(rule: &str, ip: IpAddr) -> bool {
+    let Some((net, bits)) = parse_ip_rule(rule) else {
+        return false;
+    };
+    match (net, ip) {
+        (IpAddr::V4(net), IpAddr::V4(ip)) => {
+            let mask = u32::MAX.checked_shl(32 - bits).unwrap_or(0);
+            u32::from(net) & mask == u32::from(ip) & mask
+        }
+        (IpAddr::V6(net), IpAddr::V6(ip)) => {
+            let mask = u128::MAX.checked_shl(128 - bits).unwrap_or(0);
+            u128::from(net) & mask == u128::from(ip) & mask
+        }
+        _ => false,
+    }
+}
+
+/// The key a request presents, in X-API-Key or as a bearer token
+pub fn presented(headers: &HeaderMap) -> Option<String> {
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "));
+    headers
+        .get("x-api-key")
+        .and_then(|h| h.to_str().ok())
+        .or(bearer)
+        .map(str::trim)
+        .filter(|k| k.starts_with(KEY_PREFIX))
+        .map(String::from)
+}
+
+/// The scope a request needs, none for routes keys can't use at all.
+/// Instance routes are `/api/instances/:phone/...`, account routes
+/// `/api/user/:crypto_hash/...`.
+fn required_scope(method: &Method, path: &str) -> Option<&'static str> {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let read = method == Method::GET;
+    match segments.as_slice() {
+        ["api", "user", _, section, ..] if SIGNED_IN_ONLY.contains(section) => None,
+        // Buying credits takes a person
+        ["api", "user", _, "credits", "add"] => None,
+        ["api", "instances", _, section, ..] if !read && SENDING.contains(section) => {
+            Some("messages:send")
+        }
+        ["api", "instances", _, ..] | ["api", "user", _, ..] if read => Some("instances:read"),
+        ["api", "instances", _, ..] | ["api", "user", _, ..] => Some("settings:manage"),
+        ["api", "tools", ..] if read => Some("instances:read"),
+        _ => None,
+    }
+}
+
+/// The instance a request acts on, if any
+fn target_instance(path: &str) -> Option<&str> {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match segments.as_slice() {
+        ["api", "instances", phone, ..] => Some(phone),
+        ["api", "user", _, "instances", session_id, ..] => Some(session_id),
+        _ => None,
+    }
+}
+
+fn json_list(value: Option<&str>) -> Option<Vec<String>> {
+    value.and_then(|v| serde_json::from_str(v).ok())
+}
+
+fn denied(status: StatusCode) -> Response {
+    (
+        status,
+        Json(create_secure_response(
+            response_codes::ACCESS_DENIED,
+            false,
+            None,
+        )),
+    )
+        .into_response()
+}
+
+/// Check a presented key against the request. On success the request acts
+/// as the key's owner, with claims like a user token carries.
+pub async fn authenticate(
+    state: &Arc<AppState>,
+    key: &str,
+    headers: &HeaderMap,
+    peer: Option<SocketAddr>,
+    method: &Method,
+    path: &str,
+) -> Result<Claims, Response> {
+    let api_key: Option<ApiKey> = sqlx::query_as("SELECT * FROM api_keys WHERE keyHash = ?")
+        .bind(hash_key(key))
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None);
+    let Some(api_key) = api_key else {
+        return Err(denied(StatusCode::UNAUTHORIZED));
+    };
+    let now = chrono::Utc::now();
+    if api_key.expires_at.is_some_and(|expires| expires <= now) {
+        return Err(denied(StatusCode::UNAUTHORIZED));
+    }
+
+    let ip = client_ip(headers, peer);
+    if let Some(allowed) = json_list(api_key.allowed_ips.as_deref())
+        && !allowed.is_empty()
+    {
+        let permitted = ip
+            .parse::<IpAddr>()
+            .is_ok_and(|ip| allowed.iter().any(|rule| ip_matches(rule, ip)));
+        if !permitted {
+            return Err(denied(StatusCode::FORBIDDEN));
+        }
+    }
+
+    let scopes = json_list(Some(&api_key.scopes)).unwrap_or_default();
+    let in_scope = required_scope(method, path).is_some_and(|s| scopes.iter().any(|k| k == s));
+    if !in_scope {
+        return Err(denied(StatusCode::FORBIDDEN));
+    }
+    // Keys limited to some instances only reach those instances' routes
+    if let Some(instances) = json_list(api_key.instances.as_deref())
+        && !target_instance(path).is_some_and(|t| instances.iter().any(|i| i == t))
+    {
+        return Err(denied(StatusCode::FORBIDDEN));
+    }
+
+    let user: Option<User> =
+        sqlx::query_as("SELECT * FROM users WHERE id = ? AND suspended = FALSE")
+            .bind(&api_key.user_id)
+            .fetch_optional(&state.db)
+            .await
+            .unwrap_or(None);
+    let Some(user) = user else {
+        return Err(denied(StatusCode::FORBIDDEN));
+    };
+
+    let _ = sqlx::query(
+        "UPDATE api_keys SET lastUsedAt = ?, lastUsedIp = ?
+         WHERE id = ? AND (lastUsedAt IS NULL OR lastUsedAt < ? OR lastUsedIp IS NOT ?)",
+    )
+    .bind(now)
+    .bind(&ip)
+    .bind(&api_key.id)
+    .bind(now - chrono::Duration::seconds(LAST_USED_RESOLUTION_SECS))
+    .bind(&ip)
+    .execute(&state.db)
+    .await;
+
+    Ok(Claims {
+        sub: user.crypto_hash,
+        role: "user".to_string(),
+        iat: now.timestamp(),
+        exp: now.timestamp(),
+        jti: api_key.id.clone(),
+        sid: format!("apikey:{}", api_key.id),
+        typ: "apikey".to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ip_rules_match_addresses_and_ranges() {
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        assert!(ip_matches("203.0.113.7", ip("203.0.113.7")));
+        assert!(!ip_matches("203.0.113.7", ip("203.0.113.8")));
+        assert!(ip_matches("10.0.0.0/8", ip("10.200.1.1")));
+        assert!(!ip_matches("10.0.0.0/8", ip("11.0.0.1")));
+        assert!(ip_matches("0.0.0.0/0", ip("198.51.100.1")));
+        assert!(ip_matches("2001:db8::/32", ip("2001:db8:1::1")));
+        assert!(!ip_matches("2001:db8::/32", ip("2001:db9::1")));
+        assert!(!ip_matches("10.0.0.0/8", ip("::ffff:10.0.0.1")));
+        assert!(!ip_matches("10.0.0.0/33", ip("10.0.0.1")));
+        assert!(!is_valid_ip_rule("not-an-ip"));
+        assert!(!is_valid_ip_rule("::/129"));
+    }
+
+    #[test]
+    fn scopes_follow_the_route() {
+        let get = Method::GET;
+        let post = Method::POST;
+        assert_eq!(
+            required_scope(&get, "/api/instances/123/messages"),
+            Some("instances:read")
+        );
+        assert_eq!(
+            required_scope(&post, "/api/instances/123/messages"),
+            Some("messages:send")
+        );
+        assert_eq!(
+            required_scope(&post, "/api/instances/123/statuses/"),
+            Some("messages:send")
+        );
+        assert_eq!(
+            required_scope(&post, "/api/instances/123/webhooks"),
+            Some("settings:manage")
+        );
+        assert_eq!(
+            required_scope(&get, "/api/user/abc/instances"),
+            Some("instances:read")
+        );
+        assert_eq!(
+            required_scope(&get, "/api/tools/news"),
+            Some("instances:read")
+        );
+        assert_eq!(required_scope(&post, "/api/tools/clear"), None);
+    }
+
+    #[test]
+    fn account_security_is_off_limits() {
+        for path in [
+            "/api/user/abc/api-keys",
+            "/api/user/abc/2fa/enroll",
+            "/api/user/abc/logins",
+            "/api/user/abc/credits/add",
+            "/api/admin/users",
+        ] {
+            assert_eq!(required_scope(&Method::GET, path), None, "{}", path);
+            assert_eq!(required_scope(&Method::POST, path), None, "{}", path);
+        }
+    }
+
+    #[test]
+    fn generated_keys_are_prefixed_and_hashed() {
+        let new = generate_key();
+        assert!(new.key.starts_with(KEY_PREFIX));
+        assert!(new.key.starts_with(&new.prefix));
+        assert_eq!(new.prefix.len(), SHOWN_PREFIX_LEN);
+        assert_eq!(new.hash, hash_key(&new.key));
+    }
+
+    #[test]
+    fn requests_target_the_instance_in_the_path() {
+        assert_eq!(target_instance("/api/instances/123/messages"), Some("123"));
+        assert_eq!(target_instance("/api/user/abc/instances/s1"), Some("s1"));
+        assert_eq!(target_instance("/api/user/abc/settings"), None);
+    }
+}
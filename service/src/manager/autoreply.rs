@@ -0,0 +1,236 @@
+use crate::AppState;
+use crate::logger;
+use crate::manager::events::worker_command::SendMessage;
+use crate::manager::events::worker_event::MessageEvent;
+use crate::manager::outbound::{self, SendSource};
+use crate::manager::templates;
+use crate::sql::AutoReplyRule;
+use regex::{Regex, RegexBuilder};
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+
+pub const MATCH_TYPES: &[&str] = &["exact", "contains", "regex", "first_message"];
+pub const SCOPES: &[&str] = &["all", "dm", "group", "chats"];
+pub const RESPONSE_TYPES: &[&str] = &["text", "media", "template"];
+/// Per-sender cooldown of new rules, so two bots replying to each other don't loop
+pub const DEFAULT_COOLDOWN_SECONDS: i64 = 10;
+
+/// Upper bound on compiled regex size, rules are user supplied
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+const REGEX_CACHE_CAPACITY: usize = 512;
+
+static REGEX_CACHE: LazyLock<Mutex<HashMap<(String, bool), Regex>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+pub fn compile(pattern: &str, case_sensitive: bool) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(!case_sensitive)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .build()
+}
+
+pub fn cached_regex(pattern: &str, case_sensitive: bool) -> Option<Regex> {
+    let key = (pattern.to_string(), case_sensitive);
+    let mut cache = REGEX_CACHE.lock().unwrap();
+    if let Some(re) = cache.get(&key) {
+        return Some(re.clone());
+    }
+    let re = compile(pattern, case_sensitive).ok()?;
+    if cache.len() >= REGEX_CACHE_CAPACITY {
+        cache.clear();
+    }
+    cache.insert(key, re.clone());
+    Some(re)
+}
+
+fn in_scope(rule: &AutoReplyRule, msg: &MessageEvent) -> bool {
+    match rule.scope.as_str() {
+        "dm" => !msg.is_group,
+        "group" => msg.is_group,
+        "chats" => serde_json::from_str::<Vec<String>>(&rule.chats)
+            .unwrap_or_default()
+            .contains(&msg.chat),
+        _ => true,
+    }
+}
+
+fn matches(rule: &AutoReplyRule, text: Option<&str>, first_contact: bool) -> bool {
+    if rule.match_type == "first_message" {
+        return first_contact;
+    }
+    let (Some(text), Some(pattern)) = (text, rule.pattern.as_deref()) else {
+        return false;
+    };
+
+    match rule.match_type.as_str() {
+        "exact" if rule.case_sensitive => text.trim() == pattern.trim(),
+        "exact" => text.trim().to_lowercase() == pattern.trim().to_lowercase(),
+        "contains" if rule.case_sensitive => text.contains(pattern),
+        "contains" => text.to_lowercase().contains(&pattern.to_lowercase()),
+        "regex" => cached_regex(pattern, rule.case_sensitive).is_some_and(|re| re.is_match(text)),
+        _ => false,
+    }
+}
+
+/// `{{sender}}`, `{{chat}}`, `{{text}}`, `{{date}}` and `{{time}}` of an incoming message
+pub fn message_values(msg: &MessageEvent) -> HashMap<String, String> {
+    let now = chrono::Utc::now();
+    let sender = msg.sender.split(['@', ':']).next().unwrap_or_default();
+    HashMap::from([
+        ("sender".to_string(), sender.to_string()),
+        ("chat".to_string(), msg.chat.clone()),
+        ("text".to_string(), msg.text.clone().unwrap_or_default()),
+        ("date".to_string(), now.format("%Y-%m-%d").to_string()),
+        ("time".to_string(), now.format("%H:%M").to_string()),
+    ])
+}
+
+/// Fill the incoming message's values into a reply
+pub fn render(template: &str, msg: &MessageEvent) -> String {
+    templates::substitute(template, &message_values(msg))
+}
+
+/// Whether this is the first message we have from the sender. In groups that
+/// means the sender never wrote before, in DMs that the chat has no history.
+pub async fn is_first_contact(db: &sqlx::SqlitePool, phone: &str, msg: &MessageEvent) -> bool {
+    let query = if msg.is_group {
+        "SELECT NOT EXISTS(SELECT 1 FROM messages WHERE sessionId = ? AND sender = ? AND fromMe = 0 AND messageId != ?)"
+    } else {
+        "SELECT NOT EXISTS(SELECT 1 FROM messages WHERE sessionId = ? AND chat = ? AND messageId != ?)"
+    };
+    let who = if msg.is_group { &msg.sender } else { &msg.chat };
+
+    sqlx::query_scalar(query)
+        .bind(phone)
+        .bind(who)
+        .bind(&msg.id)
+        .fetch_one(db)
+        .await
+        .unwrap_or(false)
+}
+
+/// Set a Redis key unless it exists, as a "once per TTL" guard. Fails closed
+/// when Redis is unreachable so a flapping cache can't turn into a reply storm.
+pub async fn claim_once(state: &Arc<AppState>, key: &str, ttl_seconds: i64) -> bool {
+    let Ok(mut conn) = state.redis.get_multiplexed_async_connection().await else {
+        return false;
+    };
+    let claimed: Option<String> = redis::cmd("SET")
+        .arg(key)
+        .arg(1)
+        .arg("NX")
+        .arg("EX")
+        .arg(ttl_seconds)
+        .query_async(&mut conn)
+        .await
+        .unwrap_or(None);
+    claimed.is_some()
+}
+
+/// Claim the per-sender cooldown slot of a rule
+async fn claim_cooldown(
+    state: &Arc<AppState>,
+    phone: &str,
+    rule: &AutoReplyRule,
+    sender: &str,
+) -> bool {
+    if rule.cooldown_seconds <= 0 {
+        return true;
+    }
+    let key = format!("{}:autoreply:{}:{}", phone, rule.id, sender);
+    claim_once(state, &key, rule.cooldown_seconds).await
+}
+
+/// Render the rule's stored template for the sender. The message fills
+/// `{{sender}}`, `{{chat}}` and `{{text}}` on top of the contact's values.
+async fn render_template(
+    state: &Arc<AppState>,
+    phone: &str,
+    template_id: i64,
+    msg: &MessageEvent,
+) -> Result<SendMessage, String> {
+    let stored = templates::find_by_id_for_instance(&state.db, phone, template_id)
+        .await
+        .ok_or_else(|| format!("Template {} not found", template_id))?;
+    let mut values = templates::context_values(&state.db, Some(phone), Some(&msg.sender)).await;
+    values.extend(message_values(msg));
+    templates::render(&stored, None, &values).map(|r| r.into_message(msg.chat.clone()))
+}
+
+async fn build_reply(
+    state: &Arc<AppState>,
+    phone: &str,
+    rule: &AutoReplyRule,
+    msg: &MessageEvent,
+) -> Result<SendMessage, String> {
+    if rule.response_type == "template" {
+        let template_id = rule.template_id.ok_or("Rule has no template")?;
+        return render_template(state, phone, template_id, msg).await;
+    }
+    Ok(SendMessage {
+        jid: msg.chat.clone(),
+        text: render(rule.response_text.as_deref().unwrap_or_default(), msg),
+        media_url: rule.media_url.clone().unwrap_or_default(),
+        media_type: rule.media_type.clone().unwrap_or_default(),
+        file_name: rule.file_name.clone().unwrap_or_default(),
+    })
+}
+
+/// Evaluate an instance's rules against an incoming message and send the reply
+/// of the first matching rule. Returns whether a rule handled the message.
+pub async fn handle(state: &Arc<AppState>, phone: &str, msg: &MessageEvent) -> bool {
+    if msg.chat == "status@broadcast" {
+        return false;
+    }
+
+    let rules: Vec<AutoReplyRule> = sqlx::query_as(
+        "SELECT * FROM auto_reply_rules WHERE sessionId = ? AND enabled = 1 ORDER BY priority DESC, id ASC",
+    )
+    .bind(phone)
+    .fetch_all(&state.db)
+    .await
+    .unwrap_or_default();
+
+    if rules.is_empty() {
+        return false;
+    }
+
+    let first_contact = if rules.iter().any(|r| r.match_type == "first_message") {
+        is_first_contact(&state.db, phone, msg).await
+    } else {
+        false
+    };
+
+    let Some(rule) = rules
+        .iter()
+        .find(|r| in_scope(r, msg) && matches(r, msg.text.as_deref(), first_contact))
+    else {
+        return false;
+    };
+
+    // A rule cooling down for this sender still owns the message
+    if !claim_cooldown(state, phone, rule, &msg.sender).await {
+        return true;
+    }
+
+    let _ = sqlx::query("UPDATE auto_reply_rules SET hits = hits + 1, lastHitAt = ? WHERE id = ?")
+        .bind(chrono::Utc::now())
+        .bind(rule.id)
+        .execute(&state.db)
+        .await;
+
+    let result = match build_reply(state, phone, rule, msg).await {
+        Ok(reply) => outbound::send(state, phone, SendSource::AutoReply, reply)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        logger::error(
+            "AUTOREPLY",
+            &format!("{} rule {} failed to reply: {}", phone, rule.id, e),
+        );
+    }
+    true
+}
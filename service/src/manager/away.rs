@@ -0,0 +1,247 @@
+use crate::AppState;
+use crate::logger;
+use crate::manager::autoreply;
+use crate::manager::events::worker_command::SendMessage;
+use crate::manager::events::worker_event::MessageEvent;
+use crate::manager::outbound::{self, SendSource};
+use crate::sql::BusinessHours;
+use chrono::{DateTime, Datelike, NaiveDate, Timelike, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub const WEEKDAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+/// Longest wait before a chat gets the away message again, 30 days
+pub const MAX_AWAY_COOLDOWN_HOURS: i64 = 720;
+
+/// Opening window in local `HH:MM`. A window closing at or before it opens runs past midnight.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Window {
+    pub open: String,
+    pub close: String,
+}
+
+/// Weekday (`mon`..`sun`) to opening windows
+pub type WeeklyHours = HashMap<String, Vec<Window>>;
+
+fn weekday_key(day: Weekday) -> &'static str {
+    WEEKDAYS[day.num_days_from_monday() as usize]
+}
+
+/// Minutes since midnight, `24:00` allowed as end of day
+fn minutes(time: &str) -> Option<u32> {
+    let (h, m) = time.split_once(':')?;
+    let (h, m): (u32, u32) = (h.parse().ok()?, m.parse().ok()?);
+    let total = h * 60 + m;
+    (m < 60 && total <= 24 * 60).then_some(total)
+}
+
+pub fn validate(timezone: &str, hours: &WeeklyHours, holidays: &[String]) -> Result<(), String> {
+    if timezone.parse::<Tz>().is_err() {
+        return Err(format!("Unknown timezone '{}'", timezone));
+    }
+    for (day, windows) in hours {
+        if !WEEKDAYS.contains(&day.as_str()) {
+            return Err(format!(
+                "Unknown weekday '{}', expected one of: {}",
+                day,
+                WEEKDAYS.join(", ")
+            ));
+        }
+        for w in windows {
+            if minutes(&w.open).is_none() || minutes(&w.close).is_none() {
+                return Err(format!(
+                    "Invalid window {}-{} on {}, expected HH:MM",
+                    w.open, w.close, day
+                ));
+            }
+        }
+    }
+    if let Some(bad) = holidays
+        .iter()
+        .find(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").is_err())
+    {
+        return Err(format!("Invalid holiday '{}', expected YYYY-MM-DD", bad));
+    }
+    Ok(())
+}
+
+/// Whether the business is open at `now`. A broken timezone counts as open so
+/// a misconfiguration never floods contacts with away replies.
+pub fn is_open(config: &BusinessHours, now: DateTime<Utc>) -> bool {
+    let Ok(tz) = config.timezone.parse::<Tz>() else {
+        return true;
+    };
+    let local = now.with_timezone(&tz);
+
+    let holidays: Vec<String> = serde_json::from_str(&config.holidays).unwrap_or_default();
+    if holidays.contains(&local.format("%Y-%m-%d").to_string()) {
+        return false;
+    }
+
+    let hours: WeeklyHours = serde_json::from_str(&config.hours).unwrap_or_default();
+    let windows = |day: Weekday| {
+        hours
+            .get(weekday_key(day))
+            .into_iter()
+            .flatten()
+            .filter_map(|w| Some((minutes(&w.open)?, minutes(&w.close)?)))
+    };
+    let t = local.hour() * 60 + local.minute();
+
+    let today = windows(local.weekday()).any(|(open, close)| {
+        if open < close {
+            open <= t && t < close
+        } else {
+            t >= open
+        }
+    });
+    // Overnight windows opened yesterday
+    let spill = windows(local.weekday().pred()).any(|(open, close)| close <= open && t < close);
+
+    today || spill
+}
+
+async fn reply(state: &Arc<AppState>, phone: &str, msg: &MessageEvent, text: &str) {
+    let message = SendMessage {
+        jid: msg.chat.clone(),
+        text: autoreply::render(text, msg),
+        ..Default::default()
+    };
+    if let Err(e) = outbound::send(state, phone, SendSource::Away, message).await {
+        logger::error(
+            "AWAY",
+            &format!("{} failed to reply to {}: {}", phone, msg.chat, e),
+        );
+    }
+}
+
+/// Greet first-time contacts and answer outside business hours. Only direct
+/// chats are handled. Returns whether a reply was sent.
+pub async fn handle(state: &Arc<AppState>, phone: &str, msg: &MessageEvent) -> bool {
+    if msg.is_group || msg.chat == "status@broadcast" {
+        return false;
+    }
+
+    let config: Option<BusinessHours> =
+        sqlx::query_as("SELECT * FROM business_hours WHERE sessionId = ? AND enabled = 1")
+            .bind(phone)
+            .fetch_optional(&state.db)
+            .await
+            .unwrap_or(None);
+    let Some(config) = config else {
+        return false;
+    };
+
+    if let Some(greeting) = config.greeting.as_deref().filter(|g| !g.is_empty())
+        && autoreply::is_first_contact(&state.db, phone, msg).await
+    {
+        reply(state, phone, msg, greeting).await;
+        return true;
+    }
+
+    let Some(away) = config.away_message.as_deref().filter(|a| !a.is_empty()) else {
+        return false;
+    };
+    if is_open(&config, Utc::now()) {
+        return false;
+    }
+
+    let key = format!("{}:away:{}", phone, msg.chat);
+    let cooldown = config
+        .away_cooldown_hours
+        .clamp(1, MAX_AWAY_COOLDOWN_HOURS)
+        .saturating_mul(3600);
+    if !autoreply::claim_once(state, &key, cooldown).await {
+        return false;
+    }
+
+    reply(state, phone, msg, away).await;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn config(timezone: &str, hours: serde_json::Value, holidays: &[&str]) -> BusinessHours {
+        BusinessHours {
+            session_id: "1".to_string(),
+            enabled: true,
+            timezone: timezone.to_string(),
+            hours: hours.to_string(),
+            holidays: serde_json::json!(holidays).to_string(),
+            greeting: None,
+            away_message: None,
+            away_cooldown_hours: 24,
+            updated_at: Utc::now(),
+        }
+    }
+
+    /// 2026-10-19 is a Monday
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 10, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn open_within_the_days_windows() {
+        let hours = serde_json::json!({
+            "mon": [{"open": "09:00", "close": "12:00"}, {"open": "13:00", "close": "17:30"}]
+        });
+        let c = config("UTC", hours, &[]);
+        assert!(!is_open(&c, at(19, 8, 59)));
+        assert!(is_open(&c, at(19, 9, 0)));
+        assert!(!is_open(&c, at(19, 12, 30)));
+        assert!(is_open(&c, at(19, 17, 29)));
+        assert!(!is_open(&c, at(19, 17, 30)));
+        // No windows on Tuesday
+        assert!(!is_open(&c, at(20, 10, 0)));
+    }
+
+    #[test]
+    fn overnight_windows_spill_into_the_next_day() {
+        let hours = serde_json::json!({"fri": [{"open": "22:00", "close": "02:00"}]});
+        let c = config("UTC", hours, &[]);
+        assert!(is_open(&c, at(23, 23, 0)));
+        assert!(is_open(&c, at(24, 1, 59)));
+        assert!(!is_open(&c, at(24, 2, 0)));
+        assert!(!is_open(&c, at(23, 1, 0)));
+    }
+
+    #[test]
+    fn hours_are_local_and_holidays_close() {
+        let hours = serde_json::json!({"mon": [{"open": "09:00", "close": "17:00"}]});
+        // Berlin is UTC+2 until the end of October
+        let c = config("Europe/Berlin", hours.clone(), &[]);
+        assert!(is_open(&c, at(19, 7, 0)));
+        assert!(!is_open(&c, at(19, 15, 0)));
+
+        let c = config("Europe/Berlin", hours, &["2026-10-19"]);
+        assert!(!is_open(&c, at(19, 8, 0)));
+    }
+
+    #[test]
+    fn a_broken_timezone_counts_as_open() {
+        let c = config("Mars/Olympus", serde_json::json!({}), &[]);
+        assert!(is_open(&c, at(19, 3, 0)));
+    }
+
+    #[test]
+    fn validation_rejects_bad_days_times_and_dates() {
+        let window = |open: &str, close: &str| Window {
+            open: open.to_string(),
+            close: close.to_string(),
+        };
+        let hours = |day: &str, w: Window| WeeklyHours::from([(day.to_string(), vec![w])]);
+        assert!(validate("UTC", &hours("mon", window("09:00", "24:00")), &[]).is_ok());
+        assert!(validate("Nowhere", &WeeklyHours::new(), &[]).is_err());
+        assert!(validate("UTC", &hours("monday", window("09:00", "17:00")), &[]).is_err());
+        assert!(validate("UTC", &hours("mon", window("09:60", "17:00")), &[]).is_err());
+        assert!(validate("UTC", &hours("mon", window("09:00", "24:01")), &[]).is_err());
+        assert!(validate("UTC", &WeeklyHours::new(), &["2026-13-01".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,180 @@
+use crate::AppState;
+use crate::logger;
+use crate::manager::events::worker_command::SendMessage;
+use crate::manager::outbound::{self, OutboundError, SendSource};
+use crate::manager::templates::{self, TemplateRef};
+use crate::sql::Broadcast;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::{Arc, LazyLock, Mutex};
+
+/// Broadcasts with a live sender task, so resume never starts a second one
+static RUNNING: LazyLock<Mutex<HashSet<i64>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+
+/// What every recipient gets: plain content or a template rendered per contact
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BroadcastMessage {
+    #[serde(default)]
+    pub text: Option<String>,
+    #[serde(default, rename = "mediaUrl")]
+    pub media_url: Option<String>,
+    #[serde(default, rename = "mediaType")]
+    pub media_type: Option<String>,
+    #[serde(default, rename = "fileName")]
+    pub file_name: Option<String>,
+    #[serde(default)]
+    pub template: Option<TemplateRef>,
+}
+
+impl BroadcastMessage {
+    pub fn is_empty(&self) -> bool {
+        self.template.is_none()
+            && self.text.as_deref().unwrap_or("").is_empty()
+            && self.media_url.is_none()
+    }
+
+    /// Build the message for one recipient
+    pub async fn for_recipient(
+        &self,
+        db: &sqlx::SqlitePool,
+        phone: &str,
+        jid: &str,
+    ) -> Result<SendMessage, String> {
+        if let Some(ref template) = self.template {
+            return templates::render_ref(db, phone, jid.to_string(), template).await;
+        }
+        Ok(SendMessage {
+            jid: jid.to_string(),
+            text: self.text.clone().unwrap_or_default(),
+            media_url: self.media_url.clone().unwrap_or_default(),
+            media_type: self.media_type.clone().unwrap_or_default(),
+            file_name: self.file_name.clone().unwrap_or_default(),
+        })
+    }
+}
+
+async fn set_status(db: &sqlx::SqlitePool, id: i64, status: &str, error: Option<&str>) {
+    let finished = matches!(status, "completed" | "cancelled").then(chrono::Utc::now);
+    let _ = sqlx::query(
+        "UPDATE broadcasts SET status = ?, lastError = COALESCE(?, lastError), finishedAt = COALESCE(?, finishedAt), updatedAt = ?
+         WHERE id = ?",
+    )
+    .bind(status)
+    .bind(error)
+    .bind(finished)
+    .bind(chrono::Utc::now())
+    .bind(id)
+    .execute(db)
+    .await;
+}
+
+async fn record(db: &sqlx::SqlitePool, id: i64, jid: &str, error: Option<&str>) {
+    let (status, counter) = match error {
+        None => ("sent", "sent"),
+        Some(_) => ("failed", "failed"),
+    };
+    let now = chrono::Utc::now();
+    let _ = sqlx::query(
+        "UPDATE broadcast_recipients SET status = ?, error = ?, sentAt = ? WHERE broadcastId = ? AND jid = ?",
+    )
+    .bind(status)
+    .bind(error)
+    .bind(error.is_none().then_some(now))
+    .bind(id)
+    .bind(jid)
+    .execute(db)
+    .await;
+    let _ = sqlx::query(&format!(
+        "UPDATE broadcasts SET {counter} = {counter} + 1, updatedAt = ? WHERE id = ?"
+    ))
+    .bind(now)
+    .bind(id)
+    .execute(db)
+    .await;
+}
+
+/// Start sending a broadcast's pending recipients in the background
+pub fn spawn(state: Arc<AppState>, id: i64) {
+    if !RUNNING.lock().unwrap().insert(id) {
+        return;
+    }
+    tokio::spawn(async move {
+        run(&state, id).await;
+        RUNNING.lock().unwrap().remove(&id);
+    });
+}
+
+/// Send to every pending recipient through the outbound governor. Per-minute
+/// limits are waited out, daily limits and disconnects pause the broadcast.
+async fn run(state: &Arc<AppState>, id: i64) {
+    loop {
+        let broadcast: Option<Broadcast> = sqlx::query_as("SELECT * FROM broadcasts WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&state.db)
+            .await
+            .unwrap_or(None);
+        let Some(broadcast) = broadcast.filter(|b| b.status == "running") else {
+            return;
+        };
+        let Ok(message) = serde_json::from_str::<BroadcastMessage>(&broadcast.message) else {
+            set_status(&state.db, id, "cancelled", Some("Invalid message")).await;
+            return;
+        };
+
+        let next: Option<String> = sqlx::query_scalar(
+            "SELECT jid FROM broadcast_recipients WHERE broadcastId = ? AND status = 'pending' ORDER BY jid LIMIT 1",
+        )
+        .bind(id)
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None);
+        let Some(jid) = next else {
+            set_status(&state.db, id, "completed", None).await;
+            logger::info(
+                "BROADCAST",
+                &format!("{} broadcast {} completed", broadcast.session_id, id),
+            );
+            return;
+        };
+
+        let phone = &broadcast.session_id;
+        let built = match message.for_recipient(&state.db, phone, &jid).await {
+            Ok(m) => m,
+            Err(e) => {
+                record(&state.db, id, &jid, Some(&e)).await;
+                continue;
+            }
+        };
+
+        match outbound::send(state, phone, SendSource::Broadcast, built).await {
+            Ok(_) => record(&state.db, id, &jid, None).await,
+            Err(OutboundError::MinuteLimit { retry_after, .. }) => {
+                tokio::time::sleep(std::time::Duration::from_secs(retry_after.max(1))).await;
+            }
+            Err(
+                e @ (OutboundError::DailyLimit { .. }
+                | OutboundError::NotConnected
+                | OutboundError::Unavailable(_)),
+            ) => {
+                set_status(&state.db, id, "paused", Some(&e.to_string())).await;
+                logger::warn(
+                    "BROADCAST",
+                    &format!("{} broadcast {} paused: {}", phone, id, e),
+                );
+                return;
+            }
+            Err(e) => record(&state.db, id, &jid, Some(&e.to_string())).await,
+        }
+    }
+}
+
+/// Broadcasts cut short by a restart are paused, the owner decides when to resume
+pub async fn interrupt_running(db: &sqlx::SqlitePool) {
+    let _ = sqlx::query(
+        "UPDATE broadcasts SET status = 'paused', lastError = 'Interrupted by a service restart', updatedAt = ?
+         WHERE status = 'running'",
+    )
+    .bind(chrono::Utc::now())
+    .execute(db)
+    .await;
+}
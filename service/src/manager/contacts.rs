@@ -0,0 +1,388 @@
+use crate::sql::Contact;
+use sqlx::{QueryBuilder, Sqlite};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+const MAX_TAG_LEN: usize = 50;
+
+/// Columns always present in CSV files, any other column is a custom field
+const CSV_COLUMNS: &[&str] = &["phone", "name", "tags", "lid"];
+
+/// A contact read from an import file, `phone` is not yet normalised
+#[derive(Debug, Default)]
+pub struct ImportedContact {
+    pub phone: String,
+    pub name: Option<String>,
+    pub tags: Vec<String>,
+    pub custom_fields: HashMap<String, String>,
+}
+
+/// Tags are case-insensitive labels without list separators
+pub fn normalize_tag(tag: &str) -> Option<String> {
+    let tag = tag.trim().to_lowercase();
+    (!tag.is_empty() && tag.len() <= MAX_TAG_LEN && !tag.contains([',', ';'])).then_some(tag)
+}
+
+fn split_tags(raw: &str) -> Vec<String> {
+    raw.split([';', ','])
+        .filter_map(normalize_tag)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// Parse a CSV file with a header row. `phone` (or `number`/`jid`) is required,
+/// `name` and `tags` (separated by `;`) are optional, other columns become custom fields.
+pub fn parse_csv(data: &str) -> Result<Vec<ImportedContact>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(data.as_bytes());
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(|e| format!("Invalid CSV header: {}", e))?
+        .iter()
+        .map(|h| h.to_string())
+        .collect();
+    let column = |names: &[&str]| {
+        headers
+            .iter()
+            .position(|h| names.iter().any(|n| h.eq_ignore_ascii_case(n)))
+    };
+    let phone_col =
+        column(&["phone", "number", "jid"]).ok_or("CSV needs a phone, number or jid column")?;
+    let name_col = column(&["name"]);
+    let tags_col = column(&["tags"]);
+    let lid_col = column(&["lid"]);
+
+    let mut contacts = Vec::new();
+    for (line, record) in reader.records().enumerate() {
+        let record = record.map_err(|e| format!("Invalid CSV row {}: {}", line + 2, e))?;
+        let field = |col: Option<usize>| col.and_then(|i| record.get(i)).filter(|v| !v.is_empty());
+
+        let custom_fields = headers
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| ![Some(phone_col), name_col, tags_col, lid_col].contains(&Some(*i)))
+            .filter_map(|(i, h)| Some((h.clone(), field(Some(i))?.to_string())))
+            .collect();
+
+        contacts.push(ImportedContact {
+            phone: field(Some(phone_col)).unwrap_or_default().to_string(),
+            name: field(name_col).map(str::to_string),
+            tags: field(tags_col).map(split_tags).unwrap_or_default(),
+            custom_fields,
+        });
+    }
+    Ok(contacts)
+}
+
+/// Undo vCard value escaping
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => {}
+        }
+    }
+    out
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace(';', "\\;")
+        .replace('\n', "\\n")
+}
+
+/// Parse one or more vCards (2.1, 3.0 or 4.0). Uses FN (or N) for the name,
+/// the first TEL (preferring WhatsApp's `waid` parameter) and CATEGORIES as tags.
+pub fn parse_vcard(data: &str) -> Vec<ImportedContact> {
+    // Unfold continuation lines first
+    let mut lines: Vec<String> = Vec::new();
+    for line in data.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.trim_end().to_string()),
+        }
+    }
+
+    let mut contacts = Vec::new();
+    let mut current: Option<ImportedContact> = None;
+    for line in lines {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let mut params = key.split(';');
+        let name = params.next().unwrap_or_default().to_ascii_uppercase();
+        // Grouped properties look like `item1.TEL`
+        let name = name.rsplit('.').next().unwrap_or_default();
+
+        match (name, value.to_ascii_uppercase().as_str()) {
+            ("BEGIN", "VCARD") => current = Some(ImportedContact::default()),
+            ("END", "VCARD") => {
+                if let Some(c) = current.take().filter(|c| !c.phone.is_empty()) {
+                    contacts.push(c);
+                }
+            }
+            _ => {}
+        }
+        let Some(contact) = current.as_mut() else {
+            continue;
+        };
+
+        match name {
+            "FN" => contact.name = Some(unescape(value)).filter(|v| !v.is_empty()),
+            "N" if contact.name.is_none() => {
+                let parts: Vec<String> = value
+                    .split(';')
+                    .map(unescape)
+                    .filter(|p| !p.is_empty())
+                    .collect();
+                // Family;Given;... reads better as Given Family
+                let name = parts.iter().take(2).rev().cloned().collect::<Vec<_>>();
+                contact.name = Some(name.join(" ")).filter(|n| !n.is_empty());
+            }
+            "TEL" if contact.phone.is_empty() => {
+                let waid = params
+                    .find_map(|p| {
+                        p.split_once('=')
+                            .filter(|(k, _)| k.eq_ignore_ascii_case("waid"))
+                    })
+                    .map(|(_, v)| v.to_string());
+                contact.phone =
+                    waid.unwrap_or_else(|| value.trim_start_matches("tel:").trim().to_string());
+            }
+            "CATEGORIES" => contact.tags = split_tags(&unescape(value)),
+            _ => {}
+        }
+    }
+    contacts
+}
+
+fn digits(jid: &str) -> &str {
+    jid.split(['@', ':']).next().unwrap_or_default()
+}
+
+fn parse_tags(contact: &Contact) -> Vec<String> {
+    serde_json::from_str(&contact.tags).unwrap_or_default()
+}
+
+fn parse_fields(contact: &Contact) -> BTreeMap<String, String> {
+    serde_json::from_str(&contact.custom_fields).unwrap_or_default()
+}
+
+pub fn to_csv(contacts: &[Contact]) -> Result<String, String> {
+    let field_names: BTreeSet<String> = contacts
+        .iter()
+        .flat_map(|c| parse_fields(c).into_keys())
+        .filter(|k| !CSV_COLUMNS.contains(&k.as_str()))
+        .collect();
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let header = CSV_COLUMNS
+        .iter()
+        .copied()
+        .chain(field_names.iter().map(String::as_str));
+    writer.write_record(header).map_err(|e| e.to_string())?;
+
+    for c in contacts {
+        let fields = parse_fields(c);
+        let mut record = vec![
+            digits(&c.contact_pn).to_string(),
+            c.name.clone().unwrap_or_default(),
+            parse_tags(c).join(";"),
+            c.contact_lid
+                .as_deref()
+                .map(digits)
+                .unwrap_or_default()
+                .to_string(),
+        ];
+        record.extend(
+            field_names
+                .iter()
+                .map(|k| fields.get(k).cloned().unwrap_or_default()),
+        );
+        writer.write_record(&record).map_err(|e| e.to_string())?;
+    }
+
+    let bytes = writer.into_inner().map_err(|e| e.to_string())?;
+    String::from_utf8(bytes).map_err(|e| e.to_string())
+}
+
+/// vCard 3.0 export, one card per contact with WhatsApp's `waid` on TEL
+pub fn to_vcard(contacts: &[Contact]) -> String {
+    let mut out = String::new();
+    for c in contacts {
+        let number = digits(&c.contact_pn);
+        out.push_str("BEGIN:VCARD\r\nVERSION:3.0\r\n");
+        out.push_str(&format!("FN:{}\r\n", escape(&c.display_name)));
+        out.push_str(&format!("TEL;type=CELL;waid={}:+{}\r\n", number, number));
+        let tags = parse_tags(c);
+        if !tags.is_empty() {
+            let tags: Vec<String> = tags.iter().map(|t| escape(t)).collect();
+            out.push_str(&format!("CATEGORIES:{}\r\n", tags.join(",")));
+        }
+        out.push_str("END:VCARD\r\n");
+    }
+    out
+}
+
+/// JIDs of the instance's contacts carrying any (or, with `match_all`, every)
+/// tag, leaving out those who opted out
+pub async fn audience(
+    db: &sqlx::SqlitePool,
+    phone: &str,
+    tags: &[String],
+    match_all: bool,
+) -> Vec<String> {
+    if tags.is_empty() {
+        return Vec::new();
+    }
+    let mut qb: QueryBuilder<Sqlite> =
+        QueryBuilder::new("SELECT contactPn FROM contact_tags WHERE sessionId = ");
+    qb.push_bind(phone);
+    qb.push(" AND tag IN (");
+    let mut separated = qb.separated(", ");
+    for tag in tags {
+        separated.push_bind(tag);
+    }
+    qb.push(") AND contactPn NOT IN (SELECT jid FROM opt_outs WHERE sessionId = ");
+    qb.push_bind(phone);
+    qb.push(") GROUP BY contactPn");
+    if match_all {
+        qb.push(" HAVING COUNT(DISTINCT tag) = ");
+        qb.push_bind(tags.len() as i64);
+    }
+    qb.push(" ORDER BY contactPn");
+
+    qb.build_query_scalar()
+        .fetch_all(db)
+        .await
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contact(jid: &str, name: Option<&str>, tags: &[&str], fields: &[(&str, &str)]) -> Contact {
+        let fields: BTreeMap<&str, &str> = fields.iter().copied().collect();
+        Contact {
+            contact_pn: jid.to_string(),
+            contact_lid: Some("1234@lid".to_string()),
+            name: name.map(str::to_string),
+            display_name: name.unwrap_or(digits(jid)).to_string(),
+            tags: serde_json::json!(tags).to_string(),
+            custom_fields: serde_json::json!(fields).to_string(),
+            added_at: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn tags_are_normalised() {
+        assert_eq!(normalize_tag("  VIP "), Some("vip".to_string()));
+        assert_eq!(normalize_tag(""), None);
+        assert_eq!(normalize_tag("a;b"), None);
+        assert_eq!(normalize_tag(&"x".repeat(MAX_TAG_LEN + 1)), None);
+        assert_eq!(split_tags("b; A ,a,,"), ["a", "b"]);
+    }
+
+    #[test]
+    fn csv_columns_map_to_contacts() {
+        let data = "Number,Name,Tags,Company\n\
+                    15551234567, Ada ,VIP;customer,Acme\n\
+                    15557654321,,,\n";
+        let contacts = parse_csv(data).unwrap();
+        assert_eq!(contacts.len(), 2);
+        assert_eq!(contacts[0].phone, "15551234567");
+        assert_eq!(contacts[0].name.as_deref(), Some("Ada"));
+        assert_eq!(contacts[0].tags, ["customer", "vip"]);
+        assert_eq!(contacts[0].custom_fields["Company"], "Acme");
+        assert_eq!(contacts[1].name, None);
+        assert!(contacts[1].tags.is_empty());
+        assert!(contacts[1].custom_fields.is_empty());
+
+        assert!(parse_csv("name,tags\nAda,vip\n").is_err());
+    }
+
+    #[test]
+    fn csv_export_reads_back() {
+        let contacts = [
+            contact(
+                "15551234567@s.whatsapp.net",
+                Some("Lovelace, Ada"),
+                &["vip", "customer"],
+                &[("company", "Acme")],
+            ),
+            contact("15557654321@s.whatsapp.net", None, &[], &[]),
+        ];
+        let exported = to_csv(&contacts).unwrap();
+        assert!(exported.starts_with("phone,name,tags,lid,company\n"));
+
+        let imported = parse_csv(&exported).unwrap();
+        assert_eq!(imported.len(), 2);
+        assert_eq!(imported[0].phone, "15551234567");
+        assert_eq!(imported[0].name.as_deref(), Some("Lovelace, Ada"));
+        assert_eq!(imported[0].tags, ["customer", "vip"]);
+        assert_eq!(imported[0].custom_fields["company"], "Acme");
+        assert_eq!(imported[1].phone, "15557654321");
+        assert!(imported[1].custom_fields.is_empty());
+    }
+
+    #[test]
+    fn vcards_are_parsed() {
+        let data = "BEGIN:VCARD\r\n\
+                    VERSION:3.0\r\n\
+                    N:Lovelace;Ada;;;\r\n\
+                    item1.TEL;type=CELL;waid=15551234567:+1 555-123-4567\r\n\
+                    TEL:+15550000000\r\n\
+                    CATEGORIES:VIP,Customer\r\n\
+                    END:VCARD\r\n\
+                    BEGIN:VCARD\r\n\
+                    FN:No Phone\r\n\
+                    END:VCARD\r\n\
+                    BEGIN:VCARD\r\n\
+                    FN:Grace\r\n  Hopper\r\n\
+                    TEL:tel:+15557654321\r\n\
+                    END:VCARD\r\n";
+        let contacts = parse_vcard(data);
+        assert_eq!(contacts.len(), 2);
+        assert_eq!(contacts[0].name.as_deref(), Some("Ada Lovelace"));
+        assert_eq!(contacts[0].phone, "15551234567");
+        assert_eq!(contacts[0].tags, ["customer", "vip"]);
+        // Folded lines are joined back together
+        assert_eq!(contacts[1].name.as_deref(), Some("Grace Hopper"));
+        assert_eq!(contacts[1].phone, "+15557654321");
+    }
+
+    #[test]
+    fn vcard_export_reads_back() {
+        let contacts = [
+            contact(
+                "15551234567@s.whatsapp.net",
+                Some("Ada; the first\\programmer"),
+                &["vip", "customer"],
+                &[],
+            ),
+            contact("15557654321@s.whatsapp.net", None, &[], &[]),
+        ];
+        let imported = parse_vcard(&to_vcard(&contacts));
+        assert_eq!(imported.len(), 2);
+        assert_eq!(
+            imported[0].name.as_deref(),
+            Some("Ada; the first\\programmer")
+        );
+        assert_eq!(imported[0].phone, "15551234567");
+        assert_eq!(imported[0].tags, ["customer", "vip"]);
+        assert_eq!(imported[1].name.as_deref(), Some("15557654321"));
+        assert!(imported[1].tags.is_empty());
+    }
+}
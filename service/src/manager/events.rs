@@ -2,7 +2,7 @@ use prost::Message;
 
 #[derive(Clone, PartialEq, Message)]
 pub struct WorkerEvent {
-    #[prost(oneof = "worker_event::Event", tags = "1, 2")]
+    #[prost(oneof = "worker_event::Event", tags = "1, 2, 3, 4, 5, 6")]
     pub event: Option<worker_event::Event>,
 }
 
@@ -15,6 +15,14 @@ pub mod worker_event {
         Connection(ConnectionEvent),
         #[prost(string, tag = "2")]
         RawLog(String),
+        #[prost(message, tag = "3")]
+        CommandResult(CommandResult),
+        #[prost(message, tag = "4")]
+        MessageReceived(MessageEvent),
+        #[prost(message, tag = "5")]
+        MessageSent(MessageEvent),
+        #[prost(message, tag = "6")]
+        StatusViewed(StatusView),
     }
 
     #[derive(Clone, PartialEq, prost::Message)]
@@ -28,4 +36,182 @@ pub mod worker_event {
         #[prost(string, tag = "4")]
         pub pairing_code: String,
     }
+
+    /// Reply to a `WorkerCommand`, matched back to the caller by `request_id`
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct CommandResult {
+        #[prost(string, tag = "1")]
+        pub request_id: String,
+        #[prost(bool, tag = "2")]
+        pub success: bool,
+        #[prost(string, tag = "3")]
+        pub error: String,
+        /// JSON encoded payload, command specific
+        #[prost(string, tag = "4")]
+        pub data: String,
+    }
+
+    /// Read receipt for one of the account's own status updates
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct StatusView {
+        /// Message id of the status
+        #[prost(string, tag = "1")]
+        pub id: String,
+        #[prost(string, tag = "2")]
+        pub viewer: String,
+        /// Unix seconds
+        #[prost(int64, tag = "3")]
+        pub timestamp: i64,
+    }
+
+    /// A message normalized by `util::serialize_full`
+    #[derive(Clone, PartialEq, prost::Message, serde::Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct MessageEvent {
+        #[prost(string, tag = "1")]
+        pub id: String,
+        #[prost(string, tag = "2")]
+        pub chat: String,
+        #[prost(string, tag = "3")]
+        pub sender: String,
+        #[prost(string, tag = "4")]
+        pub device: String,
+        #[prost(string, tag = "5")]
+        pub mtype: String,
+        #[prost(string, optional, tag = "6")]
+        pub text: Option<String>,
+        #[prost(bool, tag = "7")]
+        pub is_group: bool,
+        #[prost(message, optional, tag = "8")]
+        pub quoted: Option<QuotedMessage>,
+        /// Unix seconds, as reported by WhatsApp
+        #[prost(int64, tag = "9")]
+        pub timestamp: i64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message, serde::Serialize)]
+    pub struct QuotedMessage {
+        #[prost(string, tag = "1")]
+        pub id: String,
+        #[prost(string, tag = "2")]
+        pub sender: String,
+        #[prost(string, tag = "3")]
+        pub mtype: String,
+        #[prost(string, optional, tag = "4")]
+        pub text: Option<String>,
+    }
+}
+
+/// Commands sent from the service to a worker over the supervisor socket
+#[derive(Clone, PartialEq, Message)]
+pub struct WorkerCommand {
+    #[prost(string, tag = "1")]
+    pub request_id: String,
+    #[prost(oneof = "worker_command::Command", tags = "2, 3, 4, 5, 6")]
+    pub command: Option<worker_command::Command>,
+}
+
+pub mod worker_command {
+    use prost::Oneof;
+
+    #[derive(Clone, PartialEq, Oneof)]
+    pub enum Command {
+        #[prost(message, tag = "2")]
+        SendMessage(SendMessage),
+        #[prost(message, tag = "3")]
+        GroupAction(GroupAction),
+        #[prost(message, tag = "4")]
+        CheckNumbers(CheckNumbers),
+        #[prost(message, tag = "5")]
+        ProfileAction(ProfileAction),
+        #[prost(message, tag = "6")]
+        PostStatus(PostStatus),
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SendMessage {
+        #[prost(string, tag = "1")]
+        pub jid: String,
+        #[prost(string, tag = "2")]
+        pub text: String,
+        #[prost(string, tag = "3")]
+        pub media_url: String,
+        /// image, video, audio or document
+        #[prost(string, tag = "4")]
+        pub media_type: String,
+        #[prost(string, tag = "5")]
+        pub file_name: String,
+    }
+
+    /// Group management request. The result data is the group metadata for
+    /// `create`, per-participant results for participant actions and
+    /// `{"code": ...}` for invite actions.
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct GroupAction {
+        /// create, add, remove, promote, demote, subject, description,
+        /// settings, invite_code or revoke_invite
+        #[prost(string, tag = "1")]
+        pub action: String,
+        /// Target group, empty for `create`
+        #[prost(string, tag = "2")]
+        pub jid: String,
+        #[prost(string, repeated, tag = "3")]
+        pub participants: Vec<String>,
+        /// Subject, description or setting (announcement, not_announcement,
+        /// locked, unlocked)
+        #[prost(string, tag = "4")]
+        pub value: String,
+    }
+
+    /// Ask WhatsApp which numbers have an account. The result data is a JSON
+    /// array of `{"jid", "exists", "lid"}`, one entry per number.
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct CheckNumbers {
+        /// `<digits>@s.whatsapp.net`
+        #[prost(string, repeated, tag = "1")]
+        pub jids: Vec<String>,
+    }
+
+    /// Change the linked account's own profile. The result data is
+    /// `{"name", "about", "photoUrl", "privacy"}` for `get` and
+    /// `{"photoUrl"}` for photo actions.
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ProfileAction {
+        /// get, name, about, photo, remove_photo or privacy
+        #[prost(string, tag = "1")]
+        pub action: String,
+        /// New name or about text, or the privacy value
+        #[prost(string, tag = "2")]
+        pub value: String,
+        /// Privacy setting for `privacy`: last_seen, online, profile_picture,
+        /// status, read_receipts, groups_add or calls_add
+        #[prost(string, tag = "3")]
+        pub setting: String,
+        /// JPEG for `photo`
+        #[prost(bytes = "vec", tag = "4")]
+        pub image: Vec<u8>,
+    }
+
+    /// Post a status update to `status@broadcast`. The result data is
+    /// `{"id": <message id>}`.
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct PostStatus {
+        /// text, image or video
+        #[prost(string, tag = "1")]
+        pub kind: String,
+        /// Status text, or the caption of media
+        #[prost(string, tag = "2")]
+        pub text: String,
+        #[prost(string, tag = "3")]
+        pub media_url: String,
+        /// `#RRGGBB` background of text statuses
+        #[prost(string, tag = "4")]
+        pub background_color: String,
+        /// Font of text statuses, 0 to 7
+        #[prost(int32, tag = "5")]
+        pub font: i32,
+        /// Contacts the status is shown to
+        #[prost(string, repeated, tag = "6")]
+        pub audience: Vec<String>,
+    }
 }
@@ -0,0 +1,593 @@
+use crate::AppState;
+use crate::logger;
+use crate::manager::autoreply;
+use crate::manager::events::worker_command::SendMessage;
+use crate::manager::events::worker_event::MessageEvent;
+use crate::manager::outbound::{self, SendSource};
+use crate::manager::templates;
+use crate::sql::Flow;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub const TRANSITION_TYPES: &[&str] = &["exact", "contains", "regex", "any"];
+
+/// Inactivity timeout when neither the flow nor the node sets one
+const DEFAULT_TIMEOUT_SECS: i64 = 60 * 60;
+/// State outlives its timeout this long so an expired conversation can still
+/// be told it timed out when the contact comes back
+const EXPIRED_STATE_GRACE_SECS: i64 = 24 * 60 * 60;
+/// Longest inactivity timeout a flow or node may set, 30 days
+pub const MAX_TIMEOUT_SECS: i64 = 30 * 24 * 60 * 60;
+/// Guard against `next` cycles between nodes that don't wait for input
+const MAX_HOPS: usize = 32;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlowDefinition {
+    /// Node entered when the flow is triggered
+    pub start: String,
+    #[serde(default)]
+    pub trigger: Trigger,
+    #[serde(default, rename = "timeoutSeconds")]
+    pub timeout_seconds: Option<i64>,
+    /// Sent when a node has no transition for the reply and no fallback of its own
+    #[serde(default)]
+    pub fallback: Option<String>,
+    /// Sent when the contact returns to a conversation that timed out
+    #[serde(default, rename = "timeoutMessage")]
+    pub timeout_message: Option<String>,
+    pub nodes: HashMap<String, Node>,
+}
+
+/// What starts the flow for a contact without a running conversation
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Trigger {
+    /// Case-insensitive exact keywords
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    /// Start on any message
+    #[serde(default)]
+    pub any: bool,
+}
+
+/// A step of the conversation. A node waits for input when it saves the reply
+/// into a variable or has transitions, otherwise it moves on to `next`, or
+/// ends the conversation when there is none.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Node {
+    /// Sent on entering the node, `{{variable}}` placeholders are filled in
+    #[serde(default)]
+    pub message: Option<String>,
+    /// Store the reply in this variable, then go to `next`
+    #[serde(default)]
+    pub save: Option<String>,
+    #[serde(default)]
+    pub next: Option<String>,
+    #[serde(default)]
+    pub transitions: Vec<Transition>,
+    #[serde(default)]
+    pub fallback: Option<String>,
+    #[serde(default, rename = "timeoutSeconds")]
+    pub timeout_seconds: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transition {
+    #[serde(default = "default_transition_type", rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub value: Option<String>,
+    pub to: String,
+}
+
+fn default_transition_type() -> String {
+    "exact".to_string()
+}
+
+/// Per-contact conversation state
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlowState {
+    #[serde(rename = "flowId")]
+    pub flow_id: i64,
+    pub version: i64,
+    pub node: String,
+    pub vars: HashMap<String, String>,
+    /// Unix seconds after which the conversation has timed out
+    #[serde(rename = "expiresAt")]
+    pub expires_at: i64,
+}
+
+/// Outcome of feeding one message to a flow
+#[derive(Debug, Default, Serialize)]
+pub struct Step {
+    pub handled: bool,
+    pub replies: Vec<String>,
+    /// `None` once the conversation ended
+    pub state: Option<FlowState>,
+}
+
+pub fn validate(def: &FlowDefinition) -> Result<(), String> {
+    if def.nodes.is_empty() {
+        return Err("A flow needs at least one node".to_string());
+    }
+    if !def.nodes.contains_key(&def.start) {
+        return Err(format!("Start node '{}' does not exist", def.start));
+    }
+    if !def.trigger.any && def.trigger.keywords.is_empty() {
+        return Err("trigger needs keywords or any: true".to_string());
+    }
+    let in_range = |t: Option<i64>, what: &str| match t {
+        Some(t) if !(1..=MAX_TIMEOUT_SECS).contains(&t) => Err(format!(
+            "{} must be between 1 and {}",
+            what, MAX_TIMEOUT_SECS
+        )),
+        _ => Ok(()),
+    };
+    in_range(def.timeout_seconds, "timeoutSeconds")?;
+
+    for (id, node) in &def.nodes {
+        in_range(
+            node.timeout_seconds,
+            &format!("Node '{}' timeoutSeconds", id),
+        )?;
+        let targets = node
+            .next
+            .iter()
+            .chain(node.transitions.iter().map(|t| &t.to));
+        for target in targets {
+            if !def.nodes.contains_key(target) {
+                return Err(format!("Node '{}' points to unknown node '{}'", id, target));
+            }
+        }
+        if node.save.as_deref().is_some_and(str::is_empty) {
+            return Err(format!("Node '{}' has an empty save variable", id));
+        }
+        for t in &node.transitions {
+            if !TRANSITION_TYPES.contains(&t.kind.as_str()) {
+                return Err(format!(
+                    "Node '{}' has unknown transition type '{}', expected one of: {}",
+                    id,
+                    t.kind,
+                    TRANSITION_TYPES.join(", ")
+                ));
+            }
+            if t.kind != "any" && t.value.as_deref().unwrap_or("").is_empty() {
+                return Err(format!("Node '{}' has a transition without value", id));
+            }
+            if t.kind == "regex"
+                && let Err(e) = autoreply::compile(t.value.as_deref().unwrap_or(""), false)
+            {
+                return Err(format!("Node '{}' has an invalid regex: {}", id, e));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Fill the conversation's variables, then the message's values, in one pass
+/// so replies the contact typed are sent as they are
+fn render(text: &str, vars: &HashMap<String, String>, context: &HashMap<String, String>) -> String {
+    let mut values = context.clone();
+    values.extend(vars.iter().map(|(k, v)| (k.clone(), v.clone())));
+    templates::substitute(text, &values)
+}
+
+fn transition_matches(t: &Transition, text: &str) -> bool {
+    let value = t.value.as_deref().unwrap_or("");
+    match t.kind.as_str() {
+        "any" => true,
+        "exact" => text.trim().eq_ignore_ascii_case(value.trim()),
+        "contains" => text.to_lowercase().contains(&value.to_lowercase()),
+        "regex" => autoreply::cached_regex(value, false).is_some_and(|re| re.is_match(text)),
+        _ => false,
+    }
+}
+
+fn timeout_for(def: &FlowDefinition, node: &str) -> i64 {
+    def.nodes
+        .get(node)
+        .and_then(|n| n.timeout_seconds)
+        .or(def.timeout_seconds)
+        .unwrap_or(DEFAULT_TIMEOUT_SECS)
+        .clamp(1, MAX_TIMEOUT_SECS)
+}
+
+/// Enter `node_id`, following `next` through nodes that don't wait for input.
+/// Returns the node now waiting for a reply, or `None` when the flow ended.
+fn enter(
+    def: &FlowDefinition,
+    node_id: &str,
+    vars: &HashMap<String, String>,
+    context: &HashMap<String, String>,
+    replies: &mut Vec<String>,
+) -> Option<String> {
+    let mut current = node_id.to_string();
+    for _ in 0..MAX_HOPS {
+        let node = def.nodes.get(&current)?;
+        if let Some(ref message) = node.message {
+            replies.push(render(message, vars, context));
+        }
+        if node.save.is_some() || !node.transitions.is_empty() {
+            return Some(current);
+        }
+        current = node.next.clone()?;
+    }
+    None
+}
+
+/// Advance a conversation by one incoming message at unix time `now`.
+/// `context` holds the message's values, like `autoreply::message_values`.
+pub fn step(
+    flow_id: i64,
+    version: i64,
+    def: &FlowDefinition,
+    state: Option<FlowState>,
+    text: &str,
+    context: &HashMap<String, String>,
+    now: i64,
+) -> Step {
+    let mut replies = Vec::new();
+
+    let state = match state {
+        Some(s) if s.expires_at <= now => {
+            if let Some(ref message) = def.timeout_message {
+                replies.push(render(message, &s.vars, context));
+            }
+            None
+        }
+        other => other,
+    };
+
+    let (waiting, vars) = match state {
+        Some(mut s) => {
+            let Some(node) = def.nodes.get(&s.node) else {
+                return Step::default();
+            };
+            let target = match node.save {
+                Some(ref var) => {
+                    s.vars.insert(var.clone(), text.to_string());
+                    node.next.clone()
+                }
+                None => node
+                    .transitions
+                    .iter()
+                    .find(|t| transition_matches(t, text))
+                    .map(|t| t.to.clone()),
+            };
+
+            match target {
+                Some(target) => (enter(def, &target, &s.vars, context, &mut replies), s.vars),
+                // Saved without a next node, the conversation is complete
+                None if node.save.is_some() => (None, s.vars),
+                None => {
+                    if let Some(fallback) = node.fallback.as_ref().or(def.fallback.as_ref()) {
+                        replies.push(render(fallback, &s.vars, context));
+                    }
+                    (Some(s.node), s.vars)
+                }
+            }
+        }
+        None => {
+            let triggered = def.trigger.any
+                || def
+                    .trigger
+                    .keywords
+                    .iter()
+                    .any(|k| text.trim().eq_ignore_ascii_case(k.trim()));
+            if !triggered {
+                // A timeout notice alone still counts as handling the message
+                return Step {
+                    handled: !replies.is_empty(),
+                    replies,
+                    state: None,
+                };
+            }
+            let vars = HashMap::new();
+            (enter(def, &def.start, &vars, context, &mut replies), vars)
+        }
+    };
+
+    Step {
+        handled: true,
+        replies,
+        state: waiting.map(|node| FlowState {
+            flow_id,
+            version,
+            expires_at: now.saturating_add(timeout_for(def, &node)),
+            node,
+            vars,
+        }),
+    }
+}
+
+pub async fn load_definition(
+    db: &sqlx::SqlitePool,
+    flow_id: i64,
+    version: i64,
+) -> Option<FlowDefinition> {
+    let raw: Option<String> =
+        sqlx::query_scalar("SELECT definition FROM flow_versions WHERE flowId = ? AND version = ?")
+            .bind(flow_id)
+            .bind(version)
+            .fetch_optional(db)
+            .await
+            .unwrap_or(None);
+    serde_json::from_str(&raw?).ok()
+}
+
+fn state_key(phone: &str, chat: &str) -> String {
+    format!("{}:flow:{}", phone, chat)
+}
+
+/// Drive the contact's conversation with an incoming message. Returns whether
+/// a flow handled it.
+pub async fn handle(state: &Arc<AppState>, phone: &str, msg: &MessageEvent) -> bool {
+    if msg.is_group || msg.chat == "status@broadcast" {
+        return false;
+    }
+    let Ok(mut conn) = state.redis.get_multiplexed_async_connection().await else {
+        return false;
+    };
+    let key = state_key(phone, &msg.chat);
+    let text = msg.text.as_deref().unwrap_or_default();
+    let context = autoreply::message_values(msg);
+    let now = chrono::Utc::now().timestamp();
+
+    let current: Option<FlowState> = redis::cmd("GET")
+        .arg(&key)
+        .query_async::<Option<String>>(&mut conn)
+        .await
+        .ok()
+        .flatten()
+        .and_then(|raw| serde_json::from_str(&raw).ok());
+
+    let mut outcome = Step::default();
+
+    // A running conversation keeps the version it started on
+    if let Some(current) = current {
+        let enabled: bool =
+            sqlx::query_scalar("SELECT enabled FROM flows WHERE id = ? AND sessionId = ?")
+                .bind(current.flow_id)
+                .bind(phone)
+                .fetch_optional(&state.db)
+                .await
+                .unwrap_or(None)
+                .unwrap_or(false);
+        let def = if enabled {
+            load_definition(&state.db, current.flow_id, current.version).await
+        } else {
+            None
+        };
+        if let Some(def) = def {
+            outcome = step(
+                current.flow_id,
+                current.version,
+                &def,
+                Some(current),
+                text,
+                &context,
+                now,
+            );
+        }
+    }
+
+    if !outcome.handled {
+        let flows: Vec<Flow> =
+            sqlx::query_as("SELECT * FROM flows WHERE sessionId = ? AND enabled = 1 ORDER BY id")
+                .bind(phone)
+                .fetch_all(&state.db)
+                .await
+                .unwrap_or_default();
+
+        for flow in flows {
+            let Some(def) = load_definition(&state.db, flow.id, flow.active_version).await else {
+                continue;
+            };
+            let mut started = step(
+                flow.id,
+                flow.active_version,
+                &def,
+                None,
+                text,
+                &context,
+                now,
+            );
+            if started.handled {
+                outcome.replies.append(&mut started.replies);
+                outcome.handled = true;
+                outcome.state = started.state;
+                break;
+            }
+        }
+    }
+
+    match outcome.state {
+        Some(ref s) => {
+            let ttl = s
+                .expires_at
+                .saturating_sub(now)
+                .max(1)
+                .saturating_add(EXPIRED_STATE_GRACE_SECS);
+            let _: Result<(), _> = redis::cmd("SET")
+                .arg(&key)
+                .arg(serde_json::to_string(s).unwrap_or_default())
+                .arg("EX")
+                .arg(ttl)
+                .query_async(&mut conn)
+                .await;
+        }
+        None => {
+            let _: Result<(), _> = redis::cmd("DEL").arg(&key).query_async(&mut conn).await;
+        }
+    }
+
+    for reply in outcome.replies {
+        let message = SendMessage {
+            jid: msg.chat.clone(),
+            text: reply,
+            ..Default::default()
+        };
+        if let Err(e) = outbound::send(state, phone, SendSource::Flow, message).await {
+            logger::error(
+                "FLOW",
+                &format!("{} failed to reply to {}: {}", phone, msg.chat, e),
+            );
+            break;
+        }
+    }
+
+    outcome.handled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn definition() -> FlowDefinition {
+        serde_json::from_value(serde_json::json!({
+            "start": "ask_name",
+            "trigger": {"keywords": ["start"]},
+            "timeoutSeconds": 600,
+            "fallback": "Please answer 1 or 2",
+            "timeoutMessage": "Sorry {{name}}, this timed out",
+            "nodes": {
+                "ask_name": {"message": "Hi {{pushName}}, your name?", "save": "name", "next": "menu"},
+                "menu": {
+                    "message": "Thanks {{name}}. 1 for sales, 2 for support",
+                    "transitions": [
+                        {"type": "exact", "value": "1", "to": "sales"},
+                        {"type": "regex", "value": "^(2|support)$", "to": "support"}
+                    ]
+                },
+                "sales": {"message": "Sales will call you", "next": "bye"},
+                "support": {"message": "Support is on it", "timeoutSeconds": 60},
+                "bye": {"message": "Bye {{name}}"}
+            }
+        }))
+        .unwrap()
+    }
+
+    fn context() -> HashMap<String, String> {
+        HashMap::from([("pushName".to_string(), "Ada".to_string())])
+    }
+
+    #[test]
+    fn a_conversation_runs_to_the_end() {
+        let def = definition();
+        assert!(validate(&def).is_ok());
+
+        let started = step(1, 1, &def, None, "START", &context(), 1000);
+        assert!(started.handled);
+        assert_eq!(started.replies, ["Hi Ada, your name?"]);
+        let state = started.state.unwrap();
+        assert_eq!(state.node, "ask_name");
+        assert_eq!(state.expires_at, 1600);
+
+        let named = step(1, 1, &def, Some(state), "Grace", &context(), 1100);
+        assert_eq!(named.replies, ["Thanks Grace. 1 for sales, 2 for support"]);
+        let state = named.state.unwrap();
+        assert_eq!(state.node, "menu");
+        assert_eq!(state.vars["name"], "Grace");
+
+        let done = step(1, 1, &def, Some(state), " 1 ", &context(), 1200);
+        assert_eq!(done.replies, ["Sales will call you", "Bye Grace"]);
+        assert!(done.state.is_none());
+    }
+
+    #[test]
+    fn unmatched_replies_get_the_fallback_and_stay_put() {
+        let def = definition();
+        let state = FlowState {
+            flow_id: 1,
+            version: 1,
+            node: "menu".to_string(),
+            vars: HashMap::new(),
+            expires_at: 2000,
+        };
+        let result = step(1, 1, &def, Some(state), "3", &context(), 1000);
+        assert!(result.handled);
+        assert_eq!(result.replies, ["Please answer 1 or 2"]);
+        assert_eq!(result.state.unwrap().node, "menu");
+
+        let state = FlowState {
+            flow_id: 1,
+            version: 1,
+            node: "menu".to_string(),
+            vars: HashMap::new(),
+            expires_at: 2000,
+        };
+        let support = step(1, 1, &def, Some(state), "support", &context(), 1000);
+        assert_eq!(support.replies, ["Support is on it"]);
+        // Ends there, support waits for nothing
+        assert!(support.state.is_none());
+    }
+
+    #[test]
+    fn expired_conversations_are_told_and_not_resumed() {
+        let def = definition();
+        let state = FlowState {
+            flow_id: 1,
+            version: 1,
+            node: "menu".to_string(),
+            vars: HashMap::from([("name".to_string(), "Grace".to_string())]),
+            expires_at: 1000,
+        };
+        let result = step(1, 1, &def, Some(state), "1", &context(), 1000);
+        assert!(result.handled);
+        assert_eq!(result.replies, ["Sorry Grace, this timed out"]);
+        assert!(result.state.is_none());
+    }
+
+    #[test]
+    fn untriggered_messages_are_left_alone() {
+        let result = step(1, 1, &definition(), None, "hello", &context(), 1000);
+        assert!(!result.handled);
+        assert!(result.replies.is_empty());
+    }
+
+    #[test]
+    fn saved_replies_are_not_expanded() {
+        let def = definition();
+        let started = step(1, 1, &def, None, "start", &context(), 0);
+        let named = step(1, 1, &def, started.state, "{{pushName}}", &context(), 0);
+        assert_eq!(
+            named.replies,
+            ["Thanks {{pushName}}. 1 for sales, 2 for support"]
+        );
+    }
+
+    #[test]
+    fn timeouts_are_bounded() {
+        let mut def = definition();
+        def.timeout_seconds = Some(MAX_TIMEOUT_SECS + 1);
+        assert!(validate(&def).is_err());
+        def.timeout_seconds = Some(0);
+        assert!(validate(&def).is_err());
+
+        // Stored definitions from before the limit are clamped
+        def.timeout_seconds = Some(i64::MAX);
+        let started = step(1, 1, &def, None, "start", &context(), i64::MAX - 10);
+        assert_eq!(started.state.unwrap().expires_at, i64::MAX);
+        assert_eq!(timeout_for(&def, "ask_name"), MAX_TIMEOUT_SECS);
+        assert_eq!(timeout_for(&def, "support"), 60);
+    }
+
+    #[test]
+    fn broken_definitions_are_rejected() {
+        let mut def = definition();
+        def.start = "missing".to_string();
+        assert!(validate(&def).is_err());
+
+        let mut def = definition();
+        def.nodes.get_mut("sales").unwrap().next = Some("nowhere".to_string());
+        assert!(validate(&def).is_err());
+
+        let mut def = definition();
+        def.nodes.get_mut("menu").unwrap().transitions[1].value = Some("(".to_string());
+        assert!(validate(&def).is_err());
+
+        let mut def = definition();
+        def.trigger = Trigger::default();
+        assert!(validate(&def).is_err());
+    }
+}
@@ -0,0 +1,177 @@
+use crate::AppState;
+use crate::manager::CommandError;
+use crate::manager::events::worker_command::{Command, GroupAction};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+pub const PARTICIPANT_ACTIONS: &[&str] = &["add", "remove", "promote", "demote"];
+
+/// Group metadata as cached by the worker in `groups.groupInfo`
+#[derive(Debug, Clone, Deserialize)]
+pub struct GroupInfo {
+    pub id: String,
+    #[serde(default)]
+    pub subject: Option<String>,
+    #[serde(default)]
+    pub desc: Option<String>,
+    #[serde(default)]
+    pub owner: Option<String>,
+    /// Unix seconds
+    #[serde(default)]
+    pub creation: Option<i64>,
+    #[serde(default)]
+    pub size: Option<i64>,
+    /// Only admins may edit group info
+    #[serde(default)]
+    pub restrict: Option<bool>,
+    /// Only admins may send messages
+    #[serde(default)]
+    pub announce: Option<bool>,
+    #[serde(default, rename = "memberAddMode")]
+    pub member_add_mode: Option<bool>,
+    #[serde(default, rename = "joinApprovalMode")]
+    pub join_approval_mode: Option<bool>,
+    #[serde(default, rename = "ephemeralDuration")]
+    pub ephemeral_duration: Option<i64>,
+    #[serde(default, rename = "isCommunity")]
+    pub is_community: Option<bool>,
+    #[serde(default)]
+    pub participants: Vec<RawParticipant>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RawParticipant {
+    pub id: String,
+    #[serde(default, rename = "phoneNumber")]
+    pub phone_number: Option<String>,
+    /// `admin`, `superadmin` or null
+    #[serde(default)]
+    pub admin: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Participant {
+    pub id: String,
+    #[serde(rename = "phoneNumber")]
+    pub phone_number: Option<String>,
+    /// member, admin or superadmin
+    pub role: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GroupSettings {
+    /// Only admins can send messages
+    #[serde(rename = "announcementOnly")]
+    pub announcement_only: bool,
+    /// Only admins can edit subject, description and picture
+    pub locked: bool,
+    /// Only admins can add participants
+    #[serde(rename = "adminsAddMembers")]
+    pub admins_add_members: bool,
+    #[serde(rename = "joinApproval")]
+    pub join_approval: bool,
+    /// Disappearing messages timer in seconds, 0 when off
+    #[serde(rename = "ephemeralSeconds")]
+    pub ephemeral_seconds: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Group {
+    pub id: String,
+    pub subject: String,
+    pub description: Option<String>,
+    pub owner: Option<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(rename = "isCommunity")]
+    pub is_community: bool,
+    pub size: usize,
+    pub admins: Vec<String>,
+    pub settings: GroupSettings,
+    /// Left out of listings
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub participants: Option<Vec<Participant>>,
+}
+
+impl GroupInfo {
+    pub fn parse(raw: &str) -> Option<Self> {
+        serde_json::from_str(raw).ok()
+    }
+
+    pub fn into_group(self, with_participants: bool) -> Group {
+        let participants: Vec<Participant> = self
+            .participants
+            .into_iter()
+            .map(|p| Participant {
+                id: p.id,
+                phone_number: p.phone_number,
+                role: p.admin.unwrap_or_else(|| "member".to_string()),
+            })
+            .collect();
+
+        Group {
+            id: self.id,
+            subject: self.subject.unwrap_or_default(),
+            description: self.desc.filter(|d| !d.is_empty()),
+            owner: self.owner,
+            created_at: self
+                .creation
+                .and_then(|t| chrono::DateTime::from_timestamp(t, 0)),
+            is_community: self.is_community.unwrap_or(false),
+            size: self.size.map(|s| s as usize).unwrap_or(participants.len()),
+            admins: participants
+                .iter()
+                .filter(|p| p.role != "member")
+                .map(|p| p.id.clone())
+                .collect(),
+            settings: GroupSettings {
+                announcement_only: self.announce.unwrap_or(false),
+                locked: self.restrict.unwrap_or(false),
+                admins_add_members: self.member_add_mode == Some(false),
+                join_approval: self.join_approval_mode.unwrap_or(false),
+                ephemeral_seconds: self.ephemeral_duration.unwrap_or(0),
+            },
+            participants: with_participants.then_some(participants),
+        }
+    }
+}
+
+/// Normalise a group id, accepting it with or without the `@g.us` suffix
+pub fn to_group_jid(id: &str) -> Option<String> {
+    let id = id.trim();
+    let bare = id.strip_suffix("@g.us").unwrap_or(id);
+    (!bare.is_empty() && bare.chars().all(|c| c.is_ascii_digit() || c == '-'))
+        .then(|| format!("{}@g.us", bare))
+}
+
+/// Cache metadata returned by the worker, the same way the worker does on sync
+pub async fn store(db: &sqlx::SqlitePool, phone: &str, metadata: &serde_json::Value) {
+    let Some(id) = metadata.get("id").and_then(|v| v.as_str()) else {
+        return;
+    };
+    let now = chrono::Utc::now().to_rfc3339();
+    let _ = sqlx::query(
+        "INSERT INTO groups (groupId, sessionId, groupInfo, updatedAt, createdAt) VALUES (?, ?, ?, ?, ?)
+         ON CONFLICT(groupId) DO UPDATE SET groupInfo = excluded.groupInfo, updatedAt = excluded.updatedAt",
+    )
+    .bind(id)
+    .bind(phone)
+    .bind(metadata.to_string())
+    .bind(&now)
+    .bind(&now)
+    .execute(db)
+    .await;
+}
+
+/// Run a group action on the instance's worker and return its JSON result
+pub async fn run(
+    state: &Arc<AppState>,
+    phone: &str,
+    action: GroupAction,
+) -> Result<serde_json::Value, CommandError> {
+    let result = state
+        .sm
+        .dispatch(phone, Command::GroupAction(action))
+        .await?;
+    Ok(serde_json::from_str(&result.data).unwrap_or(serde_json::Value::Null))
+}
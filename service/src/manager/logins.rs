@@ -0,0 +1,273 @@
+use crate::AppState;
+use crate::logger;
+use crate::manager::events::worker_command::SendMessage;
+use crate::manager::outbound::{self, SendSource};
+use crate::ratelimit::client_ip;
+use crate::routes::messages::to_jid;
+use crate::sql::User;
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts},
+    http::{header, request::Parts},
+};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, LazyLock};
+
+/// Failures within this window count towards delays and the lockout
+const FAILURE_WINDOW_SECS: i64 = 15 * 60;
+/// Failures allowed before each further attempt has to wait
+const FREE_ATTEMPTS: i64 = 3;
+/// Longest wait between attempts, the delay doubles up to it
+const MAX_DELAY_SECS: i64 = 60;
+/// Failures that lock the account
+const LOCKOUT_THRESHOLD: i64 = 10;
+const LOCKOUT_SECS: i64 = 15 * 60;
+/// User agents are stored and shown truncated
+const MAX_USER_AGENT_LEN: usize = 200;
+/// Wait suggested when attempts can't be counted
+const UNAVAILABLE_RETRY_SECS: i64 = 30;
+
+/// Check the lockout and the delay, then count the attempt as a failure
+/// before it is made, all in one step. Parallel attempts can't slip through
+/// an open gate together this way; a successful sign-in clears the count.
+static BEGIN_ATTEMPT: LazyLock<redis::Script> = LazyLock::new(|| {
+    redis::Script::new(
+        r"
+local locked = redis.call('TTL', KEYS[1])
+if locked > 0 then
+    return {2, locked}
+end
+local delayed = redis.call('TTL', KEYS[2])
+if delayed > 0 then
+    return {1, delayed}
+end
+local window = tonumber(ARGV[1])
+local free = tonumber(ARGV[2])
+local max_delay = tonumber(ARGV[3])
+local threshold = tonumber(ARGV[4])
+local lockout = tonumber(ARGV[5])
+local failures = redis.call('INCR', KEYS[3])
+redis.call('EXPIRE', KEYS[3], window)
+if failures >= threshold then
+    redis.call('SETEX', KEYS[1], lockout, 1)
+    redis.call('DEL', KEYS[3])
+elseif failures > free then
+    local delay = math.min(2 ^ math.min(failures - free - 1, 6), max_delay)
+    redis.call('SETEX', KEYS[2], delay, 1)
+end
+return {0, 0}
+",
+    )
+});
+
+/// Where a sign-in attempt comes from
+#[derive(Debug, Clone)]
+pub struct LoginContext {
+    pub ip: String,
+    pub user_agent: Option<String>,
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for LoginContext {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|c| c.0);
+        let user_agent = parts
+            .headers
+            .get(header::USER_AGENT)
+            .and_then(|h| h.to_str().ok())
+            .map(|ua| ua.chars().take(MAX_USER_AGENT_LEN).collect());
+        Ok(Self {
+            ip: client_ip(&parts.headers, peer),
+            user_agent,
+        })
+    }
+}
+
+/// Whether an account may try a password right now
+#[derive(Debug, PartialEq)]
+pub enum Gate {
+    Open,
+    /// Seconds until the next attempt
+    Delayed(i64),
+    /// Seconds until the lockout ends
+    Locked(i64),
+    /// Attempts can't be counted, so none are allowed
+    Unavailable,
+}
+
+impl Gate {
+    /// Seconds to wait, none when open
+    pub fn retry_after(&self) -> Option<i64> {
+        match self {
+            Gate::Open => None,
+            Gate::Delayed(s) | Gate::Locked(s) => Some(*s),
+            Gate::Unavailable => Some(UNAVAILABLE_RETRY_SECS),
+        }
+    }
+}
+
+/// Let an attempt through unless the account is locked or has to wait, and
+/// count it as a failure until `clear_failures` says otherwise. Past the free
+/// attempts every failure doubles the wait before the next one, and enough
+/// of them lock the account. Without Redis nothing gets through.
+pub async fn begin_attempt(redis: &redis::Client, user_id: &str) -> Gate {
+    let mut conn = match redis.get_multiplexed_async_connection().await {
+        Ok(conn) => conn,
+        Err(e) => {
+            logger::error("AUTH", &format!("Can't count sign-in attempts: {}", e));
+            return Gate::Unavailable;
+        }
+    };
+    let result: redis::RedisResult<(i64, i64)> = BEGIN_ATTEMPT
+        .key(format!("login_lock:{}", user_id))
+        .key(format!("login_delay:{}", user_id))
+        .key(format!("login_failures:{}", user_id))
+        .arg(FAILURE_WINDOW_SECS)
+        .arg(FREE_ATTEMPTS)
+        .arg(MAX_DELAY_SECS)
+        .arg(LOCKOUT_THRESHOLD)
+        .arg(LOCKOUT_SECS)
+        .invoke_async(&mut conn)
+        .await;
+    match result {
+        Ok((2, ttl)) => Gate::Locked(ttl),
+        Ok((1, ttl)) => Gate::Delayed(ttl),
+        Ok(_) => Gate::Open,
+        Err(e) => {
+            logger::error("AUTH", &format!("Can't count sign-in attempts: {}", e));
+            Gate::Unavailable
+        }
+    }
+}
+
+/// Whether failed attempts locked the account
+pub async fn is_locked(redis: &redis::Client, user_id: &str) -> bool {
+    let Ok(mut conn) = redis.get_multiplexed_async_connection().await else {
+        return false;
+    };
+    redis::cmd("EXISTS")
+        .arg(format!("login_lock:{}", user_id))
+        .query_async::<i64>(&mut conn)
+        .await
+        .is_ok_and(|n| n > 0)
+}
+
+/// Forget failures after a successful sign-in, including a lockout the
+/// successful attempt itself tripped
+pub async fn clear_failures(redis: &redis::Client, user_id: &str) {
+    if let Ok(mut conn) = redis.get_multiplexed_async_connection().await {
+        let _: () = redis::pipe()
+            .del(format!("login_failures:{}", user_id))
+            .del(format!("login_delay:{}", user_id))
+            .del(format!("login_lock:{}", user_id))
+            .query_async(&mut conn)
+            .await
+            .unwrap_or(());
+    }
+}
+
+/// Record a sign-in attempt. Successful ones from a device or IP the account
+/// hasn't signed in from before notify the owner on WhatsApp.
+pub async fn record(
+    state: &Arc<AppState>,
+    user: &User,
+    context: &LoginContext,
+    method: &str,
+    outcome: &str,
+) {
+    if outcome == "success" && method != "register" && is_new_origin(state, user, context).await {
+        let state = state.clone();
+        let phone = user.phone_number.clone();
+        let context = context.clone();
+        tokio::spawn(async move { notify_new_origin(&state, &phone, &context).await });
+    }
+
+    let result = sqlx::query(
+        "INSERT INTO login_history (userId, method, outcome, ip, userAgent, createdAt)
+         VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&user.id)
+    .bind(method)
+    .bind(outcome)
+    .bind(&context.ip)
+    .bind(&context.user_agent)
+    .bind(chrono::Utc::now())
+    .execute(&state.db)
+    .await;
+    if let Err(e) = result {
+        logger::error("AUTH", &format!("Failed to record login: {}", e));
+    }
+}
+
+/// Whether the IP or the device is new to an account that has signed in
+/// before. The very first sign-in isn't news.
+async fn is_new_origin(state: &Arc<AppState>, user: &User, context: &LoginContext) -> bool {
+    let (any, same_ip, same_device): (bool, bool, bool) = sqlx::query_as(
+        "SELECT COUNT(*) > 0,
+                COALESCE(SUM(ip = ?), 0) > 0,
+                COALESCE(SUM(userAgent IS ?), 0) > 0
+         FROM login_history WHERE userId = ? AND outcome = 'success'",
+    )
+    .bind(&context.ip)
+    .bind(&context.user_agent)
+    .bind(&user.id)
+    .fetch_one(&state.db)
+    .await
+    .unwrap_or_else(|e| {
+        logger::error("AUTH", &format!("Failed to check sign-in origin: {}", e));
+        (false, true, true)
+    });
+    any && !(same_ip && same_device)
+}
+
+/// Send the owner a heads-up from the instance in NOTIFY_INSTANCE. Without
+/// one configured, or while it's offline, nothing is sent.
+async fn notify_new_origin(state: &Arc<AppState>, phone: &str, context: &LoginContext) {
+    let Ok(instance) = std::env::var("NOTIFY_INSTANCE") else {
+        return;
+    };
+    let Some(jid) = to_jid(phone) else {
+        logger::warn(
+            "AUTH",
+            &format!("Can't send sign-in notice to invalid number {}", phone),
+        );
+        return;
+    };
+    let text = format!(
+        "New sign-in to your Whatsaly account\n\nIP: {}\nDevice: {}\nTime: {} UTC\n\nIf this wasn't you, change your password now.",
+        context.ip,
+        context.user_agent.as_deref().unwrap_or("unknown"),
+        chrono::Utc::now().format("%Y-%m-%d %H:%M"),
+    );
+    let message = SendMessage {
+        jid,
+        text,
+        ..Default::default()
+    };
+    if let Err(e) = outbound::send(state, &instance, SendSource::Notification, message).await {
+        logger::warn(
+            "AUTH",
+            &format!("Failed to send sign-in notice to {}: {}", phone, e),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn attempts_are_refused_when_they_cant_be_counted() {
+        let redis = redis::Client::open("redis://127.0.0.1:1/").unwrap();
+        let gate = begin_attempt(&redis, "user").await;
+        assert_eq!(gate, Gate::Unavailable);
+        assert_eq!(gate.retry_after(), Some(UNAVAILABLE_RETRY_SECS));
+        assert_eq!(Gate::Open.retry_after(), None);
+    }
+}
@@ -0,0 +1,53 @@
+use crate::manager::events::worker_event::MessageEvent;
+use chrono::{DateTime, Utc};
+
+/// Persist a normalized message event.
+///
+/// The worker writes the raw message into `messageContent` under the same
+/// `(sessionId, messageId)`, so the upsert only ever touches the normalized columns.
+pub async fn persist(
+    db: &sqlx::SqlitePool,
+    phone: &str,
+    msg: &MessageEvent,
+    from_me: bool,
+) -> Result<(), sqlx::Error> {
+    let sent_at = DateTime::<Utc>::from_timestamp(msg.timestamp, 0)
+        .filter(|_| msg.timestamp > 0)
+        .unwrap_or_else(Utc::now);
+
+    let quoted = msg
+        .quoted
+        .as_ref()
+        .and_then(|q| serde_json::to_string(q).ok());
+
+    sqlx::query(
+        "INSERT INTO messages (sessionId, messageId, chat, sender, device, mtype, text, isGroup, quoted, fromMe, sentAt, createdAt)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+         ON CONFLICT(sessionId, messageId) DO UPDATE SET
+            chat = excluded.chat,
+            sender = excluded.sender,
+            device = excluded.device,
+            mtype = excluded.mtype,
+            text = excluded.text,
+            isGroup = excluded.isGroup,
+            quoted = excluded.quoted,
+            fromMe = excluded.fromMe,
+            sentAt = excluded.sentAt",
+    )
+    .bind(phone)
+    .bind(&msg.id)
+    .bind(&msg.chat)
+    .bind(&msg.sender)
+    .bind(&msg.device)
+    .bind(&msg.mtype)
+    .bind(&msg.text)
+    .bind(msg.is_group)
+    .bind(quoted)
+    .bind(from_me)
+    .bind(sent_at)
+    .bind(Utc::now())
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
@@ -1,10 +1,32 @@
+pub mod api_keys;
+pub mod autoreply;
+pub mod away;
+pub mod broadcasts;
+pub mod contacts;
 pub mod events;
+pub mod flows;
+pub mod groups;
+pub mod logins;
+pub mod messages;
+pub mod number_checks;
+pub mod opt_outs;
+pub mod outbound;
+pub mod profile;
+pub mod statuses;
 pub mod supervisor;
+pub mod templates;
+pub mod webhooks;
 
 use crate::AppState;
+use crate::manager::events::WorkerCommand;
+use crate::manager::events::worker_command::Command;
+use crate::manager::events::worker_event::CommandResult;
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::{RwLock, broadcast};
+use tokio::sync::{Mutex, RwLock, broadcast, mpsc, oneshot};
+
+/// How long a worker has to answer a command before the caller gives up
+const COMMAND_TIMEOUT_SECS: u64 = 30;
 
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct WorkerInfo {
@@ -16,9 +38,55 @@ pub struct WorkerInfo {
     pub pid: Option<u32>,
 }
 
+/// Why a command could not be delivered to, or completed by, a worker
+#[derive(Debug)]
+pub enum CommandError {
+    NotConnected,
+    Timeout,
+    Rejected(String),
+}
+
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandError::NotConnected => write!(f, "worker is not connected"),
+            CommandError::Timeout => write!(f, "worker did not respond in time"),
+            CommandError::Rejected(e) => write!(f, "worker rejected command: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+/// Something that happened on an instance, as seen by WebSocket clients and webhooks
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct InstanceEvent {
+    pub instance: String,
+    pub event: String,
+    pub data: serde_json::Value,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+}
+
+/// Fan an instance event out to live subscribers and webhook subscriptions
+pub async fn publish(state: &Arc<AppState>, phone: &str, event: &str, data: serde_json::Value) {
+    let _ = state.sm.events.send(InstanceEvent {
+        instance: phone.to_string(),
+        event: event.to_string(),
+        data: data.clone(),
+        timestamp: chrono::Utc::now(),
+    });
+    webhooks::emit(state, phone, event, data).await;
+}
+
 pub struct SessionManager {
     pub workers: Arc<RwLock<HashMap<String, WorkerInfo>>>,
     pub tx: broadcast::Sender<String>,
+    /// Instance events for live subscribers
+    pub events: broadcast::Sender<InstanceEvent>,
+    /// Write side of each worker's supervisor socket
+    pub links: Arc<RwLock<HashMap<String, mpsc::UnboundedSender<WorkerCommand>>>>,
+    /// Commands awaiting a `CommandResult`, keyed by request id
+    pub pending: Arc<Mutex<HashMap<String, oneshot::Sender<CommandResult>>>>,
 }
 
 impl SessionManager {
@@ -58,6 +126,22 @@ impl SessionManager {
         }
     }
 
+    /// Restart a dead worker or signal an idling supervisor. Returns the new status.
+    pub async fn resume_instance(&self, phone: &str, state: Arc<AppState>) -> &'static str {
+        let is_running = {
+            let workers = self.workers.read().await;
+            workers.get(phone).map(|w| w.is_running).unwrap_or(false)
+        };
+
+        if !is_running {
+            self.start_instance(phone, state).await;
+            "starting"
+        } else {
+            self.pause_instance(phone, false).await;
+            "resuming"
+        }
+    }
+
     pub async fn stop_instance(&self, phone: &str) {
         let _ = self.tx.send(format!("{}:stop", phone));
 
@@ -68,6 +152,50 @@ impl SessionManager {
         }
     }
 
+    /// Send a command to a worker and wait for its result
+    pub async fn dispatch(
+        &self,
+        phone: &str,
+        command: Command,
+    ) -> Result<CommandResult, CommandError> {
+        let request_id = uuid::Uuid::new_v4().to_string();
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().await.insert(request_id.clone(), tx);
+
+        let sent = {
+            let links = self.links.read().await;
+            links.get(phone).is_some_and(|link| {
+                link.send(WorkerCommand {
+                    request_id: request_id.clone(),
+                    command: Some(command),
+                })
+                .is_ok()
+            })
+        };
+
+        if !sent {
+            self.pending.lock().await.remove(&request_id);
+            return Err(CommandError::NotConnected);
+        }
+
+        match tokio::time::timeout(std::time::Duration::from_secs(COMMAND_TIMEOUT_SECS), rx).await {
+            Ok(Ok(result)) if result.success => Ok(result),
+            Ok(Ok(result)) => Err(CommandError::Rejected(result.error)),
+            Ok(Err(_)) => Err(CommandError::NotConnected),
+            Err(_) => {
+                self.pending.lock().await.remove(&request_id);
+                Err(CommandError::Timeout)
+            }
+        }
+    }
+
+    /// Hand a worker's `CommandResult` back to the waiting caller
+    pub async fn resolve(&self, result: CommandResult) {
+        if let Some(tx) = self.pending.lock().await.remove(&result.request_id) {
+            let _ = tx.send(result);
+        }
+    }
+
     pub async fn clear_session(
         &self,
         phone: &str,
@@ -0,0 +1,322 @@
+use crate::AppState;
+use crate::logger;
+use crate::manager::CommandError;
+use crate::manager::events::worker_command::{CheckNumbers, Command};
+use crate::sql::NumberCheckResult;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, LazyLock, Mutex};
+
+/// Numbers per worker command
+const BATCH_SIZE: i64 = 25;
+/// Pause between worker commands so lookups don't look like scraping
+const BATCH_INTERVAL_SECS: u64 = 3;
+/// Timeouts tolerated in a row before the check fails
+const MAX_TIMEOUTS: u32 = 3;
+/// How long a lookup result is reused, across checks and instances
+const CACHE_TTL_SECS: u64 = 24 * 60 * 60;
+
+/// Checks with a live task
+static RUNNING: LazyLock<Mutex<HashSet<i64>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+
+/// A lookup result as returned by the worker and kept in the cache
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Lookup {
+    #[serde(default)]
+    jid: Option<String>,
+    #[serde(default)]
+    exists: bool,
+    #[serde(default)]
+    lid: Option<String>,
+}
+
+/// Normalise a number to E.164 (`+<digits>`). Numbers written without `+` or
+/// `00` get `default_country_code` in place of their national trunk zeros.
+pub fn to_e164(raw: &str, default_country_code: Option<&str>) -> Option<String> {
+    let raw = raw.trim();
+    let raw = raw.split('@').next().unwrap_or_default();
+    let international = raw.starts_with('+') || raw.starts_with("00");
+    let mut digits: String = raw.chars().filter(|c| c.is_ascii_digit()).collect();
+    if raw.starts_with("00") {
+        digits.drain(..2);
+    } else if !international && let Some(cc) = default_country_code {
+        let cc: String = cc.chars().filter(|c| c.is_ascii_digit()).collect();
+        if !cc.is_empty() && !digits.starts_with(&cc) {
+            digits = format!("{}{}", cc, digits.trim_start_matches('0'));
+        }
+    }
+    // E.164 allows at most 15 digits and no leading zero
+    (digits.len() >= 8 && digits.len() <= 15 && !digits.starts_with('0'))
+        .then(|| format!("+{}", digits))
+}
+
+/// Numbers from a CSV upload: the `phone`/`number` column, or the first column
+/// when there is no header row
+pub fn parse_csv(data: &str) -> Result<Vec<String>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(data.as_bytes());
+    let mut rows = reader.records();
+    let Some(first) = rows.next() else {
+        return Ok(Vec::new());
+    };
+    let first = first.map_err(|e| format!("Invalid CSV row 1: {}", e))?;
+
+    let header_col = first.iter().position(|h| {
+        ["phone", "number", "phone_number", "jid"]
+            .iter()
+            .any(|n| h.eq_ignore_ascii_case(n))
+    });
+    let has_header = header_col.is_some()
+        || !first
+            .iter()
+            .any(|v| v.contains(|c: char| c.is_ascii_digit()));
+    let col = header_col.unwrap_or(0);
+
+    let mut numbers = Vec::new();
+    if !has_header && let Some(v) = first.get(col) {
+        numbers.push(v.to_string());
+    }
+    for (line, record) in rows.enumerate() {
+        let record = record.map_err(|e| format!("Invalid CSV row {}: {}", line + 2, e))?;
+        if let Some(v) = record.get(col).filter(|v| !v.is_empty()) {
+            numbers.push(v.to_string());
+        }
+    }
+    Ok(numbers)
+}
+
+pub fn to_csv(results: &[NumberCheckResult]) -> Result<String, String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer
+        .write_record(["number", "onWhatsApp", "jid", "lid", "checkedAt"])
+        .map_err(|e| e.to_string())?;
+    for r in results {
+        writer
+            .write_record([
+                r.number.as_str(),
+                match r.on_whatsapp {
+                    Some(true) => "true",
+                    Some(false) => "false",
+                    None => "",
+                },
+                r.jid.as_deref().unwrap_or_default(),
+                r.lid.as_deref().unwrap_or_default(),
+                &r.checked_at.map(|t| t.to_rfc3339()).unwrap_or_default(),
+            ])
+            .map_err(|e| e.to_string())?;
+    }
+    let bytes = writer.into_inner().map_err(|e| e.to_string())?;
+    String::from_utf8(bytes).map_err(|e| e.to_string())
+}
+
+fn cache_key(number: &str) -> String {
+    format!("onwhatsapp:{}", number.trim_start_matches('+'))
+}
+
+fn digits(jid: &str) -> &str {
+    jid.split(['@', ':']).next().unwrap_or_default()
+}
+
+async fn cached(state: &Arc<AppState>, numbers: &[String]) -> HashMap<String, Lookup> {
+    let Ok(mut conn) = state.redis.get_multiplexed_async_connection().await else {
+        return HashMap::new();
+    };
+    let keys: Vec<String> = numbers.iter().map(|n| cache_key(n)).collect();
+    let values: Vec<Option<String>> = redis::cmd("MGET")
+        .arg(&keys)
+        .query_async(&mut conn)
+        .await
+        .unwrap_or_default();
+    numbers
+        .iter()
+        .zip(values)
+        .filter_map(|(n, v)| Some((n.clone(), serde_json::from_str(&v?).ok()?)))
+        .collect()
+}
+
+async fn cache(state: &Arc<AppState>, lookups: &HashMap<String, Lookup>) {
+    let Ok(mut conn) = state.redis.get_multiplexed_async_connection().await else {
+        return;
+    };
+    let mut pipe = redis::pipe();
+    for (number, lookup) in lookups {
+        pipe.cmd("SET")
+            .arg(cache_key(number))
+            .arg(serde_json::to_string(lookup).unwrap_or_default())
+            .arg("EX")
+            .arg(CACHE_TTL_SECS)
+            .ignore();
+    }
+    let _: Result<(), _> = pipe.query_async(&mut conn).await;
+}
+
+/// Ask the worker about numbers missing from the cache
+async fn lookup(
+    state: &Arc<AppState>,
+    phone: &str,
+    numbers: &[String],
+) -> Result<HashMap<String, Lookup>, CommandError> {
+    let jids = numbers
+        .iter()
+        .map(|n| format!("{}@s.whatsapp.net", n.trim_start_matches('+')))
+        .collect();
+    let result = state
+        .sm
+        .dispatch(phone, Command::CheckNumbers(CheckNumbers { jids }))
+        .await?;
+    let found: Vec<Lookup> = serde_json::from_str(&result.data).unwrap_or_default();
+    let mut by_digits: HashMap<String, Lookup> = found
+        .into_iter()
+        .filter_map(|l| Some((digits(l.jid.as_deref()?).to_string(), l)))
+        .collect();
+
+    // Numbers left out of the reply are not on WhatsApp
+    Ok(numbers
+        .iter()
+        .map(|n| {
+            let l = by_digits
+                .remove(n.trim_start_matches('+'))
+                .unwrap_or(Lookup {
+                    jid: None,
+                    exists: false,
+                    lid: None,
+                });
+            (n.clone(), l)
+        })
+        .collect())
+}
+
+async fn finish(db: &sqlx::SqlitePool, id: i64, status: &str, error: Option<&str>) {
+    let _ = sqlx::query(
+        "UPDATE number_checks SET status = ?, lastError = ?, finishedAt = ? WHERE id = ?",
+    )
+    .bind(status)
+    .bind(error)
+    .bind(chrono::Utc::now())
+    .bind(id)
+    .execute(db)
+    .await;
+}
+
+async fn store(db: &sqlx::SqlitePool, id: i64, lookups: &HashMap<String, Lookup>) {
+    let now = chrono::Utc::now();
+    let Ok(mut tx) = db.begin().await else {
+        return;
+    };
+    let mut found = 0;
+    for (number, l) in lookups {
+        found += l.exists as i64;
+        let _ = sqlx::query(
+            "UPDATE number_check_results SET onWhatsApp = ?, jid = ?, lid = ?, checkedAt = ?
+             WHERE checkId = ? AND number = ?",
+        )
+        .bind(l.exists)
+        .bind(l.jid.as_deref().filter(|_| l.exists))
+        .bind(l.lid.as_deref().filter(|_| l.exists))
+        .bind(now)
+        .bind(id)
+        .bind(number)
+        .execute(&mut *tx)
+        .await;
+    }
+    let _ = sqlx::query(
+        "UPDATE number_checks SET checked = checked + ?, found = found + ? WHERE id = ?",
+    )
+    .bind(lookups.len() as i64)
+    .bind(found)
+    .bind(id)
+    .execute(&mut *tx)
+    .await;
+    let _ = tx.commit().await;
+}
+
+/// Start checking a job's unchecked numbers in the background
+pub fn spawn(state: Arc<AppState>, id: i64) {
+    if !RUNNING.lock().unwrap().insert(id) {
+        return;
+    }
+    tokio::spawn(async move {
+        run(&state, id).await;
+        RUNNING.lock().unwrap().remove(&id);
+    });
+}
+
+/// Work through unchecked numbers a batch at a time, answering from the cache
+/// where possible. Stops when the check is deleted.
+async fn run(state: &Arc<AppState>, id: i64) {
+    let mut timeouts = 0;
+    loop {
+        let phone: Option<String> = sqlx::query_scalar(
+            "SELECT sessionId FROM number_checks WHERE id = ? AND status = 'running'",
+        )
+        .bind(id)
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None);
+        let Some(phone) = phone else {
+            return;
+        };
+
+        let batch: Vec<String> = sqlx::query_scalar(
+            "SELECT number FROM number_check_results WHERE checkId = ? AND onWhatsApp IS NULL ORDER BY number LIMIT ?",
+        )
+        .bind(id)
+        .bind(BATCH_SIZE)
+        .fetch_all(&state.db)
+        .await
+        .unwrap_or_default();
+        if batch.is_empty() {
+            finish(&state.db, id, "completed", None).await;
+            logger::info(
+                "NUMBERCHECK",
+                &format!("{} number check {} completed", phone, id),
+            );
+            return;
+        }
+
+        let mut lookups = cached(state, &batch).await;
+        let missing: Vec<String> = batch
+            .into_iter()
+            .filter(|n| !lookups.contains_key(n))
+            .collect();
+        if !missing.is_empty() {
+            match lookup(state, &phone, &missing).await {
+                Ok(fresh) => {
+                    timeouts = 0;
+                    cache(state, &fresh).await;
+                    lookups.extend(fresh);
+                }
+                Err(CommandError::Timeout) if timeouts < MAX_TIMEOUTS => {
+                    timeouts += 1;
+                }
+                Err(e) => {
+                    finish(&state.db, id, "failed", Some(&e.to_string())).await;
+                    logger::warn(
+                        "NUMBERCHECK",
+                        &format!("{} number check {} failed: {}", phone, id, e),
+                    );
+                    return;
+                }
+            }
+        }
+        store(&state.db, id, &lookups).await;
+
+        if !missing.is_empty() {
+            tokio::time::sleep(std::time::Duration::from_secs(BATCH_INTERVAL_SECS)).await;
+        }
+    }
+}
+
+/// Checks cut short by a restart are failed, their cached lookups make a rerun cheap
+pub async fn interrupt_running(db: &sqlx::SqlitePool) {
+    let _ = sqlx::query(
+        "UPDATE number_checks SET status = 'failed', lastError = 'Interrupted by a service restart', finishedAt = ?
+         WHERE status = 'running'",
+    )
+    .bind(chrono::Utc::now())
+    .execute(db)
+    .await;
+}
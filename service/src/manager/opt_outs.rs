@@ -0,0 +1,271 @@
+use crate::AppState;
+use crate::logger;
+use crate::manager::events::worker_event::MessageEvent;
+use crate::manager::publish;
+use crate::sql::{OptOut, OptOutSettings};
+use sqlx::SqliteConnection;
+use std::sync::Arc;
+
+pub const DEFAULT_KEYWORDS: &[&str] = &["STOP", "STOPALL", "UNSUBSCRIBE", "CANCEL", "END", "QUIT"];
+pub const DEFAULT_OPT_IN_KEYWORDS: &[&str] = &["START", "UNSTOP"];
+const MAX_KEYWORD_LEN: usize = 30;
+
+/// How an opt-out was recorded or lifted, for the audit trail
+#[derive(Debug, Clone, Copy)]
+pub enum Source {
+    Keyword,
+    Manual,
+    Import,
+}
+
+impl Source {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Source::Keyword => "keyword",
+            Source::Manual => "manual",
+            Source::Import => "import",
+        }
+    }
+}
+
+/// Who asked for the change and why
+pub struct Change<'a> {
+    pub source: Source,
+    pub detail: Option<&'a str>,
+    pub actor: Option<&'a str>,
+    pub message_id: Option<&'a str>,
+}
+
+/// Keywords are compared upper-cased with surrounding whitespace removed
+pub fn normalize_keyword(keyword: &str) -> Option<String> {
+    let keyword = keyword.trim().to_uppercase();
+    (!keyword.is_empty() && keyword.len() <= MAX_KEYWORD_LEN).then_some(keyword)
+}
+
+/// The keyword the whole message consists of, ignoring case and trailing punctuation
+fn matched<'a>(text: &str, keywords: &'a [String]) -> Option<&'a String> {
+    let text = text
+        .trim()
+        .trim_end_matches(['.', '!', '?'])
+        .trim()
+        .to_uppercase();
+    keywords.iter().find(|k| **k == text)
+}
+
+pub fn defaults(phone: &str) -> OptOutSettings {
+    let list = |k: &[&str]| serde_json::to_string(k).unwrap_or_else(|_| "[]".to_string());
+    OptOutSettings {
+        session_id: phone.to_string(),
+        enabled: true,
+        keywords: list(DEFAULT_KEYWORDS),
+        opt_in_keywords: list(DEFAULT_OPT_IN_KEYWORDS),
+        updated_at: chrono::Utc::now(),
+    }
+}
+
+pub async fn settings(db: &sqlx::SqlitePool, phone: &str) -> OptOutSettings {
+    sqlx::query_as("SELECT * FROM opt_out_settings WHERE sessionId = ?")
+        .bind(phone)
+        .fetch_optional(db)
+        .await
+        .unwrap_or(None)
+        .unwrap_or_else(|| defaults(phone))
+}
+
+/// Whether a recipient is suppressed, under its phone number or its LID
+pub async fn is_opted_out(db: &sqlx::SqlitePool, phone: &str, jid: &str) -> bool {
+    sqlx::query_scalar(
+        "SELECT EXISTS(SELECT 1 FROM opt_outs WHERE sessionId = ? AND (jid = ?
+            OR jid IN (SELECT contactLid FROM contacts WHERE sessionId = ? AND contactPn = ?)
+            OR jid IN (SELECT contactPn FROM contacts WHERE sessionId = ? AND contactLid = ?)))",
+    )
+    .bind(phone)
+    .bind(jid)
+    .bind(phone)
+    .bind(jid)
+    .bind(phone)
+    .bind(jid)
+    .fetch_one(db)
+    .await
+    .unwrap_or(false)
+}
+
+async fn audit(
+    conn: &mut SqliteConnection,
+    phone: &str,
+    jid: &str,
+    action: &str,
+    change: &Change<'_>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO opt_out_events (sessionId, jid, action, source, detail, actor, messageId, createdAt)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(phone)
+    .bind(jid)
+    .bind(action)
+    .bind(change.source.as_str())
+    .bind(change.detail)
+    .bind(change.actor)
+    .bind(change.message_id)
+    .bind(chrono::Utc::now())
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Suppress a recipient. Returns false when it already was.
+pub async fn add(
+    conn: &mut SqliteConnection,
+    phone: &str,
+    jid: &str,
+    change: &Change<'_>,
+) -> Result<bool, sqlx::Error> {
+    let added = sqlx::query(
+        "INSERT INTO opt_outs (sessionId, jid, source, reason, createdAt) VALUES (?, ?, ?, ?, ?)
+         ON CONFLICT(sessionId, jid) DO NOTHING",
+    )
+    .bind(phone)
+    .bind(jid)
+    .bind(change.source.as_str())
+    .bind(change.detail)
+    .bind(chrono::Utc::now())
+    .execute(&mut *conn)
+    .await?
+    .rows_affected()
+        > 0;
+    if added {
+        audit(conn, phone, jid, "opt_out", change).await?;
+    }
+    Ok(added)
+}
+
+/// Lift a recipient's opt-out. Returns false when there was none.
+pub async fn remove(
+    conn: &mut SqliteConnection,
+    phone: &str,
+    jid: &str,
+    change: &Change<'_>,
+) -> Result<bool, sqlx::Error> {
+    let removed = sqlx::query("DELETE FROM opt_outs WHERE sessionId = ? AND jid = ?")
+        .bind(phone)
+        .bind(jid)
+        .execute(&mut *conn)
+        .await?
+        .rows_affected()
+        > 0;
+    if removed {
+        audit(conn, phone, jid, "opt_in", change).await?;
+    }
+    Ok(removed)
+}
+
+/// Chats from LID addressed contacts are stored under their phone number when known
+async fn resolve(db: &sqlx::SqlitePool, phone: &str, chat: &str) -> String {
+    if !chat.ends_with("@lid") {
+        return chat.to_string();
+    }
+    sqlx::query_scalar("SELECT contactPn FROM contacts WHERE sessionId = ? AND contactLid = ?")
+        .bind(phone)
+        .bind(chat)
+        .fetch_optional(db)
+        .await
+        .unwrap_or(None)
+        .unwrap_or_else(|| chat.to_string())
+}
+
+/// Apply opt-out and opt-in keywords from an incoming message. Returns whether
+/// the message was a keyword, even one that left the sender's status as it was,
+/// so keywords are never answered by other responders.
+pub async fn handle(state: &Arc<AppState>, phone: &str, msg: &MessageEvent) -> bool {
+    if msg.is_group || msg.chat == "status@broadcast" {
+        return false;
+    }
+    let Some(text) = msg.text.as_deref() else {
+        return false;
+    };
+    let config = settings(&state.db, phone).await;
+    if !config.enabled {
+        return false;
+    }
+    let keywords: Vec<String> = serde_json::from_str(&config.keywords).unwrap_or_default();
+    let opt_in: Vec<String> = serde_json::from_str(&config.opt_in_keywords).unwrap_or_default();
+    let (action, keyword) = match (matched(text, &keywords), matched(text, &opt_in)) {
+        (Some(k), _) => ("opt_out", k),
+        (None, Some(k)) => ("opt_in", k),
+        (None, None) => return false,
+    };
+
+    let jid = resolve(&state.db, phone, &msg.chat).await;
+    let change = Change {
+        source: Source::Keyword,
+        detail: Some(keyword),
+        actor: Some("contact"),
+        message_id: Some(&msg.id),
+    };
+    let result = async {
+        let mut tx = state.db.begin().await?;
+        let changed = if action == "opt_out" {
+            add(&mut tx, phone, &jid, &change).await?
+        } else {
+            remove(&mut tx, phone, &jid, &change).await?
+        };
+        tx.commit().await?;
+        Ok::<_, sqlx::Error>(changed)
+    }
+    .await;
+
+    match result {
+        Ok(true) => {
+            logger::info(
+                "OPTOUT",
+                &format!("{} {} {} with '{}'", phone, jid, action, keyword),
+            );
+            let event = if action == "opt_out" {
+                "contact.opted_out"
+            } else {
+                "contact.opted_in"
+            };
+            publish(
+                state,
+                phone,
+                event,
+                serde_json::json!({ "jid": jid, "keyword": keyword, "messageId": msg.id }),
+            )
+            .await;
+        }
+        Ok(false) => {}
+        Err(e) => {
+            logger::error(
+                "OPTOUT",
+                &format!("{} failed to record {} for {}: {}", phone, action, jid, e),
+            );
+        }
+    }
+    true
+}
+
+pub fn to_csv(list: &[OptOut]) -> Result<String, String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer
+        .write_record(["phone", "jid", "source", "reason", "createdAt"])
+        .map_err(|e| e.to_string())?;
+    for o in list {
+        let number = if o.jid.ends_with("@s.whatsapp.net") {
+            o.jid.split('@').next().unwrap_or_default()
+        } else {
+            ""
+        };
+        writer
+            .write_record([
+                number,
+                o.jid.as_str(),
+                o.source.as_str(),
+                o.reason.as_deref().unwrap_or_default(),
+                &o.created_at.to_rfc3339(),
+            ])
+            .map_err(|e| e.to_string())?;
+    }
+    let bytes = writer.into_inner().map_err(|e| e.to_string())?;
+    String::from_utf8(bytes).map_err(|e| e.to_string())
+}
@@ -0,0 +1,560 @@
+use crate::AppState;
+use crate::logger;
+use crate::manager::CommandError;
+use crate::manager::events::worker_command::{Command, PostStatus, SendMessage};
+use crate::manager::events::worker_event::CommandResult;
+use crate::manager::opt_outs;
+use crate::sql::OutboundLimitOverride;
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock};
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant};
+
+/// Where an outbound message originated
+#[derive(Debug, Clone, Copy)]
+pub enum SendSource {
+    Api,
+    WebSocket,
+    AutoReply,
+    Away,
+    Flow,
+    Broadcast,
+    /// Status updates, posted now or on schedule
+    Status,
+    /// Account notices sent from the NOTIFY_INSTANCE
+    Notification,
+}
+
+impl SendSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SendSource::Api => "api",
+            SendSource::WebSocket => "websocket",
+            SendSource::AutoReply => "auto-reply",
+            SendSource::Away => "away",
+            SendSource::Flow => "flow",
+            SendSource::Broadcast => "broadcast",
+            SendSource::Status => "status",
+            SendSource::Notification => "notification",
+        }
+    }
+
+    /// Bulk and automated sends skip recipients who opted out, direct sends
+    /// and account notices don't
+    pub fn honors_opt_outs(&self) -> bool {
+        matches!(
+            self,
+            SendSource::Broadcast
+                | SendSource::AutoReply
+                | SendSource::Away
+                | SendSource::Flow
+                | SendSource::Status
+        )
+    }
+}
+
+/// Something an instance sends out. Statuses count against the same limits
+/// as messages; their audience is filtered for opt-outs when it's built.
+pub enum Outgoing {
+    Message(SendMessage),
+    Status(PostStatus),
+}
+
+impl From<SendMessage> for Outgoing {
+    fn from(message: SendMessage) -> Self {
+        Outgoing::Message(message)
+    }
+}
+
+impl From<PostStatus> for Outgoing {
+    fn from(status: PostStatus) -> Self {
+        Outgoing::Status(status)
+    }
+}
+
+/// Outbound limits applied to a single instance
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct OutboundLimits {
+    #[serde(rename = "perMinute")]
+    pub per_minute: u32,
+    #[serde(rename = "perDay")]
+    pub per_day: u32,
+    /// Days after pairing during which the daily cap ramps up to `per_day`
+    #[serde(rename = "warmupDays")]
+    pub warmup_days: u32,
+    #[serde(rename = "minDelayMs")]
+    pub min_delay_ms: u64,
+    #[serde(rename = "maxDelayMs")]
+    pub max_delay_ms: u64,
+}
+
+/// Billing plans and their default outbound limits
+pub const PLANS: &[(&str, OutboundLimits)] = &[
+    (
+        "free",
+        OutboundLimits {
+            per_minute: 10,
+            per_day: 200,
+            warmup_days: 14,
+            min_delay_ms: 2000,
+            max_delay_ms: 6000,
+        },
+    ),
+    (
+        "basic",
+        OutboundLimits {
+            per_minute: 20,
+            per_day: 1000,
+            warmup_days: 10,
+            min_delay_ms: 1500,
+            max_delay_ms: 4500,
+        },
+    ),
+    (
+        "pro",
+        OutboundLimits {
+            per_minute: 40,
+            per_day: 5000,
+            warmup_days: 7,
+            min_delay_ms: 1000,
+            max_delay_ms: 3000,
+        },
+    ),
+];
+
+/// Daily cap on the day a number is paired, the warm-up curve starts here
+const WARMUP_FLOOR: u32 = 20;
+
+pub fn plan_limits(plan: &str) -> Option<OutboundLimits> {
+    PLANS
+        .iter()
+        .find(|(name, _)| *name == plan)
+        .map(|(_, limits)| *limits)
+}
+
+impl OutboundLimits {
+    fn with_override(mut self, o: &OutboundLimitOverride) -> Self {
+        if let Some(v) = o.per_minute {
+            self.per_minute = v.max(0) as u32;
+        }
+        if let Some(v) = o.per_day {
+            self.per_day = v.max(0) as u32;
+        }
+        if let Some(v) = o.warmup_days {
+            self.warmup_days = v.max(0) as u32;
+        }
+        if let Some(v) = o.min_delay_ms {
+            self.min_delay_ms = v.max(0) as u64;
+        }
+        if let Some(v) = o.max_delay_ms {
+            self.max_delay_ms = v.max(0) as u64;
+        }
+        self.max_delay_ms = self.max_delay_ms.max(self.min_delay_ms);
+        self
+    }
+
+    /// Daily cap after applying the linear warm-up curve
+    pub fn daily_cap(&self, days_since_pairing: Option<i64>) -> u32 {
+        match days_since_pairing {
+            Some(days) if days >= 0 && (days as u32) < self.warmup_days => {
+                let floor = WARMUP_FLOOR.min(self.per_day);
+                floor + (self.per_day - floor) * days as u32 / self.warmup_days
+            }
+            _ => self.per_day,
+        }
+    }
+}
+
+/// Limits currently in force for an instance
+#[derive(Debug, Clone, Serialize)]
+pub struct OutboundPolicy {
+    pub plan: String,
+    pub limits: OutboundLimits,
+    #[serde(rename = "dailyCap")]
+    pub daily_cap: u32,
+    #[serde(rename = "warmingUp")]
+    pub warming_up: bool,
+    #[serde(rename = "pairedAt")]
+    pub paired_at: Option<DateTime<Utc>>,
+    #[serde(rename = "override")]
+    pub overridden: Option<OutboundLimitOverride>,
+}
+
+/// Reasons the governor refused or failed a send
+#[derive(Debug)]
+pub enum OutboundError {
+    NotConnected,
+    MinuteLimit { limit: u32, retry_after: u64 },
+    DailyLimit { limit: u32, warming_up: bool },
+    Unavailable(String),
+    OptedOut,
+    Worker(CommandError),
+}
+
+impl OutboundError {
+    /// Stable identifier for API clients
+    pub fn code(&self) -> &'static str {
+        match self {
+            OutboundError::NotConnected => "not_connected",
+            OutboundError::MinuteLimit { .. } => "minute_limit",
+            OutboundError::DailyLimit { .. } => "daily_limit",
+            OutboundError::Unavailable(_) => "unavailable",
+            OutboundError::OptedOut => "opted_out",
+            OutboundError::Worker(_) => "worker_error",
+        }
+    }
+
+    /// Seconds until the caller may try again, when that is known
+    pub fn retry_after(&self) -> Option<u64> {
+        match self {
+            OutboundError::MinuteLimit { retry_after, .. } => Some(*retry_after),
+            OutboundError::DailyLimit { .. } => {
+                let now = Utc::now();
+                let tomorrow = (now.date_naive() + chrono::Days::new(1))
+                    .and_hms_opt(0, 0, 0)
+                    .unwrap()
+                    .and_utc();
+                Some((tomorrow - now).num_seconds().max(1) as u64)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for OutboundError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutboundError::NotConnected => write!(f, "Instance is not connected"),
+            OutboundError::MinuteLimit { limit, .. } => {
+                write!(f, "Per-minute limit of {} messages reached", limit)
+            }
+            OutboundError::DailyLimit { limit, warming_up } if *warming_up => write!(
+                f,
+                "Daily limit of {} messages reached (number is still warming up)",
+                limit
+            ),
+            OutboundError::DailyLimit { limit, .. } => {
+                write!(f, "Daily limit of {} messages reached", limit)
+            }
+            OutboundError::Unavailable(e) => write!(f, "Rate limiter unavailable: {}", e),
+            OutboundError::OptedOut => write!(f, "Recipient has opted out"),
+            OutboundError::Worker(e) => write!(f, "Send failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for OutboundError {}
+
+/// Resolve the plan, admin override and warm-up state for an instance
+pub async fn policy(db: &sqlx::SqlitePool, phone: &str) -> OutboundPolicy {
+    let plan: String = sqlx::query_scalar(
+        "SELECT u.plan FROM user_instances ui JOIN users u ON ui.userId = u.id WHERE ui.sessionId = ?",
+    )
+    .bind(phone)
+    .fetch_optional(db)
+    .await
+    .unwrap_or_else(|e| {
+        logger::error(
+            "OUTBOUND",
+            &format!("{} plan lookup failed, using free limits: {}", phone, e),
+        );
+        None
+    })
+    .unwrap_or_else(|| "free".to_string());
+
+    let overridden: Option<OutboundLimitOverride> =
+        sqlx::query_as("SELECT * FROM outbound_limits WHERE sessionId = ?")
+            .bind(phone)
+            .fetch_optional(db)
+            .await
+            .unwrap_or_else(|e| {
+                logger::error(
+                    "OUTBOUND",
+                    &format!("{} limit override lookup failed: {}", phone, e),
+                );
+                None
+            });
+
+    let paired_at: Option<DateTime<Utc>> =
+        sqlx::query_scalar("SELECT pairedAt FROM sessions WHERE id = ?")
+            .bind(phone)
+            .fetch_optional(db)
+            .await
+            .unwrap_or(None)
+            .flatten();
+
+    let mut limits = plan_limits(&plan).unwrap_or(PLANS[0].1);
+    if let Some(ref o) = overridden {
+        limits = limits.with_override(o);
+    }
+
+    let days = paired_at.map(|p| (Utc::now() - p).num_days());
+    let daily_cap = limits.daily_cap(days);
+
+    OutboundPolicy {
+        plan,
+        limits,
+        daily_cap,
+        warming_up: daily_cap < limits.per_day,
+        paired_at,
+        overridden,
+    }
+}
+
+fn minute_key(phone: &str) -> (String, u64) {
+    let now = Utc::now().timestamp();
+    let retry_after = (60 - now % 60) as u64;
+    (
+        format!("{}:outbound:minute:{}", phone, now / 60),
+        retry_after,
+    )
+}
+
+fn day_key(phone: &str) -> String {
+    format!("{}:outbound:day:{}", phone, Utc::now().format("%Y%m%d"))
+}
+
+/// Counter keys claimed by a send, released again if the worker fails it
+struct Reservation {
+    minute: String,
+    day: String,
+}
+
+async fn reserve(
+    state: &Arc<AppState>,
+    phone: &str,
+    policy: &OutboundPolicy,
+) -> Result<Reservation, OutboundError> {
+    let mut conn = state
+        .redis
+        .get_multiplexed_async_connection()
+        .await
+        .map_err(|e| OutboundError::Unavailable(e.to_string()))?;
+
+    let (minute, retry_after) = minute_key(phone);
+    let day = day_key(phone);
+
+    let (sent_minute, sent_day): (u32, u32) = redis::pipe()
+        .atomic()
+        .incr(&minute, 1)
+        .expire(&minute, 120)
+        .ignore()
+        .incr(&day, 1)
+        .expire(&day, 2 * 86400)
+        .ignore()
+        .query_async(&mut conn)
+        .await
+        .map_err(|e| OutboundError::Unavailable(e.to_string()))?;
+
+    let reservation = Reservation { minute, day };
+
+    if sent_minute > policy.limits.per_minute {
+        release(&mut conn, &reservation).await;
+        return Err(OutboundError::MinuteLimit {
+            limit: policy.limits.per_minute,
+            retry_after,
+        });
+    }
+
+    if sent_day > policy.daily_cap {
+        release(&mut conn, &reservation).await;
+        return Err(OutboundError::DailyLimit {
+            limit: policy.daily_cap,
+            warming_up: policy.warming_up,
+        });
+    }
+
+    Ok(reservation)
+}
+
+async fn release(conn: &mut redis::aio::MultiplexedConnection, reservation: &Reservation) {
+    let _: Result<(), _> = redis::pipe()
+        .decr(&reservation.minute, 1)
+        .ignore()
+        .decr(&reservation.day, 1)
+        .ignore()
+        .query_async(conn)
+        .await;
+}
+
+/// Messages sent by an instance in the current minute and day
+pub async fn usage(state: &Arc<AppState>, phone: &str) -> (u32, u32) {
+    let Ok(mut conn) = state.redis.get_multiplexed_async_connection().await else {
+        return (0, 0);
+    };
+    let (minute, _) = minute_key(phone);
+    redis::pipe()
+        .get(&minute)
+        .get(day_key(phone))
+        .query_async::<(Option<u32>, Option<u32>)>(&mut conn)
+        .await
+        .map(|(m, d)| (m.unwrap_or(0), d.unwrap_or(0)))
+        .unwrap_or((0, 0))
+}
+
+/// Time of the last send on an instance. Holding the lock also serialises
+/// sends, so the randomized gap is kept between every pair of messages.
+type Pacer = Arc<Mutex<Option<Instant>>>;
+
+static PACERS: LazyLock<std::sync::Mutex<HashMap<String, Pacer>>> =
+    LazyLock::new(|| std::sync::Mutex::new(HashMap::new()));
+
+fn pacer(phone: &str) -> Pacer {
+    let mut pacers = PACERS.lock().unwrap_or_else(|e| e.into_inner());
+    pacers.entry(phone.to_string()).or_default().clone()
+}
+
+/// Send a message or status through an instance, subject to its outbound
+/// limits. Every send path must go through here rather than dispatching directly.
+pub async fn send(
+    state: &Arc<AppState>,
+    phone: &str,
+    source: SendSource,
+    outgoing: impl Into<Outgoing>,
+) -> Result<CommandResult, OutboundError> {
+    let outgoing = outgoing.into();
+    let connected = {
+        let workers = state.sm.workers.read().await;
+        workers.get(phone).is_some_and(|w| w.status == "connected")
+    };
+    if !connected {
+        return Err(OutboundError::NotConnected);
+    }
+    if let Outgoing::Message(ref message) = outgoing
+        && source.honors_opt_outs()
+        && opt_outs::is_opted_out(&state.db, phone, &message.jid).await
+    {
+        return Err(OutboundError::OptedOut);
+    }
+
+    let policy = policy(&state.db, phone).await;
+    let reservation = reserve(state, phone, &policy).await?;
+
+    let pacer = pacer(phone);
+    let mut last_sent = pacer.lock().await;
+    if let Some(prev) = *last_sent {
+        let gap =
+            rand::thread_rng().gen_range(policy.limits.min_delay_ms..=policy.limits.max_delay_ms);
+        tokio::time::sleep_until(prev + Duration::from_millis(gap)).await;
+    }
+
+    let command = match outgoing {
+        Outgoing::Message(message) => Command::SendMessage(message),
+        Outgoing::Status(status) => Command::PostStatus(status),
+    };
+    let result = state.sm.dispatch(phone, command).await;
+    *last_sent = Some(Instant::now());
+    drop(last_sent);
+
+    match result {
+        Ok(r) => {
+            logger::debug(
+                "OUTBOUND",
+                &format!("{} sent message ({})", phone, source.as_str()),
+            );
+            Ok(r)
+        }
+        Err(e) => {
+            if let Ok(mut conn) = state.redis.get_multiplexed_async_connection().await {
+                release(&mut conn, &reservation).await;
+            }
+            logger::warn(
+                "OUTBOUND",
+                &format!("{} send failed ({}): {}", phone, source.as_str(), e),
+            );
+            Err(OutboundError::Worker(e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn free() -> OutboundLimits {
+        plan_limits("free").unwrap()
+    }
+
+    #[test]
+    fn plans_are_known_by_name() {
+        assert_eq!(free().per_day, 200);
+        assert_eq!(plan_limits("pro").unwrap().per_minute, 40);
+        assert!(plan_limits("enterprise").is_none());
+        for (name, limits) in PLANS {
+            assert!(limits.min_delay_ms <= limits.max_delay_ms, "{}", name);
+            assert!(limits.per_day >= WARMUP_FLOOR, "{}", name);
+        }
+    }
+
+    #[test]
+    fn warmup_ramps_linearly_to_the_plan_cap() {
+        let limits = free();
+        assert_eq!(limits.daily_cap(Some(0)), WARMUP_FLOOR);
+        assert_eq!(limits.daily_cap(Some(7)), 110);
+        assert_eq!(limits.daily_cap(Some(13)), 187);
+        assert_eq!(limits.daily_cap(Some(14)), 200);
+        assert_eq!(limits.daily_cap(Some(400)), 200);
+        // Unknown or future pairing dates get the full cap
+        assert_eq!(limits.daily_cap(None), 200);
+        assert_eq!(limits.daily_cap(Some(-1)), 200);
+    }
+
+    #[test]
+    fn warmup_never_exceeds_a_small_cap() {
+        let limits = OutboundLimits {
+            per_day: 5,
+            ..free()
+        };
+        assert_eq!(limits.daily_cap(Some(0)), 5);
+        assert_eq!(limits.daily_cap(Some(10)), 5);
+
+        let no_warmup = OutboundLimits {
+            warmup_days: 0,
+            ..free()
+        };
+        assert_eq!(no_warmup.daily_cap(Some(0)), 200);
+    }
+
+    #[test]
+    fn overrides_replace_plan_limits() {
+        let o = OutboundLimitOverride {
+            session_id: "1".to_string(),
+            per_minute: Some(5),
+            per_day: None,
+            warmup_days: Some(-3),
+            min_delay_ms: Some(8000),
+            max_delay_ms: None,
+            updated_at: Utc::now(),
+        };
+        let limits = free().with_override(&o);
+        assert_eq!(limits.per_minute, 5);
+        assert_eq!(limits.per_day, 200);
+        assert_eq!(limits.warmup_days, 0);
+        assert_eq!(limits.min_delay_ms, 8000);
+        // The delay range stays ordered
+        assert_eq!(limits.max_delay_ms, 8000);
+    }
+
+    #[test]
+    fn automated_sources_honor_opt_outs() {
+        for source in [
+            SendSource::Broadcast,
+            SendSource::AutoReply,
+            SendSource::Away,
+            SendSource::Flow,
+            SendSource::Status,
+        ] {
+            assert!(source.honors_opt_outs(), "{}", source.as_str());
+        }
+        for source in [
+            SendSource::Api,
+            SendSource::WebSocket,
+            SendSource::Notification,
+        ] {
+            assert!(!source.honors_opt_outs(), "{}", source.as_str());
+        }
+    }
+}
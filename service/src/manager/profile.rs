@@ -0,0 +1,139 @@
+use crate::AppState;
+use crate::manager::CommandError;
+use crate::manager::events::worker_command::{Command, ProfileAction};
+use image::{ImageFormat, ImageReader, Limits, codecs::jpeg::JpegEncoder, imageops::FilterType};
+use std::io::Cursor;
+use std::sync::Arc;
+
+/// WhatsApp's own limits for the display name and about text
+pub const MAX_NAME_LEN: usize = 25;
+pub const MAX_ABOUT_LEN: usize = 139;
+
+/// Largest upload accepted before decoding
+pub const MAX_PHOTO_BYTES: usize = 5 * 1024 * 1024;
+/// WhatsApp stores profile photos as 640x640 JPEGs
+const PHOTO_SIZE: u32 = 640;
+const MIN_PHOTO_SIZE: u32 = 192;
+const MAX_PHOTO_DIMENSION: u32 = 8192;
+const PHOTO_QUALITY: u8 = 85;
+
+/// API name, worker setting and accepted values of each privacy setting
+pub const PRIVACY_SETTINGS: &[(&str, &str, &[&str])] = &[
+    (
+        "lastSeen",
+        "last_seen",
+        &["all", "contacts", "contact_blacklist", "none"],
+    ),
+    ("online", "online", &["all", "match_last_seen"]),
+    (
+        "profilePicture",
+        "profile_picture",
+        &["all", "contacts", "contact_blacklist", "none"],
+    ),
+    (
+        "status",
+        "status",
+        &["all", "contacts", "contact_blacklist", "none"],
+    ),
+    ("readReceipts", "read_receipts", &["all", "none"]),
+    (
+        "groupsAdd",
+        "groups_add",
+        &["all", "contacts", "contact_blacklist"],
+    ),
+    ("callsAdd", "calls_add", &["all", "known"]),
+];
+
+/// Check a privacy change and return the worker's setting name
+pub fn privacy_setting(name: &str, value: &str) -> Result<&'static str, String> {
+    let (_, setting, values) = PRIVACY_SETTINGS
+        .iter()
+        .find(|(n, _, _)| *n == name)
+        .ok_or_else(|| format!("Unknown privacy setting '{}'", name))?;
+    if !values.contains(&value) {
+        return Err(format!("{} must be one of {}", name, values.join(", ")));
+    }
+    Ok(setting)
+}
+
+/// Validate an uploaded photo and turn it into a square JPEG WhatsApp accepts.
+/// Off-centre content is cropped, large photos are scaled down.
+pub fn prepare_photo(data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.len() > MAX_PHOTO_BYTES {
+        return Err(format!(
+            "Photo must be at most {} MB",
+            MAX_PHOTO_BYTES / 1024 / 1024
+        ));
+    }
+    let format = image::guess_format(data).map_err(|_| "Unrecognised image format")?;
+    if !matches!(
+        format,
+        ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP
+    ) {
+        return Err("Photo must be a JPEG, PNG or WebP image".to_string());
+    }
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_PHOTO_DIMENSION);
+    limits.max_image_height = Some(MAX_PHOTO_DIMENSION);
+    let mut reader = ImageReader::with_format(Cursor::new(data), format);
+    reader.limits(limits);
+    let img = reader
+        .decode()
+        .map_err(|e| format!("Invalid image: {}", e))?;
+
+    let (width, height) = (img.width(), img.height());
+    let side = width.min(height);
+    if side < MIN_PHOTO_SIZE {
+        return Err(format!(
+            "Photo must be at least {}x{} pixels",
+            MIN_PHOTO_SIZE, MIN_PHOTO_SIZE
+        ));
+    }
+    let mut img = img.crop_imm((width - side) / 2, (height - side) / 2, side, side);
+    if side > PHOTO_SIZE {
+        img = img.resize_exact(PHOTO_SIZE, PHOTO_SIZE, FilterType::Lanczos3);
+    }
+
+    let mut out = Vec::new();
+    JpegEncoder::new_with_quality(&mut out, PHOTO_QUALITY)
+        .encode_image(&img.to_rgb8())
+        .map_err(|e| format!("Failed to encode photo: {}", e))?;
+    Ok(out)
+}
+
+/// Run a profile action on the instance's worker and return its JSON result
+pub async fn run(
+    state: &Arc<AppState>,
+    phone: &str,
+    action: ProfileAction,
+) -> Result<serde_json::Value, CommandError> {
+    let result = state
+        .sm
+        .dispatch(phone, Command::ProfileAction(action))
+        .await?;
+    Ok(serde_json::from_str(&result.data).unwrap_or(serde_json::Value::Null))
+}
+
+/// Keep the session row in line with the profile WhatsApp reports
+pub async fn reflect(
+    db: &sqlx::SqlitePool,
+    phone: &str,
+    name: Option<&str>,
+    photo_url: Option<Option<&str>>,
+) {
+    if let Some(name) = name {
+        let _ = sqlx::query("UPDATE sessions SET name = ? WHERE id = ?")
+            .bind(name)
+            .bind(phone)
+            .execute(db)
+            .await;
+    }
+    if let Some(url) = photo_url {
+        let _ = sqlx::query("UPDATE sessions SET profileUrl = ? WHERE id = ?")
+            .bind(url)
+            .bind(phone)
+            .execute(db)
+            .await;
+    }
+}
@@ -0,0 +1,232 @@
+use crate::AppState;
+use crate::logger;
+use crate::manager::CommandError;
+use crate::manager::events::worker_command::PostStatus;
+use crate::manager::events::worker_event::StatusView;
+use crate::manager::outbound::{self, OutboundError, SendSource};
+use crate::sql::StatusUpdate;
+use sqlx::{QueryBuilder, Sqlite};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use tokio::time::Duration;
+
+pub const KINDS: &[&str] = &["text", "image", "video"];
+pub const AUDIENCES: &[&str] = &["all", "list", "exclude"];
+pub const MAX_FONT: i64 = 7;
+pub const MAX_TEXT_LEN: usize = 700;
+/// A scheduled status still goes out if the instance reconnects within this window
+const MAX_LATENESS_SECS: i64 = 60 * 60;
+const POLL_INTERVAL_SECS: u64 = 15;
+
+/// `#RRGGBB`
+pub fn is_valid_color(color: &str) -> bool {
+    color.len() == 7 && color.starts_with('#') && color[1..].chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Contacts a status is shown to. Everyone who opted out is left out.
+async fn audience(
+    db: &sqlx::SqlitePool,
+    phone: &str,
+    mode: &str,
+    contacts: &[String],
+) -> Vec<String> {
+    if mode == "list" {
+        let opted_out: HashSet<String> =
+            sqlx::query_scalar("SELECT jid FROM opt_outs WHERE sessionId = ?")
+                .bind(phone)
+                .fetch_all(db)
+                .await
+                .unwrap_or_default()
+                .into_iter()
+                .collect();
+        return contacts
+            .iter()
+            .filter(|jid| !opted_out.contains(*jid))
+            .cloned()
+            .collect();
+    }
+
+    let mut qb: QueryBuilder<Sqlite> =
+        QueryBuilder::new("SELECT contactPn FROM contacts WHERE sessionId = ");
+    qb.push_bind(phone);
+    qb.push(" AND contactPn LIKE '%@s.whatsapp.net'");
+    qb.push(" AND contactPn NOT IN (SELECT jid FROM opt_outs WHERE sessionId = ");
+    qb.push_bind(phone);
+    qb.push(")");
+    if mode == "exclude" && !contacts.is_empty() {
+        qb.push(" AND contactPn NOT IN (");
+        let mut separated = qb.separated(", ");
+        for jid in contacts {
+            separated.push_bind(jid);
+        }
+        qb.push(")");
+    }
+    qb.push(" ORDER BY contactPn");
+
+    qb.build_query_scalar()
+        .fetch_all(db)
+        .await
+        .unwrap_or_default()
+}
+
+async fn fail(db: &sqlx::SqlitePool, id: i64, error: &str) {
+    let _ = sqlx::query("UPDATE statuses SET status = 'failed', lastError = ? WHERE id = ?")
+        .bind(error)
+        .bind(id)
+        .execute(db)
+        .await;
+}
+
+/// Failures a later attempt may get past
+fn is_transient(e: &OutboundError) -> bool {
+    matches!(
+        e,
+        OutboundError::NotConnected
+            | OutboundError::MinuteLimit { .. }
+            | OutboundError::Worker(CommandError::NotConnected)
+    )
+}
+
+/// Post a scheduled status now. When the instance is offline or at its
+/// per-minute limit the status stays scheduled for a retry if `retry_offline`
+/// is set and it isn't too late.
+pub async fn post(state: &Arc<AppState>, id: i64, retry_offline: bool) {
+    let claimed =
+        sqlx::query("UPDATE statuses SET status = 'posting' WHERE id = ? AND status = 'scheduled'")
+            .bind(id)
+            .execute(&state.db)
+            .await
+            .is_ok_and(|r| r.rows_affected() > 0);
+    if !claimed {
+        return;
+    }
+    let status: Option<StatusUpdate> = sqlx::query_as("SELECT * FROM statuses WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None);
+    let Some(status) = status else {
+        return;
+    };
+
+    let contacts: Vec<String> = serde_json::from_str(&status.contacts).unwrap_or_default();
+    let audience = audience(&state.db, &status.session_id, &status.audience, &contacts).await;
+    if audience.is_empty() {
+        fail(&state.db, id, "No contacts in the audience").await;
+        return;
+    }
+    let recipients = audience.len() as i64;
+
+    let command = PostStatus {
+        kind: status.kind.clone(),
+        text: status.text.clone().unwrap_or_default(),
+        media_url: status.media_url.clone().unwrap_or_default(),
+        background_color: status.background_color.clone().unwrap_or_default(),
+        font: status.font.unwrap_or(0) as i32,
+        audience,
+    };
+    match outbound::send(state, &status.session_id, SendSource::Status, command).await {
+        Ok(result) => {
+            let message_id = serde_json::from_str::<serde_json::Value>(&result.data)
+                .ok()
+                .and_then(|d| d.get("id")?.as_str().map(str::to_string));
+            let _ = sqlx::query(
+                "UPDATE statuses SET status = 'posted', postedAt = ?, messageId = ?, recipients = ?, lastError = NULL
+                 WHERE id = ?",
+            )
+            .bind(chrono::Utc::now())
+            .bind(message_id)
+            .bind(recipients)
+            .bind(id)
+            .execute(&state.db)
+            .await;
+            logger::info(
+                "STATUS",
+                &format!(
+                    "{} posted status {} to {} contacts",
+                    status.session_id, id, recipients
+                ),
+            );
+        }
+        Err(e)
+            if retry_offline
+                && is_transient(&e)
+                && (chrono::Utc::now() - status.scheduled_at).num_seconds() < MAX_LATENESS_SECS =>
+        {
+            let _ =
+                sqlx::query("UPDATE statuses SET status = 'scheduled', lastError = ? WHERE id = ?")
+                    .bind(e.to_string())
+                    .bind(id)
+                    .execute(&state.db)
+                    .await;
+        }
+        Err(e) => {
+            fail(&state.db, id, &e.to_string()).await;
+            logger::warn(
+                "STATUS",
+                &format!("{} status {} failed: {}", status.session_id, id, e),
+            );
+        }
+    }
+}
+
+/// Post scheduled statuses as they come due. Each instance posts from its own
+/// task, so one that is slow to answer doesn't hold up the others.
+pub async fn run(state: Arc<AppState>) {
+    // Posts cut short by a restart can't be told apart from posted ones
+    let _ = sqlx::query(
+        "UPDATE statuses SET status = 'failed', lastError = 'Interrupted by a service restart'
+         WHERE status = 'posting'",
+    )
+    .execute(&state.db)
+    .await;
+
+    // Instances with a posting task still running
+    let busy: Arc<Mutex<HashSet<String>>> = Arc::default();
+
+    loop {
+        let due: Vec<(i64, String)> = sqlx::query_as(
+            "SELECT id, sessionId FROM statuses WHERE status = 'scheduled' AND scheduledAt <= ?
+             ORDER BY scheduledAt",
+        )
+        .bind(chrono::Utc::now())
+        .fetch_all(&state.db)
+        .await
+        .unwrap_or_default();
+
+        let mut by_instance: HashMap<String, Vec<i64>> = HashMap::new();
+        for (id, phone) in due {
+            by_instance.entry(phone).or_default().push(id);
+        }
+        for (phone, ids) in by_instance {
+            if !busy.lock().unwrap().insert(phone.clone()) {
+                continue;
+            }
+            let state = state.clone();
+            let busy = busy.clone();
+            tokio::spawn(async move {
+                for id in ids {
+                    post(&state, id, true).await;
+                }
+                busy.lock().unwrap().remove(&phone);
+            });
+        }
+        tokio::time::sleep(Duration::from_secs(POLL_INTERVAL_SECS)).await;
+    }
+}
+
+/// Store a viewer of one of the instance's posted statuses
+pub async fn record_view(db: &sqlx::SqlitePool, phone: &str, view: &StatusView) {
+    let viewed_at =
+        chrono::DateTime::from_timestamp(view.timestamp, 0).unwrap_or_else(chrono::Utc::now);
+    let _ = sqlx::query(
+        "INSERT OR IGNORE INTO status_views (statusId, viewer, viewedAt)
+         SELECT id, ?, ? FROM statuses WHERE sessionId = ? AND messageId = ?",
+    )
+    .bind(&view.viewer)
+    .bind(viewed_at)
+    .bind(phone)
+    .bind(&view.id)
+    .execute(db)
+    .await;
+}
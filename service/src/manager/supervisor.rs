@@ -1,11 +1,12 @@
 use crate::AppState;
 use crate::logger;
-use crate::manager::events::WorkerEvent;
 use crate::manager::events::worker_event::Event;
+use crate::manager::events::{WorkerCommand, WorkerEvent};
+use crate::manager::{autoreply, away, flows, messages, opt_outs, publish, statuses};
 use prost::Message;
 use std::process::Stdio;
 use std::sync::Arc;
-use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
 use tokio::net::TcpListener;
 use tokio::time::{Duration, sleep};
 
@@ -90,11 +91,11 @@ pub async fn run(phone: String, state: Arc<AppState>) {
         loop {
             tokio::select! {
                 accept_res = listener.accept() => {
-                    if let Ok((mut stream, _)) = accept_res {
+                    if let Ok((stream, _)) = accept_res {
                         let st = state.clone();
                         let p = phone.clone();
                         tokio::spawn(async move {
-                            if let Err(e) = process_socket(&mut stream, st, &p).await {
+                            if let Err(e) = process_socket(stream, st, &p).await {
                                 logger::error("SUPERVISOR", &format!("{} socket error: {}", p, e));
                             }
                         });
@@ -166,32 +167,77 @@ async fn kill_process_tree(pid: u32) {
 }
 
 async fn process_socket(
-    stream: &mut tokio::net::TcpStream,
+    stream: tokio::net::TcpStream,
     state: Arc<AppState>,
     phone: &str,
 ) -> anyhow::Result<()> {
+    let (mut reader, mut writer) = stream.into_split();
+
+    // Commands for the worker are framed the same way as its events
+    let (cmd_tx, mut cmd_rx) = tokio::sync::mpsc::unbounded_channel::<WorkerCommand>();
+    state
+        .sm
+        .links
+        .write()
+        .await
+        .insert(phone.to_string(), cmd_tx.clone());
+
+    let writer_task = tokio::spawn(async move {
+        while let Some(cmd) = cmd_rx.recv().await {
+            let bytes = cmd.encode_to_vec();
+            let header = (bytes.len() as u32).to_be_bytes();
+            if writer.write_all(&header).await.is_err() || writer.write_all(&bytes).await.is_err() {
+                break;
+            }
+        }
+    });
+
     let mut header = [0u8; 4];
-    while stream.read_exact(&mut header).await.is_ok() {
-        let len = u32::from_be_bytes(header) as usize;
-        let mut buf = vec![0u8; len];
-        stream.read_exact(&mut buf).await?;
-        if let Ok(event) = WorkerEvent::decode(&buf[..]) {
-            handle_event(event, state.clone(), phone).await;
+    let result = async {
+        while reader.read_exact(&mut header).await.is_ok() {
+            let len = u32::from_be_bytes(header) as usize;
+            let mut buf = vec![0u8; len];
+            reader.read_exact(&mut buf).await?;
+            if let Ok(event) = WorkerEvent::decode(&buf[..]) {
+                handle_event(event, state.clone(), phone).await;
+            }
         }
+        anyhow::Ok(())
     }
-    Ok(())
+    .await;
+
+    writer_task.abort();
+    let mut links = state.sm.links.write().await;
+    if links.get(phone).is_some_and(|l| l.same_channel(&cmd_tx)) {
+        links.remove(phone);
+    }
+
+    result
 }
 
 async fn update_db_status(phone: &str, status: &str, state: &Arc<AppState>) {
-    let mut workers = state.sm.workers.write().await;
-    if let Some(w) = workers.get_mut(phone) {
-        w.status = status.to_string();
+    {
+        let mut workers = state.sm.workers.write().await;
+        if let Some(w) = workers.get_mut(phone) {
+            w.status = status.to_string();
+        }
     }
     let _ = sqlx::query("UPDATE sessions SET status = ? WHERE id = ?")
         .bind(status)
         .bind(phone)
         .execute(&state.db)
         .await;
+    emit_status(state, phone, status).await;
+}
+
+async fn emit_status(state: &Arc<AppState>, phone: &str, status: &str) {
+    publish(
+        state,
+        phone,
+        "instance.status",
+        serde_json::json!({ "status": status }),
+    )
+    .await;
 }
 
 async fn handle_event(event: WorkerEvent, state: Arc<AppState>, phone: &str) {
@@ -199,6 +245,7 @@ async fn handle_event(event: WorkerEvent, state: Arc<AppState>, phone: &str) {
         match inner_event {
             Event::Connection(conn) => {
                 logger::debug("EVENT", &format!("{} status: {}", conn.phone, conn.status));
+                emit_status(&state, &conn.phone, &conn.status).await;
 
                 if conn.status == "logged_out" {
                     logger::warn(
@@ -228,9 +275,19 @@ async fn handle_event(event: WorkerEvent, state: Arc<AppState>, phone: &str) {
                         w.pairing_code = Some(conn.qr.clone());
                     }
                 }
+                drop(workers);
 
                 if conn.status == "connected" {
                     logger::success("SESSION", &format!("{} connected", conn.phone));
+
+                    // First successful connection starts the outbound warm-up period
+                    let _ = sqlx::query(
+                        "UPDATE sessions SET pairedAt = COALESCE(pairedAt, ?) WHERE id = ?",
+                    )
+                    .bind(chrono::Utc::now())
+                    .bind(&conn.phone)
+                    .execute(&state.db)
+                    .await;
                 }
             }
             Event::RawLog(log) => {
@@ -238,6 +295,44 @@ async fn handle_event(event: WorkerEvent, state: Arc<AppState>, phone: &str) {
                     logger::debug("WORKER", &format!("{}: {}", phone, log));
                 }
             }
+            Event::CommandResult(result) => {
+                state.sm.resolve(result).await;
+            }
+            Event::MessageReceived(msg) => {
+                if let Err(e) = messages::persist(&state.db, phone, &msg, false).await {
+                    logger::error(
+                        "MESSAGE",
+                        &format!("{} failed to store {}: {}", phone, msg.id, e),
+                    );
+                }
+                publish(&state, phone, "message.received", serde_json::json!(msg)).await;
+
+                // Replies are paced by the outbound governor, keep the socket reading
+                let phone = phone.to_string();
+                tokio::spawn(async move {
+                    // Opt-out keywords are never answered, a running conversation
+                    // owns the message, explicit rules win over the greeting and
+                    // away message
+                    if !opt_outs::handle(&state, &phone, &msg).await
+                        && !flows::handle(&state, &phone, &msg).await
+                        && !autoreply::handle(&state, &phone, &msg).await
+                    {
+                        away::handle(&state, &phone, &msg).await;
+                    }
+                });
+            }
+            Event::MessageSent(msg) => {
+                if let Err(e) = messages::persist(&state.db, phone, &msg, true).await {
+                    logger::error(
+                        "MESSAGE",
+                        &format!("{} failed to store {}: {}", phone, msg.id, e),
+                    );
+                }
+                publish(&state, phone, "message.sent", serde_json::json!(msg)).await;
+            }
+            Event::StatusViewed(view) => {
+                statuses::record_view(&state.db, phone, &view).await;
+            }
         }
     }
 }
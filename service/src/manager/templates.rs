@@ -0,0 +1,436 @@
+use crate::manager::events::worker_command::SendMessage;
+use crate::sql::MessageTemplate;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::LazyLock;
+
+/// Filled from the clock and the recipient, usable without declaring them
+pub const BUILTIN_VARIABLES: &[&str] = &["date", "time", "phone", "lid"];
+
+static PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{\{\s*([A-Za-z_][A-Za-z0-9_]*)\s*\}\}").unwrap());
+
+/// A placeholder the template expects. Values come from the caller, then the
+/// contact, then `default`; a required variable without a value fails the render.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Variable {
+    pub name: String,
+    #[serde(default)]
+    pub default: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+}
+
+/// Content of a template in one language
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Variant {
+    #[serde(default)]
+    pub body: String,
+    #[serde(default, rename = "mediaUrl")]
+    pub media_url: Option<String>,
+    #[serde(default, rename = "mediaType")]
+    pub media_type: Option<String>,
+    #[serde(default, rename = "fileName")]
+    pub file_name: Option<String>,
+}
+
+/// Language tag (`en`, `pt-BR`) to variant
+pub type Variants = HashMap<String, Variant>;
+
+/// A stored template referenced by name from a send request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateRef {
+    pub name: String,
+    #[serde(default)]
+    pub language: Option<String>,
+    #[serde(default)]
+    pub variables: HashMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Rendered {
+    pub language: String,
+    pub text: String,
+    #[serde(rename = "mediaUrl")]
+    pub media_url: Option<String>,
+    #[serde(rename = "mediaType")]
+    pub media_type: Option<String>,
+    #[serde(rename = "fileName")]
+    pub file_name: Option<String>,
+}
+
+impl Rendered {
+    pub fn into_message(self, jid: String) -> SendMessage {
+        SendMessage {
+            jid,
+            text: self.text,
+            media_url: self.media_url.unwrap_or_default(),
+            media_type: self.media_type.unwrap_or_default(),
+            file_name: self.file_name.unwrap_or_default(),
+        }
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn is_language_tag(tag: &str) -> bool {
+    !tag.is_empty()
+        && tag.len() <= 16
+        && tag
+            .split('-')
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+/// Placeholder names used in a body
+pub fn placeholders(body: &str) -> BTreeSet<String> {
+    PLACEHOLDER
+        .captures_iter(body)
+        .map(|c| c[1].to_string())
+        .collect()
+}
+
+pub fn validate(
+    default_language: &str,
+    variables: &[Variable],
+    variants: &Variants,
+) -> Result<(), String> {
+    let mut declared = BTreeSet::new();
+    for v in variables {
+        if !is_identifier(&v.name) {
+            return Err(format!(
+                "Invalid variable name '{}', use letters, digits and underscores",
+                v.name
+            ));
+        }
+        if !declared.insert(v.name.as_str()) {
+            return Err(format!("Variable '{}' is declared twice", v.name));
+        }
+        if v.required && v.default.is_some() {
+            return Err(format!(
+                "Variable '{}' can't be required and have a default",
+                v.name
+            ));
+        }
+    }
+
+    if variants.is_empty() {
+        return Err("At least one language variant is required".to_string());
+    }
+    if !variants.contains_key(default_language) {
+        return Err(format!(
+            "defaultLanguage '{}' has no variant",
+            default_language
+        ));
+    }
+    for (language, variant) in variants {
+        if !is_language_tag(language) {
+            return Err(format!("Invalid language tag '{}'", language));
+        }
+        if variant.body.trim().is_empty() && variant.media_url.is_none() {
+            return Err(format!(
+                "The '{}' variant needs a body or a mediaUrl",
+                language
+            ));
+        }
+        if let Some(unknown) = placeholders(&variant.body)
+            .into_iter()
+            .find(|p| !declared.contains(p.as_str()) && !BUILTIN_VARIABLES.contains(&p.as_str()))
+        {
+            return Err(format!(
+                "Undeclared placeholder {{{{{}}}}} in the '{}' variant",
+                unknown, language
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The requested language, then its base language (`pt` for `pt-BR`), then the default
+fn pick_variant<'a>(
+    variants: &'a Variants,
+    default_language: &'a str,
+    requested: Option<&str>,
+) -> Option<(&'a String, &'a Variant)> {
+    let find = |tag: &str| {
+        variants
+            .iter()
+            .find(|(language, _)| language.eq_ignore_ascii_case(tag))
+    };
+    requested
+        .and_then(|tag| find(tag).or_else(|| find(tag.split('-').next().unwrap_or(tag))))
+        .or_else(|| variants.get_key_value(default_language))
+}
+
+/// Fill placeholders from `values` in a single pass, so values are inserted
+/// literally even when they contain placeholders themselves. Unknown
+/// placeholders are left as they are.
+pub fn substitute(text: &str, values: &HashMap<String, String>) -> String {
+    PLACEHOLDER
+        .replace_all(text, |c: &regex::Captures| match values.get(&c[1]) {
+            Some(value) => value.clone(),
+            None => c[0].to_string(),
+        })
+        .into_owned()
+}
+
+/// Render a template with `values`, which should already hold the builtin,
+/// contact and caller supplied values in increasing priority
+pub fn render(
+    template: &MessageTemplate,
+    language: Option<&str>,
+    values: &HashMap<String, String>,
+) -> Result<Rendered, String> {
+    let variables: Vec<Variable> = serde_json::from_str(&template.variables).unwrap_or_default();
+    let variants: Variants = serde_json::from_str(&template.variants).unwrap_or_default();
+    let (language, variant) = pick_variant(&variants, &template.default_language, language)
+        .ok_or_else(|| format!("Template '{}' has no variants", template.name))?;
+
+    let missing: Vec<&str> = variables
+        .iter()
+        .filter(|v| v.required && values.get(&v.name).is_none_or(|s| s.is_empty()))
+        .map(|v| v.name.as_str())
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!(
+            "Missing required variables: {}",
+            missing.join(", ")
+        ));
+    }
+
+    let defaults: HashMap<&str, &str> = variables
+        .iter()
+        .filter_map(|v| Some((v.name.as_str(), v.default.as_deref()?)))
+        .collect();
+    let text = PLACEHOLDER.replace_all(&variant.body, |c: &regex::Captures| {
+        let name = &c[1];
+        values
+            .get(name)
+            .filter(|s| !s.is_empty())
+            .map(String::as_str)
+            .or_else(|| defaults.get(name).copied())
+            .unwrap_or_default()
+            .to_string()
+    });
+
+    Ok(Rendered {
+        language: language.clone(),
+        text: text.into_owned(),
+        media_url: variant.media_url.clone(),
+        media_type: variant.media_type.clone(),
+        file_name: variant.file_name.clone(),
+    })
+}
+
+/// Values available to every render: `{{date}}`, `{{time}}` and, given a
+/// recipient, `{{phone}}` plus the contact's lid, name and custom fields
+pub async fn context_values(
+    db: &sqlx::SqlitePool,
+    phone: Option<&str>,
+    jid: Option<&str>,
+) -> HashMap<String, String> {
+    let now = chrono::Utc::now();
+    let mut values = HashMap::from([
+        ("date".to_string(), now.format("%Y-%m-%d").to_string()),
+        ("time".to_string(), now.format("%H:%M").to_string()),
+    ]);
+
+    let Some(jid) = jid else {
+        return values;
+    };
+    values.insert(
+        "phone".to_string(),
+        jid.split(['@', ':']).next().unwrap_or_default().to_string(),
+    );
+
+    if let Some(phone) = phone {
+        let contact: Option<(Option<String>, Option<String>, String)> = sqlx::query_as(
+            "SELECT contactLid, name, customFields FROM contacts
+             WHERE sessionId = ? AND (contactPn = ? OR contactLid = ?)",
+        )
+        .bind(phone)
+        .bind(jid)
+        .bind(jid)
+        .fetch_optional(db)
+        .await
+        .unwrap_or(None);
+        if let Some((lid, name, custom_fields)) = contact {
+            let fields: HashMap<String, String> =
+                serde_json::from_str(&custom_fields).unwrap_or_default();
+            values.extend(fields);
+            if let Some(name) = name.filter(|n| !n.is_empty()) {
+                values.insert("name".to_string(), name);
+            }
+            if let Some(lid) = lid {
+                values.insert(
+                    "lid".to_string(),
+                    lid.split('@').next().unwrap_or_default().to_string(),
+                );
+            }
+        }
+    }
+    values
+}
+
+/// A template by name, owned by one of the instance's users
+pub async fn find_for_instance(
+    db: &sqlx::SqlitePool,
+    phone: &str,
+    name: &str,
+) -> Option<MessageTemplate> {
+    sqlx::query_as(
+        "SELECT t.* FROM message_templates t JOIN user_instances ui ON ui.userId = t.userId
+         WHERE ui.sessionId = ? AND t.name = ? ORDER BY t.id LIMIT 1",
+    )
+    .bind(phone)
+    .bind(name)
+    .fetch_optional(db)
+    .await
+    .unwrap_or(None)
+}
+
+/// A template by id, owned by one of the instance's users
+pub async fn find_by_id_for_instance(
+    db: &sqlx::SqlitePool,
+    phone: &str,
+    id: i64,
+) -> Option<MessageTemplate> {
+    sqlx::query_as(
+        "SELECT t.* FROM message_templates t JOIN user_instances ui ON ui.userId = t.userId
+         WHERE ui.sessionId = ? AND t.id = ? LIMIT 1",
+    )
+    .bind(phone)
+    .bind(id)
+    .fetch_optional(db)
+    .await
+    .unwrap_or(None)
+}
+
+/// Render a referenced template for a recipient of the instance
+pub async fn render_ref(
+    db: &sqlx::SqlitePool,
+    phone: &str,
+    jid: String,
+    template: &TemplateRef,
+) -> Result<SendMessage, String> {
+    let stored = find_for_instance(db, phone, &template.name)
+        .await
+        .ok_or_else(|| format!("Template '{}' not found", template.name))?;
+    let mut values = context_values(db, Some(phone), Some(&jid)).await;
+    values.extend(template.variables.clone());
+    render(&stored, template.language.as_deref(), &values).map(|r| r.into_message(jid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(variables: serde_json::Value, variants: serde_json::Value) -> MessageTemplate {
+        MessageTemplate {
+            id: 1,
+            user_id: "u".to_string(),
+            name: "welcome".to_string(),
+            description: None,
+            default_language: "en".to_string(),
+            variables: variables.to_string(),
+            variants: variants.to_string(),
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        }
+    }
+
+    fn values(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn welcome() -> MessageTemplate {
+        template(
+            serde_json::json!([
+                {"name": "name", "required": true},
+                {"name": "city", "default": "your city"}
+            ]),
+            serde_json::json!({
+                "en": {"body": "Hi {{ name }}, welcome to {{city}}"},
+                "pt": {"body": "Olá {{name}}, bem-vindo a {{city}}"}
+            }),
+        )
+    }
+
+    #[test]
+    fn substitute_fills_known_placeholders_once() {
+        let v = values(&[("name", "{{secret}}"), ("secret", "leaked")]);
+        assert_eq!(
+            substitute("Hi {{name}}, {{ unknown }}", &v),
+            "Hi {{secret}}, {{ unknown }}"
+        );
+        assert_eq!(substitute("no placeholders", &v), "no placeholders");
+    }
+
+    #[test]
+    fn render_uses_values_then_defaults() {
+        let t = welcome();
+        let rendered = render(&t, None, &values(&[("name", "Ada")])).unwrap();
+        assert_eq!(rendered.language, "en");
+        assert_eq!(rendered.text, "Hi Ada, welcome to your city");
+
+        let rendered = render(&t, None, &values(&[("name", "Ada"), ("city", "")])).unwrap();
+        assert_eq!(rendered.text, "Hi Ada, welcome to your city");
+
+        let v = values(&[("name", "{{city}}"), ("city", "Lisbon")]);
+        assert_eq!(
+            render(&t, None, &v).unwrap().text,
+            "Hi {{city}}, welcome to Lisbon"
+        );
+    }
+
+    #[test]
+    fn render_requires_required_variables() {
+        let err = render(&welcome(), None, &values(&[("name", "")])).unwrap_err();
+        assert!(err.contains("name"), "{}", err);
+    }
+
+    #[test]
+    fn render_falls_back_through_languages() {
+        let t = welcome();
+        let v = values(&[("name", "Ada")]);
+        assert_eq!(render(&t, Some("pt-BR"), &v).unwrap().language, "pt");
+        assert_eq!(render(&t, Some("PT"), &v).unwrap().language, "pt");
+        assert_eq!(render(&t, Some("de"), &v).unwrap().language, "en");
+    }
+
+    #[test]
+    fn validation_checks_declarations_and_variants() {
+        let body = |text: &str| {
+            Variants::from([(
+                "en".to_string(),
+                Variant {
+                    body: text.to_string(),
+                    media_url: None,
+                    media_type: None,
+                    file_name: None,
+                },
+            )])
+        };
+        let var = |name: &str| Variable {
+            name: name.to_string(),
+            default: None,
+            required: false,
+        };
+        assert!(validate("en", &[var("name")], &body("Hi {{name}} on {{date}}")).is_ok());
+        assert!(validate("en", &[], &body("Hi {{name}}")).is_err());
+        assert!(validate("en", &[var("1name")], &body("Hi")).is_err());
+        assert!(validate("en", &[var("a"), var("a")], &body("Hi")).is_err());
+        assert!(validate("fr", &[], &body("Hi")).is_err());
+        assert!(validate("en", &[], &body("  ")).is_err());
+        assert!(validate("en", &[], &Variants::new()).is_err());
+    }
+}
@@ -0,0 +1,391 @@
+use crate::AppState;
+use crate::logger;
+use crate::security::sign_with_secret;
+use crate::sql::{Webhook, WebhookDelivery};
+use chrono::Utc;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, LazyLock};
+use tokio::sync::Notify;
+use tokio::time::{Duration, Instant};
+
+/// Events a subscription can filter on. Filters may also be `*` or `<group>.*`
+pub const EVENTS: &[&str] = &[
+    "message.received",
+    "message.sent",
+    "instance.status",
+    "contact.opted_out",
+    "contact.opted_in",
+    "ping",
+];
+
+/// Attempts before a delivery is moved to the dead-letter list
+const MAX_ATTEMPTS: i64 = 8;
+/// First retry delay, doubled on every failed attempt
+const BASE_RETRY_SECS: i64 = 30;
+const MAX_RETRY_SECS: i64 = 6 * 60 * 60;
+const DELIVERY_TIMEOUT_SECS: u64 = 10;
+const POLL_INTERVAL_SECS: u64 = 5;
+const BATCH_SIZE: i64 = 50;
+
+/// Wakes the dispatcher as soon as something is queued
+static WAKE: LazyLock<Notify> = LazyLock::new(Notify::new);
+
+/// Whether a filter list (JSON array) selects an event. An empty list selects everything.
+pub fn subscribed(filters: &str, event: &str) -> bool {
+    let filters: Vec<String> = serde_json::from_str(filters).unwrap_or_default();
+    filters.is_empty()
+        || filters.iter().any(|f| {
+            f == "*"
+                || f == event
+                || f.strip_suffix(".*")
+                    .is_some_and(|group| event.split('.').next() == Some(group))
+        })
+}
+
+/// Whether a filter is something `subscribed` can ever match
+pub fn is_valid_filter(filter: &str) -> bool {
+    filter == "*"
+        || EVENTS.contains(&filter)
+        || filter
+            .strip_suffix(".*")
+            .is_some_and(|group| EVENTS.iter().any(|e| e.split('.').next() == Some(group)))
+}
+
+/// Hosts from `WEBHOOK_ALLOWED_HOSTS` that may resolve to private addresses
+static ALLOWED_HOSTS: LazyLock<Vec<String>> = LazyLock::new(|| {
+    std::env::var("WEBHOOK_ALLOWED_HOSTS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|h| h.trim().to_lowercase())
+        .filter(|h| !h.is_empty())
+        .collect()
+});
+
+/// Whether an address is reachable from the public internet. Loopback,
+/// private, link-local and other internal ranges are not.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                // Carrier-grade NAT, 100.64.0.0/10
+                || (a == 100 && (64..128).contains(&b))
+                || a == 0)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(v4) = ip.to_ipv4_mapped() {
+                return is_public(IpAddr::V4(v4));
+            }
+            let first = ip.segments()[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // Unique local, fc00::/7
+                || (first & 0xfe00) == 0xfc00
+                // Link-local, fe80::/10
+                || (first & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+/// A webhook URL that passed `check_target`, with the addresses it may be
+/// delivered to
+pub struct Target {
+    host: String,
+    addrs: Vec<SocketAddr>,
+}
+
+/// Resolve a webhook URL and make sure it points at a public address, so
+/// subscriptions can't be used to reach the service's own network. Hosts in
+/// `WEBHOOK_ALLOWED_HOSTS` are exempt.
+pub async fn check_target(raw: &str) -> Result<Target, String> {
+    let url = url::Url::parse(raw).map_err(|_| "Webhook URL must be an absolute http(s) URL")?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err("Webhook URL must be an absolute http(s) URL".to_string());
+    }
+    let host = url
+        .host_str()
+        .ok_or("Webhook URL must have a host")?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_lowercase();
+    let port = url.port_or_known_default().unwrap_or(443);
+
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), port))
+        .await
+        .map_err(|_| format!("Could not resolve {}", host))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(format!("Could not resolve {}", host));
+    }
+    if !ALLOWED_HOSTS.contains(&host) && addrs.iter().any(|a| !is_public(a.ip())) {
+        return Err(format!("{} resolves to a private or local address", host));
+    }
+    Ok(Target { host, addrs })
+}
+
+pub fn generate_secret() -> String {
+    let random_bytes: [u8; 32] = rand::random();
+    format!("whsec_{}", hex::encode(random_bytes))
+}
+
+/// Queue an instance event for every subscription that wants it
+pub async fn emit(state: &Arc<AppState>, phone: &str, event: &str, data: serde_json::Value) {
+    let hooks: Vec<Webhook> = sqlx::query_as(
+        "SELECT w.* FROM webhooks w
+         WHERE w.active = 1 AND (w.sessionId = ? OR (w.sessionId IS NULL AND EXISTS (
+            SELECT 1 FROM user_instances ui WHERE ui.userId = w.userId AND ui.sessionId = ?)))",
+    )
+    .bind(phone)
+    .bind(phone)
+    .fetch_all(&state.db)
+    .await
+    .unwrap_or_default();
+
+    for hook in hooks.iter().filter(|h| subscribed(&h.events, event)) {
+        if let Err(e) = enqueue(&state.db, hook.id, Some(phone), event, &data).await {
+            logger::error(
+                "WEBHOOK",
+                &format!("Failed to queue {} for webhook {}: {}", event, hook.id, e),
+            );
+        }
+    }
+}
+
+/// Queue a single delivery and wake the dispatcher. Returns the delivery id.
+pub async fn enqueue(
+    db: &sqlx::SqlitePool,
+    webhook_id: i64,
+    phone: Option<&str>,
+    event: &str,
+    data: &serde_json::Value,
+) -> Result<String, sqlx::Error> {
+    let id = uuid::Uuid::new_v4().to_string();
+    let now = Utc::now();
+    let payload = serde_json::json!({
+        "id": id,
+        "event": event,
+        "instance": phone,
+        "timestamp": now.to_rfc3339(),
+        "data": data
+    });
+
+    sqlx::query(
+        "INSERT INTO webhook_deliveries (id, webhookId, sessionId, event, payload, status, nextAttemptAt, createdAt, updatedAt)
+         VALUES (?, ?, ?, ?, ?, 'pending', ?, ?, ?)",
+    )
+    .bind(&id)
+    .bind(webhook_id)
+    .bind(phone)
+    .bind(event)
+    .bind(payload.to_string())
+    .bind(now)
+    .bind(now)
+    .bind(now)
+    .execute(db)
+    .await?;
+
+    WAKE.notify_one();
+    Ok(id)
+}
+
+/// Put a delivery (usually a dead letter) back in the queue with a fresh retry budget
+pub async fn redeliver(db: &sqlx::SqlitePool, delivery_id: &str) -> Result<bool, sqlx::Error> {
+    let now = Utc::now();
+    let result = sqlx::query(
+        "UPDATE webhook_deliveries SET status = 'pending', attempts = 0, nextAttemptAt = ?, updatedAt = ?
+         WHERE id = ?",
+    )
+    .bind(now)
+    .bind(now)
+    .bind(delivery_id)
+    .execute(db)
+    .await?;
+
+    WAKE.notify_one();
+    Ok(result.rows_affected() > 0)
+}
+
+fn retry_delay(attempt: i64) -> chrono::Duration {
+    let secs = BASE_RETRY_SECS.saturating_mul(1 << (attempt - 1).clamp(0, 20));
+    chrono::Duration::seconds(secs.min(MAX_RETRY_SECS))
+}
+
+/// Delivery loop, spawned once at startup. Pending deliveries survive restarts
+/// since the queue lives in the database.
+pub async fn run(state: Arc<AppState>) {
+    loop {
+        let due: Vec<WebhookDelivery> = sqlx::query_as(
+            "SELECT d.* FROM webhook_deliveries d JOIN webhooks w ON w.id = d.webhookId
+             WHERE d.status = 'pending' AND w.active = 1 AND d.nextAttemptAt <= ?
+             ORDER BY d.nextAttemptAt LIMIT ?",
+        )
+        .bind(Utc::now())
+        .bind(BATCH_SIZE)
+        .fetch_all(&state.db)
+        .await
+        .unwrap_or_default();
+
+        let full_batch = due.len() as i64 == BATCH_SIZE;
+        futures::future::join_all(due.into_iter().map(|d| attempt(&state.db, d))).await;
+
+        if !full_batch {
+            tokio::select! {
+                _ = WAKE.notified() => {}
+                _ = tokio::time::sleep(Duration::from_secs(POLL_INTERVAL_SECS)) => {}
+            }
+        }
+    }
+}
+
+/// POST a payload to a checked target. The connection goes only to the
+/// addresses the check saw, so a DNS answer that changes after the check
+/// can't redirect the delivery, and redirects aren't followed.
+async fn post(
+    target: &Target,
+    url: &str,
+    headers: [(&str, String); 4],
+    body: String,
+) -> Result<reqwest::Response, String> {
+    let client = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(DELIVERY_TIMEOUT_SECS))
+        .redirect(reqwest::redirect::Policy::none())
+        .resolve_to_addrs(&target.host, &target.addrs)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let mut request = client
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json");
+    for (name, value) in headers {
+        request = request.header(name, value);
+    }
+    request.body(body).send().await.map_err(|e| e.to_string())
+}
+
+async fn attempt(db: &sqlx::SqlitePool, delivery: WebhookDelivery) {
+    let hook: Option<Webhook> = sqlx::query_as("SELECT * FROM webhooks WHERE id = ?")
+        .bind(delivery.webhook_id)
+        .fetch_optional(db)
+        .await
+        .unwrap_or(None);
+    let Some(hook) = hook else {
+        return;
+    };
+
+    let number = delivery.attempts + 1;
+    let timestamp = Utc::now().timestamp();
+    // Receivers verify HMAC-SHA256("<timestamp>.<body>") with the subscription secret
+    let signature = sign_with_secret(&hook.secret, &format!("{}.{}", timestamp, delivery.payload));
+
+    let started = Instant::now();
+    // Checked again on every attempt, the host may resolve elsewhere by now
+    let response = match check_target(&hook.url).await {
+        Ok(target) => {
+            let headers = [
+                ("X-Whatsaly-Event", delivery.event.clone()),
+                ("X-Whatsaly-Delivery", delivery.id.clone()),
+                ("X-Whatsaly-Timestamp", timestamp.to_string()),
+                ("X-Whatsaly-Signature", format!("sha256={}", signature)),
+            ];
+            post(&target, &hook.url, headers, delivery.payload.clone()).await
+        }
+        Err(e) => Err(e),
+    };
+    let duration_ms = started.elapsed().as_millis() as i64;
+
+    let (status_code, error) = match response {
+        Ok(r) if r.status().is_success() => (Some(r.status().as_u16() as i64), None),
+        Ok(r) => (
+            Some(r.status().as_u16() as i64),
+            Some(format!("Receiver responded with {}", r.status())),
+        ),
+        Err(e) => (None, Some(e)),
+    };
+
+    let now = Utc::now();
+    let _ = sqlx::query(
+        "INSERT INTO webhook_attempts (deliveryId, attempt, statusCode, error, durationMs, createdAt)
+         VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&delivery.id)
+    .bind(number)
+    .bind(status_code)
+    .bind(&error)
+    .bind(duration_ms)
+    .bind(now)
+    .execute(db)
+    .await;
+
+    let (status, next_attempt_at, delivered_at) = match error {
+        None => ("delivered", None, Some(now)),
+        Some(_) if number >= MAX_ATTEMPTS => {
+            logger::warn(
+                "WEBHOOK",
+                &format!(
+                    "Delivery {} to webhook {} failed {} times, moved to dead letters",
+                    delivery.id, hook.id, number
+                ),
+            );
+            ("dead", None, None)
+        }
+        Some(_) => ("pending", Some(now + retry_delay(number)), None),
+    };
+
+    let _ = sqlx::query(
+        "UPDATE webhook_deliveries SET status = ?, attempts = ?, nextAttemptAt = ?, lastStatusCode = ?,
+            lastError = ?, deliveredAt = ?, updatedAt = ?
+         WHERE id = ?",
+    )
+    .bind(status)
+    .bind(number)
+    .bind(next_attempt_at)
+    .bind(status_code)
+    .bind(&error)
+    .bind(delivered_at)
+    .bind(now)
+    .bind(&delivery.id)
+    .execute(db)
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn internal_addresses_are_not_public() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "0.0.0.0",
+            "100.64.0.1",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["8.8.8.8", "1.1.1.1", "2606:4700:4700::1111"] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn local_targets_are_rejected() {
+        assert!(check_target("http://127.0.0.1:8080/hook").await.is_err());
+        assert!(check_target("http://[::1]/hook").await.is_err());
+        assert!(check_target("http://localhost/hook").await.is_err());
+        assert!(check_target("ftp://example.com/hook").await.is_err());
+    }
+}
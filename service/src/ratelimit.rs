@@ -0,0 +1,387 @@
+use crate::AppState;
+use crate::security::{AdminSession, Claims, create_secure_response, response_codes};
+use axum::{
+    Json,
+    body::Body,
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::net::SocketAddr;
+use std::sync::{Arc, LazyLock};
+
+/// Refills a bucket and takes a token in one step. Buckets are hashes of
+/// the tokens left and the last refill time, and expire once full again.
+static TAKE_TOKEN: LazyLock<redis::Script> = LazyLock::new(|| {
+    redis::Script::new(
+        r"
+local capacity = tonumber(ARGV[1])
+local rate = tonumber(ARGV[2])
+local now = tonumber(ARGV[3])
+local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'ts')
+local tokens = tonumber(bucket[1]) or capacity
+local ts = tonumber(bucket[2]) or now
+tokens = math.min(capacity, tokens + math.max(0, now - ts) * rate)
+local allowed = 0
+if tokens >= 1 then
+    tokens = tokens - 1
+    allowed = 1
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'ts', now)
+redis.call('PEXPIRE', KEYS[1], math.ceil((capacity - tokens) / rate) + 1000)
+return {allowed, tostring(tokens)}
+",
+    )
+});
+
+/// A token bucket: `capacity` requests at once, refilled over `period` seconds
+#[derive(Debug, Clone, Copy)]
+struct Limit {
+    capacity: u32,
+    period: u32,
+}
+
+impl Limit {
+    /// Tokens per millisecond
+    fn rate(&self) -> f64 {
+        self.capacity as f64 / (self.period as f64 * 1000.0)
+    }
+}
+
+/// Limits for a group of routes, per client IP and per signed-in account
+struct Policy {
+    group: &'static str,
+    per_ip: Option<Limit>,
+    per_user: Option<Limit>,
+}
+
+/// `RATE_LIMIT_<NAME>` as `requests/seconds`, `off` disables the limit
+fn limit_from_env(name: &str, default: Option<Limit>) -> Option<Limit> {
+    let Ok(value) = std::env::var(format!("RATE_LIMIT_{}", name)) else {
+        return default;
+    };
+    if value.eq_ignore_ascii_case("off") {
+        return None;
+    }
+    let parsed = value.split_once('/').and_then(|(capacity, period)| {
+        Some(Limit {
+            capacity: capacity.trim().parse().ok().filter(|c| *c > 0)?,
+            period: period.trim().parse().ok().filter(|p| *p > 0)?,
+        })
+    });
+    if parsed.is_none() {
+        eprintln!(
+            "WARNING: RATE_LIMIT_{} is not requests/seconds, using the default",
+            name
+        );
+    }
+    parsed.or(default)
+}
+
+/// Sign-in endpoints, the ones worth brute-forcing
+static AUTH_POLICY: LazyLock<Policy> = LazyLock::new(|| Policy {
+    group: "auth",
+    per_ip: limit_from_env(
+        "AUTH",
+        Some(Limit {
+            capacity: 10,
+            period: 60,
+        }),
+    ),
+    per_user: None,
+});
+
+/// Everything else under /api
+static API_POLICY: LazyLock<Policy> = LazyLock::new(|| Policy {
+    group: "api",
+    per_ip: limit_from_env(
+        "API",
+        Some(Limit {
+            capacity: 300,
+            period: 60,
+        }),
+    ),
+    per_user: limit_from_env(
+        "API_USER",
+        Some(Limit {
+            capacity: 300,
+            period: 60,
+        }),
+    ),
+});
+
+fn policy_for(path: &str) -> &'static Policy {
+    let sign_in = [
+        "/api/auth/login",
+        "/api/auth/login/2fa",
+        "/api/auth/register",
+        "/api/auth/admin",
+    ]
+    .contains(&path)
+        || path.starts_with("/api/auth/passkey/")
+        || path.starts_with("/api/auth/verify/");
+    if sign_in { &AUTH_POLICY } else { &API_POLICY }
+}
+
+/// Proxies in front of the service, each appending to X-Forwarded-For
+/// (`TRUSTED_PROXY_HOPS`, 1 by default)
+static TRUSTED_PROXY_HOPS: LazyLock<usize> = LazyLock::new(|| {
+    std::env::var("TRUSTED_PROXY_HOPS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|hops| *hops > 0)
+        .unwrap_or(1)
+});
+
+/// The entry our own proxies added to an X-Forwarded-For value. Entries to
+/// the left of it came from the client and could be anything.
+fn forwarded_ip(value: &str, hops: usize) -> Option<&str> {
+    value
+        .rsplit(',')
+        .nth(hops.checked_sub(1)?)
+        .map(str::trim)
+        .filter(|ip| !ip.is_empty())
+}
+
+/// The client address. Behind reverse proxies (TRUST_PROXY=true) it's the
+/// X-Forwarded-For entry added by the outermost trusted one, otherwise
+/// forwarded headers are ignored so clients can't pick their own bucket.
+pub fn client_ip(headers: &HeaderMap, peer: Option<SocketAddr>) -> String {
+    let trust_proxy = std::env::var("TRUST_PROXY")
+        .map(|v| v == "true")
+        .unwrap_or(false);
+    if trust_proxy
+        && let Some(ip) = headers
+            .get("x-forwarded-for")
+            .and_then(|h| h.to_str().ok())
+            .and_then(|v| forwarded_ip(v, *TRUSTED_PROXY_HOPS))
+    {
+        return ip.to_string();
+    }
+    peer.map(|p| p.ip().to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// State of a bucket after taking a token
+#[derive(Debug, Clone, Copy)]
+struct Outcome {
+    allowed: bool,
+    limit: Limit,
+    tokens: f64,
+}
+
+impl Outcome {
+    /// Seconds until the bucket is full again
+    fn reset(&self) -> u64 {
+        ((self.limit.capacity as f64 - self.tokens) / self.limit.rate() / 1000.0).ceil() as u64
+    }
+
+    /// Seconds until the next token
+    fn retry_after(&self) -> u64 {
+        ((1.0 - self.tokens).max(0.0) / self.limit.rate() / 1000.0)
+            .ceil()
+            .max(1.0) as u64
+    }
+}
+
+async fn take_token(redis: &redis::Client, key: &str, limit: Limit) -> Option<Outcome> {
+    let mut conn = redis.get_multiplexed_async_connection().await.ok()?;
+    let (allowed, tokens): (i64, String) = TAKE_TOKEN
+        .key(key)
+        .arg(limit.capacity)
+        .arg(limit.rate())
+        .arg(chrono::Utc::now().timestamp_millis())
+        .invoke_async(&mut conn)
+        .await
+        .ok()?;
+    Some(Outcome {
+        allowed: allowed == 1,
+        limit,
+        tokens: tokens.parse().unwrap_or(0.0),
+    })
+}
+
+fn set_headers(headers: &mut HeaderMap, outcome: &Outcome) {
+    let values = [
+        ("ratelimit-limit", outcome.limit.capacity as u64),
+        ("ratelimit-remaining", outcome.tokens.floor() as u64),
+        ("ratelimit-reset", outcome.reset()),
+    ];
+    for (name, value) in values {
+        headers.insert(name, HeaderValue::from(value));
+    }
+}
+
+fn limited(outcome: &Outcome) -> Response {
+    let mut response = (
+        StatusCode::TOO_MANY_REQUESTS,
+        Json(create_secure_response(
+            response_codes::RATE_LIMITED,
+            false,
+            None,
+        )),
+    )
+        .into_response();
+    let headers = response.headers_mut();
+    set_headers(headers, outcome);
+    headers.insert("retry-after", HeaderValue::from(outcome.retry_after()));
+    response
+}
+
+/// Whether `outcome` is closer to running out than `current`
+fn is_tighter(current: Option<&Outcome>, outcome: &Outcome) -> bool {
+    current.is_none_or(|t| {
+        (t.allowed && !outcome.allowed)
+            || (t.allowed == outcome.allowed && outcome.tokens < t.tokens)
+    })
+}
+
+/// Per-IP rate limiting. Runs before authentication, so requests with bad
+/// tokens or API keys are throttled too and never reach the auth lookups.
+/// When Redis is unreachable requests go through rather than locking
+/// everyone out.
+pub async fn ip_rate_limit_middleware(
+    State(state): State<Arc<AppState>>,
+    mut request: Request<Body>,
+    next: Next,
+) -> Response {
+    let path = request.uri().path();
+    if !path.starts_with("/api/") {
+        return next.run(request).await;
+    }
+
+    let policy = policy_for(path);
+    let Some(limit) = policy.per_ip else {
+        return next.run(request).await;
+    };
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|c| c.0);
+    let ip = client_ip(request.headers(), peer);
+    let key = format!("ratelimit:{}:ip:{}", policy.group, ip);
+    let Some(outcome) = take_token(&state.redis, &key, limit).await else {
+        return next.run(request).await;
+    };
+    if !outcome.allowed {
+        return limited(&outcome);
+    }
+
+    // The per-account limiter reports whichever bucket is tighter
+    request.extensions_mut().insert(outcome);
+    let mut response = next.run(request).await;
+    if !response.headers().contains_key("ratelimit-limit") {
+        set_headers(response.headers_mut(), &outcome);
+    }
+    response
+}
+
+/// Per-account rate limiting. Runs after authentication, so signed-in
+/// callers get a bucket of their own on top of their IP's.
+pub async fn user_rate_limit_middleware(
+    State(state): State<Arc<AppState>>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let path = request.uri().path();
+    if !path.starts_with("/api/") {
+        return next.run(request).await;
+    }
+
+    let policy = policy_for(path);
+    let user = request
+        .extensions()
+        .get::<AdminSession>()
+        .map(|s| format!("admin:{}", s.user_id))
+        .or_else(|| request.extensions().get::<Claims>().map(|c| c.sub.clone()));
+
+    // The bucket closest to running out is the one reported
+    let mut tightest = request.extensions().get::<Outcome>().copied();
+    if let (Some(limit), Some(user)) = (policy.per_user, user) {
+        let key = format!("ratelimit:{}:user:{}", policy.group, user);
+        if let Some(outcome) = take_token(&state.redis, &key, limit).await
+            && is_tighter(tightest.as_ref(), &outcome)
+        {
+            tightest = Some(outcome);
+        }
+    }
+
+    let Some(outcome) = tightest else {
+        return next.run(request).await;
+    };
+    if !outcome.allowed {
+        return limited(&outcome);
+    }
+
+    let mut response = next.run(request).await;
+    set_headers(response.headers_mut(), &outcome);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forwarded_ip_counts_hops_from_the_right() {
+        let value = "6.6.6.6, 203.0.113.7, 10.0.0.2";
+        assert_eq!(forwarded_ip(value, 1), Some("10.0.0.2"));
+        assert_eq!(forwarded_ip(value, 2), Some("203.0.113.7"));
+        assert_eq!(forwarded_ip(value, 4), None);
+        assert_eq!(forwarded_ip(value, 0), None);
+        assert_eq!(forwarded_ip("198.51.100.1", 1), Some("198.51.100.1"));
+        assert_eq!(forwarded_ip("1.2.3.4, ", 1), None);
+    }
+
+    #[test]
+    fn bucket_timing_follows_the_refill_rate() {
+        let limit = Limit {
+            capacity: 10,
+            period: 60,
+        };
+        assert!((limit.rate() - 10.0 / 60_000.0).abs() < f64::EPSILON);
+
+        let empty = Outcome {
+            allowed: false,
+            limit,
+            tokens: 0.0,
+        };
+        assert_eq!(empty.retry_after(), 6);
+        assert_eq!(empty.reset(), 60);
+
+        let partial = Outcome {
+            allowed: true,
+            limit,
+            tokens: 4.5,
+        };
+        assert_eq!(partial.retry_after(), 1);
+        assert_eq!(partial.reset(), 33);
+    }
+
+    #[test]
+    fn the_bucket_closest_to_running_out_is_reported() {
+        let limit = Limit {
+            capacity: 10,
+            period: 60,
+        };
+        let outcome = |allowed, tokens| Outcome {
+            allowed,
+            limit,
+            tokens,
+        };
+        assert!(is_tighter(None, &outcome(true, 9.0)));
+        assert!(is_tighter(Some(&outcome(true, 5.0)), &outcome(true, 2.0)));
+        assert!(!is_tighter(Some(&outcome(true, 2.0)), &outcome(true, 5.0)));
+        assert!(is_tighter(Some(&outcome(true, 0.5)), &outcome(false, 0.9)));
+        assert!(!is_tighter(Some(&outcome(false, 0.9)), &outcome(true, 0.1)));
+    }
+
+    #[test]
+    fn sign_in_routes_get_the_auth_policy() {
+        assert_eq!(policy_for("/api/auth/login").group, "auth");
+        assert_eq!(policy_for("/api/auth/passkey/login/start").group, "auth");
+        assert_eq!(policy_for("/api/auth/logout").group, "api");
+        assert_eq!(policy_for("/api/instances").group, "api");
+    }
+}
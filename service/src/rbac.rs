@@ -0,0 +1,170 @@
+use crate::AppState;
+use crate::security::{AdminSession, Claims, cookie_value, create_secure_response, response_codes};
+use axum::{
+    Json,
+    body::Body,
+    http::{Request, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::MethodRouter,
+};
+use std::sync::Arc;
+
+/// Staff roles. Regular users have none.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// Everything, including managing other staff
+    Owner,
+    /// Everything but staff management
+    Admin,
+    /// Read-only view of users and instances, handles tickets
+    Support,
+    /// Read-only view of users, manages plans and billing
+    Billing,
+}
+
+/// What a route needs from staff
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    UsersRead,
+    UsersManage,
+    UsersDelete,
+    BillingRead,
+    BillingManage,
+    InstancesRead,
+    InstancesManage,
+    SupportRead,
+    SupportManage,
+    SystemRead,
+    StaffManage,
+}
+
+impl Role {
+    pub fn parse(role: &str) -> Option<Self> {
+        match role {
+            "owner" => Some(Self::Owner),
+            "admin" => Some(Self::Admin),
+            "support" => Some(Self::Support),
+            "billing" => Some(Self::Billing),
+            _ => None,
+        }
+    }
+
+    /// Role of a staff account. Admins from before roles existed have no
+    /// role stored and keep full admin rights.
+    pub fn of(stored: Option<&str>) -> Self {
+        stored.and_then(Self::parse).unwrap_or(Self::Admin)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Owner => "owner",
+            Self::Admin => "admin",
+            Self::Support => "support",
+            Self::Billing => "billing",
+        }
+    }
+
+    pub fn grants(self, permission: Permission) -> bool {
+        use Permission::*;
+        match self {
+            Self::Owner => true,
+            Self::Admin => permission != StaffManage,
+            Self::Support => matches!(
+                permission,
+                UsersRead | InstancesRead | SupportRead | SupportManage
+            ),
+            Self::Billing => matches!(permission, UsersRead | BillingRead | BillingManage),
+        }
+    }
+
+    pub fn permissions(self) -> Vec<&'static str> {
+        use Permission::*;
+        [
+            UsersRead,
+            UsersManage,
+            UsersDelete,
+            BillingRead,
+            BillingManage,
+            InstancesRead,
+            InstancesManage,
+            SupportRead,
+            SupportManage,
+            SystemRead,
+            StaffManage,
+        ]
+        .into_iter()
+        .filter(|p| self.grants(*p))
+        .map(Permission::as_str)
+        .collect()
+    }
+}
+
+impl Permission {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::UsersRead => "users:read",
+            Self::UsersManage => "users:manage",
+            Self::UsersDelete => "users:delete",
+            Self::BillingRead => "billing:read",
+            Self::BillingManage => "billing:manage",
+            Self::InstancesRead => "instances:read",
+            Self::InstancesManage => "instances:manage",
+            Self::SupportRead => "support:read",
+            Self::SupportManage => "support:manage",
+            Self::SystemRead => "system:read",
+            Self::StaffManage => "staff:manage",
+        }
+    }
+}
+
+/// Declares the permission a route needs
+pub trait Guarded {
+    /// Staff only
+    fn requires(self, permission: Permission) -> Self;
+    /// Staff with the permission, or any signed-in user. The handler scopes
+    /// users to their own instances.
+    fn requires_or_user(self, permission: Permission) -> Self;
+}
+
+impl Guarded for MethodRouter<Arc<AppState>> {
+    fn requires(self, permission: Permission) -> Self {
+        self.route_layer(middleware::from_fn(move |req, next| {
+            authorize(permission, false, req, next)
+        }))
+    }
+
+    fn requires_or_user(self, permission: Permission) -> Self {
+        self.route_layer(middleware::from_fn(move |req, next| {
+            authorize(permission, true, req, next)
+        }))
+    }
+}
+
+async fn authorize(
+    permission: Permission,
+    allow_users: bool,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let session = request.extensions().get::<AdminSession>();
+    let claims = request.extensions().get::<Claims>();
+
+    let allowed = session.is_some_and(|s| s.role.grants(permission))
+        || claims.is_some_and(|c| {
+            // Admin tokens are only issued to owners and admins
+            (c.role == "admin" && Role::Admin.grants(permission)) || allow_users
+        });
+    if allowed {
+        return next.run(request).await;
+    }
+
+    let (status, code) = if session.is_some() || claims.is_some() {
+        (StatusCode::FORBIDDEN, response_codes::ACCESS_DENIED)
+    } else if cookie_value(request.headers(), "admin_session").is_some() {
+        (StatusCode::UNAUTHORIZED, response_codes::TOKEN_EXPIRED)
+    } else {
+        (StatusCode::UNAUTHORIZED, response_codes::ACCESS_DENIED)
+    };
+    (status, Json(create_secure_response(code, false, None))).into_response()
+}
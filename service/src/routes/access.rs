@@ -0,0 +1,108 @@
+use crate::AppState;
+use crate::security::Claims;
+use crate::sql::User;
+use axum::{
+    Json, async_trait,
+    extract::{FromRequestParts, Path},
+    http::{StatusCode, request::Parts},
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Check whether the caller may act on an instance.
+///
+/// Requests on admin routes without claims were let through by an admin
+/// session cookie, so they (and admin tokens) see every instance. Users only
+/// see instances linked to their account.
+pub async fn can_access_instance(
+    state: &Arc<AppState>,
+    claims: Option<&Claims>,
+    phone: &str,
+) -> bool {
+    let claims = match claims {
+        Some(c) if c.role != "admin" => c,
+        _ => return true,
+    };
+
+    sqlx::query_scalar(
+        "SELECT EXISTS(SELECT 1 FROM user_instances ui JOIN users u ON ui.userId = u.id
+         WHERE u.cryptoHash = ? AND ui.sessionId = ?)",
+    )
+    .bind(&claims.sub)
+    .bind(phone)
+    .fetch_one(&state.db)
+    .await
+    .unwrap_or(false)
+}
+
+/// The account a user route acts for, resolved from the verified token.
+///
+/// The crypto hash in `/api/user/:crypto_hash/...` only names the account, it
+/// grants nothing: it has to match the token's subject. Admin tokens may act
+/// for any account.
+pub struct CurrentUser {
+    pub user: User,
+}
+
+impl CurrentUser {
+    /// Whether the user owns an instance
+    pub async fn owns_instance(&self, state: &Arc<AppState>, session_id: &str) -> bool {
+        sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM user_instances WHERE userId = ? AND sessionId = ?)",
+        )
+        .bind(&self.user.id)
+        .bind(session_id)
+        .fetch_one(&state.db)
+        .await
+        .unwrap_or(false)
+    }
+}
+
+fn reject(status: StatusCode, message: &str) -> (StatusCode, Json<serde_json::Value>) {
+    (
+        status,
+        Json(serde_json::json!({
+            "success": false,
+            "message": message
+        })),
+    )
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for CurrentUser {
+    type Rejection = (StatusCode, Json<serde_json::Value>);
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let Some(claims) = parts.extensions.get::<Claims>().cloned() else {
+            return Err(reject(StatusCode::UNAUTHORIZED, "Sign in required"));
+        };
+
+        let named = Path::<HashMap<String, String>>::from_request_parts(parts, state)
+            .await
+            .ok()
+            .and_then(|Path(params)| params.get("crypto_hash").cloned());
+        let crypto_hash = match named {
+            Some(hash) if hash != claims.sub && claims.role != "admin" => {
+                return Err(reject(
+                    StatusCode::FORBIDDEN,
+                    "You can only access your own account",
+                ));
+            }
+            Some(hash) => hash,
+            None => claims.sub,
+        };
+
+        let user: Option<User> = sqlx::query_as("SELECT * FROM users WHERE cryptoHash = ?")
+            .bind(&crypto_hash)
+            .fetch_optional(&state.db)
+            .await
+            .unwrap_or(None);
+        match user {
+            Some(user) => Ok(CurrentUser { user }),
+            None => Err(reject(StatusCode::NOT_FOUND, "User not found")),
+        }
+    }
+}
@@ -1,7 +1,10 @@
 use crate::AppState;
+use crate::manager::outbound;
+use crate::rbac::{Permission, Role};
+use crate::security::{self, AdminSession};
 use crate::sql::{SupportRequest, User};
 use axum::{
-    Json,
+    Extension, Json,
     extract::{Path, State},
     http::StatusCode,
 };
@@ -16,6 +19,8 @@ pub struct UserWithStats {
     pub phone_number: String,
     pub credits: f64,
     pub suspended: bool,
+    /// Staff role, none for regular users
+    pub role: Option<&'static str>,
     #[serde(rename = "instanceLimit")]
     pub instance_limit: i32,
     #[serde(rename = "createdAt")]
@@ -60,6 +65,9 @@ pub async fn list_users(
             phone_number: user.phone_number,
             credits: user.credits,
             suspended: user.suspended,
+            role: user
+                .is_admin
+                .then(|| Role::of(user.role.as_deref()).as_str()),
             instance_limit: user.instance_limit,
             created_at: user.created_at,
             instance_count,
@@ -180,6 +188,35 @@ pub async fn get_user_billing(
     )
 }
 
+/// Whether the target is a staff account the caller may not act on. Only
+/// staff managers (owners) suspend or delete staff, so admins can't lock an
+/// owner out. Admin tokens act as the admin role.
+async fn is_protected_staff(
+    state: &Arc<AppState>,
+    admin: Option<&Extension<AdminSession>>,
+    user_id: &str,
+) -> bool {
+    let role = admin.map_or(Role::Admin, |Extension(a)| a.role);
+    if role.grants(Permission::StaffManage) {
+        return false;
+    }
+    sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM users WHERE id = ? AND isAdmin = TRUE)")
+        .bind(user_id)
+        .fetch_one(&state.db)
+        .await
+        .unwrap_or(true)
+}
+
+fn staff_protected() -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::FORBIDDEN,
+        Json(serde_json::json!({
+            "success": false,
+            "message": "Only owners can change staff accounts"
+        })),
+    )
+}
+
 #[derive(Debug, Deserialize)]
 pub struct SuspendUserRequest {
     pub suspended: bool,
@@ -188,9 +225,27 @@ pub struct SuspendUserRequest {
 /// Suspend or unsuspend a user (admin only)
 pub async fn suspend_user(
     State(state): State<Arc<AppState>>,
+    admin: Option<Extension<AdminSession>>,
     Path(user_id): Path<String>,
     Json(payload): Json<SuspendUserRequest>,
 ) -> (StatusCode, Json<serde_json::Value>) {
+    if payload.suspended
+        && admin
+            .as_ref()
+            .is_some_and(|Extension(a)| a.user_id == user_id)
+    {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "success": false,
+                "message": "You can't suspend your own account"
+            })),
+        );
+    }
+    if is_protected_staff(&state, admin.as_ref(), &user_id).await {
+        return staff_protected();
+    }
+
     let result = sqlx::query("UPDATE users SET suspended = ?, updatedAt = ? WHERE id = ?")
         .bind(payload.suspended)
         .bind(chrono::Utc::now())
@@ -199,13 +254,18 @@ pub async fn suspend_user(
         .await;
 
     match result {
-        Ok(r) if r.rows_affected() > 0 => (
-            StatusCode::OK,
-            Json(serde_json::json!({
-                "success": true,
-                "message": if payload.suspended { "User suspended" } else { "User unsuspended" }
-            })),
-        ),
+        Ok(r) if r.rows_affected() > 0 => {
+            if payload.suspended {
+                security::revoke_admin_sessions(&state.redis, &user_id).await;
+            }
+            (
+                StatusCode::OK,
+                Json(serde_json::json!({
+                    "success": true,
+                    "message": if payload.suspended { "User suspended" } else { "User unsuspended" }
+                })),
+            )
+        }
         Ok(_) => (
             StatusCode::NOT_FOUND,
             Json(serde_json::json!({
@@ -269,8 +329,26 @@ pub async fn set_user_limit(
 /// Delete a user account (admin only)
 pub async fn delete_user(
     State(state): State<Arc<AppState>>,
+    admin: Option<Extension<AdminSession>>,
     Path(user_id): Path<String>,
 ) -> (StatusCode, Json<serde_json::Value>) {
+    // Keep admins from locking themselves out
+    if admin
+        .as_ref()
+        .is_some_and(|Extension(a)| a.user_id == user_id)
+    {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "success": false,
+                "message": "You can't delete your own account"
+            })),
+        );
+    }
+    if is_protected_staff(&state, admin.as_ref(), &user_id).await {
+        return staff_protected();
+    }
+
     // First delete user's instances
     let _ = sqlx::query(
         "DELETE FROM sessions WHERE id IN (SELECT sessionId FROM user_instances WHERE userId = ?)",
@@ -286,13 +364,16 @@ pub async fn delete_user(
         .await;
 
     match result {
-        Ok(r) if r.rows_affected() > 0 => (
-            StatusCode::OK,
-            Json(serde_json::json!({
-                "success": true,
-                "message": "User deleted successfully"
-            })),
-        ),
+        Ok(r) if r.rows_affected() > 0 => {
+            security::revoke_admin_sessions(&state.redis, &user_id).await;
+            (
+                StatusCode::OK,
+                Json(serde_json::json!({
+                    "success": true,
+                    "message": "User deleted successfully"
+                })),
+            )
+        }
         Ok(_) => (
             StatusCode::NOT_FOUND,
             Json(serde_json::json!({
@@ -466,3 +547,274 @@ pub async fn update_support_request(
         ),
     }
 }
+
+#[derive(Debug, Deserialize)]
+pub struct SetPlanRequest {
+    pub plan: String,
+}
+
+/// Change a user's billing plan (admin only)
+pub async fn set_user_plan(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<String>,
+    Json(payload): Json<SetPlanRequest>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if outbound::plan_limits(&payload.plan).is_none() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "success": false,
+                "message": format!("Unknown plan: {}", payload.plan),
+                "plans": outbound::PLANS.iter().map(|(name, _)| *name).collect::<Vec<_>>()
+            })),
+        );
+    }
+
+    let result = sqlx::query("UPDATE users SET plan = ?, updatedAt = ? WHERE id = ?")
+        .bind(&payload.plan)
+        .bind(chrono::Utc::now())
+        .bind(&user_id)
+        .execute(&state.db)
+        .await;
+
+    match result {
+        Ok(r) if r.rows_affected() > 0 => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "success": true,
+                "message": format!("Plan set to {}", payload.plan)
+            })),
+        ),
+        Ok(_) => (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({
+                "success": false,
+                "message": "User not found"
+            })),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({
+                "success": false,
+                "message": format!("Failed to update user: {}", e)
+            })),
+        ),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetRoleRequest {
+    /// Staff role, or none to make the account a regular user again
+    pub role: Option<String>,
+}
+
+/// Grant, change or remove a staff role (owners only)
+pub async fn set_user_role(
+    State(state): State<Arc<AppState>>,
+    admin: Option<Extension<AdminSession>>,
+    Path(user_id): Path<String>,
+    Json(payload): Json<SetRoleRequest>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let role = match payload.role.as_deref() {
+        None | Some("") => None,
+        Some(r) => match Role::parse(r) {
+            Some(role) => Some(role),
+            None => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(serde_json::json!({
+                        "success": false,
+                        "message": format!("Unknown role: {}", r),
+                        "roles": ["owner", "admin", "support", "billing"]
+                    })),
+                );
+            }
+        },
+    };
+
+    // An owner demoting themselves could leave nobody able to manage staff
+    if admin.is_some_and(|Extension(a)| a.user_id == user_id) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "success": false,
+                "message": "You can't change your own role"
+            })),
+        );
+    }
+
+    let result = sqlx::query("UPDATE users SET isAdmin = ?, role = ?, updatedAt = ? WHERE id = ?")
+        .bind(role.is_some())
+        .bind(role.map(Role::as_str))
+        .bind(chrono::Utc::now())
+        .bind(&user_id)
+        .execute(&state.db)
+        .await;
+
+    match result {
+        Ok(r) if r.rows_affected() > 0 => {
+            // Sessions pick up the new role on their next request, only
+            // removing staff access ends them
+            if role.is_none() {
+                security::revoke_admin_sessions(&state.redis, &user_id).await;
+            }
+            (
+                StatusCode::OK,
+                Json(serde_json::json!({
+                    "success": true,
+                    "message": match role {
+                        Some(role) => format!("Role set to {}", role.as_str()),
+                        None => "Staff access removed".to_string(),
+                    }
+                })),
+            )
+        }
+        Ok(_) => (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({
+                "success": false,
+                "message": "User not found"
+            })),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({
+                "success": false,
+                "message": format!("Failed to update user: {}", e)
+            })),
+        ),
+    }
+}
+
+/// Get the outbound limits and current usage of an instance (admin only)
+pub async fn get_instance_limits(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let policy = outbound::policy(&state.db, &phone).await;
+    let (sent_minute, sent_today) = outbound::usage(&state, &phone).await;
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "success": true,
+            "policy": policy,
+            "usage": {
+                "minute": sent_minute,
+                "today": sent_today
+            }
+        })),
+    )
+}
+
+/// Outbound limit override, omitted fields fall back to the owner's plan
+#[derive(Debug, Deserialize)]
+pub struct InstanceLimitsRequest {
+    #[serde(rename = "perMinute")]
+    pub per_minute: Option<i64>,
+    #[serde(rename = "perDay")]
+    pub per_day: Option<i64>,
+    #[serde(rename = "warmupDays")]
+    pub warmup_days: Option<i64>,
+    #[serde(rename = "minDelayMs")]
+    pub min_delay_ms: Option<i64>,
+    #[serde(rename = "maxDelayMs")]
+    pub max_delay_ms: Option<i64>,
+}
+
+/// Override the outbound limits of an instance (admin only)
+pub async fn set_instance_limits(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+    Json(payload): Json<InstanceLimitsRequest>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let values = [
+        payload.per_minute,
+        payload.per_day,
+        payload.warmup_days,
+        payload.min_delay_ms,
+        payload.max_delay_ms,
+    ];
+    if values.iter().flatten().any(|v| *v < 0) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "success": false,
+                "message": "Limits must not be negative"
+            })),
+        );
+    }
+
+    let result = sqlx::query(
+        "INSERT INTO outbound_limits (sessionId, perMinute, perDay, warmupDays, minDelayMs, maxDelayMs, updatedAt)
+         VALUES (?, ?, ?, ?, ?, ?, ?)
+         ON CONFLICT(sessionId) DO UPDATE SET
+            perMinute = excluded.perMinute,
+            perDay = excluded.perDay,
+            warmupDays = excluded.warmupDays,
+            minDelayMs = excluded.minDelayMs,
+            maxDelayMs = excluded.maxDelayMs,
+            updatedAt = excluded.updatedAt",
+    )
+    .bind(&phone)
+    .bind(payload.per_minute)
+    .bind(payload.per_day)
+    .bind(payload.warmup_days)
+    .bind(payload.min_delay_ms)
+    .bind(payload.max_delay_ms)
+    .bind(chrono::Utc::now())
+    .execute(&state.db)
+    .await;
+
+    match result {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "success": true,
+                "policy": outbound::policy(&state.db, &phone).await
+            })),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({
+                "success": false,
+                "message": format!("Failed to update limits: {}", e)
+            })),
+        ),
+    }
+}
+
+/// Remove an instance's outbound override (admin only)
+pub async fn clear_instance_limits(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let result = sqlx::query("DELETE FROM outbound_limits WHERE sessionId = ?")
+        .bind(&phone)
+        .execute(&state.db)
+        .await;
+
+    match result {
+        Ok(r) if r.rows_affected() > 0 => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "success": true,
+                "message": "Override removed"
+            })),
+        ),
+        Ok(_) => (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({
+                "success": false,
+                "message": "No override set for this instance"
+            })),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({
+                "success": false,
+                "message": format!("Failed to remove override: {}", e)
+            })),
+        ),
+    }
+}
@@ -0,0 +1,231 @@
+use crate::AppState;
+use crate::logger;
+use crate::manager::api_keys;
+use crate::routes::access::CurrentUser;
+use crate::sql::ApiKey;
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// Keys an account can hold at once
+const MAX_KEYS_PER_USER: i64 = 25;
+const MAX_NAME_LEN: usize = 64;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateApiKeyRequest {
+    pub name: String,
+    /// Any of `instances:read`, `messages:send` and `settings:manage`
+    pub scopes: Vec<String>,
+    /// Limit the key to these instances, all owned instances otherwise
+    pub instances: Option<Vec<String>>,
+    /// Addresses or CIDR ranges the key may be used from, anywhere otherwise
+    #[serde(rename = "allowedIps")]
+    pub allowed_ips: Option<Vec<String>>,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+type ApiResponse = (StatusCode, Json<serde_json::Value>);
+
+fn error(status: StatusCode, message: &str) -> ApiResponse {
+    (
+        status,
+        Json(serde_json::json!({
+            "success": false,
+            "message": message
+        })),
+    )
+}
+
+/// Empty lists mean no restriction, like leaving them out
+fn to_json_list(values: Option<Vec<String>>) -> Option<String> {
+    values
+        .filter(|v| !v.is_empty())
+        .map(|v| serde_json::to_string(&v).unwrap_or_else(|_| "[]".to_string()))
+}
+
+/// List the user's API keys
+pub async fn list_api_keys(
+    State(state): State<Arc<AppState>>,
+    CurrentUser { user }: CurrentUser,
+) -> ApiResponse {
+    let keys: Vec<ApiKey> =
+        sqlx::query_as("SELECT * FROM api_keys WHERE userId = ? ORDER BY createdAt DESC")
+            .bind(&user.id)
+            .fetch_all(&state.db)
+            .await
+            .unwrap_or_default();
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "success": true,
+            "keys": keys,
+            "scopes": api_keys::SCOPES
+        })),
+    )
+}
+
+/// Create an API key. The key itself is only returned here.
+pub async fn create_api_key(
+    State(state): State<Arc<AppState>>,
+    CurrentUser { user }: CurrentUser,
+    Json(payload): Json<CreateApiKeyRequest>,
+) -> ApiResponse {
+    let name = payload.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+        return error(
+            StatusCode::BAD_REQUEST,
+            &format!("Name must be 1 to {} characters", MAX_NAME_LEN),
+        );
+    }
+    if payload.scopes.is_empty() {
+        return error(StatusCode::BAD_REQUEST, "Pick at least one scope");
+    }
+    if let Some(bad) = payload
+        .scopes
+        .iter()
+        .find(|s| !api_keys::SCOPES.contains(&s.as_str()))
+    {
+        return error(
+            StatusCode::BAD_REQUEST,
+            &format!(
+                "Unknown scope '{}', expected one of: {}",
+                bad,
+                api_keys::SCOPES.join(", ")
+            ),
+        );
+    }
+    if let Some(bad) = payload
+        .allowed_ips
+        .iter()
+        .flatten()
+        .find(|ip| !api_keys::is_valid_ip_rule(ip))
+    {
+        return error(
+            StatusCode::BAD_REQUEST,
+            &format!("'{}' is not an IP address or CIDR range", bad),
+        );
+    }
+    if payload.expires_at.is_some_and(|e| e <= Utc::now()) {
+        return error(StatusCode::BAD_REQUEST, "Expiry must be in the future");
+    }
+
+    for instance in payload.instances.iter().flatten() {
+        let owned: bool = sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM user_instances WHERE userId = ? AND sessionId = ?)",
+        )
+        .bind(&user.id)
+        .bind(instance)
+        .fetch_one(&state.db)
+        .await
+        .unwrap_or(false);
+        if !owned {
+            return error(
+                StatusCode::FORBIDDEN,
+                &format!("You don't have access to instance {}", instance),
+            );
+        }
+    }
+
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM api_keys WHERE userId = ?")
+        .bind(&user.id)
+        .fetch_one(&state.db)
+        .await
+        .unwrap_or(0);
+    if count >= MAX_KEYS_PER_USER {
+        return error(
+            StatusCode::CONFLICT,
+            &format!(
+                "An account can have up to {} API keys, revoke one first",
+                MAX_KEYS_PER_USER
+            ),
+        );
+    }
+
+    let id = hex::encode(rand::random::<[u8; 16]>());
+    let new_key = api_keys::generate_key();
+    let mut scopes = payload.scopes;
+    scopes.sort();
+    scopes.dedup();
+    let result = sqlx::query(
+        "INSERT INTO api_keys (id, userId, name, keyPrefix, keyHash, scopes, instances, allowedIps, expiresAt, createdAt)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&id)
+    .bind(&user.id)
+    .bind(name)
+    .bind(&new_key.prefix)
+    .bind(&new_key.hash)
+    .bind(serde_json::to_string(&scopes).unwrap_or_else(|_| "[]".to_string()))
+    .bind(to_json_list(payload.instances))
+    .bind(to_json_list(payload.allowed_ips))
+    .bind(payload.expires_at)
+    .bind(Utc::now())
+    .execute(&state.db)
+    .await;
+
+    if let Err(e) = result {
+        return error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to create API key: {}", e),
+        );
+    }
+    logger::info(
+        "AUTH",
+        &format!("{} created API key '{}'", user.phone_number, name),
+    );
+
+    let created: Option<ApiKey> = sqlx::query_as("SELECT * FROM api_keys WHERE id = ?")
+        .bind(&id)
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None);
+    (
+        StatusCode::CREATED,
+        Json(serde_json::json!({
+            "success": true,
+            "apiKey": created,
+            "key": new_key.key
+        })),
+    )
+}
+
+/// Revoke an API key, it stops working right away
+pub async fn revoke_api_key(
+    State(state): State<Arc<AppState>>,
+    CurrentUser { user }: CurrentUser,
+    Path((_, key_id)): Path<(String, String)>,
+) -> ApiResponse {
+    let result = sqlx::query("DELETE FROM api_keys WHERE id = ? AND userId = ?")
+        .bind(&key_id)
+        .bind(&user.id)
+        .execute(&state.db)
+        .await;
+
+    match result {
+        Ok(r) if r.rows_affected() > 0 => {
+            logger::info(
+                "AUTH",
+                &format!("{} revoked API key {}", user.phone_number, key_id),
+            );
+            (
+                StatusCode::OK,
+                Json(serde_json::json!({
+                    "success": true,
+                    "message": "API key revoked"
+                })),
+            )
+        }
+        Ok(_) => error(StatusCode::NOT_FOUND, "API key not found"),
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to revoke API key: {}", e),
+        ),
+    }
+}
@@ -1,15 +1,23 @@
 use crate::AppState;
-use crate::security::{create_auth_cookie, generate_token_pair, response_codes, sign_response};
+use crate::logger;
+use crate::manager::logins::{self, LoginContext};
+use crate::rbac::Role;
+use crate::routes::two_factor;
+use crate::security::{
+    self, Claims, TokenPair, create_auth_cookie, create_logout_cookie, create_secure_response,
+    generate_token_pair, response_codes, sign_response,
+};
 use crate::sql::{
-    LoginRequest, PasskeyCredential, PasskeyLoginRequest, PasskeyRegisterRequest, RegisterRequest,
-    SecureAuthResponse, TokenResponse, User,
+    LoginRequest, PasskeyCredential, PasskeyLoginRequest, PasskeyRegisterRequest, RefreshRequest,
+    RegisterRequest, SecureAuthResponse, TokenResponse, TwoFactorLoginRequest, User,
 };
+use crate::webauthn;
 use argon2::{
     Argon2,
     password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString, rand_core::OsRng},
 };
 use axum::{
-    Json,
+    Extension, Json,
     extract::State,
     http::{StatusCode, header},
     response::IntoResponse,
@@ -23,7 +31,7 @@ fn get_rp_id() -> String {
 }
 
 /// Get the WebAuthn relying party name from environment or use default
-fn get_rp_name() -> String {
+pub fn get_rp_name() -> String {
     std::env::var("WEBAUTHN_RP_NAME").unwrap_or_else(|_| "Whatsaly".to_string())
 }
 
@@ -51,7 +59,7 @@ fn hash_password(password: &str) -> Result<(String, String), argon2::password_ha
 }
 
 /// Verify password against stored hash
-fn verify_password(password: &str, password_hash: &str) -> bool {
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
     let parsed_hash = match PasswordHash::new(password_hash) {
         Ok(h) => h,
         Err(_) => return false,
@@ -67,31 +75,52 @@ fn generate_user_id() -> String {
     hex::encode(random_bytes)
 }
 
-/// Create a secure auth response with JWT tokens
+/// Create a secure auth response without tokens
 fn create_secure_auth_response(
     code: u32,
     success: bool,
     user: Option<&User>,
     crypto_hash: Option<&str>,
-    include_tokens: bool,
 ) -> SecureAuthResponse {
-    let timestamp = chrono::Utc::now().timestamp();
+    secure_auth_response_with_tokens(code, success, user, crypto_hash, None)
+}
 
-    // Generate tokens if needed
-    let tokens = if include_tokens && success {
-        user.and_then(|u| {
-            let role = if u.is_admin { "admin" } else { "user" };
-            generate_token_pair(&u.crypto_hash, role)
-                .ok()
-                .map(|tp| TokenResponse {
-                    access_token: tp.access_token,
-                    refresh_token: tp.refresh_token,
-                    expires_in: tp.expires_in,
-                })
-        })
+/// Tokens for a new session of `user`
+async fn issue_tokens(state: &Arc<AppState>, user: &User) -> Option<TokenPair> {
+    generate_token_pair(&user.crypto_hash, token_role(state, user).await).ok()
+}
+
+/// The role `user`'s tokens carry under the current admin 2FA policy
+async fn token_role(state: &Arc<AppState>, user: &User) -> &'static str {
+    user_role(user, two_factor::admin_2fa_required(&state.db).await)
+}
+
+fn user_role(user: &User, admin_2fa_required: bool) -> &'static str {
+    // Tokens only carry full admin rights, narrower staff roles work through
+    // admin sessions. Admins without the second factor the policy asks for
+    // get a plain user token.
+    let full_admin = matches!(Role::of(user.role.as_deref()), Role::Owner | Role::Admin);
+    if user.is_admin && full_admin && (user.totp_enabled || !admin_2fa_required) {
+        "admin"
     } else {
-        None
-    };
+        "user"
+    }
+}
+
+/// Create a secure auth response carrying already issued tokens
+fn secure_auth_response_with_tokens(
+    code: u32,
+    success: bool,
+    user: Option<&User>,
+    crypto_hash: Option<&str>,
+    tokens: Option<TokenPair>,
+) -> SecureAuthResponse {
+    let timestamp = chrono::Utc::now().timestamp();
+    let tokens = tokens.map(|tp| TokenResponse {
+        access_token: tp.access_token,
+        refresh_token: tp.refresh_token,
+        expires_in: tp.expires_in,
+    });
 
     // Encode user data as base64
     let encoded_data = user.map(|u| {
@@ -127,23 +156,43 @@ fn create_secure_auth_response(
     }
 }
 
+/// Auth response with the access token also set as a cookie
+fn signed_in(status: StatusCode, response: SecureAuthResponse) -> axum::response::Response {
+    let is_production = std::env::var("PRODUCTION").unwrap_or_default() == "true";
+    if let Some(ref tokens) = response.tokens {
+        let mut headers = axum::http::HeaderMap::new();
+        for cookie in [
+            create_auth_cookie(&tokens.access_token, is_production),
+            security::create_refresh_cookie(&tokens.refresh_token, is_production),
+        ] {
+            if let Ok(cookie_value) = cookie.parse() {
+                headers.append(header::SET_COOKIE, cookie_value);
+            }
+        }
+        return (status, headers, Json(response)).into_response();
+    }
+
+    (status, Json(response)).into_response()
+}
+
 /// Register a new user
 pub async fn register(
     State(state): State<Arc<AppState>>,
+    context: LoginContext,
     Json(payload): Json<RegisterRequest>,
-) -> impl IntoResponse {
+) -> axum::response::Response {
     // Validate phone number
     if payload.phone_number.is_empty() || payload.phone_number.len() < 10 {
         let response =
-            create_secure_auth_response(response_codes::VALIDATION_ERROR, false, None, None, false);
-        return (StatusCode::BAD_REQUEST, Json(response));
+            create_secure_auth_response(response_codes::VALIDATION_ERROR, false, None, None);
+        return (StatusCode::BAD_REQUEST, Json(response)).into_response();
     }
 
     // Validate password
     if payload.password.len() < 6 {
         let response =
-            create_secure_auth_response(response_codes::VALIDATION_ERROR, false, None, None, false);
-        return (StatusCode::BAD_REQUEST, Json(response));
+            create_secure_auth_response(response_codes::VALIDATION_ERROR, false, None, None);
+        return (StatusCode::BAD_REQUEST, Json(response)).into_response();
     }
 
     // Check if user already exists
@@ -154,9 +203,8 @@ pub async fn register(
         .unwrap_or(None);
 
     if existing.is_some() {
-        let response =
-            create_secure_auth_response(response_codes::USER_EXISTS, false, None, None, false);
-        return (StatusCode::CONFLICT, Json(response));
+        let response = create_secure_auth_response(response_codes::USER_EXISTS, false, None, None);
+        return (StatusCode::CONFLICT, Json(response)).into_response();
     }
 
     // Generate unique crypto hash for this user
@@ -166,14 +214,9 @@ pub async fn register(
     let (password_hash, password_salt) = match hash_password(&payload.password) {
         Ok(result) => result,
         Err(_) => {
-            let response = create_secure_auth_response(
-                response_codes::INTERNAL_ERROR,
-                false,
-                None,
-                None,
-                false,
-            );
-            return (StatusCode::INTERNAL_SERVER_ERROR, Json(response));
+            let response =
+                create_secure_auth_response(response_codes::INTERNAL_ERROR, false, None, None);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(response)).into_response();
         }
     };
 
@@ -204,32 +247,54 @@ pub async fn register(
                 .fetch_one(&state.db)
                 .await
                 .unwrap();
+            logins::record(&state, &user, &context, "register", "success").await;
 
-            let response = create_secure_auth_response(
+            let response = secure_auth_response_with_tokens(
                 response_codes::USER_CREATED,
                 true,
                 Some(&user),
                 Some(&crypto_hash),
-                true,
+                issue_tokens(&state, &user).await,
             );
-            (StatusCode::CREATED, Json(response))
+            signed_in(StatusCode::CREATED, response)
         }
         Err(_) => {
-            let response = create_secure_auth_response(
-                response_codes::INTERNAL_ERROR,
-                false,
-                None,
-                None,
-                false,
-            );
-            (StatusCode::INTERNAL_SERVER_ERROR, Json(response))
+            let response =
+                create_secure_auth_response(response_codes::INTERNAL_ERROR, false, None, None);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(response)).into_response()
         }
     }
 }
 
-/// Login with phone number, password, and crypto hash
+/// Turn away an account that is locked or has to wait after failed logins
+fn throttled(retry_after: i64) -> axum::response::Response {
+    let response = create_secure_auth_response(response_codes::RATE_LIMITED, false, None, None);
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, retry_after.to_string())],
+        Json(response),
+    )
+        .into_response()
+}
+
+/// Record a wrong password or code, `begin_attempt` already counted it
+async fn login_failed(state: &Arc<AppState>, user: &User, context: &LoginContext, method: &str) {
+    if logins::is_locked(&state.redis, &user.id).await {
+        logger::warn(
+            "AUTH",
+            &format!(
+                "Locked {} after repeated failed logins, last from {}",
+                user.phone_number, context.ip
+            ),
+        );
+    }
+    logins::record(state, user, context, method, "failed").await;
+}
+
+/// Login with phone number and password
 pub async fn login(
     State(state): State<Arc<AppState>>,
+    context: LoginContext,
     Json(payload): Json<LoginRequest>,
 ) -> axum::response::Response {
     // Find user by phone number
@@ -243,41 +308,201 @@ pub async fn login(
         Some(u) => u,
         None => {
             let response =
-                create_secure_auth_response(response_codes::AUTH_FAILED, false, None, None, false);
+                create_secure_auth_response(response_codes::AUTH_FAILED, false, None, None);
             return (StatusCode::UNAUTHORIZED, Json(response)).into_response();
         }
     };
 
-    // Verify crypto hash matches
-    if user.crypto_hash != payload.crypto_hash {
-        let response =
-            create_secure_auth_response(response_codes::AUTH_FAILED, false, None, None, false);
-        return (StatusCode::UNAUTHORIZED, Json(response)).into_response();
+    // Locked and throttled accounts don't get to try the password
+    if let Some(retry_after) = logins::begin_attempt(&state.redis, &user.id)
+        .await
+        .retry_after()
+    {
+        logins::record(&state, &user, &context, "password", "locked").await;
+        return throttled(retry_after);
     }
 
     // Verify password
     if !verify_password(&payload.password, &user.password_hash) {
-        let response =
-            create_secure_auth_response(response_codes::AUTH_FAILED, false, None, None, false);
+        login_failed(&state, &user, &context, "password").await;
+        let response = create_secure_auth_response(response_codes::AUTH_FAILED, false, None, None);
         return (StatusCode::UNAUTHORIZED, Json(response)).into_response();
     }
 
+    // With 2FA on, tokens wait for the code, and failures are only cleared
+    // once it checks out
+    if user.totp_enabled {
+        let Some(challenge_id) = two_factor::start_challenge(&state.redis, &user.id).await else {
+            let response =
+                create_secure_auth_response(response_codes::INTERNAL_ERROR, false, None, None);
+            return (StatusCode::SERVICE_UNAVAILABLE, Json(response)).into_response();
+        };
+        let response = create_secure_response(
+            response_codes::TWO_FACTOR_REQUIRED,
+            false,
+            Some(serde_json::json!({ "c": challenge_id })),
+        );
+        return (StatusCode::OK, Json(response)).into_response();
+    }
+
+    logins::clear_failures(&state.redis, &user.id).await;
+    logins::record(&state, &user, &context, "password", "success").await;
+
     // Generate secure response with tokens
-    let response =
-        create_secure_auth_response(response_codes::AUTH_SUCCESS, true, Some(&user), None, true);
+    let response = secure_auth_response_with_tokens(
+        response_codes::AUTH_SUCCESS,
+        true,
+        Some(&user),
+        None,
+        issue_tokens(&state, &user).await,
+    );
 
-    // Create response with Set-Cookie header for the token
-    let is_production = std::env::var("PRODUCTION").unwrap_or_default() == "true";
-    if let Some(ref tokens) = response.tokens {
-        let cookie = create_auth_cookie(&tokens.access_token, is_production);
-        let mut headers = axum::http::HeaderMap::new();
+    signed_in(StatusCode::OK, response)
+}
+
+/// Second login step: the code from the authenticator app, or a recovery code
+pub async fn login_two_factor(
+    State(state): State<Arc<AppState>>,
+    context: LoginContext,
+    Json(payload): Json<TwoFactorLoginRequest>,
+) -> axum::response::Response {
+    let failed = |code: u32| {
+        let response = create_secure_auth_response(code, false, None, None);
+        (StatusCode::UNAUTHORIZED, Json(response)).into_response()
+    };
+
+    let Some(user_id) = two_factor::challenge_user(&state.redis, &payload.challenge_id).await
+    else {
+        return failed(response_codes::TOKEN_INVALID);
+    };
+    let user: Option<User> = sqlx::query_as("SELECT * FROM users WHERE id = ?")
+        .bind(&user_id)
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None);
+    let Some(user) = user else {
+        return failed(response_codes::TOKEN_INVALID);
+    };
+
+    if let Some(retry_after) = logins::begin_attempt(&state.redis, &user.id)
+        .await
+        .retry_after()
+    {
+        logins::record(&state, &user, &context, "totp", "locked").await;
+        return throttled(retry_after);
+    }
+
+    if !two_factor::verify_second_factor(&state, &user, &payload.code).await {
+        two_factor::challenge_failed(&state.redis, &payload.challenge_id).await;
+        login_failed(&state, &user, &context, "totp").await;
+        return failed(response_codes::AUTH_FAILED);
+    }
+
+    two_factor::end_challenge(&state.redis, &payload.challenge_id).await;
+    logins::clear_failures(&state.redis, &user.id).await;
+    logins::record(&state, &user, &context, "totp", "success").await;
+
+    let response = secure_auth_response_with_tokens(
+        response_codes::AUTH_SUCCESS,
+        true,
+        Some(&user),
+        None,
+        issue_tokens(&state, &user).await,
+    );
+    signed_in(StatusCode::OK, response)
+}
+
+/// Trade a refresh token for a new token pair. Each refresh token works once,
+/// presenting a used one again revokes the whole session.
+pub async fn refresh(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    payload: Option<Json<RefreshRequest>>,
+) -> axum::response::Response {
+    let failed = || {
+        let response =
+            create_secure_auth_response(response_codes::TOKEN_INVALID, false, None, None);
+        (StatusCode::UNAUTHORIZED, Json(response)).into_response()
+    };
+
+    // Browsers send the refresh cookie, API clients the token in the body
+    let Some(token) = payload
+        .map(|Json(p)| p.refresh_token)
+        .or_else(|| security::cookie_value(&headers, "whatsaly_refresh"))
+    else {
+        return failed();
+    };
+    let Some(claims) = security::verify_session_token(&state.redis, &token, "refresh").await else {
+        return failed();
+    };
+    if !security::consume_refresh_token(&state.redis, &claims).await {
+        security::revoke_session(&state.redis, &claims.sid).await;
+        logger::warn(
+            "AUTH",
+            &format!(
+                "Refresh token reused, revoked session {} of {}",
+                claims.sid, claims.sub
+            ),
+        );
+        return failed();
+    }
+
+    let user: Option<User> = sqlx::query_as("SELECT * FROM users WHERE cryptoHash = ?")
+        .bind(&claims.sub)
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None);
+    let Some(user) = user else {
+        return failed();
+    };
+    let Ok(tokens) = security::generate_session_tokens(
+        &user.crypto_hash,
+        token_role(&state, &user).await,
+        &claims.sid,
+    ) else {
+        return failed();
+    };
+
+    let response = secure_auth_response_with_tokens(
+        response_codes::TOKEN_VALID,
+        true,
+        Some(&user),
+        None,
+        Some(tokens),
+    );
+    signed_in(StatusCode::OK, response)
+}
+
+/// Sign out, revoking every token of the current session
+pub async fn logout(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    claims: Option<Extension<Claims>>,
+) -> axum::response::Response {
+    // With an expired access token the refresh cookie still names the session
+    let claims = match claims {
+        Some(Extension(c)) => Some(c),
+        None => match security::cookie_value(&headers, "whatsaly_refresh") {
+            Some(token) => security::verify_session_token(&state.redis, &token, "refresh").await,
+            None => None,
+        },
+    };
+    if let Some(claims) = claims {
+        security::revoke_token(&state.redis, &claims).await;
+        security::revoke_session(&state.redis, &claims.sid).await;
+    }
+
+    let mut headers = axum::http::HeaderMap::new();
+    for cookie in [
+        create_logout_cookie(),
+        security::create_refresh_logout_cookie(),
+    ] {
         if let Ok(cookie_value) = cookie.parse() {
-            headers.insert(header::SET_COOKIE, cookie_value);
+            headers.append(header::SET_COOKIE, cookie_value);
         }
-        return (StatusCode::OK, headers, Json(response)).into_response();
     }
-
-    (StatusCode::OK, Json(response)).into_response()
+    let response = create_secure_response(response_codes::OPERATION_OK, true, None);
+    (StatusCode::OK, headers, Json(response)).into_response()
 }
 
 /// Get user's unique crypto hash (requires phone verification)
@@ -310,11 +535,35 @@ pub async fn get_crypto_hash(
     }
 }
 
-/// Verify a crypto hash belongs to a user (for encrypted queries)
+/// Check that a crypto hash names the caller's own account
 pub async fn verify_crypto_hash(
     State(state): State<Arc<AppState>>,
     axum::extract::Path(crypto_hash): axum::extract::Path<String>,
+    claims: Option<Extension<Claims>>,
 ) -> (StatusCode, Json<serde_json::Value>) {
+    // The hash is only an account identifier, don't tell strangers whose it is
+    match claims.as_deref() {
+        Some(c) if c.sub == crypto_hash || c.role == "admin" => {}
+        Some(_) => {
+            return (
+                StatusCode::FORBIDDEN,
+                Json(serde_json::json!({
+                    "success": false,
+                    "message": "You can only verify your own account"
+                })),
+            );
+        }
+        None => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(serde_json::json!({
+                    "success": false,
+                    "message": "Sign in required"
+                })),
+            );
+        }
+    }
+
     let user: Option<User> = sqlx::query_as("SELECT * FROM users WHERE cryptoHash = ?")
         .bind(&crypto_hash)
         .fetch_optional(&state.db)
@@ -344,29 +593,45 @@ pub async fn verify_crypto_hash(
 
 // ========== Passkey (WebAuthn) Routes ==========
 
+/// The account a passkey is added to must be the caller's own
+async fn signed_in_user(
+    state: &Arc<AppState>,
+    claims: Option<&Claims>,
+    user_id: &str,
+) -> Result<User, (StatusCode, Json<serde_json::Value>)> {
+    let Some(claims) = claims else {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            Json(serde_json::json!({
+                "success": false,
+                "message": "Sign in to manage passkeys"
+            })),
+        ));
+    };
+    let user: Option<User> = sqlx::query_as("SELECT * FROM users WHERE id = ? AND cryptoHash = ?")
+        .bind(user_id)
+        .bind(&claims.sub)
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None);
+    user.ok_or((
+        StatusCode::FORBIDDEN,
+        Json(serde_json::json!({
+            "success": false,
+            "message": "You can only manage passkeys of your own account"
+        })),
+    ))
+}
+
 /// Generate a challenge for passkey registration
 pub async fn passkey_register_challenge(
     State(state): State<Arc<AppState>>,
     axum::extract::Path(user_id): axum::extract::Path<String>,
+    claims: Option<Extension<Claims>>,
 ) -> (StatusCode, Json<serde_json::Value>) {
-    // Verify user exists
-    let user: Option<User> = sqlx::query_as("SELECT * FROM users WHERE id = ?")
-        .bind(&user_id)
-        .fetch_optional(&state.db)
-        .await
-        .unwrap_or(None);
-
-    let user = match user {
-        Some(u) => u,
-        None => {
-            return (
-                StatusCode::NOT_FOUND,
-                Json(serde_json::json!({
-                    "success": false,
-                    "message": "User not found"
-                })),
-            );
-        }
+    let user = match signed_in_user(&state, claims.as_deref(), &user_id).await {
+        Ok(u) => u,
+        Err(e) => return e,
     };
 
     // Generate a random challenge
@@ -375,13 +640,14 @@ pub async fn passkey_register_challenge(
         base64::Engine::encode(&base64::engine::general_purpose::URL_SAFE_NO_PAD, challenge);
 
     // Store challenge in Redis with 5 minute expiry
-    if let Ok(mut conn) = state.redis.get_connection() {
+    if let Ok(mut conn) = state.redis.get_multiplexed_async_connection().await {
         let key = format!("passkey_challenge:{}", user_id);
         let _: () = redis::cmd("SETEX")
             .arg(&key)
             .arg(300) // 5 minutes
             .arg(&challenge_b64)
-            .query(&mut conn)
+            .query_async(&mut conn)
+            .await
             .unwrap_or(());
     }
 
@@ -408,38 +674,71 @@ pub async fn passkey_register_challenge(
             "authenticatorSelection": {
                 "authenticatorAttachment": "platform",
                 "requireResidentKey": false,
-                "userVerification": "preferred"
+                "userVerification": webauthn::user_verification()
             }
         })),
     )
 }
 
+/// Check the attestation answering our registration challenge
+fn verify_registration(
+    payload: &PasskeyRegisterRequest,
+    challenge: &str,
+) -> Result<webauthn::NewCredential, String> {
+    let rp_id = get_rp_id();
+    let client_data = webauthn::decode(&payload.client_data_json)?;
+    let attestation = webauthn::decode(&payload.attestation_object)?;
+    webauthn::verify_client_data(
+        &client_data,
+        "webauthn.create",
+        challenge,
+        &rp_id,
+        &webauthn::allowed_origins(),
+    )?;
+    webauthn::verify_attestation(&attestation, &client_data, &rp_id)
+}
+
 /// Register a new passkey credential
 pub async fn passkey_register(
     State(state): State<Arc<AppState>>,
+    claims: Option<Extension<Claims>>,
     Json(payload): Json<PasskeyRegisterRequest>,
 ) -> (StatusCode, Json<serde_json::Value>) {
-    // Verify user exists
-    let user: Option<User> = sqlx::query_as("SELECT * FROM users WHERE id = ?")
-        .bind(&payload.user_id)
-        .fetch_optional(&state.db)
-        .await
-        .unwrap_or(None);
+    if let Err(e) = signed_in_user(&state, claims.as_deref(), &payload.user_id).await {
+        return e;
+    }
 
-    if user.is_none() {
+    let Some(challenge) =
+        take_challenge(&state, &format!("passkey_challenge:{}", payload.user_id)).await
+    else {
         return (
-            StatusCode::NOT_FOUND,
+            StatusCode::BAD_REQUEST,
             Json(serde_json::json!({
                 "success": false,
-                "message": "User not found"
+                "message": "Unknown or expired challenge"
             })),
         );
-    }
+    };
+
+    let credential = match verify_registration(&payload, &challenge) {
+        Ok(c) => c,
+        Err(e) => {
+            logger::warn("AUTH", &format!("Passkey registration rejected: {}", e));
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "success": false,
+                    "message": format!("Passkey verification failed: {}", e)
+                })),
+            );
+        }
+    };
+    let credential_id = webauthn::encode(&credential.credential_id);
 
     // Check if credential already exists
     let existing: Option<PasskeyCredential> =
         sqlx::query_as("SELECT * FROM passkey_credentials WHERE credentialId = ?")
-            .bind(&payload.credential_id)
+            .bind(&credential_id)
             .fetch_optional(&state.db)
             .await
             .unwrap_or(None);
@@ -464,9 +763,9 @@ pub async fn passkey_register(
     )
     .bind(&id)
     .bind(&payload.user_id)
-    .bind(&payload.credential_id)
-    .bind(&payload.public_key)
-    .bind(0)
+    .bind(&credential_id)
+    .bind(webauthn::encode(&credential.public_key))
+    .bind(i64::from(credential.sign_count))
     .bind(&payload.device_name)
     .bind(now)
     .execute(&state.db)
@@ -502,13 +801,14 @@ pub async fn passkey_login_challenge(
     let challenge_id_hex = hex::encode(challenge_id);
 
     // Store challenge in Redis with 5 minute expiry
-    if let Ok(mut conn) = state.redis.get_connection() {
+    if let Ok(mut conn) = state.redis.get_multiplexed_async_connection().await {
         let key = format!("passkey_login_challenge:{}", challenge_id_hex);
         let _: () = redis::cmd("SETEX")
             .arg(&key)
             .arg(300) // 5 minutes
             .arg(&challenge_b64)
-            .query(&mut conn)
+            .query_async(&mut conn)
+            .await
             .unwrap_or(());
     }
 
@@ -520,47 +820,101 @@ pub async fn passkey_login_challenge(
             "challenge": challenge_b64,
             "rpId": get_rp_id(),
             "timeout": 300000,
-            "userVerification": "preferred"
+            "userVerification": webauthn::user_verification()
         })),
     )
 }
 
-/// Authenticate with a passkey
-pub async fn passkey_login(
-    State(state): State<Arc<AppState>>,
-    Json(payload): Json<PasskeyLoginRequest>,
-) -> axum::response::Response {
-    // Find the credential
-    let credential: Option<PasskeyCredential> =
+/// Fetch and consume a stored challenge, it can only be answered once
+async fn take_challenge(state: &Arc<AppState>, key: &str) -> Option<String> {
+    let mut conn = state.redis.get_multiplexed_async_connection().await.ok()?;
+    let (challenge, _): (Option<String>, i64) = redis::pipe()
+        .atomic()
+        .get(key)
+        .del(key)
+        .query_async(&mut conn)
+        .await
+        .ok()?;
+    challenge
+}
+
+/// Verify a passkey assertion against the issued challenge and the stored
+/// credential, then record the new signature counter
+async fn verify_assertion(
+    state: &Arc<AppState>,
+    payload: &PasskeyLoginRequest,
+) -> Result<PasskeyCredential, String> {
+    let challenge = take_challenge(
+        state,
+        &format!("passkey_login_challenge:{}", payload.challenge_id),
+    )
+    .await
+    .ok_or("Unknown or expired challenge")?;
+
+    let credential: PasskeyCredential =
         sqlx::query_as("SELECT * FROM passkey_credentials WHERE credentialId = ?")
             .bind(&payload.credential_id)
             .fetch_optional(&state.db)
             .await
-            .unwrap_or(None);
+            .unwrap_or(None)
+            .ok_or("Unknown credential")?;
+
+    let rp_id = get_rp_id();
+    let client_data = webauthn::decode(&payload.client_data_json)?;
+    let auth_data_raw = webauthn::decode(&payload.authenticator_data)?;
+    let signature = webauthn::decode(&payload.signature)?;
+    let public_key = webauthn::decode(&credential.public_key)?;
+
+    webauthn::verify_client_data(
+        &client_data,
+        "webauthn.get",
+        &challenge,
+        &rp_id,
+        &webauthn::allowed_origins(),
+    )?;
+    let auth_data = webauthn::AuthenticatorData::parse(&auth_data_raw)?;
+    auth_data.check(&rp_id)?;
+    webauthn::verify_signature(&public_key, &auth_data_raw, &client_data, &signature)?;
+    webauthn::check_sign_count(credential.counter, auth_data.sign_count).map_err(|e| {
+        format!(
+            "{} for credential {}, possibly cloned",
+            e, credential.credential_id
+        )
+    })?;
 
-    let credential = match credential {
-        Some(c) => c,
-        None => {
+    // Compare-and-set so two logins racing on the same counter can't both pass
+    let updated = sqlx::query(
+        "UPDATE passkey_credentials SET lastUsedAt = ?, counter = ? WHERE id = ? AND counter = ?",
+    )
+    .bind(chrono::Utc::now())
+    .bind(i64::from(auth_data.sign_count))
+    .bind(&credential.id)
+    .bind(credential.counter)
+    .execute(&state.db)
+    .await
+    .map_err(|e| e.to_string())?;
+    if updated.rows_affected() == 0 {
+        return Err("Credential was used concurrently".to_string());
+    }
+    Ok(credential)
+}
+
+/// Authenticate with a passkey
+pub async fn passkey_login(
+    State(state): State<Arc<AppState>>,
+    context: LoginContext,
+    Json(payload): Json<PasskeyLoginRequest>,
+) -> axum::response::Response {
+    let credential = match verify_assertion(&state, &payload).await {
+        Ok(c) => c,
+        Err(e) => {
+            logger::warn("AUTH", &format!("Passkey login rejected: {}", e));
             let response =
-                create_secure_auth_response(response_codes::AUTH_FAILED, false, None, None, false);
+                create_secure_auth_response(response_codes::AUTH_FAILED, false, None, None);
             return (StatusCode::UNAUTHORIZED, Json(response)).into_response();
         }
     };
 
-    // In a production system, we would verify the signature here using the stored public key
-    // For now, we trust that the WebAuthn API has done client-side verification
-    // The authenticator_data and signature would be cryptographically verified
-
-    // Update last used time and counter
-    let now = chrono::Utc::now();
-    let _ = sqlx::query(
-        "UPDATE passkey_credentials SET lastUsedAt = ?, counter = counter + 1 WHERE id = ?",
-    )
-    .bind(now)
-    .bind(&credential.id)
-    .execute(&state.db)
-    .await;
-
     // Get the user
     let user: Option<User> = sqlx::query_as("SELECT * FROM users WHERE id = ?")
         .bind(&credential.user_id)
@@ -570,30 +924,20 @@ pub async fn passkey_login(
 
     match user {
         Some(u) => {
-            let response = create_secure_auth_response(
+            logins::record(&state, &u, &context, "passkey", "success").await;
+            let response = secure_auth_response_with_tokens(
                 response_codes::PASSKEY_OK,
                 true,
                 Some(&u),
                 Some(&u.crypto_hash),
-                true,
+                issue_tokens(&state, &u).await,
             );
 
-            // Set auth cookie
-            let is_production = std::env::var("PRODUCTION").unwrap_or_default() == "true";
-            if let Some(ref tokens) = response.tokens {
-                let cookie = create_auth_cookie(&tokens.access_token, is_production);
-                let mut headers = axum::http::HeaderMap::new();
-                if let Ok(cookie_value) = cookie.parse() {
-                    headers.insert(header::SET_COOKIE, cookie_value);
-                }
-                return (StatusCode::OK, headers, Json(response)).into_response();
-            }
-
-            (StatusCode::OK, Json(response)).into_response()
+            signed_in(StatusCode::OK, response)
         }
         None => {
             let response =
-                create_secure_auth_response(response_codes::AUTH_FAILED, false, None, None, false);
+                create_secure_auth_response(response_codes::AUTH_FAILED, false, None, None);
             (StatusCode::UNAUTHORIZED, Json(response)).into_response()
         }
     }
@@ -603,7 +947,11 @@ pub async fn passkey_login(
 pub async fn get_passkeys(
     State(state): State<Arc<AppState>>,
     axum::extract::Path(user_id): axum::extract::Path<String>,
+    claims: Option<Extension<Claims>>,
 ) -> (StatusCode, Json<serde_json::Value>) {
+    if let Err(e) = signed_in_user(&state, claims.as_deref(), &user_id).await {
+        return e;
+    }
     let passkeys: Vec<PasskeyCredential> = sqlx::query_as(
         "SELECT * FROM passkey_credentials WHERE userId = ? ORDER BY createdAt DESC",
     )
@@ -632,7 +980,11 @@ pub async fn get_passkeys(
 pub async fn delete_passkey(
     State(state): State<Arc<AppState>>,
     axum::extract::Path((user_id, passkey_id)): axum::extract::Path<(String, String)>,
+    claims: Option<Extension<Claims>>,
 ) -> (StatusCode, Json<serde_json::Value>) {
+    if let Err(e) = signed_in_user(&state, claims.as_deref(), &user_id).await {
+        return e;
+    }
     let result = sqlx::query("DELETE FROM passkey_credentials WHERE id = ? AND userId = ?")
         .bind(&passkey_id)
         .bind(&user_id)
@@ -669,74 +1021,99 @@ pub async fn delete_passkey(
 /// Admin login request
 #[derive(Debug, serde::Deserialize)]
 pub struct AdminLoginRequest {
+    #[serde(rename = "phoneNumber")]
+    pub phone_number: String,
     pub password: String,
+    /// Authenticator or recovery code, for admins with 2FA on
+    pub code: Option<String>,
 }
 
-/// Get the admin password from environment or use default
-fn get_admin_password() -> String {
-    std::env::var("ADMIN_PASSWORD").unwrap_or_else(|_| "astrox11".to_string())
-}
-
-/// Constant-time string comparison to prevent timing attacks
-fn constant_time_compare(a: &str, b: &str) -> bool {
-    let a_bytes = a.as_bytes();
-    let b_bytes = b.as_bytes();
-
-    // If lengths differ, still do comparison to maintain constant time
-    if a_bytes.len() != b_bytes.len() {
-        return false;
-    }
-
-    // XOR all bytes and accumulate - result is 0 only if all bytes match
-    let mut result = 0u8;
-    for (x, y) in a_bytes.iter().zip(b_bytes.iter()) {
-        result |= x ^ y;
+/// Create the initial owner account on first run.
+///
+/// Uses ADMIN_PHONE and ADMIN_PASSWORD; without a password a random one is
+/// generated and printed once, so a fresh install never ships a known login.
+pub async fn bootstrap_admin(db: &sqlx::SqlitePool) {
+    let has_owner: bool =
+        sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM users WHERE role = 'owner')")
+            .fetch_one(db)
+            .await
+            .unwrap_or(true);
+    if has_owner {
+        return;
     }
-    result == 0
-}
-
-/// Generate admin session token with 30 minute expiry
-fn generate_admin_session_token() -> (String, i64) {
-    use sha2::{Digest, Sha256};
-
-    let expiry = chrono::Utc::now().timestamp() + (30 * 60); // 30 minutes
-    let random_bytes: [u8; 32] = rand::random();
 
-    let mut hasher = Sha256::new();
-    hasher.update(random_bytes);
-    hasher.update(expiry.to_le_bytes());
-    hasher.update(get_admin_password().as_bytes());
-
-    let token = hex::encode(hasher.finalize());
-    (format!("{}:{}", token, expiry), expiry)
-}
-
-/// Verify admin session token
-pub fn verify_admin_session_token(token: &str) -> bool {
-    let parts: Vec<&str> = token.split(':').collect();
-    if parts.len() != 2 {
-        return false;
+    // Admins from before roles existed: the oldest one becomes the owner
+    let promoted = sqlx::query(
+        "UPDATE users SET role = 'owner' WHERE id = (
+             SELECT id FROM users WHERE isAdmin = TRUE ORDER BY createdAt LIMIT 1)",
+    )
+    .execute(db)
+    .await
+    .map(|r| r.rows_affected() > 0)
+    .unwrap_or(false);
+    if promoted {
+        logger::info("ADMIN", "Promoted the oldest admin to owner");
+        return;
     }
 
-    let expiry: i64 = match parts[1].parse() {
-        Ok(e) => e,
-        Err(_) => return false,
+    let phone_number = std::env::var("ADMIN_PHONE").unwrap_or_else(|_| "admin".to_string());
+    let (password, generated) = match std::env::var("ADMIN_PASSWORD") {
+        Ok(p) if !p.is_empty() => (p, false),
+        _ => (hex::encode(rand::random::<[u8; 12]>()), true),
     };
+    let Ok((password_hash, password_salt)) = hash_password(&password) else {
+        logger::error("ADMIN", "Failed to hash the initial admin password");
+        return;
+    };
+    let now = chrono::Utc::now();
 
-    // Check if token has expired
-    if chrono::Utc::now().timestamp() > expiry {
-        return false;
-    }
+    // An existing account with that number is left alone, taking it over
+    // would hand its password and role to whoever set ADMIN_PHONE
+    let result = sqlx::query(
+        "INSERT INTO users (id, phoneNumber, passwordHash, passwordSalt, cryptoHash, isAdmin, role, credits, createdAt, updatedAt)
+         VALUES (?, ?, ?, ?, ?, TRUE, 'owner', 0, ?, ?)
+         ON CONFLICT(phoneNumber) DO NOTHING",
+    )
+    .bind(generate_user_id())
+    .bind(&phone_number)
+    .bind(&password_hash)
+    .bind(&password_salt)
+    .bind(generate_crypto_hash(&phone_number))
+    .bind(now)
+    .bind(now)
+    .execute(db)
+    .await;
 
-    true
+    match result {
+        Ok(r) if r.rows_affected() == 0 => logger::warn(
+            "ADMIN",
+            &format!(
+                "An account with '{}' already exists, set ADMIN_PHONE to a new number to create the admin",
+                phone_number
+            ),
+        ),
+        Ok(_) if generated => {
+            logger::warn(
+                "ADMIN",
+                &format!(
+                    "Created admin '{}' with a generated password, change it or set ADMIN_PASSWORD",
+                    phone_number
+                ),
+            );
+            // Straight to the terminal, the log stream is readable by staff
+            eprintln!("Initial admin password: {}", password);
+        }
+        Ok(_) => logger::success("ADMIN", &format!("Created admin '{}'", phone_number)),
+        Err(e) => logger::error("ADMIN", &format!("Failed to create admin: {}", e)),
+    }
 }
 
 /// Create admin session cookie with 30 minute expiry
 pub fn create_admin_session_cookie(token: &str, is_production: bool) -> String {
-    let max_age = 30 * 60; // 30 minutes
     let mut cookie = format!(
         "admin_session={}; Path=/; Max-Age={}; HttpOnly; SameSite=Strict",
-        token, max_age
+        token,
+        security::ADMIN_SESSION_TTL
     );
 
     if is_production {
@@ -746,65 +1123,146 @@ pub fn create_admin_session_cookie(token: &str, is_production: bool) -> String {
     cookie
 }
 
-/// Verify admin password
+/// Login with an admin account
 pub async fn admin_login(
+    State(state): State<Arc<AppState>>,
+    context: LoginContext,
     Json(payload): Json<AdminLoginRequest>,
-) -> impl axum::response::IntoResponse {
-    let admin_password = get_admin_password();
+) -> axum::response::Response {
     let is_production = std::env::var("PRODUCTION").is_ok();
+    let failed = || {
+        (
+            StatusCode::UNAUTHORIZED,
+            Json(serde_json::json!({
+                "success": false,
+                "message": "Invalid admin credentials"
+            })),
+        )
+            .into_response()
+    };
 
-    if constant_time_compare(&payload.password, &admin_password) {
-        let (session_token, _expiry) = generate_admin_session_token();
-        let cookie = create_admin_session_cookie(&session_token, is_production);
+    let admin: Option<User> = sqlx::query_as(
+        "SELECT * FROM users WHERE phoneNumber = ? AND isAdmin = TRUE AND suspended = FALSE",
+    )
+    .bind(&payload.phone_number)
+    .fetch_optional(&state.db)
+    .await
+    .ok()
+    .flatten();
+    let Some(admin) = admin else {
+        return failed();
+    };
 
-        (
-            StatusCode::OK,
-            [(header::SET_COOKIE, cookie)],
+    if let Some(retry_after) = logins::begin_attempt(&state.redis, &admin.id)
+        .await
+        .retry_after()
+    {
+        logins::record(&state, &admin, &context, "admin", "locked").await;
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, retry_after.to_string())],
             Json(serde_json::json!({
-                "success": true,
-                "message": "Admin authentication successful"
+                "success": false,
+                "message": "Too many failed attempts, try again later",
+                "retryAfter": retry_after
             })),
         )
-    } else {
-        (
-            StatusCode::UNAUTHORIZED,
-            [(header::SET_COOKIE, "".to_string())],
+            .into_response();
+    }
+
+    if !verify_password(&payload.password, &admin.password_hash) {
+        login_failed(&state, &admin, &context, "admin").await;
+        return failed();
+    }
+
+    if admin.totp_enabled {
+        let Some(code) = payload.code.as_deref().filter(|c| !c.trim().is_empty()) else {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(serde_json::json!({
+                    "success": false,
+                    "twoFactorRequired": true,
+                    "message": "Enter the code from your authenticator app"
+                })),
+            )
+                .into_response();
+        };
+        if !two_factor::verify_second_factor(&state, &admin, code).await {
+            login_failed(&state, &admin, &context, "admin").await;
+            return failed();
+        }
+    } else if two_factor::admin_2fa_required(&state.db).await {
+        return (
+            StatusCode::FORBIDDEN,
             Json(serde_json::json!({
                 "success": false,
-                "message": "Invalid admin password"
+                "message": "Admin accounts need two-factor authentication. Turn it on from your account's Security page first."
             })),
         )
+            .into_response();
     }
+
+    let Some(session_token) = security::create_admin_session(&state.redis, &admin.id).await else {
+        return failed();
+    };
+    logins::clear_failures(&state.redis, &admin.id).await;
+    logins::record(&state, &admin, &context, "admin", "success").await;
+
+    let cookie = create_admin_session_cookie(&session_token, is_production);
+    (
+        StatusCode::OK,
+        [(header::SET_COOKIE, cookie)],
+        Json(serde_json::json!({
+            "success": true,
+            "message": "Admin authentication successful"
+        })),
+    )
+        .into_response()
+}
+
+/// End the current admin session
+pub async fn admin_logout(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+) -> impl axum::response::IntoResponse {
+    if let Some(token) = security::cookie_value(&headers, "admin_session") {
+        security::end_admin_session(&state.redis, &token).await;
+    }
+
+    (
+        StatusCode::OK,
+        [(
+            header::SET_COOKIE,
+            "admin_session=; Path=/; Max-Age=0; HttpOnly; SameSite=Strict".to_string(),
+        )],
+        Json(serde_json::json!({
+            "success": true,
+            "message": "Logged out"
+        })),
+    )
 }
 
 /// Validate admin session from cookie
 pub async fn validate_admin_session(
+    State(state): State<Arc<AppState>>,
     headers: axum::http::HeaderMap,
 ) -> (StatusCode, Json<serde_json::Value>) {
-    // Get cookie from headers
-    let cookie_header = headers.get(header::COOKIE);
-    let admin_token = cookie_header
-        .and_then(|h| h.to_str().ok())
-        .and_then(|cookies| {
-            cookies.split(';').find_map(|cookie| {
-                let parts: Vec<&str> = cookie.trim().splitn(2, '=').collect();
-                if parts.len() == 2 && parts[0] == "admin_session" {
-                    Some(parts[1].to_string())
-                } else {
-                    None
-                }
-            })
-        });
+    let session = match security::cookie_value(&headers, "admin_session") {
+        Some(token) => security::admin_session(&state, &token).await,
+        None => None,
+    };
 
-    match admin_token {
-        Some(token) if verify_admin_session_token(&token) => (
+    match session {
+        Some(session) => (
             StatusCode::OK,
             Json(serde_json::json!({
                 "valid": true,
-                "message": "Admin session is valid"
+                "message": "Admin session is valid",
+                "role": session.role.as_str(),
+                "permissions": session.role.permissions()
             })),
         ),
-        _ => (
+        None => (
             StatusCode::UNAUTHORIZED,
             Json(serde_json::json!({
                 "valid": false,
@@ -813,3 +1271,118 @@ pub async fn validate_admin_session(
         ),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use axum::extract::Path;
+
+    async fn add_passkey(state: &AppState, id: &str, user_id: &str) {
+        sqlx::query(
+            "INSERT INTO passkey_credentials (id, userId, credentialId, publicKey) VALUES (?, ?, ?, '')",
+        )
+        .bind(id)
+        .bind(user_id)
+        .bind(format!("cred-{}", id))
+        .execute(&state.db)
+        .await
+        .unwrap();
+    }
+
+    async fn load_user(state: &AppState, id: &str) -> User {
+        sqlx::query_as("SELECT * FROM users WHERE id = ?")
+            .bind(id)
+            .fetch_one(&state.db)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn admins_without_2fa_get_user_tokens_under_the_policy() {
+        let state = testing::state().await;
+        let id = testing::user(&state, "111", true, Some("owner")).await;
+        let staff = testing::user(&state, "222", true, Some("support")).await;
+        let admin = load_user(&state, &id).await;
+        assert_eq!(token_role(&state, &admin).await, "admin");
+        assert_eq!(
+            token_role(&state, &load_user(&state, &staff).await).await,
+            "user"
+        );
+
+        sqlx::query("INSERT INTO system_settings (name, value) VALUES ('requireAdmin2fa', 'true')")
+            .execute(&state.db)
+            .await
+            .unwrap();
+        assert_eq!(token_role(&state, &admin).await, "user");
+
+        sqlx::query("UPDATE users SET totpEnabled = TRUE WHERE id = ?")
+            .bind(&id)
+            .execute(&state.db)
+            .await
+            .unwrap();
+        assert_eq!(
+            token_role(&state, &load_user(&state, &id).await).await,
+            "admin"
+        );
+    }
+
+    #[tokio::test]
+    async fn bootstrap_leaves_an_existing_account_alone() {
+        let state = testing::state().await;
+        // SAFETY: no other test reads ADMIN_PHONE
+        unsafe { std::env::set_var("ADMIN_PHONE", "555") };
+        let id = testing::user(&state, "555", false, None).await;
+        bootstrap_admin(&state.db).await;
+
+        let user = load_user(&state, &id).await;
+        assert!(!user.is_admin);
+        assert_eq!(user.role, None);
+        assert_eq!(user.password_hash, "");
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users")
+            .fetch_one(&state.db)
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[tokio::test]
+    async fn passkeys_are_only_listed_for_their_owner() {
+        let state = testing::state().await;
+        let owner = testing::user(&state, "111", false, None).await;
+        testing::user(&state, "222", false, None).await;
+        add_passkey(&state, "pk1", &owner).await;
+
+        let (status, _) = get_passkeys(State(state.clone()), Path(owner.clone()), None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let other = Some(Extension(testing::claims("222", "user")));
+        let (status, _) = get_passkeys(State(state.clone()), Path(owner.clone()), other).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let own = Some(Extension(testing::claims("111", "user")));
+        let (status, Json(body)) = get_passkeys(State(state.clone()), Path(owner), own).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["passkeys"][0]["id"], "pk1");
+    }
+
+    #[tokio::test]
+    async fn passkeys_are_only_deleted_by_their_owner() {
+        let state = testing::state().await;
+        let owner = testing::user(&state, "111", false, None).await;
+        testing::user(&state, "222", false, None).await;
+        add_passkey(&state, "pk1", &owner).await;
+        let path = || Path((owner.clone(), "pk1".to_string()));
+
+        let (status, _) = delete_passkey(State(state.clone()), path(), None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let other = Some(Extension(testing::claims("222", "user")));
+        let (status, _) = delete_passkey(State(state.clone()), path(), other).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let own = Some(Extension(testing::claims("111", "user")));
+        let (status, _) = delete_passkey(State(state.clone()), path(), own).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+}
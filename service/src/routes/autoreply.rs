@@ -0,0 +1,423 @@
+use crate::AppState;
+use crate::manager::{autoreply, templates};
+use crate::routes::access::can_access_instance;
+use crate::security::Claims;
+use crate::sql::AutoReplyRule;
+use axum::{
+    Extension, Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// Create or partially update a rule. Omitted fields keep their current value.
+#[derive(Debug, Deserialize)]
+pub struct RuleRequest {
+    pub name: Option<String>,
+    #[serde(rename = "matchType")]
+    pub match_type: Option<String>,
+    pub pattern: Option<String>,
+    #[serde(rename = "caseSensitive")]
+    pub case_sensitive: Option<bool>,
+    pub scope: Option<String>,
+    pub chats: Option<Vec<String>>,
+    #[serde(rename = "cooldownSeconds")]
+    pub cooldown_seconds: Option<i64>,
+    #[serde(rename = "responseType")]
+    pub response_type: Option<String>,
+    #[serde(rename = "responseText")]
+    pub response_text: Option<String>,
+    #[serde(rename = "mediaUrl")]
+    pub media_url: Option<String>,
+    #[serde(rename = "mediaType")]
+    pub media_type: Option<String>,
+    #[serde(rename = "fileName")]
+    pub file_name: Option<String>,
+    /// Template sent by `template` responses
+    #[serde(rename = "templateId")]
+    pub template_id: Option<i64>,
+    pub priority: Option<i64>,
+    pub enabled: Option<bool>,
+}
+
+type ApiResponse = (StatusCode, Json<serde_json::Value>);
+
+fn error(status: StatusCode, message: &str) -> ApiResponse {
+    (
+        status,
+        Json(serde_json::json!({
+            "success": false,
+            "message": message
+        })),
+    )
+}
+
+fn forbidden() -> ApiResponse {
+    error(
+        StatusCode::FORBIDDEN,
+        "You don't have access to this instance",
+    )
+}
+
+fn apply(rule: &mut AutoReplyRule, req: RuleRequest) {
+    if let Some(v) = req.name {
+        rule.name = v;
+    }
+    if let Some(v) = req.match_type {
+        rule.match_type = v;
+    }
+    if let Some(v) = req.pattern {
+        rule.pattern = Some(v);
+    }
+    if let Some(v) = req.case_sensitive {
+        rule.case_sensitive = v;
+    }
+    if let Some(v) = req.scope {
+        rule.scope = v;
+    }
+    if let Some(v) = req.chats {
+        rule.chats = serde_json::to_string(&v).unwrap_or_else(|_| "[]".to_string());
+    }
+    if let Some(v) = req.cooldown_seconds {
+        rule.cooldown_seconds = v;
+    }
+    if let Some(v) = req.response_type {
+        rule.response_type = v;
+    }
+    if let Some(v) = req.response_text {
+        rule.response_text = Some(v);
+    }
+    if let Some(v) = req.media_url {
+        rule.media_url = Some(v);
+    }
+    if let Some(v) = req.media_type {
+        rule.media_type = Some(v);
+    }
+    if let Some(v) = req.file_name {
+        rule.file_name = Some(v);
+    }
+    if let Some(v) = req.template_id {
+        rule.template_id = Some(v);
+    }
+    if let Some(v) = req.priority {
+        rule.priority = v;
+    }
+    if let Some(v) = req.enabled {
+        rule.enabled = v;
+    }
+}
+
+fn validate(rule: &AutoReplyRule) -> Result<(), String> {
+    if rule.name.trim().is_empty() {
+        return Err("name is required".to_string());
+    }
+    if !autoreply::MATCH_TYPES.contains(&rule.match_type.as_str()) {
+        return Err(format!(
+            "matchType must be one of: {}",
+            autoreply::MATCH_TYPES.join(", ")
+        ));
+    }
+    if rule.match_type != "first_message" {
+        match rule.pattern.as_deref() {
+            Some(p) if !p.is_empty() => {}
+            _ => return Err("pattern is required for this matchType".to_string()),
+        }
+    }
+    if rule.match_type == "regex"
+        && let Err(e) =
+            autoreply::compile(rule.pattern.as_deref().unwrap_or(""), rule.case_sensitive)
+    {
+        return Err(format!("Invalid regex: {}", e));
+    }
+    if !autoreply::SCOPES.contains(&rule.scope.as_str()) {
+        return Err(format!(
+            "scope must be one of: {}",
+            autoreply::SCOPES.join(", ")
+        ));
+    }
+    if rule.scope == "chats" && rule.chats == "[]" {
+        return Err("chats is required for the chats scope".to_string());
+    }
+    if rule.cooldown_seconds < 0 {
+        return Err("cooldownSeconds can't be negative".to_string());
+    }
+    if !autoreply::RESPONSE_TYPES.contains(&rule.response_type.as_str()) {
+        return Err(format!(
+            "responseType must be one of: {}",
+            autoreply::RESPONSE_TYPES.join(", ")
+        ));
+    }
+    if rule.response_type == "media" && rule.media_url.as_deref().unwrap_or("").is_empty() {
+        return Err("mediaUrl is required for media responses".to_string());
+    }
+    if rule.response_type == "template" && rule.template_id.is_none() {
+        return Err("templateId is required for template responses".to_string());
+    }
+    if rule.response_type == "text" && rule.response_text.as_deref().unwrap_or("").is_empty() {
+        return Err("responseText is required".to_string());
+    }
+    Ok(())
+}
+
+/// Template responses must reference a template of one of the instance's users
+async fn validate_template(state: &Arc<AppState>, rule: &AutoReplyRule) -> Result<(), String> {
+    match rule.template_id {
+        Some(id) if rule.response_type == "template" => {
+            templates::find_by_id_for_instance(&state.db, &rule.session_id, id)
+                .await
+                .map(|_| ())
+                .ok_or_else(|| format!("Template {} not found", id))
+        }
+        _ => Ok(()),
+    }
+}
+
+async fn find_rule(state: &Arc<AppState>, phone: &str, rule_id: i64) -> Option<AutoReplyRule> {
+    sqlx::query_as("SELECT * FROM auto_reply_rules WHERE id = ? AND sessionId = ?")
+        .bind(rule_id)
+        .bind(phone)
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None)
+}
+
+/// List an instance's auto-reply rules with their hit counters
+pub async fn list_rules(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+    claims: Option<Extension<Claims>>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+
+    let rules: Vec<AutoReplyRule> = sqlx::query_as(
+        "SELECT * FROM auto_reply_rules WHERE sessionId = ? ORDER BY priority DESC, id ASC",
+    )
+    .bind(&phone)
+    .fetch_all(&state.db)
+    .await
+    .unwrap_or_default();
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "success": true,
+            "rules": rules
+        })),
+    )
+}
+
+/// Create an auto-reply rule
+pub async fn create_rule(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+    claims: Option<Extension<Claims>>,
+    Json(payload): Json<RuleRequest>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+
+    let now = chrono::Utc::now();
+    let mut rule = AutoReplyRule {
+        id: 0,
+        session_id: phone.clone(),
+        name: String::new(),
+        match_type: String::new(),
+        pattern: None,
+        case_sensitive: false,
+        scope: "all".to_string(),
+        chats: "[]".to_string(),
+        cooldown_seconds: autoreply::DEFAULT_COOLDOWN_SECONDS,
+        response_type: "text".to_string(),
+        response_text: None,
+        media_url: None,
+        media_type: None,
+        file_name: None,
+        template_id: None,
+        priority: 0,
+        enabled: true,
+        hits: 0,
+        last_hit_at: None,
+        created_at: now,
+        updated_at: now,
+    };
+    apply(&mut rule, payload);
+    if let Err(e) = validate(&rule) {
+        return error(StatusCode::BAD_REQUEST, &e);
+    }
+    if let Err(e) = validate_template(&state, &rule).await {
+        return error(StatusCode::BAD_REQUEST, &e);
+    }
+
+    let result = sqlx::query(
+        "INSERT INTO auto_reply_rules (sessionId, name, matchType, pattern, caseSensitive, scope, chats,
+            cooldownSeconds, responseType, responseText, mediaUrl, mediaType, fileName, templateId, priority,
+            enabled, createdAt, updatedAt)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&phone)
+    .bind(&rule.name)
+    .bind(&rule.match_type)
+    .bind(&rule.pattern)
+    .bind(rule.case_sensitive)
+    .bind(&rule.scope)
+    .bind(&rule.chats)
+    .bind(rule.cooldown_seconds)
+    .bind(&rule.response_type)
+    .bind(&rule.response_text)
+    .bind(&rule.media_url)
+    .bind(&rule.media_type)
+    .bind(&rule.file_name)
+    .bind(rule.template_id)
+    .bind(rule.priority)
+    .bind(rule.enabled)
+    .bind(now)
+    .bind(now)
+    .execute(&state.db)
+    .await;
+
+    match result {
+        Ok(r) => (
+            StatusCode::CREATED,
+            Json(serde_json::json!({
+                "success": true,
+                "rule": find_rule(&state, &phone, r.last_insert_rowid()).await
+            })),
+        ),
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to create rule: {}", e),
+        ),
+    }
+}
+
+/// Update an auto-reply rule
+pub async fn update_rule(
+    State(state): State<Arc<AppState>>,
+    Path((phone, rule_id)): Path<(String, i64)>,
+    claims: Option<Extension<Claims>>,
+    Json(payload): Json<RuleRequest>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+    let mut rule = match find_rule(&state, &phone, rule_id).await {
+        Some(r) => r,
+        None => return error(StatusCode::NOT_FOUND, "Rule not found"),
+    };
+
+    apply(&mut rule, payload);
+    if let Err(e) = validate(&rule) {
+        return error(StatusCode::BAD_REQUEST, &e);
+    }
+    if let Err(e) = validate_template(&state, &rule).await {
+        return error(StatusCode::BAD_REQUEST, &e);
+    }
+
+    let result = sqlx::query(
+        "UPDATE auto_reply_rules SET name = ?, matchType = ?, pattern = ?, caseSensitive = ?, scope = ?,
+            chats = ?, cooldownSeconds = ?, responseType = ?, responseText = ?, mediaUrl = ?, mediaType = ?,
+            fileName = ?, templateId = ?, priority = ?, enabled = ?, updatedAt = ?
+         WHERE id = ?",
+    )
+    .bind(&rule.name)
+    .bind(&rule.match_type)
+    .bind(&rule.pattern)
+    .bind(rule.case_sensitive)
+    .bind(&rule.scope)
+    .bind(&rule.chats)
+    .bind(rule.cooldown_seconds)
+    .bind(&rule.response_type)
+    .bind(&rule.response_text)
+    .bind(&rule.media_url)
+    .bind(&rule.media_type)
+    .bind(&rule.file_name)
+    .bind(rule.template_id)
+    .bind(rule.priority)
+    .bind(rule.enabled)
+    .bind(chrono::Utc::now())
+    .bind(rule_id)
+    .execute(&state.db)
+    .await;
+
+    match result {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "success": true,
+                "rule": find_rule(&state, &phone, rule_id).await
+            })),
+        ),
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to update rule: {}", e),
+        ),
+    }
+}
+
+/// Delete an auto-reply rule
+pub async fn delete_rule(
+    State(state): State<Arc<AppState>>,
+    Path((phone, rule_id)): Path<(String, i64)>,
+    claims: Option<Extension<Claims>>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+
+    match sqlx::query("DELETE FROM auto_reply_rules WHERE id = ? AND sessionId = ?")
+        .bind(rule_id)
+        .bind(&phone)
+        .execute(&state.db)
+        .await
+    {
+        Ok(r) if r.rows_affected() > 0 => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "success": true,
+                "message": "Rule deleted"
+            })),
+        ),
+        Ok(_) => error(StatusCode::NOT_FOUND, "Rule not found"),
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to delete rule: {}", e),
+        ),
+    }
+}
+
+/// Reset a rule's hit counter
+pub async fn reset_rule_hits(
+    State(state): State<Arc<AppState>>,
+    Path((phone, rule_id)): Path<(String, i64)>,
+    claims: Option<Extension<Claims>>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+
+    match sqlx::query(
+        "UPDATE auto_reply_rules SET hits = 0, lastHitAt = NULL WHERE id = ? AND sessionId = ?",
+    )
+    .bind(rule_id)
+    .bind(&phone)
+    .execute(&state.db)
+    .await
+    {
+        Ok(r) if r.rows_affected() > 0 => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "success": true,
+                "message": "Hit counter reset"
+            })),
+        ),
+        Ok(_) => error(StatusCode::NOT_FOUND, "Rule not found"),
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to reset rule: {}", e),
+        ),
+    }
+}
@@ -0,0 +1,284 @@
+use crate::AppState;
+use crate::manager::broadcasts::{self, BroadcastMessage};
+use crate::manager::contacts;
+use crate::routes::access::can_access_instance;
+use crate::security::Claims;
+use crate::sql::Broadcast;
+use axum::{
+    Extension, Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateBroadcastRequest {
+    pub name: Option<String>,
+    /// Audience: contacts carrying any of these tags
+    pub tags: Vec<String>,
+    /// Require every tag instead of any
+    #[serde(default, rename = "matchAll")]
+    pub match_all: bool,
+    pub message: BroadcastMessage,
+}
+
+type ApiResponse = (StatusCode, Json<serde_json::Value>);
+
+fn error(status: StatusCode, message: &str) -> ApiResponse {
+    (
+        status,
+        Json(serde_json::json!({
+            "success": false,
+            "message": message
+        })),
+    )
+}
+
+fn forbidden() -> ApiResponse {
+    error(
+        StatusCode::FORBIDDEN,
+        "You don't have access to this instance",
+    )
+}
+
+async fn find_broadcast(
+    state: &Arc<AppState>,
+    phone: &str,
+    broadcast_id: i64,
+) -> Result<Broadcast, ApiResponse> {
+    sqlx::query_as("SELECT * FROM broadcasts WHERE id = ? AND sessionId = ?")
+        .bind(broadcast_id)
+        .bind(phone)
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None)
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "Broadcast not found"))
+}
+
+/// List an instance's broadcasts, newest first
+pub async fn list_broadcasts(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+    claims: Option<Extension<Claims>>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+
+    let list: Vec<Broadcast> =
+        sqlx::query_as("SELECT * FROM broadcasts WHERE sessionId = ? ORDER BY id DESC")
+            .bind(&phone)
+            .fetch_all(&state.db)
+            .await
+            .unwrap_or_default();
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "success": true,
+            "broadcasts": list
+        })),
+    )
+}
+
+/// Start a broadcast to every contact matching the tag audience
+pub async fn create_broadcast(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+    claims: Option<Extension<Claims>>,
+    Json(payload): Json<CreateBroadcastRequest>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+    if payload.message.is_empty() {
+        return error(
+            StatusCode::BAD_REQUEST,
+            "message needs text, mediaUrl or template",
+        );
+    }
+    let Some(tags) = payload
+        .tags
+        .iter()
+        .map(|t| contacts::normalize_tag(t))
+        .collect::<Option<Vec<_>>>()
+        .filter(|t| !t.is_empty())
+    else {
+        return error(
+            StatusCode::BAD_REQUEST,
+            "tags must be a non-empty list of tags",
+        );
+    };
+
+    let recipients = contacts::audience(&state.db, &phone, &tags, payload.match_all).await;
+    let Some(first) = recipients.first() else {
+        return error(StatusCode::BAD_REQUEST, "No contacts match the audience");
+    };
+    // Surface template errors now rather than once per recipient
+    if let Err(e) = payload
+        .message
+        .for_recipient(&state.db, &phone, first)
+        .await
+    {
+        return error(StatusCode::UNPROCESSABLE_ENTITY, &e);
+    }
+
+    let now = chrono::Utc::now();
+    let result = async {
+        let mut tx = state.db.begin().await?;
+        let id = sqlx::query(
+            "INSERT INTO broadcasts (sessionId, name, tags, matchAll, message, status, total, createdAt, updatedAt)
+             VALUES (?, ?, ?, ?, ?, 'running', ?, ?, ?)",
+        )
+        .bind(&phone)
+        .bind(&payload.name)
+        .bind(serde_json::to_string(&tags).unwrap_or_else(|_| "[]".to_string()))
+        .bind(payload.match_all)
+        .bind(serde_json::to_string(&payload.message).unwrap_or_default())
+        .bind(recipients.len() as i64)
+        .bind(now)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+        for jid in &recipients {
+            sqlx::query("INSERT INTO broadcast_recipients (broadcastId, jid) VALUES (?, ?)")
+                .bind(id)
+                .bind(jid)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok::<_, sqlx::Error>(id)
+    }
+    .await;
+
+    match result {
+        Ok(id) => {
+            broadcasts::spawn(state.clone(), id);
+            (
+                StatusCode::ACCEPTED,
+                Json(serde_json::json!({
+                    "success": true,
+                    "broadcast": find_broadcast(&state, &phone, id).await.ok()
+                })),
+            )
+        }
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to start broadcast: {}", e),
+        ),
+    }
+}
+
+/// Get a broadcast with its failed recipients
+pub async fn get_broadcast(
+    State(state): State<Arc<AppState>>,
+    Path((phone, broadcast_id)): Path<(String, i64)>,
+    claims: Option<Extension<Claims>>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+    let broadcast = match find_broadcast(&state, &phone, broadcast_id).await {
+        Ok(b) => b,
+        Err(e) => return e,
+    };
+
+    let failures: Vec<(String, Option<String>)> = sqlx::query_as(
+        "SELECT jid, error FROM broadcast_recipients WHERE broadcastId = ? AND status = 'failed' ORDER BY jid",
+    )
+    .bind(broadcast_id)
+    .fetch_all(&state.db)
+    .await
+    .unwrap_or_default();
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "success": true,
+            "broadcast": broadcast,
+            "failures": failures.into_iter().map(|(jid, error)| serde_json::json!({
+                "jid": jid,
+                "error": error
+            })).collect::<Vec<_>>()
+        })),
+    )
+}
+
+/// Continue a paused broadcast
+pub async fn resume_broadcast(
+    State(state): State<Arc<AppState>>,
+    Path((phone, broadcast_id)): Path<(String, i64)>,
+    claims: Option<Extension<Claims>>,
+) -> ApiResponse {
+    set_status(state, phone, broadcast_id, claims, "running").await
+}
+
+/// Stop a broadcast, recipients not reached yet are skipped
+pub async fn cancel_broadcast(
+    State(state): State<Arc<AppState>>,
+    Path((phone, broadcast_id)): Path<(String, i64)>,
+    claims: Option<Extension<Claims>>,
+) -> ApiResponse {
+    set_status(state, phone, broadcast_id, claims, "cancelled").await
+}
+
+async fn set_status(
+    state: Arc<AppState>,
+    phone: String,
+    broadcast_id: i64,
+    claims: Option<Extension<Claims>>,
+    status: &str,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+    let broadcast = match find_broadcast(&state, &phone, broadcast_id).await {
+        Ok(b) => b,
+        Err(e) => return e,
+    };
+    let allowed = match status {
+        "running" => broadcast.status == "paused",
+        _ => matches!(broadcast.status.as_str(), "running" | "paused"),
+    };
+    if !allowed {
+        return error(
+            StatusCode::CONFLICT,
+            &format!("Broadcast is {}", broadcast.status),
+        );
+    }
+
+    let now = chrono::Utc::now();
+    let result = sqlx::query(
+        "UPDATE broadcasts SET status = ?, updatedAt = ?, finishedAt = CASE WHEN ? = 'cancelled' THEN ? ELSE finishedAt END
+         WHERE id = ?",
+    )
+    .bind(status)
+    .bind(now)
+    .bind(status)
+    .bind(now)
+    .bind(broadcast_id)
+    .execute(&state.db)
+    .await;
+
+    match result {
+        Ok(_) => {
+            if status == "running" {
+                broadcasts::spawn(state.clone(), broadcast_id);
+            }
+            (
+                StatusCode::OK,
+                Json(serde_json::json!({
+                    "success": true,
+                    "broadcast": find_broadcast(&state, &phone, broadcast_id).await.ok()
+                })),
+            )
+        }
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to update broadcast: {}", e),
+        ),
+    }
+}
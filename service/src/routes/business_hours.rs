@@ -0,0 +1,171 @@
+use crate::AppState;
+use crate::manager::away::{self, WeeklyHours};
+use crate::routes::access::can_access_instance;
+use crate::security::Claims;
+use crate::sql::BusinessHours;
+use axum::{
+    Extension, Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// Omitted fields keep their current value
+#[derive(Debug, Deserialize)]
+pub struct BusinessHoursRequest {
+    pub enabled: Option<bool>,
+    pub timezone: Option<String>,
+    pub hours: Option<WeeklyHours>,
+    pub holidays: Option<Vec<String>>,
+    pub greeting: Option<String>,
+    #[serde(rename = "awayMessage")]
+    pub away_message: Option<String>,
+    #[serde(rename = "awayCooldownHours")]
+    pub away_cooldown_hours: Option<i64>,
+}
+
+fn forbidden() -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::FORBIDDEN,
+        Json(serde_json::json!({
+            "success": false,
+            "message": "You don't have access to this instance"
+        })),
+    )
+}
+
+async fn load(state: &Arc<AppState>, phone: &str) -> BusinessHours {
+    sqlx::query_as("SELECT * FROM business_hours WHERE sessionId = ?")
+        .bind(phone)
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None)
+        .unwrap_or_else(|| BusinessHours {
+            session_id: phone.to_string(),
+            enabled: false,
+            timezone: "UTC".to_string(),
+            hours: "{}".to_string(),
+            holidays: "[]".to_string(),
+            greeting: None,
+            away_message: None,
+            away_cooldown_hours: 24,
+            updated_at: chrono::Utc::now(),
+        })
+}
+
+fn with_status(config: &BusinessHours) -> serde_json::Value {
+    serde_json::json!({
+        "success": true,
+        "businessHours": config,
+        "openNow": away::is_open(config, chrono::Utc::now())
+    })
+}
+
+/// Get an instance's business hours, greeting and away message
+pub async fn get_business_hours(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+    claims: Option<Extension<Claims>>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+
+    let config = load(&state, &phone).await;
+    (StatusCode::OK, Json(with_status(&config)))
+}
+
+/// Update an instance's business hours, greeting and away message
+pub async fn update_business_hours(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+    claims: Option<Extension<Claims>>,
+    Json(payload): Json<BusinessHoursRequest>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+
+    let mut config = load(&state, &phone).await;
+    if let Some(v) = payload.enabled {
+        config.enabled = v;
+    }
+    if let Some(v) = payload.timezone {
+        config.timezone = v;
+    }
+    if let Some(ref v) = payload.hours {
+        config.hours = serde_json::to_string(v).unwrap_or_else(|_| "{}".to_string());
+    }
+    if let Some(ref v) = payload.holidays {
+        config.holidays = serde_json::to_string(v).unwrap_or_else(|_| "[]".to_string());
+    }
+    if let Some(v) = payload.greeting {
+        config.greeting = Some(v).filter(|g| !g.is_empty());
+    }
+    if let Some(v) = payload.away_message {
+        config.away_message = Some(v).filter(|a| !a.is_empty());
+    }
+    if let Some(v) = payload.away_cooldown_hours {
+        config.away_cooldown_hours = v;
+    }
+
+    let hours: WeeklyHours = serde_json::from_str(&config.hours).unwrap_or_default();
+    let holidays: Vec<String> = serde_json::from_str(&config.holidays).unwrap_or_default();
+    let validation = away::validate(&config.timezone, &hours, &holidays).and_then(|_| {
+        if !(1..=away::MAX_AWAY_COOLDOWN_HOURS).contains(&config.away_cooldown_hours) {
+            Err(format!(
+                "awayCooldownHours must be between 1 and {}",
+                away::MAX_AWAY_COOLDOWN_HOURS
+            ))
+        } else {
+            Ok(())
+        }
+    });
+    if let Err(e) = validation {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "success": false,
+                "message": e
+            })),
+        );
+    }
+
+    config.updated_at = chrono::Utc::now();
+    let result = sqlx::query(
+        "INSERT INTO business_hours (sessionId, enabled, timezone, hours, holidays, greeting, awayMessage, awayCooldownHours, updatedAt)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+         ON CONFLICT(sessionId) DO UPDATE SET
+            enabled = excluded.enabled,
+            timezone = excluded.timezone,
+            hours = excluded.hours,
+            holidays = excluded.holidays,
+            greeting = excluded.greeting,
+            awayMessage = excluded.awayMessage,
+            awayCooldownHours = excluded.awayCooldownHours,
+            updatedAt = excluded.updatedAt",
+    )
+    .bind(&phone)
+    .bind(config.enabled)
+    .bind(&config.timezone)
+    .bind(&config.hours)
+    .bind(&config.holidays)
+    .bind(&config.greeting)
+    .bind(&config.away_message)
+    .bind(config.away_cooldown_hours)
+    .bind(config.updated_at)
+    .execute(&state.db)
+    .await;
+
+    match result {
+        Ok(_) => (StatusCode::OK, Json(with_status(&config))),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({
+                "success": false,
+                "message": format!("Failed to save business hours: {}", e)
+            })),
+        ),
+    }
+}
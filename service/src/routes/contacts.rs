@@ -0,0 +1,614 @@
+use crate::AppState;
+use crate::manager::contacts::{self, ImportedContact};
+use crate::routes::access::can_access_instance;
+use crate::routes::messages::to_jid;
+use crate::security::Claims;
+use crate::sql::Contact;
+use axum::{
+    Extension, Json,
+    extract::{Path, Query, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use sqlx::{QueryBuilder, Sqlite, SqliteConnection};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 500;
+/// Rejected rows reported back from an import
+const MAX_REPORTED_SKIPS: usize = 100;
+
+/// Tags come back as a sorted JSON array, the display name falls back to the number
+const CONTACT_SELECT: &str = "SELECT c.contactPn, c.contactLid, c.name,
+        COALESCE(NULLIF(c.name, ''), substr(c.contactPn, 1, instr(c.contactPn || '@', '@') - 1)) AS displayName,
+        (SELECT COALESCE(json_group_array(tag), '[]') FROM
+            (SELECT tag FROM contact_tags t WHERE t.sessionId = c.sessionId AND t.contactPn = c.contactPn ORDER BY tag)) AS tags,
+        c.customFields, c.addedAt
+    FROM contacts c WHERE c.sessionId = ";
+
+#[derive(Debug, Deserialize)]
+pub struct ContactsQuery {
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+    /// Matches name, number or LID
+    pub q: Option<String>,
+    pub tag: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateContactRequest {
+    /// Phone number or JID
+    pub phone: String,
+    pub name: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default, rename = "customFields")]
+    pub custom_fields: HashMap<String, String>,
+}
+
+/// Omitted fields keep their current value
+#[derive(Debug, Deserialize)]
+pub struct UpdateContactRequest {
+    /// Empty string clears the name
+    pub name: Option<String>,
+    /// Replaces every tag
+    pub tags: Option<Vec<String>>,
+    /// Merged into the current fields, `null` removes a field
+    #[serde(rename = "customFields")]
+    pub custom_fields: Option<HashMap<String, Option<String>>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TransferQuery {
+    /// csv or vcard, detected from the file when omitted on import
+    pub format: Option<String>,
+    /// Export only contacts with this tag
+    pub tag: Option<String>,
+    /// Tags added to every imported contact
+    pub tags: Option<String>,
+}
+
+type ApiResponse = (StatusCode, Json<serde_json::Value>);
+
+fn error(status: StatusCode, message: &str) -> ApiResponse {
+    (
+        status,
+        Json(serde_json::json!({
+            "success": false,
+            "message": message
+        })),
+    )
+}
+
+fn forbidden() -> ApiResponse {
+    error(
+        StatusCode::FORBIDDEN,
+        "You don't have access to this instance",
+    )
+}
+
+fn encode_cursor(jid: &str) -> String {
+    base64::Engine::encode(&base64::engine::general_purpose::URL_SAFE_NO_PAD, jid)
+}
+
+fn decode_cursor(cursor: &str) -> Option<String> {
+    let raw =
+        base64::Engine::decode(&base64::engine::general_purpose::URL_SAFE_NO_PAD, cursor).ok()?;
+    String::from_utf8(raw).ok()
+}
+
+fn normalize_tags(tags: &[String]) -> Result<Vec<String>, ApiResponse> {
+    let mut out = Vec::with_capacity(tags.len());
+    for tag in tags {
+        match contacts::normalize_tag(tag) {
+            Some(t) if !out.contains(&t) => out.push(t),
+            Some(_) => {}
+            None => {
+                return Err(error(
+                    StatusCode::BAD_REQUEST,
+                    &format!("Invalid tag '{}'", tag),
+                ));
+            }
+        }
+    }
+    Ok(out)
+}
+
+fn contact_jid(raw: &str) -> Result<String, ApiResponse> {
+    to_jid(raw).ok_or_else(|| error(StatusCode::BAD_REQUEST, "Invalid phone number"))
+}
+
+async fn find_contact(state: &Arc<AppState>, phone: &str, jid: &str) -> Option<Contact> {
+    let mut qb = QueryBuilder::<Sqlite>::new(CONTACT_SELECT);
+    qb.push_bind(phone);
+    qb.push(" AND c.contactPn = ").push_bind(jid);
+    qb.build_query_as()
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None)
+}
+
+/// Create the contact if needed and apply the given changes. Returns whether it was created.
+async fn save_contact(
+    conn: &mut SqliteConnection,
+    phone: &str,
+    jid: &str,
+    name: Option<&str>,
+    fields_patch: Option<serde_json::Value>,
+) -> Result<bool, sqlx::Error> {
+    let now = chrono::Utc::now();
+    let created = sqlx::query(
+        "INSERT INTO contacts (sessionId, contactPn, addedAt, createdAt) VALUES (?, ?, ?, ?)
+         ON CONFLICT(sessionId, contactPn) DO NOTHING",
+    )
+    .bind(phone)
+    .bind(jid)
+    .bind(now)
+    .bind(now)
+    .execute(&mut *conn)
+    .await?
+    .rows_affected()
+        > 0;
+
+    if let Some(name) = name {
+        sqlx::query(
+            "UPDATE contacts SET name = NULLIF(?, '') WHERE sessionId = ? AND contactPn = ?",
+        )
+        .bind(name.trim())
+        .bind(phone)
+        .bind(jid)
+        .execute(&mut *conn)
+        .await?;
+    }
+    if let Some(patch) = fields_patch {
+        sqlx::query(
+            "UPDATE contacts SET customFields = json_patch(COALESCE(customFields, '{}'), ?)
+             WHERE sessionId = ? AND contactPn = ?",
+        )
+        .bind(patch.to_string())
+        .bind(phone)
+        .bind(jid)
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(created)
+}
+
+async fn add_tags(
+    conn: &mut SqliteConnection,
+    phone: &str,
+    jid: &str,
+    tags: &[String],
+) -> Result<(), sqlx::Error> {
+    for tag in tags {
+        sqlx::query(
+            "INSERT OR IGNORE INTO contact_tags (sessionId, contactPn, tag) VALUES (?, ?, ?)",
+        )
+        .bind(phone)
+        .bind(jid)
+        .bind(tag)
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+/// List an instance's contacts by number, with search and tag filters
+pub async fn list_contacts(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+    claims: Option<Extension<Claims>>,
+    Query(query): Query<ContactsQuery>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let cursor = match query.cursor.as_deref().map(decode_cursor) {
+        Some(None) => return error(StatusCode::BAD_REQUEST, "Invalid cursor"),
+        Some(c) => c,
+        None => None,
+    };
+
+    let mut qb = QueryBuilder::<Sqlite>::new(CONTACT_SELECT);
+    qb.push_bind(&phone);
+    if let Some(q) = query.q.as_deref().map(str::trim).filter(|q| !q.is_empty()) {
+        let pattern = format!(
+            "%{}%",
+            q.replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+        );
+        qb.push(" AND (c.name LIKE ")
+            .push_bind(pattern.clone())
+            .push(" ESCAPE '\\' OR c.contactPn LIKE ")
+            .push_bind(pattern.clone())
+            .push(" ESCAPE '\\' OR c.contactLid LIKE ")
+            .push_bind(pattern)
+            .push(" ESCAPE '\\')");
+    }
+    if let Some(tag) = query.tag.as_deref().and_then(contacts::normalize_tag) {
+        qb.push(
+            " AND EXISTS (SELECT 1 FROM contact_tags t WHERE t.sessionId = c.sessionId AND t.contactPn = c.contactPn AND t.tag = ",
+        )
+        .push_bind(tag)
+        .push(")");
+    }
+    if let Some(after) = &cursor {
+        qb.push(" AND c.contactPn > ").push_bind(after);
+    }
+    qb.push(" ORDER BY c.contactPn LIMIT ").push_bind(limit);
+
+    match qb.build_query_as::<Contact>().fetch_all(&state.db).await {
+        Ok(list) => {
+            let next_cursor = (list.len() as i64 == limit)
+                .then(|| list.last().map(|c| encode_cursor(&c.contact_pn)))
+                .flatten();
+            (
+                StatusCode::OK,
+                Json(serde_json::json!({
+                    "success": true,
+                    "contacts": list,
+                    "nextCursor": next_cursor
+                })),
+            )
+        }
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to load contacts: {}", e),
+        ),
+    }
+}
+
+/// Get a single contact
+pub async fn get_contact(
+    State(state): State<Arc<AppState>>,
+    Path((phone, contact)): Path<(String, String)>,
+    claims: Option<Extension<Claims>>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+    let jid = match contact_jid(&contact) {
+        Ok(j) => j,
+        Err(e) => return e,
+    };
+
+    match find_contact(&state, &phone, &jid).await {
+        Some(c) => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "success": true,
+                "contact": c
+            })),
+        ),
+        None => error(StatusCode::NOT_FOUND, "Contact not found"),
+    }
+}
+
+/// Add a contact, or merge into an existing one
+pub async fn create_contact(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+    claims: Option<Extension<Claims>>,
+    Json(payload): Json<CreateContactRequest>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+    let (jid, tags) =
+        match contact_jid(&payload.phone).and_then(|j| Ok((j, normalize_tags(&payload.tags)?))) {
+            Ok(v) => v,
+            Err(e) => return e,
+        };
+    let fields = (!payload.custom_fields.is_empty())
+        .then(|| serde_json::to_value(&payload.custom_fields).unwrap_or_default());
+
+    let result = async {
+        let mut tx = state.db.begin().await?;
+        let created = save_contact(&mut tx, &phone, &jid, payload.name.as_deref(), fields).await?;
+        add_tags(&mut tx, &phone, &jid, &tags).await?;
+        tx.commit().await?;
+        Ok::<_, sqlx::Error>(created)
+    }
+    .await;
+
+    match result {
+        Ok(created) => (
+            if created {
+                StatusCode::CREATED
+            } else {
+                StatusCode::OK
+            },
+            Json(serde_json::json!({
+                "success": true,
+                "contact": find_contact(&state, &phone, &jid).await
+            })),
+        ),
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to save contact: {}", e),
+        ),
+    }
+}
+
+/// Update a contact's name, tags or custom fields
+pub async fn update_contact(
+    State(state): State<Arc<AppState>>,
+    Path((phone, contact)): Path<(String, String)>,
+    claims: Option<Extension<Claims>>,
+    Json(payload): Json<UpdateContactRequest>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+    let jid = match contact_jid(&contact) {
+        Ok(j) => j,
+        Err(e) => return e,
+    };
+    if find_contact(&state, &phone, &jid).await.is_none() {
+        return error(StatusCode::NOT_FOUND, "Contact not found");
+    }
+    let tags = match payload.tags.as_deref().map(normalize_tags).transpose() {
+        Ok(t) => t,
+        Err(e) => return e,
+    };
+    let fields = payload
+        .custom_fields
+        .map(|f| serde_json::to_value(f).unwrap_or_default());
+
+    let result = async {
+        let mut tx = state.db.begin().await?;
+        save_contact(&mut tx, &phone, &jid, payload.name.as_deref(), fields).await?;
+        if let Some(ref tags) = tags {
+            sqlx::query("DELETE FROM contact_tags WHERE sessionId = ? AND contactPn = ?")
+                .bind(&phone)
+                .bind(&jid)
+                .execute(&mut *tx)
+                .await?;
+            add_tags(&mut tx, &phone, &jid, tags).await?;
+        }
+        tx.commit().await
+    }
+    .await;
+
+    match result {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "success": true,
+                "contact": find_contact(&state, &phone, &jid).await
+            })),
+        ),
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to update contact: {}", e),
+        ),
+    }
+}
+
+/// Delete a contact and its tags
+pub async fn delete_contact(
+    State(state): State<Arc<AppState>>,
+    Path((phone, contact)): Path<(String, String)>,
+    claims: Option<Extension<Claims>>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+    let jid = match contact_jid(&contact) {
+        Ok(j) => j,
+        Err(e) => return e,
+    };
+
+    match sqlx::query("DELETE FROM contacts WHERE sessionId = ? AND contactPn = ?")
+        .bind(&phone)
+        .bind(&jid)
+        .execute(&state.db)
+        .await
+    {
+        Ok(r) if r.rows_affected() > 0 => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "success": true,
+                "message": "Contact deleted"
+            })),
+        ),
+        Ok(_) => error(StatusCode::NOT_FOUND, "Contact not found"),
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to delete contact: {}", e),
+        ),
+    }
+}
+
+/// Tags in use on the instance with their contact counts
+pub async fn list_tags(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+    claims: Option<Extension<Claims>>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+
+    let tags: Vec<(String, i64)> = sqlx::query_as(
+        "SELECT tag, COUNT(*) FROM contact_tags WHERE sessionId = ? GROUP BY tag ORDER BY tag",
+    )
+    .bind(&phone)
+    .fetch_all(&state.db)
+    .await
+    .unwrap_or_default();
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "success": true,
+            "tags": tags.into_iter().map(|(tag, count)| serde_json::json!({
+                "tag": tag,
+                "contacts": count
+            })).collect::<Vec<_>>()
+        })),
+    )
+}
+
+/// Import contacts from a CSV or vCard request body, merging into existing ones
+pub async fn import_contacts(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+    claims: Option<Extension<Claims>>,
+    Query(query): Query<TransferQuery>,
+    body: String,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+
+    let format = query.format.unwrap_or_else(|| {
+        if body
+            .trim_start()
+            .to_ascii_uppercase()
+            .starts_with("BEGIN:VCARD")
+        {
+            "vcard".to_string()
+        } else {
+            "csv".to_string()
+        }
+    });
+    let parsed: Vec<ImportedContact> = match format.as_str() {
+        "csv" => match contacts::parse_csv(&body) {
+            Ok(c) => c,
+            Err(e) => return error(StatusCode::BAD_REQUEST, &e),
+        },
+        "vcard" => contacts::parse_vcard(&body),
+        _ => return error(StatusCode::BAD_REQUEST, "format must be csv or vcard"),
+    };
+    let extra_tags: Vec<String> = query
+        .tags
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .filter(|t| !t.trim().is_empty())
+        .map(str::to_string)
+        .collect();
+    let extra_tags = match normalize_tags(&extra_tags) {
+        Ok(t) => t,
+        Err(e) => return e,
+    };
+
+    let mut skipped = Vec::new();
+    let mut rows = Vec::new();
+    for (i, contact) in parsed.into_iter().enumerate() {
+        match to_jid(&contact.phone) {
+            Some(jid) => rows.push((jid, contact)),
+            None => skipped.push(serde_json::json!({
+                "row": i + 1,
+                "phone": contact.phone,
+                "reason": "Invalid phone number"
+            })),
+        }
+    }
+
+    let result = async {
+        let (mut created, mut updated) = (0, 0);
+        let mut tx = state.db.begin().await?;
+        for (jid, contact) in &rows {
+            let fields = (!contact.custom_fields.is_empty())
+                .then(|| serde_json::to_value(&contact.custom_fields).unwrap_or_default());
+            if save_contact(&mut tx, &phone, jid, contact.name.as_deref(), fields).await? {
+                created += 1;
+            } else {
+                updated += 1;
+            }
+            add_tags(&mut tx, &phone, jid, &contact.tags).await?;
+            add_tags(&mut tx, &phone, jid, &extra_tags).await?;
+        }
+        tx.commit().await?;
+        Ok::<_, sqlx::Error>((created, updated))
+    }
+    .await;
+
+    match result {
+        Ok((created, updated)) => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "success": true,
+                "created": created,
+                "updated": updated,
+                "skippedCount": skipped.len(),
+                "skipped": skipped.into_iter().take(MAX_REPORTED_SKIPS).collect::<Vec<_>>()
+            })),
+        ),
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to import contacts: {}", e),
+        ),
+    }
+}
+
+/// Download contacts as CSV or vCard
+pub async fn export_contacts(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+    claims: Option<Extension<Claims>>,
+    Query(query): Query<TransferQuery>,
+) -> Response {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden().into_response();
+    }
+
+    let mut qb = QueryBuilder::<Sqlite>::new(CONTACT_SELECT);
+    qb.push_bind(&phone);
+    if let Some(tag) = query.tag.as_deref().and_then(contacts::normalize_tag) {
+        qb.push(
+            " AND EXISTS (SELECT 1 FROM contact_tags t WHERE t.sessionId = c.sessionId AND t.contactPn = c.contactPn AND t.tag = ",
+        )
+        .push_bind(tag)
+        .push(")");
+    }
+    qb.push(" ORDER BY c.contactPn");
+    let list: Vec<Contact> = qb
+        .build_query_as()
+        .fetch_all(&state.db)
+        .await
+        .unwrap_or_default();
+
+    let (body, content_type, extension) = match query.format.as_deref().unwrap_or("csv") {
+        "csv" => match contacts::to_csv(&list) {
+            Ok(csv) => (csv, "text/csv; charset=utf-8", "csv"),
+            Err(e) => {
+                return error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    &format!("Failed to export contacts: {}", e),
+                )
+                .into_response();
+            }
+        },
+        "vcard" => (
+            contacts::to_vcard(&list),
+            "text/vcard; charset=utf-8",
+            "vcf",
+        ),
+        _ => {
+            return error(StatusCode::BAD_REQUEST, "format must be csv or vcard").into_response();
+        }
+    };
+
+    (
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"contacts-{}.{}\"", phone, extension),
+            ),
+        ],
+        body,
+    )
+        .into_response()
+}
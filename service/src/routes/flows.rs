@@ -0,0 +1,423 @@
+use crate::AppState;
+use crate::manager::flows::{self, FlowDefinition, FlowState};
+use crate::routes::access::can_access_instance;
+use crate::security::Claims;
+use crate::sql::{Flow, FlowVersion};
+use axum::{
+    Extension, Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateFlowRequest {
+    pub name: String,
+    pub definition: serde_json::Value,
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateFlowRequest {
+    pub name: Option<String>,
+    pub enabled: Option<bool>,
+    /// Roll forward or back to an uploaded version
+    #[serde(rename = "activeVersion")]
+    pub active_version: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UploadVersionRequest {
+    pub definition: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum DryRunMessage {
+    Text(String),
+    Timed {
+        text: String,
+        /// Simulated delay since the previous message
+        #[serde(default, rename = "afterSeconds")]
+        after_seconds: i64,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DryRunRequest {
+    pub messages: Vec<DryRunMessage>,
+    /// Defaults to the active version
+    pub version: Option<i64>,
+    /// Simulate an unsaved definition instead of a stored version
+    pub definition: Option<serde_json::Value>,
+}
+
+type ApiResponse = (StatusCode, Json<serde_json::Value>);
+
+fn error(status: StatusCode, message: &str) -> ApiResponse {
+    (
+        status,
+        Json(serde_json::json!({
+            "success": false,
+            "message": message
+        })),
+    )
+}
+
+fn forbidden() -> ApiResponse {
+    error(
+        StatusCode::FORBIDDEN,
+        "You don't have access to this instance",
+    )
+}
+
+fn parse_definition(raw: serde_json::Value) -> Result<FlowDefinition, ApiResponse> {
+    let def: FlowDefinition = serde_json::from_value(raw)
+        .map_err(|e| error(StatusCode::BAD_REQUEST, &format!("Invalid flow: {}", e)))?;
+    flows::validate(&def).map_err(|e| error(StatusCode::BAD_REQUEST, &e))?;
+    Ok(def)
+}
+
+async fn find_flow(state: &Arc<AppState>, phone: &str, flow_id: i64) -> Result<Flow, ApiResponse> {
+    sqlx::query_as("SELECT * FROM flows WHERE id = ? AND sessionId = ?")
+        .bind(flow_id)
+        .bind(phone)
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None)
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "Flow not found"))
+}
+
+/// List an instance's flows
+pub async fn list_flows(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+    claims: Option<Extension<Claims>>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+
+    let flows: Vec<Flow> = sqlx::query_as("SELECT * FROM flows WHERE sessionId = ? ORDER BY id")
+        .bind(&phone)
+        .fetch_all(&state.db)
+        .await
+        .unwrap_or_default();
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "success": true,
+            "flows": flows
+        })),
+    )
+}
+
+/// Create a flow from its first version
+pub async fn create_flow(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+    claims: Option<Extension<Claims>>,
+    Json(payload): Json<CreateFlowRequest>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+    if payload.name.trim().is_empty() {
+        return error(StatusCode::BAD_REQUEST, "name is required");
+    }
+    let raw = payload.definition.to_string();
+    if let Err(e) = parse_definition(payload.definition) {
+        return e;
+    }
+
+    let now = chrono::Utc::now();
+    let result = async {
+        let mut tx = state.db.begin().await?;
+        let flow_id = sqlx::query(
+            "INSERT INTO flows (sessionId, name, enabled, activeVersion, createdAt, updatedAt)
+             VALUES (?, ?, ?, 1, ?, ?)",
+        )
+        .bind(&phone)
+        .bind(payload.name.trim())
+        .bind(payload.enabled.unwrap_or(true))
+        .bind(now)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+        sqlx::query(
+            "INSERT INTO flow_versions (flowId, version, definition, createdAt) VALUES (?, 1, ?, ?)",
+        )
+        .bind(flow_id)
+        .bind(&raw)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok::<_, sqlx::Error>(flow_id)
+    }
+    .await;
+
+    match result {
+        Ok(flow_id) => (
+            StatusCode::CREATED,
+            Json(serde_json::json!({
+                "success": true,
+                "flow": find_flow(&state, &phone, flow_id).await.ok()
+            })),
+        ),
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to create flow: {}", e),
+        ),
+    }
+}
+
+/// Get a flow with all of its versions
+pub async fn get_flow(
+    State(state): State<Arc<AppState>>,
+    Path((phone, flow_id)): Path<(String, i64)>,
+    claims: Option<Extension<Claims>>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+    let flow = match find_flow(&state, &phone, flow_id).await {
+        Ok(f) => f,
+        Err(e) => return e,
+    };
+
+    let versions: Vec<FlowVersion> =
+        sqlx::query_as("SELECT * FROM flow_versions WHERE flowId = ? ORDER BY version DESC")
+            .bind(flow_id)
+            .fetch_all(&state.db)
+            .await
+            .unwrap_or_default();
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "success": true,
+            "flow": flow,
+            "versions": versions
+        })),
+    )
+}
+
+/// Upload a new version of a flow and make it active
+pub async fn upload_version(
+    State(state): State<Arc<AppState>>,
+    Path((phone, flow_id)): Path<(String, i64)>,
+    claims: Option<Extension<Claims>>,
+    Json(payload): Json<UploadVersionRequest>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+    if let Err(e) = find_flow(&state, &phone, flow_id).await {
+        return e;
+    }
+    let raw = payload.definition.to_string();
+    if let Err(e) = parse_definition(payload.definition) {
+        return e;
+    }
+
+    let now = chrono::Utc::now();
+    let result = async {
+        let mut tx = state.db.begin().await?;
+        let version: i64 = sqlx::query_scalar(
+            "SELECT COALESCE(MAX(version), 0) + 1 FROM flow_versions WHERE flowId = ?",
+        )
+        .bind(flow_id)
+        .fetch_one(&mut *tx)
+        .await?;
+        sqlx::query(
+            "INSERT INTO flow_versions (flowId, version, definition, createdAt) VALUES (?, ?, ?, ?)",
+        )
+        .bind(flow_id)
+        .bind(version)
+        .bind(&raw)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("UPDATE flows SET activeVersion = ?, updatedAt = ? WHERE id = ?")
+            .bind(version)
+            .bind(now)
+            .bind(flow_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok::<_, sqlx::Error>(version)
+    }
+    .await;
+
+    match result {
+        Ok(version) => (
+            StatusCode::CREATED,
+            Json(serde_json::json!({
+                "success": true,
+                "version": version
+            })),
+        ),
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to upload flow: {}", e),
+        ),
+    }
+}
+
+/// Rename, enable/disable or switch the active version of a flow
+pub async fn update_flow(
+    State(state): State<Arc<AppState>>,
+    Path((phone, flow_id)): Path<(String, i64)>,
+    claims: Option<Extension<Claims>>,
+    Json(payload): Json<UpdateFlowRequest>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+    let flow = match find_flow(&state, &phone, flow_id).await {
+        Ok(f) => f,
+        Err(e) => return e,
+    };
+
+    let active_version = payload.active_version.unwrap_or(flow.active_version);
+    if flows::load_definition(&state.db, flow_id, active_version)
+        .await
+        .is_none()
+    {
+        return error(StatusCode::BAD_REQUEST, "Unknown version");
+    }
+
+    let result = sqlx::query(
+        "UPDATE flows SET name = ?, enabled = ?, activeVersion = ?, updatedAt = ? WHERE id = ?",
+    )
+    .bind(payload.name.unwrap_or(flow.name))
+    .bind(payload.enabled.unwrap_or(flow.enabled))
+    .bind(active_version)
+    .bind(chrono::Utc::now())
+    .bind(flow_id)
+    .execute(&state.db)
+    .await;
+
+    match result {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "success": true,
+                "flow": find_flow(&state, &phone, flow_id).await.ok()
+            })),
+        ),
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to update flow: {}", e),
+        ),
+    }
+}
+
+/// Delete a flow and all of its versions
+pub async fn delete_flow(
+    State(state): State<Arc<AppState>>,
+    Path((phone, flow_id)): Path<(String, i64)>,
+    claims: Option<Extension<Claims>>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+
+    match sqlx::query("DELETE FROM flows WHERE id = ? AND sessionId = ?")
+        .bind(flow_id)
+        .bind(&phone)
+        .execute(&state.db)
+        .await
+    {
+        Ok(r) if r.rows_affected() > 0 => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "success": true,
+                "message": "Flow deleted"
+            })),
+        ),
+        Ok(_) => error(StatusCode::NOT_FOUND, "Flow not found"),
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to delete flow: {}", e),
+        ),
+    }
+}
+
+/// Simulate a conversation against a flow without touching WhatsApp or live state
+pub async fn dry_run(
+    State(state): State<Arc<AppState>>,
+    Path((phone, flow_id)): Path<(String, i64)>,
+    claims: Option<Extension<Claims>>,
+    Json(payload): Json<DryRunRequest>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+    let flow = match find_flow(&state, &phone, flow_id).await {
+        Ok(f) => f,
+        Err(e) => return e,
+    };
+
+    let version = payload.version.unwrap_or(flow.active_version);
+    let def = match payload.definition {
+        Some(raw) => match parse_definition(raw) {
+            Ok(d) => d,
+            Err(e) => return e,
+        },
+        None => match flows::load_definition(&state.db, flow_id, version).await {
+            Some(d) => d,
+            None => return error(StatusCode::BAD_REQUEST, "Unknown version"),
+        },
+    };
+
+    let mut now = chrono::Utc::now().timestamp();
+    let mut conversation: Option<FlowState> = None;
+    // Message placeholders stay as they are, there is no real contact
+    let context = HashMap::new();
+    let mut transcript = Vec::new();
+
+    for message in payload.messages {
+        let text = match message {
+            DryRunMessage::Text(text) => text,
+            DryRunMessage::Timed {
+                text,
+                after_seconds,
+            } => {
+                now = now.saturating_add(after_seconds.max(0));
+                text
+            }
+        };
+        let step = flows::step(
+            flow_id,
+            version,
+            &def,
+            conversation.take(),
+            &text,
+            &context,
+            now,
+        );
+        transcript.push(serde_json::json!({
+            "in": text,
+            "out": step.replies,
+            "handled": step.handled,
+            "node": step.state.as_ref().map(|s| s.node.clone()),
+            "vars": step.state.as_ref().map(|s| s.vars.clone()),
+            "ended": step.handled && step.state.is_none()
+        }));
+        conversation = step.state;
+    }
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "success": true,
+            "transcript": transcript
+        })),
+    )
+}
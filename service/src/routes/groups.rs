@@ -0,0 +1,367 @@
+use crate::AppState;
+use crate::manager::CommandError;
+use crate::manager::events::worker_command::GroupAction;
+use crate::manager::groups::{self, GroupInfo};
+use crate::routes::access::can_access_instance;
+use crate::routes::messages::to_jid;
+use crate::security::Claims;
+use axum::{
+    Extension, Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateGroupRequest {
+    pub subject: String,
+    /// Phone numbers or JIDs
+    #[serde(default)]
+    pub participants: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ParticipantsRequest {
+    /// add, remove, promote or demote
+    pub action: String,
+    pub participants: Vec<String>,
+}
+
+/// Omitted fields are left untouched
+#[derive(Debug, Deserialize)]
+pub struct UpdateGroupRequest {
+    pub subject: Option<String>,
+    pub description: Option<String>,
+    /// Only admins can send messages
+    #[serde(rename = "announcementOnly")]
+    pub announcement_only: Option<bool>,
+    /// Only admins can edit group info
+    pub locked: Option<bool>,
+}
+
+type ApiResponse = (StatusCode, Json<serde_json::Value>);
+
+fn error(status: StatusCode, message: &str) -> ApiResponse {
+    (
+        status,
+        Json(serde_json::json!({
+            "success": false,
+            "message": message
+        })),
+    )
+}
+
+fn forbidden() -> ApiResponse {
+    error(
+        StatusCode::FORBIDDEN,
+        "You don't have access to this instance",
+    )
+}
+
+fn worker_error(e: CommandError) -> ApiResponse {
+    let status = match e {
+        CommandError::NotConnected => StatusCode::CONFLICT,
+        CommandError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+        CommandError::Rejected(_) => StatusCode::BAD_GATEWAY,
+    };
+    error(status, &e.to_string())
+}
+
+fn group_jid(id: &str) -> Result<String, ApiResponse> {
+    groups::to_group_jid(id).ok_or_else(|| error(StatusCode::BAD_REQUEST, "Invalid group id"))
+}
+
+fn participant_jids(participants: &[String]) -> Result<Vec<String>, ApiResponse> {
+    if participants.is_empty() {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            "At least one participant is required",
+        ));
+    }
+    participants
+        .iter()
+        .map(|p| {
+            to_jid(p).ok_or_else(|| {
+                error(
+                    StatusCode::BAD_REQUEST,
+                    &format!("Invalid participant '{}'", p),
+                )
+            })
+        })
+        .collect()
+}
+
+async fn run(
+    state: &Arc<AppState>,
+    phone: &str,
+    action: &str,
+    jid: &str,
+    participants: Vec<String>,
+    value: &str,
+) -> Result<serde_json::Value, ApiResponse> {
+    groups::run(
+        state,
+        phone,
+        GroupAction {
+            action: action.to_string(),
+            jid: jid.to_string(),
+            participants,
+            value: value.to_string(),
+        },
+    )
+    .await
+    .map_err(worker_error)
+}
+
+/// List the instance's groups from the worker's metadata cache
+pub async fn list_groups(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+    claims: Option<Extension<Claims>>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+
+    let rows: Vec<(String,)> = sqlx::query_as(
+        "SELECT groupInfo FROM groups WHERE sessionId = ? AND groupInfo IS NOT NULL",
+    )
+    .bind(&phone)
+    .fetch_all(&state.db)
+    .await
+    .unwrap_or_default();
+
+    let mut list: Vec<_> = rows
+        .iter()
+        .filter_map(|(raw,)| GroupInfo::parse(raw))
+        .map(|info| info.into_group(false))
+        .collect();
+    list.sort_by_key(|g| g.subject.to_lowercase());
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "success": true,
+            "groups": list
+        })),
+    )
+}
+
+/// Get a group with its participants
+pub async fn get_group(
+    State(state): State<Arc<AppState>>,
+    Path((phone, group_id)): Path<(String, String)>,
+    claims: Option<Extension<Claims>>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+    let jid = match group_jid(&group_id) {
+        Ok(j) => j,
+        Err(e) => return e,
+    };
+
+    let raw: Option<Option<String>> =
+        sqlx::query_scalar("SELECT groupInfo FROM groups WHERE sessionId = ? AND groupId = ?")
+            .bind(&phone)
+            .bind(&jid)
+            .fetch_optional(&state.db)
+            .await
+            .unwrap_or(None);
+
+    match raw.flatten().as_deref().and_then(GroupInfo::parse) {
+        Some(info) => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "success": true,
+                "group": info.into_group(true)
+            })),
+        ),
+        None => error(StatusCode::NOT_FOUND, "Group not found"),
+    }
+}
+
+/// Create a group through the worker
+pub async fn create_group(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+    claims: Option<Extension<Claims>>,
+    Json(payload): Json<CreateGroupRequest>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+    let subject = payload.subject.trim();
+    if subject.is_empty() {
+        return error(StatusCode::BAD_REQUEST, "subject is required");
+    }
+    let participants = match participant_jids(&payload.participants) {
+        Ok(p) => p,
+        Err(e) => return e,
+    };
+
+    match run(&state, &phone, "create", "", participants, subject).await {
+        Ok(metadata) => {
+            groups::store(&state.db, &phone, &metadata).await;
+            let group = serde_json::from_value::<GroupInfo>(metadata)
+                .ok()
+                .map(|info| info.into_group(true));
+            (
+                StatusCode::CREATED,
+                Json(serde_json::json!({
+                    "success": true,
+                    "group": group
+                })),
+            )
+        }
+        Err(e) => e,
+    }
+}
+
+/// Add, remove, promote or demote participants
+pub async fn update_participants(
+    State(state): State<Arc<AppState>>,
+    Path((phone, group_id)): Path<(String, String)>,
+    claims: Option<Extension<Claims>>,
+    Json(payload): Json<ParticipantsRequest>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+    if !groups::PARTICIPANT_ACTIONS.contains(&payload.action.as_str()) {
+        return error(
+            StatusCode::BAD_REQUEST,
+            &format!(
+                "Unknown action '{}', expected one of: {}",
+                payload.action,
+                groups::PARTICIPANT_ACTIONS.join(", ")
+            ),
+        );
+    }
+    let (jid, participants) = match group_jid(&group_id)
+        .and_then(|j| Ok((j, participant_jids(&payload.participants)?)))
+    {
+        Ok(v) => v,
+        Err(e) => return e,
+    };
+
+    match run(&state, &phone, &payload.action, &jid, participants, "").await {
+        Ok(results) => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "success": true,
+                "results": results
+            })),
+        ),
+        Err(e) => e,
+    }
+}
+
+/// Change a group's subject, description or settings
+pub async fn update_group(
+    State(state): State<Arc<AppState>>,
+    Path((phone, group_id)): Path<(String, String)>,
+    claims: Option<Extension<Claims>>,
+    Json(payload): Json<UpdateGroupRequest>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+    let jid = match group_jid(&group_id) {
+        Ok(j) => j,
+        Err(e) => return e,
+    };
+
+    let mut actions: Vec<(&str, String)> = Vec::new();
+    if let Some(subject) = payload.subject {
+        if subject.trim().is_empty() {
+            return error(StatusCode::BAD_REQUEST, "subject can't be empty");
+        }
+        actions.push(("subject", subject.trim().to_string()));
+    }
+    if let Some(description) = payload.description {
+        actions.push(("description", description));
+    }
+    if let Some(announce) = payload.announcement_only {
+        let setting = if announce {
+            "announcement"
+        } else {
+            "not_announcement"
+        };
+        actions.push(("settings", setting.to_string()));
+    }
+    if let Some(locked) = payload.locked {
+        let setting = if locked { "locked" } else { "unlocked" };
+        actions.push(("settings", setting.to_string()));
+    }
+    if actions.is_empty() {
+        return error(StatusCode::BAD_REQUEST, "Nothing to update");
+    }
+
+    for (action, value) in &actions {
+        if let Err(e) = run(&state, &phone, action, &jid, Vec::new(), value).await {
+            return e;
+        }
+    }
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "success": true,
+            "message": "Group updated"
+        })),
+    )
+}
+
+/// Get the group's invite link
+pub async fn get_invite(
+    State(state): State<Arc<AppState>>,
+    Path((phone, group_id)): Path<(String, String)>,
+    claims: Option<Extension<Claims>>,
+) -> ApiResponse {
+    invite(state, phone, group_id, claims, "invite_code").await
+}
+
+/// Revoke the group's invite link and return the new one
+pub async fn revoke_invite(
+    State(state): State<Arc<AppState>>,
+    Path((phone, group_id)): Path<(String, String)>,
+    claims: Option<Extension<Claims>>,
+) -> ApiResponse {
+    invite(state, phone, group_id, claims, "revoke_invite").await
+}
+
+async fn invite(
+    state: Arc<AppState>,
+    phone: String,
+    group_id: String,
+    claims: Option<Extension<Claims>>,
+    action: &str,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+    let jid = match group_jid(&group_id) {
+        Ok(j) => j,
+        Err(e) => return e,
+    };
+
+    match run(&state, &phone, action, &jid, Vec::new(), "").await {
+        Ok(data) => {
+            let code = data
+                .get("code")
+                .and_then(|c| c.as_str())
+                .unwrap_or_default();
+            (
+                StatusCode::OK,
+                Json(serde_json::json!({
+                    "success": true,
+                    "code": code,
+                    "link": format!("https://chat.whatsapp.com/{}", code)
+                })),
+            )
+        }
+        Err(e) => e,
+    }
+}
@@ -98,18 +98,8 @@ pub async fn resume_instance(
     Path(phone): Path<String>,
     State(state): State<Arc<AppState>>,
 ) -> Json<Value> {
-    let is_running = {
-        let workers = state.sm.workers.read().await;
-        workers.get(&phone).map(|w| w.is_running).unwrap_or(false)
-    };
-
-    if !is_running {
-        state.sm.start_instance(&phone, state.clone()).await;
-        Json(json!({"status": "starting", "phone": phone}))
-    } else {
-        state.sm.pause_instance(&phone, false).await;
-        Json(json!({"status": "resuming", "phone": phone}))
-    }
+    let status = state.sm.resume_instance(&phone, state.clone()).await;
+    Json(json!({"status": status, "phone": phone}))
 }
 pub async fn reset_instance(
     Path(phone): Path<String>,
@@ -0,0 +1,386 @@
+use crate::AppState;
+use crate::manager::CommandError;
+use crate::manager::events::worker_command::SendMessage;
+use crate::manager::outbound::{self, OutboundError, SendSource};
+use crate::manager::templates::{self, TemplateRef};
+use crate::routes::access::can_access_instance;
+use crate::security::Claims;
+use crate::sql::{ChatSummary, StoredMessage};
+use axum::{
+    Extension, Json,
+    extract::{Path, Query, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use sqlx::{QueryBuilder, Sqlite};
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize)]
+pub struct SendMessageRequest {
+    /// Phone number or full JID of the recipient
+    pub to: String,
+    pub text: Option<String>,
+    #[serde(rename = "mediaUrl")]
+    pub media_url: Option<String>,
+    #[serde(rename = "mediaType")]
+    pub media_type: Option<String>,
+    #[serde(rename = "fileName")]
+    pub file_name: Option<String>,
+    /// Send a stored template instead of `text`/`mediaUrl`
+    pub template: Option<TemplateRef>,
+}
+
+impl SendMessageRequest {
+    /// Validate the request and build the worker command, rendering the
+    /// template against the recipient when one is referenced
+    pub async fn into_message(
+        self,
+        db: &sqlx::SqlitePool,
+        phone: &str,
+    ) -> Result<SendMessage, String> {
+        let jid = to_jid(&self.to).ok_or("Invalid recipient")?;
+
+        if let Some(ref template) = self.template {
+            return templates::render_ref(db, phone, jid, template).await;
+        }
+
+        if self.text.as_deref().unwrap_or("").is_empty() && self.media_url.is_none() {
+            return Err("Either text, mediaUrl or template is required".to_string());
+        }
+
+        Ok(SendMessage {
+            jid,
+            text: self.text.unwrap_or_default(),
+            media_url: self.media_url.unwrap_or_default(),
+            media_type: self.media_type.unwrap_or_default(),
+            file_name: self.file_name.unwrap_or_default(),
+        })
+    }
+}
+
+/// Normalise a phone number or JID into a WhatsApp JID
+pub fn to_jid(to: &str) -> Option<String> {
+    if to.contains('@') {
+        return Some(to.trim().to_string());
+    }
+    let digits: String = to.chars().filter(|c| c.is_ascii_digit()).collect();
+    if digits.is_empty() {
+        None
+    } else {
+        Some(format!("{}@s.whatsapp.net", digits))
+    }
+}
+
+/// Map a governor error onto an HTTP response, with `Retry-After` when known
+pub fn outbound_error_response(e: &OutboundError) -> Response {
+    let status = match e {
+        OutboundError::NotConnected => StatusCode::CONFLICT,
+        OutboundError::MinuteLimit { .. } | OutboundError::DailyLimit { .. } => {
+            StatusCode::TOO_MANY_REQUESTS
+        }
+        OutboundError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+        OutboundError::OptedOut => StatusCode::UNPROCESSABLE_ENTITY,
+        OutboundError::Worker(CommandError::Timeout) => StatusCode::GATEWAY_TIMEOUT,
+        OutboundError::Worker(_) => StatusCode::BAD_GATEWAY,
+    };
+
+    let body = Json(serde_json::json!({
+        "success": false,
+        "error": e.code(),
+        "message": e.to_string(),
+        "retryAfter": e.retry_after()
+    }));
+
+    match e.retry_after() {
+        Some(secs) => (status, [(header::RETRY_AFTER, secs.to_string())], body).into_response(),
+        None => (status, body).into_response(),
+    }
+}
+
+/// Send a message through an instance
+pub async fn send_message(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+    claims: Option<Extension<Claims>>,
+    Json(payload): Json<SendMessageRequest>,
+) -> Response {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden().into_response();
+    }
+
+    let message = match payload.into_message(&state.db, &phone).await {
+        Ok(m) => m,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "success": false,
+                    "message": e
+                })),
+            )
+                .into_response();
+        }
+    };
+
+    match outbound::send(&state, &phone, SendSource::Api, message).await {
+        Ok(result) => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "success": true,
+                "result": serde_json::from_str::<serde_json::Value>(&result.data).ok()
+            })),
+        )
+            .into_response(),
+        Err(e) => outbound_error_response(&e),
+    }
+}
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+    /// Restrict to a single chat (cross-chat search otherwise)
+    pub chat: Option<String>,
+    /// Message type, e.g. `conversation` or `imageMessage`
+    #[serde(rename = "type")]
+    pub mtype: Option<String>,
+    pub sender: Option<String>,
+    pub from: Option<chrono::DateTime<chrono::Utc>>,
+    pub to: Option<chrono::DateTime<chrono::Utc>>,
+    /// Full-text search over message text
+    pub q: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChatsQuery {
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+}
+
+fn page_size(limit: Option<i64>) -> i64 {
+    limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE)
+}
+
+/// Opaque cursor: base64 of `<unix micros>|<tie breaker>`
+fn encode_cursor(at: chrono::DateTime<chrono::Utc>, tie: &str) -> String {
+    base64::Engine::encode(
+        &base64::engine::general_purpose::URL_SAFE_NO_PAD,
+        format!("{}|{}", at.timestamp_micros(), tie),
+    )
+}
+
+fn decode_cursor(cursor: &str) -> Option<(chrono::DateTime<chrono::Utc>, String)> {
+    let raw =
+        base64::Engine::decode(&base64::engine::general_purpose::URL_SAFE_NO_PAD, cursor).ok()?;
+    let raw = String::from_utf8(raw).ok()?;
+    let (micros, tie) = raw.split_once('|')?;
+    let at = chrono::DateTime::from_timestamp_micros(micros.parse().ok()?)?;
+    Some((at, tie.to_string()))
+}
+
+/// Quote every term so user input is never parsed as FTS5 query syntax
+fn fts_query(q: &str) -> Option<String> {
+    let terms: Vec<String> = q
+        .split_whitespace()
+        .map(|t| format!("\"{}\"", t.replace('"', "\"\"")))
+        .collect();
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+fn forbidden() -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::FORBIDDEN,
+        Json(serde_json::json!({
+            "success": false,
+            "message": "You don't have access to this instance"
+        })),
+    )
+}
+
+fn invalid_cursor() -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(serde_json::json!({
+            "success": false,
+            "message": "Invalid cursor"
+        })),
+    )
+}
+
+/// List an instance's chats, most recently active first
+pub async fn list_chats(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+    claims: Option<Extension<Claims>>,
+    Query(query): Query<ChatsQuery>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+
+    let limit = page_size(query.limit);
+    let cursor = match query.cursor.as_deref().map(decode_cursor) {
+        Some(None) => return invalid_cursor(),
+        Some(c) => c,
+        None => None,
+    };
+
+    let mut qb = QueryBuilder::<Sqlite>::new(
+        "SELECT chat, MAX(isGroup) AS isGroup, COUNT(*) AS messageCount, MAX(sentAt) AS lastAt,
+            (SELECT m2.text FROM messages m2 WHERE m2.sessionId = m.sessionId AND m2.chat = m.chat
+             ORDER BY m2.sentAt DESC, m2.rowid DESC LIMIT 1) AS lastText
+         FROM messages m WHERE m.sessionId = ",
+    );
+    qb.push_bind(&phone);
+    qb.push(" AND m.chat IS NOT NULL GROUP BY m.chat");
+    if let Some((at, chat)) = &cursor {
+        qb.push(" HAVING (lastAt < ")
+            .push_bind(*at)
+            .push(" OR (lastAt = ")
+            .push_bind(*at)
+            .push(" AND chat < ")
+            .push_bind(chat)
+            .push("))");
+    }
+    qb.push(" ORDER BY lastAt DESC, chat DESC LIMIT ")
+        .push_bind(limit);
+
+    match qb
+        .build_query_as::<ChatSummary>()
+        .fetch_all(&state.db)
+        .await
+    {
+        Ok(chats) => {
+            let next_cursor = (chats.len() as i64 == limit)
+                .then(|| chats.last().map(|c| encode_cursor(c.last_at, &c.chat)))
+                .flatten();
+            (
+                StatusCode::OK,
+                Json(serde_json::json!({
+                    "success": true,
+                    "chats": chats,
+                    "nextCursor": next_cursor
+                })),
+            )
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({
+                "success": false,
+                "message": format!("Failed to load chats: {}", e)
+            })),
+        ),
+    }
+}
+
+/// Messages of a single chat, newest first
+pub async fn get_chat_messages(
+    State(state): State<Arc<AppState>>,
+    Path((phone, jid)): Path<(String, String)>,
+    claims: Option<Extension<Claims>>,
+    Query(mut query): Query<HistoryQuery>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    query.chat = Some(jid);
+    search_messages(State(state), Path(phone), claims, Query(query)).await
+}
+
+/// Search an instance's message history, newest first
+pub async fn search_messages(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+    claims: Option<Extension<Claims>>,
+    Query(query): Query<HistoryQuery>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+
+    let limit = page_size(query.limit);
+    let cursor = match query.cursor.as_deref().map(decode_cursor) {
+        Some(None) => return invalid_cursor(),
+        Some(c) => c,
+        None => None,
+    };
+    let search = query.q.as_deref().and_then(fts_query);
+
+    let mut qb = QueryBuilder::<Sqlite>::new(
+        "SELECT m.rowid AS rowid, m.messageId, m.chat, m.sender, m.device, m.mtype, m.text,
+            m.isGroup, m.quoted, m.fromMe, m.sentAt
+         FROM messages m",
+    );
+    if let Some(ref fts) = search {
+        qb.push(" JOIN messages_fts ON messages_fts.rowid = m.rowid AND messages_fts MATCH ")
+            .push_bind(fts.clone());
+    }
+    qb.push(" WHERE m.sessionId = ").push_bind(&phone);
+    qb.push(" AND m.chat IS NOT NULL");
+    if let Some(ref chat) = query.chat {
+        qb.push(" AND m.chat = ").push_bind(chat);
+    }
+    if let Some(ref mtype) = query.mtype {
+        qb.push(" AND m.mtype = ").push_bind(mtype);
+    }
+    if let Some(ref sender) = query.sender {
+        qb.push(" AND m.sender = ").push_bind(sender);
+    }
+    if let Some(from) = query.from {
+        qb.push(" AND m.sentAt >= ").push_bind(from);
+    }
+    if let Some(to) = query.to {
+        qb.push(" AND m.sentAt <= ").push_bind(to);
+    }
+    if let Some((at, rowid)) = &cursor {
+        let rowid: i64 = match rowid.parse() {
+            Ok(r) => r,
+            Err(_) => return invalid_cursor(),
+        };
+        qb.push(" AND (m.sentAt < ")
+            .push_bind(*at)
+            .push(" OR (m.sentAt = ")
+            .push_bind(*at)
+            .push(" AND m.rowid < ")
+            .push_bind(rowid)
+            .push("))");
+    }
+    qb.push(" ORDER BY m.sentAt DESC, m.rowid DESC LIMIT ")
+        .push_bind(limit);
+
+    match qb
+        .build_query_as::<StoredMessage>()
+        .fetch_all(&state.db)
+        .await
+    {
+        Ok(messages) => {
+            let next_cursor = (messages.len() as i64 == limit)
+                .then(|| {
+                    messages
+                        .last()
+                        .map(|m| encode_cursor(m.sent_at, &m.rowid.to_string()))
+                })
+                .flatten();
+            (
+                StatusCode::OK,
+                Json(serde_json::json!({
+                    "success": true,
+                    "messages": messages,
+                    "nextCursor": next_cursor
+                })),
+            )
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({
+                "success": false,
+                "message": format!("Failed to load messages: {}", e)
+            })),
+        ),
+    }
+}
@@ -1,65 +1,344 @@
+pub mod access;
 pub mod admin;
+pub mod api_keys;
 pub mod auth;
+pub mod autoreply;
+pub mod broadcasts;
+pub mod business_hours;
+pub mod contacts;
+pub mod flows;
+pub mod groups;
 pub mod instance;
 pub mod logs;
+pub mod messages;
+pub mod number_checks;
+pub mod opt_outs;
 pub mod pair;
+pub mod profile;
 pub mod settings;
 pub mod stats;
+pub mod statuses;
 pub mod system;
+pub mod templates;
 pub mod tools;
+pub mod two_factor;
 pub mod user;
 pub mod util;
+pub mod webhooks;
+pub mod ws;
 
 use crate::AppState;
+use crate::manager::profile::MAX_PHOTO_BYTES;
+use crate::rbac::{Guarded, Permission};
 use axum::{
     Router,
-    routing::{delete, get, patch, post},
+    extract::DefaultBodyLimit,
+    routing::{delete, get, patch, post, put},
 };
 use std::sync::Arc;
 
 pub fn create_routes() -> Router<Arc<AppState>> {
     Router::new()
         // Admin routes (existing - hidden technical workspace)
-        .route("/api/instances", get(instance::list_instances))
-        .route("/api/instances/stream", get(instance::instance_stream))
-        .route("/api/instances/:phone", get(instance::get_instance))
+        .route(
+            "/api/instances",
+            get(instance::list_instances).requires(Permission::InstancesRead),
+        )
+        .route(
+            "/api/instances/stream",
+            get(instance::instance_stream).requires(Permission::InstancesRead),
+        )
+        .route(
+            "/api/instances/:phone",
+            get(instance::get_instance).requires(Permission::InstancesRead),
+        )
         .route(
             "/api/instances/:phone/start",
-            post(instance::start_instance),
+            post(instance::start_instance).requires(Permission::InstancesManage),
         )
         .route(
             "/api/instances/:phone/pause",
-            post(instance::pause_instance),
+            post(instance::pause_instance).requires(Permission::InstancesManage),
         )
         .route(
             "/api/instances/:phone/resume",
-            post(instance::resume_instance),
+            post(instance::resume_instance).requires(Permission::InstancesManage),
         )
         .route(
             "/api/instances/:phone/reset",
-            post(instance::reset_instance),
+            post(instance::reset_instance).requires(Permission::InstancesManage),
         )
         .route(
             "/api/instances/:phone/stats",
-            get(stats::get_instance_stats),
+            get(stats::get_instance_stats).requires(Permission::InstancesRead),
+        )
+        .route(
+            "/api/instances/:phone/pair",
+            post(pair::pair_instance).requires(Permission::InstancesManage),
+        )
+        .route(
+            "/api/instances/:phone/messages",
+            get(messages::search_messages).requires_or_user(Permission::InstancesRead),
+        )
+        .route(
+            "/api/instances/:phone/messages",
+            post(messages::send_message).requires_or_user(Permission::InstancesManage),
+        )
+        .route(
+            "/api/instances/:phone/chats",
+            get(messages::list_chats).requires_or_user(Permission::InstancesRead),
+        )
+        .route(
+            "/api/instances/:phone/chats/:jid/messages",
+            get(messages::get_chat_messages).requires_or_user(Permission::InstancesRead),
+        )
+        .route(
+            "/api/instances/:phone/auto-replies",
+            get(autoreply::list_rules).requires_or_user(Permission::InstancesRead),
+        )
+        .route(
+            "/api/instances/:phone/auto-replies",
+            post(autoreply::create_rule).requires_or_user(Permission::InstancesManage),
+        )
+        .route(
+            "/api/instances/:phone/auto-replies/:rule_id",
+            patch(autoreply::update_rule)
+                .delete(autoreply::delete_rule)
+                .requires_or_user(Permission::InstancesManage),
+        )
+        .route(
+            "/api/instances/:phone/auto-replies/:rule_id/reset",
+            post(autoreply::reset_rule_hits).requires_or_user(Permission::InstancesManage),
+        )
+        .route(
+            "/api/instances/:phone/business-hours",
+            get(business_hours::get_business_hours).requires_or_user(Permission::InstancesRead),
+        )
+        .route(
+            "/api/instances/:phone/business-hours",
+            put(business_hours::update_business_hours)
+                .requires_or_user(Permission::InstancesManage),
+        )
+        .route(
+            "/api/instances/:phone/flows",
+            get(flows::list_flows).requires_or_user(Permission::InstancesRead),
+        )
+        .route(
+            "/api/instances/:phone/flows",
+            post(flows::create_flow).requires_or_user(Permission::InstancesManage),
+        )
+        .route(
+            "/api/instances/:phone/flows/:flow_id",
+            get(flows::get_flow).requires_or_user(Permission::InstancesRead),
+        )
+        .route(
+            "/api/instances/:phone/flows/:flow_id",
+            patch(flows::update_flow)
+                .delete(flows::delete_flow)
+                .requires_or_user(Permission::InstancesManage),
+        )
+        .route(
+            "/api/instances/:phone/flows/:flow_id/versions",
+            post(flows::upload_version).requires_or_user(Permission::InstancesManage),
+        )
+        .route(
+            "/api/instances/:phone/flows/:flow_id/dry-run",
+            post(flows::dry_run).requires_or_user(Permission::InstancesManage),
+        )
+        .route(
+            "/api/instances/:phone/groups",
+            get(groups::list_groups).requires_or_user(Permission::InstancesRead),
+        )
+        .route(
+            "/api/instances/:phone/groups",
+            post(groups::create_group).requires_or_user(Permission::InstancesManage),
+        )
+        .route(
+            "/api/instances/:phone/groups/:group_id",
+            get(groups::get_group).requires_or_user(Permission::InstancesRead),
+        )
+        .route(
+            "/api/instances/:phone/groups/:group_id",
+            patch(groups::update_group).requires_or_user(Permission::InstancesManage),
+        )
+        .route(
+            "/api/instances/:phone/groups/:group_id/participants",
+            post(groups::update_participants).requires_or_user(Permission::InstancesManage),
+        )
+        .route(
+            "/api/instances/:phone/groups/:group_id/invite",
+            get(groups::get_invite).requires_or_user(Permission::InstancesRead),
+        )
+        .route(
+            "/api/instances/:phone/groups/:group_id/invite",
+            delete(groups::revoke_invite).requires_or_user(Permission::InstancesManage),
+        )
+        .route(
+            "/api/instances/:phone/contacts",
+            get(contacts::list_contacts).requires_or_user(Permission::InstancesRead),
+        )
+        .route(
+            "/api/instances/:phone/contacts",
+            post(contacts::create_contact).requires_or_user(Permission::InstancesManage),
+        )
+        .route(
+            "/api/instances/:phone/contacts/tags",
+            get(contacts::list_tags).requires_or_user(Permission::InstancesRead),
+        )
+        .route(
+            "/api/instances/:phone/contacts/import",
+            post(contacts::import_contacts).requires_or_user(Permission::InstancesManage),
+        )
+        .route(
+            "/api/instances/:phone/contacts/export",
+            get(contacts::export_contacts).requires_or_user(Permission::InstancesRead),
+        )
+        .route(
+            "/api/instances/:phone/contacts/:contact",
+            get(contacts::get_contact).requires_or_user(Permission::InstancesRead),
+        )
+        .route(
+            "/api/instances/:phone/contacts/:contact",
+            patch(contacts::update_contact)
+                .delete(contacts::delete_contact)
+                .requires_or_user(Permission::InstancesManage),
+        )
+        .route(
+            "/api/instances/:phone/broadcasts",
+            get(broadcasts::list_broadcasts).requires_or_user(Permission::InstancesRead),
+        )
+        .route(
+            "/api/instances/:phone/broadcasts",
+            post(broadcasts::create_broadcast).requires_or_user(Permission::InstancesManage),
+        )
+        .route(
+            "/api/instances/:phone/broadcasts/:broadcast_id",
+            get(broadcasts::get_broadcast).requires_or_user(Permission::InstancesRead),
+        )
+        .route(
+            "/api/instances/:phone/broadcasts/:broadcast_id/resume",
+            post(broadcasts::resume_broadcast).requires_or_user(Permission::InstancesManage),
+        )
+        .route(
+            "/api/instances/:phone/broadcasts/:broadcast_id/cancel",
+            post(broadcasts::cancel_broadcast).requires_or_user(Permission::InstancesManage),
+        )
+        .route(
+            "/api/instances/:phone/number-checks",
+            get(number_checks::list_checks).requires_or_user(Permission::InstancesRead),
+        )
+        .route(
+            "/api/instances/:phone/number-checks",
+            post(number_checks::create_check).requires_or_user(Permission::InstancesManage),
+        )
+        .route(
+            "/api/instances/:phone/number-checks/:check_id",
+            get(number_checks::get_check).requires_or_user(Permission::InstancesRead),
+        )
+        .route(
+            "/api/instances/:phone/number-checks/:check_id",
+            delete(number_checks::delete_check).requires_or_user(Permission::InstancesManage),
+        )
+        .route(
+            "/api/instances/:phone/number-checks/:check_id/export",
+            get(number_checks::export_check).requires_or_user(Permission::InstancesRead),
+        )
+        .route(
+            "/api/instances/:phone/opt-outs",
+            get(opt_outs::list_opt_outs).requires_or_user(Permission::InstancesRead),
+        )
+        .route(
+            "/api/instances/:phone/opt-outs",
+            post(opt_outs::add_opt_out).requires_or_user(Permission::InstancesManage),
+        )
+        .route(
+            "/api/instances/:phone/opt-outs/settings",
+            get(opt_outs::get_opt_out_settings).requires_or_user(Permission::InstancesRead),
+        )
+        .route(
+            "/api/instances/:phone/opt-outs/settings",
+            put(opt_outs::update_opt_out_settings).requires_or_user(Permission::InstancesManage),
+        )
+        .route(
+            "/api/instances/:phone/opt-outs/events",
+            get(opt_outs::list_opt_out_events).requires_or_user(Permission::InstancesRead),
+        )
+        .route(
+            "/api/instances/:phone/opt-outs/import",
+            post(opt_outs::import_opt_outs).requires_or_user(Permission::InstancesManage),
+        )
+        .route(
+            "/api/instances/:phone/opt-outs/export",
+            get(opt_outs::export_opt_outs).requires_or_user(Permission::InstancesRead),
+        )
+        .route(
+            "/api/instances/:phone/opt-outs/:contact",
+            delete(opt_outs::remove_opt_out).requires_or_user(Permission::InstancesManage),
+        )
+        .route(
+            "/api/instances/:phone/profile",
+            get(profile::get_profile).requires_or_user(Permission::InstancesRead),
+        )
+        .route(
+            "/api/instances/:phone/profile",
+            patch(profile::update_profile).requires_or_user(Permission::InstancesManage),
+        )
+        .route(
+            "/api/instances/:phone/profile/photo",
+            put(profile::set_photo)
+                .delete(profile::remove_photo)
+                .layer(DefaultBodyLimit::max(MAX_PHOTO_BYTES))
+                .requires_or_user(Permission::InstancesManage),
+        )
+        .route(
+            "/api/instances/:phone/statuses",
+            get(statuses::list_statuses).requires_or_user(Permission::InstancesRead),
+        )
+        .route(
+            "/api/instances/:phone/statuses",
+            post(statuses::create_status).requires_or_user(Permission::InstancesManage),
         )
-        .route("/api/instances/:phone/pair", post(pair::pair_instance))
-        .route("/api/settings/:phone", get(settings::get_settings))
-        .route("/api/settings/:phone", patch(settings::update_setting))
-        .route("/api/system/stream", get(system::system_stream))
-        .route("/api/logs/stream", get(logs::logs_stream))
+        .route(
+            "/api/instances/:phone/statuses/:status_id",
+            get(statuses::get_status).requires_or_user(Permission::InstancesRead),
+        )
+        .route(
+            "/api/instances/:phone/statuses/:status_id",
+            delete(statuses::cancel_status).requires_or_user(Permission::InstancesManage),
+        )
+        .route(
+            "/api/settings/:phone",
+            get(settings::get_settings).requires(Permission::InstancesRead),
+        )
+        .route(
+            "/api/settings/:phone",
+            patch(settings::update_setting).requires(Permission::InstancesManage),
+        )
+        .route(
+            "/api/system/stream",
+            get(system::system_stream).requires(Permission::SystemRead),
+        )
+        .route(
+            "/api/logs/stream",
+            get(logs::logs_stream).requires(Permission::SystemRead),
+        )
+        .route("/api/ws", get(ws::ws_handler))
         .route("/util/whatsapp-news", get(util::get_whatsapp_news))
         // Authentication routes
         .route("/api/auth/register", post(auth::register))
         .route("/api/auth/login", post(auth::login))
+        .route("/api/auth/login/2fa", post(auth::login_two_factor))
+        .route("/api/auth/refresh", post(auth::refresh))
+        .route("/api/auth/logout", post(auth::logout))
         .route("/api/auth/admin", post(auth::admin_login))
+        .route("/api/auth/admin/logout", post(auth::admin_logout))
         .route(
             "/api/auth/admin/validate",
             get(auth::validate_admin_session),
         )
         .route(
             "/api/dashboard/user/cryptooooooohash/:phone",
-            get(auth::get_crypto_hash),
+            get(auth::get_crypto_hash).requires(Permission::UsersRead),
         )
         .route(
             "/api/auth/verify/:crypto_hash",
@@ -107,6 +386,39 @@ pub fn create_routes() -> Router<Arc<AppState>> {
             post(user::add_credits),
         )
         .route("/api/user/:crypto_hash/usage", get(user::get_usage_history))
+        .route(
+            "/api/user/:crypto_hash/logins",
+            get(user::get_login_history),
+        )
+        .route(
+            "/api/user/:crypto_hash/2fa",
+            get(two_factor::get_two_factor),
+        )
+        .route(
+            "/api/user/:crypto_hash/2fa/enroll",
+            post(two_factor::enroll_two_factor),
+        )
+        .route(
+            "/api/user/:crypto_hash/2fa/verify",
+            post(two_factor::confirm_two_factor),
+        )
+        .route(
+            "/api/user/:crypto_hash/2fa/disable",
+            post(two_factor::disable_two_factor),
+        )
+        .route(
+            "/api/user/:crypto_hash/2fa/recovery-codes",
+            post(two_factor::regenerate_recovery_codes),
+        )
+        // API keys for integrations
+        .route(
+            "/api/user/:crypto_hash/api-keys",
+            get(api_keys::list_api_keys).post(api_keys::create_api_key),
+        )
+        .route(
+            "/api/user/:crypto_hash/api-keys/:key_id",
+            delete(api_keys::revoke_api_key),
+        )
         .route(
             "/api/user/:crypto_hash/support",
             get(user::get_support_requests),
@@ -115,35 +427,115 @@ pub fn create_routes() -> Router<Arc<AppState>> {
             "/api/user/:crypto_hash/support",
             post(user::submit_support_request),
         )
+        // Outgoing webhooks
+        .route(
+            "/api/user/:crypto_hash/webhooks",
+            get(webhooks::list_webhooks).post(webhooks::create_webhook),
+        )
+        .route(
+            "/api/user/:crypto_hash/webhooks/:webhook_id",
+            patch(webhooks::update_webhook).delete(webhooks::delete_webhook),
+        )
+        .route(
+            "/api/user/:crypto_hash/webhooks/:webhook_id/test",
+            post(webhooks::test_webhook),
+        )
+        .route(
+            "/api/user/:crypto_hash/webhook-deliveries",
+            get(webhooks::list_deliveries),
+        )
+        .route(
+            "/api/user/:crypto_hash/webhook-deliveries/:delivery_id",
+            get(webhooks::get_delivery),
+        )
+        .route(
+            "/api/user/:crypto_hash/webhook-deliveries/:delivery_id/redeliver",
+            post(webhooks::redeliver),
+        )
+        .route(
+            "/api/user/:crypto_hash/templates",
+            get(templates::list_templates).post(templates::create_template),
+        )
+        .route(
+            "/api/user/:crypto_hash/templates/:template_id",
+            get(templates::get_template)
+                .patch(templates::update_template)
+                .delete(templates::delete_template),
+        )
+        .route(
+            "/api/user/:crypto_hash/templates/:template_id/preview",
+            post(templates::preview_template),
+        )
         // User command tools (no text input required)
-        .route("/api/tools", get(tools::get_available_tools))
-        .route("/api/tools/quick-actions", get(tools::get_quick_actions))
+        .route(
+            "/api/tools",
+            get(tools::get_available_tools).requires_or_user(Permission::InstancesRead),
+        )
+        .route(
+            "/api/tools/quick-actions",
+            get(tools::get_quick_actions).requires_or_user(Permission::InstancesRead),
+        )
         .route(
             "/api/user/:crypto_hash/tools/execute",
             post(tools::execute_tool),
         )
         // Admin management routes
-        .route("/api/admin/users", get(admin::list_users))
+        .route(
+            "/api/admin/users",
+            get(admin::list_users).requires(Permission::UsersRead),
+        )
         .route(
             "/api/admin/users/:user_id/billing",
-            get(admin::get_user_billing),
+            get(admin::get_user_billing).requires(Permission::BillingRead),
         )
         .route(
             "/api/admin/users/:user_id/suspend",
-            post(admin::suspend_user),
+            post(admin::suspend_user).requires(Permission::UsersManage),
         )
         .route(
             "/api/admin/users/:user_id/limit",
-            post(admin::set_user_limit),
+            post(admin::set_user_limit).requires(Permission::UsersManage),
+        )
+        .route(
+            "/api/admin/users/:user_id/plan",
+            post(admin::set_user_plan).requires(Permission::BillingManage),
+        )
+        .route(
+            "/api/admin/users/:user_id/role",
+            post(admin::set_user_role).requires(Permission::StaffManage),
+        )
+        .route(
+            "/api/admin/users/:user_id",
+            delete(admin::delete_user).requires(Permission::UsersDelete),
+        )
+        .route(
+            "/api/admin/security",
+            get(two_factor::get_security_policy).requires(Permission::UsersRead),
+        )
+        .route(
+            "/api/admin/security",
+            put(two_factor::set_security_policy).requires(Permission::UsersManage),
         )
-        .route("/api/admin/users/:user_id", delete(admin::delete_user))
         .route(
             "/api/admin/instances/grouped",
-            get(admin::get_grouped_instances),
+            get(admin::get_grouped_instances).requires(Permission::InstancesRead),
+        )
+        .route(
+            "/api/admin/instances/:phone/limits",
+            get(admin::get_instance_limits).requires(Permission::InstancesRead),
+        )
+        .route(
+            "/api/admin/instances/:phone/limits",
+            put(admin::set_instance_limits)
+                .delete(admin::clear_instance_limits)
+                .requires(Permission::InstancesManage),
+        )
+        .route(
+            "/api/admin/support",
+            get(admin::list_support_requests).requires(Permission::SupportRead),
         )
-        .route("/api/admin/support", get(admin::list_support_requests))
         .route(
             "/api/admin/support/:request_id",
-            patch(admin::update_support_request),
+            patch(admin::update_support_request).requires(Permission::SupportManage),
         )
 }
@@ -0,0 +1,310 @@
+use crate::AppState;
+use crate::manager::number_checks;
+use crate::routes::access::can_access_instance;
+use crate::security::Claims;
+use crate::sql::{NumberCheck, NumberCheckResult};
+use axum::{
+    Extension, Json,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+/// Distinct numbers accepted per check
+const MAX_NUMBERS: usize = 10_000;
+/// Rejected inputs reported back
+const MAX_REPORTED_INVALID: usize = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateCheckRequest {
+    pub numbers: Vec<String>,
+    #[serde(rename = "defaultCountryCode")]
+    pub default_country_code: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateCheckQuery {
+    /// Applied to numbers written without `+` or `00`, for CSV uploads
+    #[serde(rename = "defaultCountryCode")]
+    pub default_country_code: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResultsQuery {
+    /// Only numbers on WhatsApp (`true`) or not (`false`)
+    #[serde(rename = "onWhatsApp")]
+    pub on_whatsapp: Option<bool>,
+}
+
+type ApiResponse = (StatusCode, Json<serde_json::Value>);
+
+fn error(status: StatusCode, message: &str) -> ApiResponse {
+    (
+        status,
+        Json(serde_json::json!({
+            "success": false,
+            "message": message
+        })),
+    )
+}
+
+fn forbidden() -> ApiResponse {
+    error(
+        StatusCode::FORBIDDEN,
+        "You don't have access to this instance",
+    )
+}
+
+async fn find_check(
+    state: &Arc<AppState>,
+    phone: &str,
+    check_id: i64,
+) -> Result<NumberCheck, ApiResponse> {
+    sqlx::query_as("SELECT * FROM number_checks WHERE id = ? AND sessionId = ?")
+        .bind(check_id)
+        .bind(phone)
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None)
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "Number check not found"))
+}
+
+async fn results(
+    state: &Arc<AppState>,
+    check_id: i64,
+    on_whatsapp: Option<bool>,
+) -> Vec<NumberCheckResult> {
+    sqlx::query_as(
+        "SELECT number, onWhatsApp, jid, lid, checkedAt FROM number_check_results
+         WHERE checkId = ? AND (? IS NULL OR onWhatsApp = ?) ORDER BY number",
+    )
+    .bind(check_id)
+    .bind(on_whatsapp)
+    .bind(on_whatsapp)
+    .fetch_all(&state.db)
+    .await
+    .unwrap_or_default()
+}
+
+/// List an instance's number checks, newest first
+pub async fn list_checks(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+    claims: Option<Extension<Claims>>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+
+    let list: Vec<NumberCheck> =
+        sqlx::query_as("SELECT * FROM number_checks WHERE sessionId = ? ORDER BY id DESC")
+            .bind(&phone)
+            .fetch_all(&state.db)
+            .await
+            .unwrap_or_default();
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "success": true,
+            "checks": list
+        })),
+    )
+}
+
+/// Start checking which numbers are on WhatsApp. Takes a JSON body with
+/// `numbers` or a CSV upload (`Content-Type: text/csv`).
+pub async fn create_check(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+    claims: Option<Extension<Claims>>,
+    Query(query): Query<CreateCheckQuery>,
+    headers: HeaderMap,
+    body: String,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+
+    let is_csv = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("csv"));
+    let (raw, country_code) = if is_csv {
+        match number_checks::parse_csv(&body) {
+            Ok(n) => (n, query.default_country_code),
+            Err(e) => return error(StatusCode::BAD_REQUEST, &e),
+        }
+    } else {
+        match serde_json::from_str::<CreateCheckRequest>(&body) {
+            Ok(r) => (
+                r.numbers,
+                r.default_country_code.or(query.default_country_code),
+            ),
+            Err(e) => {
+                return error(
+                    StatusCode::BAD_REQUEST,
+                    &format!("Invalid request body: {}", e),
+                );
+            }
+        }
+    };
+
+    let mut numbers = BTreeSet::new();
+    let mut invalid = Vec::new();
+    for input in raw {
+        match number_checks::to_e164(&input, country_code.as_deref()) {
+            Some(n) => {
+                numbers.insert(n);
+            }
+            None => invalid.push(input),
+        }
+    }
+    if numbers.is_empty() {
+        return error(StatusCode::BAD_REQUEST, "No valid numbers to check");
+    }
+    if numbers.len() > MAX_NUMBERS {
+        return error(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            &format!("At most {} numbers per check", MAX_NUMBERS),
+        );
+    }
+
+    let result = async {
+        let mut tx = state.db.begin().await?;
+        let id = sqlx::query(
+            "INSERT INTO number_checks (sessionId, status, total, createdAt) VALUES (?, 'running', ?, ?)",
+        )
+        .bind(&phone)
+        .bind(numbers.len() as i64)
+        .bind(chrono::Utc::now())
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+        for number in &numbers {
+            sqlx::query("INSERT INTO number_check_results (checkId, number) VALUES (?, ?)")
+                .bind(id)
+                .bind(number)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok::<_, sqlx::Error>(id)
+    }
+    .await;
+
+    match result {
+        Ok(id) => {
+            number_checks::spawn(state.clone(), id);
+            let invalid_count = invalid.len();
+            invalid.truncate(MAX_REPORTED_INVALID);
+            (
+                StatusCode::ACCEPTED,
+                Json(serde_json::json!({
+                    "success": true,
+                    "check": find_check(&state, &phone, id).await.ok(),
+                    "invalidCount": invalid_count,
+                    "invalid": invalid
+                })),
+            )
+        }
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to start number check: {}", e),
+        ),
+    }
+}
+
+/// Get a check's progress and per-number results
+pub async fn get_check(
+    State(state): State<Arc<AppState>>,
+    Path((phone, check_id)): Path<(String, i64)>,
+    claims: Option<Extension<Claims>>,
+    Query(query): Query<ResultsQuery>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+    let check = match find_check(&state, &phone, check_id).await {
+        Ok(c) => c,
+        Err(e) => return e,
+    };
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "success": true,
+            "check": check,
+            "results": results(&state, check_id, query.on_whatsapp).await
+        })),
+    )
+}
+
+/// Download a check's results as CSV
+pub async fn export_check(
+    State(state): State<Arc<AppState>>,
+    Path((phone, check_id)): Path<(String, i64)>,
+    claims: Option<Extension<Claims>>,
+    Query(query): Query<ResultsQuery>,
+) -> Response {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden().into_response();
+    }
+    if let Err(e) = find_check(&state, &phone, check_id).await {
+        return e.into_response();
+    }
+
+    match number_checks::to_csv(&results(&state, check_id, query.on_whatsapp).await) {
+        Ok(body) => (
+            [
+                (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"number-check-{}.csv\"", check_id),
+                ),
+            ],
+            body,
+        )
+            .into_response(),
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to export results: {}", e),
+        )
+        .into_response(),
+    }
+}
+
+/// Delete a check, stopping it if still running
+pub async fn delete_check(
+    State(state): State<Arc<AppState>>,
+    Path((phone, check_id)): Path<(String, i64)>,
+    claims: Option<Extension<Claims>>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+
+    let result = sqlx::query("DELETE FROM number_checks WHERE id = ? AND sessionId = ?")
+        .bind(check_id)
+        .bind(&phone)
+        .execute(&state.db)
+        .await;
+
+    match result {
+        Ok(r) if r.rows_affected() > 0 => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "success": true,
+                "message": "Number check deleted"
+            })),
+        ),
+        Ok(_) => error(StatusCode::NOT_FOUND, "Number check not found"),
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to delete number check: {}", e),
+        ),
+    }
+}
@@ -0,0 +1,436 @@
+use crate::AppState;
+use crate::manager::number_checks;
+use crate::manager::opt_outs::{self, Change, Source};
+use crate::routes::access::can_access_instance;
+use crate::routes::messages::to_jid;
+use crate::security::Claims;
+use crate::sql::{OptOut, OptOutEvent, OptOutSettings};
+use axum::{
+    Extension, Json,
+    extract::{Path, Query, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+const DEFAULT_AUDIT_LIMIT: i64 = 100;
+const MAX_AUDIT_LIMIT: i64 = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct AddOptOutRequest {
+    /// Phone number or JID
+    pub phone: String,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReasonQuery {
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    /// Only this contact's history
+    pub contact: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// Omitted fields keep their current value
+#[derive(Debug, Deserialize)]
+pub struct OptOutSettingsRequest {
+    pub enabled: Option<bool>,
+    pub keywords: Option<Vec<String>>,
+    #[serde(rename = "optInKeywords")]
+    pub opt_in_keywords: Option<Vec<String>>,
+}
+
+type ApiResponse = (StatusCode, Json<serde_json::Value>);
+
+fn error(status: StatusCode, message: &str) -> ApiResponse {
+    (
+        status,
+        Json(serde_json::json!({
+            "success": false,
+            "message": message
+        })),
+    )
+}
+
+fn forbidden() -> ApiResponse {
+    error(
+        StatusCode::FORBIDDEN,
+        "You don't have access to this instance",
+    )
+}
+
+/// Who made a manual change, recorded in the audit trail
+fn actor(claims: Option<&Claims>) -> String {
+    claims
+        .map(|c| format!("{}:{}", c.role, c.sub))
+        .unwrap_or_else(|| "api".to_string())
+}
+
+fn normalize_keywords(keywords: &[String]) -> Result<Vec<String>, ApiResponse> {
+    let mut out = Vec::with_capacity(keywords.len());
+    for keyword in keywords {
+        match opt_outs::normalize_keyword(keyword) {
+            Some(k) if !out.contains(&k) => out.push(k),
+            Some(_) => {}
+            None => {
+                return Err(error(
+                    StatusCode::BAD_REQUEST,
+                    &format!("Invalid keyword '{}'", keyword),
+                ));
+            }
+        }
+    }
+    Ok(out)
+}
+
+async fn list(state: &Arc<AppState>, phone: &str) -> Vec<OptOut> {
+    sqlx::query_as(
+        "SELECT jid, source, reason, createdAt FROM opt_outs WHERE sessionId = ? ORDER BY createdAt DESC, jid",
+    )
+    .bind(phone)
+    .fetch_all(&state.db)
+    .await
+    .unwrap_or_default()
+}
+
+/// List an instance's suppressed recipients
+pub async fn list_opt_outs(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+    claims: Option<Extension<Claims>>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "success": true,
+            "optOuts": list(&state, &phone).await
+        })),
+    )
+}
+
+/// Suppress a recipient by hand
+pub async fn add_opt_out(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+    claims: Option<Extension<Claims>>,
+    Json(payload): Json<AddOptOutRequest>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+    let Some(jid) = to_jid(&payload.phone) else {
+        return error(StatusCode::BAD_REQUEST, "Invalid phone number");
+    };
+
+    let actor = actor(claims.as_deref());
+    let change = Change {
+        source: Source::Manual,
+        detail: payload.reason.as_deref().filter(|r| !r.is_empty()),
+        actor: Some(&actor),
+        message_id: None,
+    };
+    let result = async {
+        let mut tx = state.db.begin().await?;
+        let added = opt_outs::add(&mut tx, &phone, &jid, &change).await?;
+        tx.commit().await?;
+        Ok::<_, sqlx::Error>(added)
+    }
+    .await;
+
+    match result {
+        Ok(added) => (
+            if added {
+                StatusCode::CREATED
+            } else {
+                StatusCode::OK
+            },
+            Json(serde_json::json!({
+                "success": true,
+                "jid": jid,
+                "added": added
+            })),
+        ),
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to add opt-out: {}", e),
+        ),
+    }
+}
+
+/// Lift a recipient's opt-out by hand
+pub async fn remove_opt_out(
+    State(state): State<Arc<AppState>>,
+    Path((phone, contact)): Path<(String, String)>,
+    claims: Option<Extension<Claims>>,
+    Query(query): Query<ReasonQuery>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+    let Some(jid) = to_jid(&contact) else {
+        return error(StatusCode::BAD_REQUEST, "Invalid phone number");
+    };
+
+    let actor = actor(claims.as_deref());
+    let change = Change {
+        source: Source::Manual,
+        detail: query.reason.as_deref().filter(|r| !r.is_empty()),
+        actor: Some(&actor),
+        message_id: None,
+    };
+    let result = async {
+        let mut tx = state.db.begin().await?;
+        let removed = opt_outs::remove(&mut tx, &phone, &jid, &change).await?;
+        tx.commit().await?;
+        Ok::<_, sqlx::Error>(removed)
+    }
+    .await;
+
+    match result {
+        Ok(true) => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "success": true,
+                "message": "Opt-out removed"
+            })),
+        ),
+        Ok(false) => error(StatusCode::NOT_FOUND, "Contact has not opted out"),
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to remove opt-out: {}", e),
+        ),
+    }
+}
+
+/// Suppress every number of a CSV upload (`phone` column or the first column)
+pub async fn import_opt_outs(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+    claims: Option<Extension<Claims>>,
+    Query(query): Query<ReasonQuery>,
+    body: String,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+    let numbers = match number_checks::parse_csv(&body) {
+        Ok(n) => n,
+        Err(e) => return error(StatusCode::BAD_REQUEST, &e),
+    };
+
+    let mut invalid = Vec::new();
+    let mut jids = Vec::new();
+    for number in numbers {
+        match to_jid(&number) {
+            Some(jid) => jids.push(jid),
+            None => invalid.push(number),
+        }
+    }
+
+    let actor = actor(claims.as_deref());
+    let change = Change {
+        source: Source::Import,
+        detail: query.reason.as_deref().filter(|r| !r.is_empty()),
+        actor: Some(&actor),
+        message_id: None,
+    };
+    let result = async {
+        let mut added = 0;
+        let mut tx = state.db.begin().await?;
+        for jid in &jids {
+            added += opt_outs::add(&mut tx, &phone, jid, &change).await? as usize;
+        }
+        tx.commit().await?;
+        Ok::<_, sqlx::Error>(added)
+    }
+    .await;
+
+    match result {
+        Ok(added) => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "success": true,
+                "added": added,
+                "alreadyOptedOut": jids.len() - added,
+                "invalid": invalid
+            })),
+        ),
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to import opt-outs: {}", e),
+        ),
+    }
+}
+
+/// Download the suppression list as CSV
+pub async fn export_opt_outs(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+    claims: Option<Extension<Claims>>,
+) -> Response {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden().into_response();
+    }
+
+    match opt_outs::to_csv(&list(&state, &phone).await) {
+        Ok(body) => (
+            [
+                (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"opt-outs-{}.csv\"", phone),
+                ),
+            ],
+            body,
+        )
+            .into_response(),
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to export opt-outs: {}", e),
+        )
+        .into_response(),
+    }
+}
+
+/// Opt-out and opt-in history, newest first
+pub async fn list_opt_out_events(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+    claims: Option<Extension<Claims>>,
+    Query(query): Query<AuditQuery>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+    let contact = match query.contact.as_deref().map(to_jid) {
+        Some(None) => return error(StatusCode::BAD_REQUEST, "Invalid phone number"),
+        Some(jid) => jid,
+        None => None,
+    };
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_AUDIT_LIMIT)
+        .clamp(1, MAX_AUDIT_LIMIT);
+
+    let events: Vec<OptOutEvent> = sqlx::query_as(
+        "SELECT id, jid, action, source, detail, actor, messageId, createdAt FROM opt_out_events
+         WHERE sessionId = ? AND (? IS NULL OR jid = ?) ORDER BY id DESC LIMIT ?",
+    )
+    .bind(&phone)
+    .bind(&contact)
+    .bind(&contact)
+    .bind(limit)
+    .fetch_all(&state.db)
+    .await
+    .unwrap_or_default();
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "success": true,
+            "events": events
+        })),
+    )
+}
+
+/// Get an instance's opt-out keywords
+pub async fn get_opt_out_settings(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+    claims: Option<Extension<Claims>>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "success": true,
+            "settings": opt_outs::settings(&state.db, &phone).await
+        })),
+    )
+}
+
+/// Update an instance's opt-out keywords
+pub async fn update_opt_out_settings(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+    claims: Option<Extension<Claims>>,
+    Json(payload): Json<OptOutSettingsRequest>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+
+    let mut config: OptOutSettings = opt_outs::settings(&state.db, &phone).await;
+    if let Some(v) = payload.enabled {
+        config.enabled = v;
+    }
+    let keywords = match payload.keywords.as_deref().map(normalize_keywords) {
+        Some(Ok(k)) => Some(k),
+        Some(Err(e)) => return e,
+        None => None,
+    };
+    let opt_in = match payload.opt_in_keywords.as_deref().map(normalize_keywords) {
+        Some(Ok(k)) => Some(k),
+        Some(Err(e)) => return e,
+        None => None,
+    };
+    if let Some(ref k) = keywords {
+        config.keywords = serde_json::to_string(k).unwrap_or_else(|_| "[]".to_string());
+    }
+    if let Some(ref k) = opt_in {
+        config.opt_in_keywords = serde_json::to_string(k).unwrap_or_else(|_| "[]".to_string());
+    }
+    let current: Vec<String> = serde_json::from_str(&config.keywords).unwrap_or_default();
+    let current_opt_in: Vec<String> =
+        serde_json::from_str(&config.opt_in_keywords).unwrap_or_default();
+    if let Some(k) = current.iter().find(|k| current_opt_in.contains(k)) {
+        return error(
+            StatusCode::BAD_REQUEST,
+            &format!("'{}' can't be both an opt-out and an opt-in keyword", k),
+        );
+    }
+
+    config.updated_at = chrono::Utc::now();
+    let result = sqlx::query(
+        "INSERT INTO opt_out_settings (sessionId, enabled, keywords, optInKeywords, updatedAt)
+         VALUES (?, ?, ?, ?, ?)
+         ON CONFLICT(sessionId) DO UPDATE SET
+            enabled = excluded.enabled,
+            keywords = excluded.keywords,
+            optInKeywords = excluded.optInKeywords,
+            updatedAt = excluded.updatedAt",
+    )
+    .bind(&phone)
+    .bind(config.enabled)
+    .bind(&config.keywords)
+    .bind(&config.opt_in_keywords)
+    .bind(config.updated_at)
+    .execute(&state.db)
+    .await;
+
+    match result {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "success": true,
+                "settings": config
+            })),
+        ),
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to save opt-out settings: {}", e),
+        ),
+    }
+}
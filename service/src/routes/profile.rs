@@ -0,0 +1,228 @@
+use crate::AppState;
+use crate::manager::CommandError;
+use crate::manager::events::worker_command::ProfileAction;
+use crate::manager::profile::{self, MAX_ABOUT_LEN, MAX_NAME_LEN, PRIVACY_SETTINGS};
+use crate::routes::access::can_access_instance;
+use crate::security::Claims;
+use axum::{
+    Extension, Json,
+    body::Bytes,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Omitted fields keep their current value
+#[derive(Debug, Deserialize)]
+pub struct UpdateProfileRequest {
+    pub name: Option<String>,
+    pub about: Option<String>,
+    /// Privacy setting (`lastSeen`, `online`, ...) to value
+    #[serde(default)]
+    pub privacy: BTreeMap<String, String>,
+}
+
+type ApiResponse = (StatusCode, Json<serde_json::Value>);
+
+fn error(status: StatusCode, message: &str) -> ApiResponse {
+    (
+        status,
+        Json(serde_json::json!({
+            "success": false,
+            "message": message
+        })),
+    )
+}
+
+fn forbidden() -> ApiResponse {
+    error(
+        StatusCode::FORBIDDEN,
+        "You don't have access to this instance",
+    )
+}
+
+fn worker_error(e: CommandError) -> ApiResponse {
+    let status = match e {
+        CommandError::NotConnected => StatusCode::CONFLICT,
+        CommandError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+        CommandError::Rejected(_) => StatusCode::BAD_GATEWAY,
+    };
+    error(status, &e.to_string())
+}
+
+fn action(action: &str, setting: &str, value: &str) -> ProfileAction {
+    ProfileAction {
+        action: action.to_string(),
+        value: value.to_string(),
+        setting: setting.to_string(),
+        image: Vec::new(),
+    }
+}
+
+/// Fetch the profile from WhatsApp, syncing the session's name and photo
+async fn fetch(state: &Arc<AppState>, phone: &str) -> Result<serde_json::Value, ApiResponse> {
+    let data = profile::run(state, phone, action("get", "", ""))
+        .await
+        .map_err(worker_error)?;
+    let name = data.get("name").and_then(|v| v.as_str());
+    let photo_url = data.get("photoUrl").and_then(|v| v.as_str());
+    profile::reflect(&state.db, phone, name, Some(photo_url)).await;
+
+    let privacy: serde_json::Map<String, serde_json::Value> = PRIVACY_SETTINGS
+        .iter()
+        .filter_map(|(api, setting, _)| {
+            let value = data.get("privacy")?.get(*setting)?;
+            Some((api.to_string(), value.clone()))
+        })
+        .collect();
+    Ok(serde_json::json!({
+        "name": name,
+        "about": data.get("about").and_then(|v| v.as_str()),
+        "photoUrl": photo_url,
+        "privacy": privacy
+    }))
+}
+
+fn profile_response(result: Result<serde_json::Value, ApiResponse>) -> ApiResponse {
+    match result {
+        Ok(profile) => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "success": true,
+                "profile": profile
+            })),
+        ),
+        Err(e) => e,
+    }
+}
+
+/// Get the linked account's name, about text, photo and privacy settings
+pub async fn get_profile(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+    claims: Option<Extension<Claims>>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+    profile_response(fetch(&state, &phone).await)
+}
+
+/// Change the display name, about text or privacy settings
+pub async fn update_profile(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+    claims: Option<Extension<Claims>>,
+    Json(payload): Json<UpdateProfileRequest>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+
+    // Validate everything before the first change reaches WhatsApp
+    let mut actions = Vec::new();
+    if let Some(name) = payload.name.as_deref().map(str::trim) {
+        if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+            return error(
+                StatusCode::BAD_REQUEST,
+                &format!("name must be 1 to {} characters", MAX_NAME_LEN),
+            );
+        }
+        actions.push(action("name", "", name));
+    }
+    if let Some(about) = payload.about.as_deref().map(str::trim) {
+        if about.chars().count() > MAX_ABOUT_LEN {
+            return error(
+                StatusCode::BAD_REQUEST,
+                &format!("about must be at most {} characters", MAX_ABOUT_LEN),
+            );
+        }
+        actions.push(action("about", "", about));
+    }
+    for (name, value) in &payload.privacy {
+        match profile::privacy_setting(name, value) {
+            Ok(setting) => actions.push(action("privacy", setting, value)),
+            Err(e) => return error(StatusCode::BAD_REQUEST, &e),
+        }
+    }
+    if actions.is_empty() {
+        return error(StatusCode::BAD_REQUEST, "Nothing to update");
+    }
+
+    for a in actions {
+        let name = (a.action == "name").then(|| a.value.clone());
+        if let Err(e) = profile::run(&state, &phone, a).await {
+            return worker_error(e);
+        }
+        if name.is_some() {
+            profile::reflect(&state.db, &phone, name.as_deref(), None).await;
+        }
+    }
+    profile_response(fetch(&state, &phone).await)
+}
+
+/// Replace the profile photo with an uploaded JPEG, PNG or WebP image
+pub async fn set_photo(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+    claims: Option<Extension<Claims>>,
+    body: Bytes,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+
+    // Decoding and scaling is CPU bound, keep it off the runtime threads
+    let prepared = tokio::task::spawn_blocking(move || profile::prepare_photo(&body)).await;
+    let image = match prepared {
+        Ok(Ok(image)) => image,
+        Ok(Err(e)) => return error(StatusCode::BAD_REQUEST, &e),
+        Err(_) => {
+            return error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to process photo");
+        }
+    };
+
+    let mut photo = action("photo", "", "");
+    photo.image = image;
+    match profile::run(&state, &phone, photo).await {
+        Ok(data) => {
+            let url = data.get("photoUrl").and_then(|v| v.as_str());
+            profile::reflect(&state.db, &phone, None, Some(url)).await;
+            (
+                StatusCode::OK,
+                Json(serde_json::json!({
+                    "success": true,
+                    "photoUrl": url
+                })),
+            )
+        }
+        Err(e) => worker_error(e),
+    }
+}
+
+/// Remove the profile photo
+pub async fn remove_photo(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+    claims: Option<Extension<Claims>>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+
+    match profile::run(&state, &phone, action("remove_photo", "", "")).await {
+        Ok(_) => {
+            profile::reflect(&state.db, &phone, None, Some(None)).await;
+            (
+                StatusCode::OK,
+                Json(serde_json::json!({
+                    "success": true,
+                    "message": "Profile photo removed"
+                })),
+            )
+        }
+        Err(e) => worker_error(e),
+    }
+}
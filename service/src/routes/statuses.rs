@@ -0,0 +1,302 @@
+use crate::AppState;
+use crate::manager::statuses::{self, AUDIENCES, KINDS, MAX_FONT, MAX_TEXT_LEN};
+use crate::routes::access::can_access_instance;
+use crate::routes::messages::to_jid;
+use crate::security::Claims;
+use crate::sql::StatusUpdate;
+use axum::{
+    Extension, Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::sync::Arc;
+
+const STATUS_SELECT: &str =
+    "SELECT s.*, (SELECT COUNT(*) FROM status_views v WHERE v.statusId = s.id) AS views
+    FROM statuses s";
+
+#[derive(Debug, Deserialize)]
+pub struct CreateStatusRequest {
+    /// text, image or video
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Status text, or the caption of media
+    pub text: Option<String>,
+    #[serde(rename = "mediaUrl")]
+    pub media_url: Option<String>,
+    /// `#RRGGBB`, text statuses only
+    #[serde(rename = "backgroundColor")]
+    pub background_color: Option<String>,
+    /// 0 to 7, text statuses only
+    pub font: Option<i64>,
+    /// all (default), list or exclude
+    pub audience: Option<String>,
+    /// Numbers or JIDs the audience mode applies to
+    #[serde(default)]
+    pub contacts: Vec<String>,
+    /// Post later instead of now
+    #[serde(rename = "scheduledAt")]
+    pub scheduled_at: Option<DateTime<Utc>>,
+}
+
+type ApiResponse = (StatusCode, Json<serde_json::Value>);
+
+fn error(status: StatusCode, message: &str) -> ApiResponse {
+    (
+        status,
+        Json(serde_json::json!({
+            "success": false,
+            "message": message
+        })),
+    )
+}
+
+fn forbidden() -> ApiResponse {
+    error(
+        StatusCode::FORBIDDEN,
+        "You don't have access to this instance",
+    )
+}
+
+async fn find_status(
+    state: &Arc<AppState>,
+    phone: &str,
+    status_id: i64,
+) -> Result<StatusUpdate, ApiResponse> {
+    sqlx::query_as(&format!(
+        "{} WHERE s.id = ? AND s.sessionId = ?",
+        STATUS_SELECT
+    ))
+    .bind(status_id)
+    .bind(phone)
+    .fetch_optional(&state.db)
+    .await
+    .unwrap_or(None)
+    .ok_or_else(|| error(StatusCode::NOT_FOUND, "Status not found"))
+}
+
+fn validate(payload: &CreateStatusRequest) -> Result<(), String> {
+    if !KINDS.contains(&payload.kind.as_str()) {
+        return Err(format!("type must be one of {}", KINDS.join(", ")));
+    }
+    let text = payload.text.as_deref().unwrap_or("");
+    if text.chars().count() > MAX_TEXT_LEN {
+        return Err(format!("text must be at most {} characters", MAX_TEXT_LEN));
+    }
+    if payload.kind == "text" {
+        if text.trim().is_empty() {
+            return Err("text is required for text statuses".to_string());
+        }
+        if payload.media_url.is_some() {
+            return Err("mediaUrl is only allowed for image and video statuses".to_string());
+        }
+    } else {
+        let url = payload.media_url.as_deref().unwrap_or("");
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            return Err(format!(
+                "mediaUrl must be an http(s) URL for {} statuses",
+                payload.kind
+            ));
+        }
+        if payload.background_color.is_some() || payload.font.is_some() {
+            return Err("backgroundColor and font are only allowed for text statuses".to_string());
+        }
+    }
+    if let Some(ref color) = payload.background_color
+        && !statuses::is_valid_color(color)
+    {
+        return Err("backgroundColor must look like #RRGGBB".to_string());
+    }
+    if let Some(font) = payload.font
+        && !(0..=MAX_FONT).contains(&font)
+    {
+        return Err(format!("font must be between 0 and {}", MAX_FONT));
+    }
+    let audience = payload.audience.as_deref().unwrap_or("all");
+    if !AUDIENCES.contains(&audience) {
+        return Err(format!("audience must be one of {}", AUDIENCES.join(", ")));
+    }
+    if audience == "list" && payload.contacts.is_empty() {
+        return Err("contacts is required for a list audience".to_string());
+    }
+    Ok(())
+}
+
+/// List an instance's statuses with their view counts, newest first
+pub async fn list_statuses(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+    claims: Option<Extension<Claims>>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+
+    let list: Vec<StatusUpdate> = sqlx::query_as(&format!(
+        "{} WHERE s.sessionId = ? ORDER BY s.scheduledAt DESC, s.id DESC",
+        STATUS_SELECT
+    ))
+    .bind(&phone)
+    .fetch_all(&state.db)
+    .await
+    .unwrap_or_default();
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "success": true,
+            "statuses": list
+        })),
+    )
+}
+
+/// Post a status now, or schedule it with `scheduledAt`
+pub async fn create_status(
+    State(state): State<Arc<AppState>>,
+    Path(phone): Path<String>,
+    claims: Option<Extension<Claims>>,
+    Json(payload): Json<CreateStatusRequest>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+    if let Err(e) = validate(&payload) {
+        return error(StatusCode::BAD_REQUEST, &e);
+    }
+    let Some(contacts) = payload
+        .contacts
+        .iter()
+        .map(|c| to_jid(c))
+        .collect::<Option<Vec<_>>>()
+    else {
+        return error(StatusCode::BAD_REQUEST, "Invalid phone number in contacts");
+    };
+
+    let now = Utc::now();
+    let scheduled = payload.scheduled_at.filter(|t| *t > now);
+    let result = sqlx::query(
+        "INSERT INTO statuses (sessionId, kind, text, mediaUrl, backgroundColor, font, audience, contacts, status, scheduledAt, createdAt)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, 'scheduled', ?, ?)",
+    )
+    .bind(&phone)
+    .bind(&payload.kind)
+    .bind(payload.text.as_deref().filter(|t| !t.is_empty()))
+    .bind(&payload.media_url)
+    .bind(&payload.background_color)
+    .bind(payload.font)
+    .bind(payload.audience.as_deref().unwrap_or("all"))
+    .bind(serde_json::to_string(&contacts).unwrap_or_else(|_| "[]".to_string()))
+    .bind(scheduled.unwrap_or(now))
+    .bind(now)
+    .execute(&state.db)
+    .await;
+
+    let id = match result {
+        Ok(r) => r.last_insert_rowid(),
+        Err(e) => {
+            return error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &format!("Failed to save status: {}", e),
+            );
+        }
+    };
+    if scheduled.is_none() {
+        statuses::post(&state, id, false).await;
+    }
+
+    match find_status(&state, &phone, id).await {
+        Ok(status) if status.status == "failed" => (
+            StatusCode::BAD_GATEWAY,
+            Json(serde_json::json!({
+                "success": false,
+                "message": status.last_error.clone().unwrap_or_default(),
+                "status": status
+            })),
+        ),
+        Ok(status) => (
+            StatusCode::CREATED,
+            Json(serde_json::json!({
+                "success": true,
+                "status": status
+            })),
+        ),
+        Err(e) => e,
+    }
+}
+
+/// Get a status with the contacts who viewed it
+pub async fn get_status(
+    State(state): State<Arc<AppState>>,
+    Path((phone, status_id)): Path<(String, i64)>,
+    claims: Option<Extension<Claims>>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+    let status = match find_status(&state, &phone, status_id).await {
+        Ok(s) => s,
+        Err(e) => return e,
+    };
+
+    let viewers: Vec<(String, DateTime<Utc>)> = sqlx::query_as(
+        "SELECT viewer, viewedAt FROM status_views WHERE statusId = ? ORDER BY viewedAt",
+    )
+    .bind(status_id)
+    .fetch_all(&state.db)
+    .await
+    .unwrap_or_default();
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "success": true,
+            "status": status,
+            "viewers": viewers.into_iter().map(|(jid, viewed_at)| serde_json::json!({
+                "jid": jid,
+                "viewedAt": viewed_at
+            })).collect::<Vec<_>>()
+        })),
+    )
+}
+
+/// Cancel a scheduled status
+pub async fn cancel_status(
+    State(state): State<Arc<AppState>>,
+    Path((phone, status_id)): Path<(String, i64)>,
+    claims: Option<Extension<Claims>>,
+) -> ApiResponse {
+    if !can_access_instance(&state, claims.as_deref(), &phone).await {
+        return forbidden();
+    }
+    let status = match find_status(&state, &phone, status_id).await {
+        Ok(s) => s,
+        Err(e) => return e,
+    };
+
+    let result = sqlx::query(
+        "UPDATE statuses SET status = 'cancelled' WHERE id = ? AND status = 'scheduled'",
+    )
+    .bind(status_id)
+    .execute(&state.db)
+    .await;
+
+    match result {
+        Ok(r) if r.rows_affected() > 0 => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "success": true,
+                "message": "Status cancelled"
+            })),
+        ),
+        Ok(_) => error(
+            StatusCode::CONFLICT,
+            &format!("Status is {}", status.status),
+        ),
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to cancel status: {}", e),
+        ),
+    }
+}
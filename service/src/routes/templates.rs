@@ -0,0 +1,309 @@
+use crate::AppState;
+use crate::manager::templates::{self, Variable, Variants};
+use crate::routes::access::CurrentUser;
+use crate::routes::messages::to_jid;
+use crate::sql::{MessageTemplate, User};
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTemplateRequest {
+    pub name: String,
+    pub description: Option<String>,
+    #[serde(rename = "defaultLanguage")]
+    pub default_language: Option<String>,
+    #[serde(default)]
+    pub variables: Vec<Variable>,
+    pub variants: Variants,
+}
+
+/// Omitted fields keep their current value
+#[derive(Debug, Deserialize)]
+pub struct UpdateTemplateRequest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    #[serde(rename = "defaultLanguage")]
+    pub default_language: Option<String>,
+    pub variables: Option<Vec<Variable>>,
+    pub variants: Option<Variants>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PreviewRequest {
+    /// Instance whose contacts fill contact fields
+    pub instance: Option<String>,
+    /// Phone number or JID of the contact to render for
+    pub contact: Option<String>,
+    pub language: Option<String>,
+    #[serde(default)]
+    pub variables: HashMap<String, String>,
+}
+
+type ApiResponse = (StatusCode, Json<serde_json::Value>);
+
+fn error(status: StatusCode, message: &str) -> ApiResponse {
+    (
+        status,
+        Json(serde_json::json!({
+            "success": false,
+            "message": message
+        })),
+    )
+}
+
+async fn find_template(
+    state: &Arc<AppState>,
+    user: &User,
+    template_id: i64,
+) -> Result<MessageTemplate, ApiResponse> {
+    sqlx::query_as("SELECT * FROM message_templates WHERE id = ? AND userId = ?")
+        .bind(template_id)
+        .bind(&user.id)
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None)
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "Template not found"))
+}
+
+fn save_error(e: sqlx::Error, action: &str) -> ApiResponse {
+    match e {
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => error(
+            StatusCode::CONFLICT,
+            "A template with this name already exists",
+        ),
+        e => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to {} template: {}", action, e),
+        ),
+    }
+}
+
+/// List the user's message templates
+pub async fn list_templates(
+    State(state): State<Arc<AppState>>,
+    CurrentUser { user }: CurrentUser,
+) -> ApiResponse {
+    let list: Vec<MessageTemplate> =
+        sqlx::query_as("SELECT * FROM message_templates WHERE userId = ? ORDER BY name")
+            .bind(&user.id)
+            .fetch_all(&state.db)
+            .await
+            .unwrap_or_default();
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "success": true,
+            "templates": list,
+            "builtinVariables": templates::BUILTIN_VARIABLES
+        })),
+    )
+}
+
+/// Create a message template
+pub async fn create_template(
+    State(state): State<Arc<AppState>>,
+    CurrentUser { user }: CurrentUser,
+    Json(payload): Json<CreateTemplateRequest>,
+) -> ApiResponse {
+    let name = payload.name.trim();
+    if name.is_empty() {
+        return error(StatusCode::BAD_REQUEST, "name is required");
+    }
+    let default_language = payload.default_language.unwrap_or_else(|| "en".to_string());
+    if let Err(e) = templates::validate(&default_language, &payload.variables, &payload.variants) {
+        return error(StatusCode::BAD_REQUEST, &e);
+    }
+
+    let now = chrono::Utc::now();
+    let result = sqlx::query(
+        "INSERT INTO message_templates (userId, name, description, defaultLanguage, variables, variants, createdAt, updatedAt)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&user.id)
+    .bind(name)
+    .bind(&payload.description)
+    .bind(&default_language)
+    .bind(serde_json::to_string(&payload.variables).unwrap_or_else(|_| "[]".to_string()))
+    .bind(serde_json::to_string(&payload.variants).unwrap_or_else(|_| "{}".to_string()))
+    .bind(now)
+    .bind(now)
+    .execute(&state.db)
+    .await;
+
+    match result {
+        Ok(r) => (
+            StatusCode::CREATED,
+            Json(serde_json::json!({
+                "success": true,
+                "template": find_template(&state, &user, r.last_insert_rowid()).await.ok()
+            })),
+        ),
+        Err(e) => save_error(e, "create"),
+    }
+}
+
+/// Get a single message template
+pub async fn get_template(
+    State(state): State<Arc<AppState>>,
+    CurrentUser { user }: CurrentUser,
+    Path((_, template_id)): Path<(String, i64)>,
+) -> ApiResponse {
+    match find_template(&state, &user, template_id).await {
+        Ok(template) => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "success": true,
+                "template": template
+            })),
+        ),
+        Err(e) => e,
+    }
+}
+
+/// Update a message template
+pub async fn update_template(
+    State(state): State<Arc<AppState>>,
+    CurrentUser { user }: CurrentUser,
+    Path((_, template_id)): Path<(String, i64)>,
+    Json(payload): Json<UpdateTemplateRequest>,
+) -> ApiResponse {
+    let mut template = match find_template(&state, &user, template_id).await {
+        Ok(t) => t,
+        Err(e) => return e,
+    };
+
+    if let Some(v) = payload.name {
+        if v.trim().is_empty() {
+            return error(StatusCode::BAD_REQUEST, "name can't be empty");
+        }
+        template.name = v.trim().to_string();
+    }
+    if let Some(v) = payload.description {
+        template.description = Some(v).filter(|d| !d.is_empty());
+    }
+    if let Some(v) = payload.default_language {
+        template.default_language = v;
+    }
+    if let Some(ref v) = payload.variables {
+        template.variables = serde_json::to_string(v).unwrap_or_else(|_| "[]".to_string());
+    }
+    if let Some(ref v) = payload.variants {
+        template.variants = serde_json::to_string(v).unwrap_or_else(|_| "{}".to_string());
+    }
+
+    let variables: Vec<Variable> = serde_json::from_str(&template.variables).unwrap_or_default();
+    let variants: Variants = serde_json::from_str(&template.variants).unwrap_or_default();
+    if let Err(e) = templates::validate(&template.default_language, &variables, &variants) {
+        return error(StatusCode::BAD_REQUEST, &e);
+    }
+
+    let result = sqlx::query(
+        "UPDATE message_templates SET name = ?, description = ?, defaultLanguage = ?, variables = ?, variants = ?, updatedAt = ?
+         WHERE id = ?",
+    )
+    .bind(&template.name)
+    .bind(&template.description)
+    .bind(&template.default_language)
+    .bind(&template.variables)
+    .bind(&template.variants)
+    .bind(chrono::Utc::now())
+    .bind(template_id)
+    .execute(&state.db)
+    .await;
+
+    match result {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "success": true,
+                "template": find_template(&state, &user, template_id).await.ok()
+            })),
+        ),
+        Err(e) => save_error(e, "update"),
+    }
+}
+
+/// Delete a message template
+pub async fn delete_template(
+    State(state): State<Arc<AppState>>,
+    CurrentUser { user }: CurrentUser,
+    Path((_, template_id)): Path<(String, i64)>,
+) -> ApiResponse {
+    match sqlx::query("DELETE FROM message_templates WHERE id = ? AND userId = ?")
+        .bind(template_id)
+        .bind(&user.id)
+        .execute(&state.db)
+        .await
+    {
+        Ok(r) if r.rows_affected() > 0 => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "success": true,
+                "message": "Template deleted"
+            })),
+        ),
+        Ok(_) => error(StatusCode::NOT_FOUND, "Template not found"),
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to delete template: {}", e),
+        ),
+    }
+}
+
+/// Render a template for a contact without sending it
+pub async fn preview_template(
+    State(state): State<Arc<AppState>>,
+    CurrentUser { user }: CurrentUser,
+    Path((_, template_id)): Path<(String, i64)>,
+    Json(payload): Json<PreviewRequest>,
+) -> ApiResponse {
+    let template = match find_template(&state, &user, template_id).await {
+        Ok(t) => t,
+        Err(e) => return e,
+    };
+
+    if let Some(ref instance) = payload.instance {
+        let owned: bool = sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM user_instances WHERE userId = ? AND sessionId = ?)",
+        )
+        .bind(&user.id)
+        .bind(instance)
+        .fetch_one(&state.db)
+        .await
+        .unwrap_or(false);
+
+        if !owned {
+            return error(
+                StatusCode::FORBIDDEN,
+                "You don't have access to this instance",
+            );
+        }
+    }
+    let jid = match payload.contact.as_deref().map(to_jid) {
+        Some(None) => return error(StatusCode::BAD_REQUEST, "Invalid contact"),
+        Some(jid) => jid,
+        None => None,
+    };
+
+    let mut values =
+        templates::context_values(&state.db, payload.instance.as_deref(), jid.as_deref()).await;
+    values.extend(payload.variables);
+
+    match templates::render(&template, payload.language.as_deref(), &values) {
+        Ok(rendered) => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "success": true,
+                "preview": rendered
+            })),
+        ),
+        Err(e) => error(StatusCode::UNPROCESSABLE_ENTITY, &e),
+    }
+}
@@ -1,10 +1,6 @@
 use crate::AppState;
-use crate::sql::User;
-use axum::{
-    Json,
-    extract::{Path, State},
-    http::StatusCode,
-};
+use crate::routes::access::CurrentUser;
+use axum::{Json, extract::State, http::StatusCode};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
@@ -110,41 +106,11 @@ pub struct ToolResult {
 /// Execute a command tool without text (one-click action)
 pub async fn execute_tool(
     State(state): State<Arc<AppState>>,
-    Path(crypto_hash): Path<String>,
+    current: CurrentUser,
     Json(payload): Json<ExecuteToolRequest>,
 ) -> (StatusCode, Json<ToolResult>) {
-    // Verify user
-    let user: Option<User> = sqlx::query_as("SELECT * FROM users WHERE cryptoHash = ?")
-        .bind(&crypto_hash)
-        .fetch_optional(&state.db)
-        .await
-        .unwrap_or(None);
-
-    let user = match user {
-        Some(u) => u,
-        None => {
-            return (
-                StatusCode::UNAUTHORIZED,
-                Json(ToolResult {
-                    success: false,
-                    message: "Invalid crypto hash".to_string(),
-                    data: None,
-                }),
-            );
-        }
-    };
-
     // Verify user owns this session
-    let owns_session: bool = sqlx::query_scalar(
-        "SELECT EXISTS(SELECT 1 FROM user_instances WHERE userId = ? AND sessionId = ?)",
-    )
-    .bind(&user.id)
-    .bind(&payload.session_id)
-    .fetch_one(&state.db)
-    .await
-    .unwrap_or(false);
-
-    if !owns_session {
+    if !current.owns_instance(&state, &payload.session_id).await {
         return (
             StatusCode::FORBIDDEN,
             Json(ToolResult {
@@ -241,15 +207,19 @@ async fn execute_clear_cache(
     session_id: &str,
 ) -> (StatusCode, Json<ToolResult>) {
     // Clear Redis cache for this session
-    if let Ok(mut conn) = state.redis.get_connection() {
+    if let Ok(mut conn) = state.redis.get_multiplexed_async_connection().await {
         let pattern = format!("session:{}:*", session_id);
         let keys: Vec<String> = redis::cmd("KEYS")
             .arg(&pattern)
-            .query(&mut conn)
+            .query_async(&mut conn)
+            .await
             .unwrap_or_default();
 
         for key in keys {
-            let _ = redis::cmd("DEL").arg(&key).query::<()>(&mut conn);
+            let _ = redis::cmd("DEL")
+                .arg(&key)
+                .query_async::<()>(&mut conn)
+                .await;
         }
     }
 
@@ -429,15 +399,19 @@ async fn execute_reset_session(
         .await;
 
     // Clear Redis data
-    if let Ok(mut conn) = state.redis.get_connection() {
+    if let Ok(mut conn) = state.redis.get_multiplexed_async_connection().await {
         let pattern = format!("session:{}:*", session_id);
         let keys: Vec<String> = redis::cmd("KEYS")
             .arg(&pattern)
-            .query(&mut conn)
+            .query_async(&mut conn)
+            .await
             .unwrap_or_default();
 
         for key in keys {
-            let _ = redis::cmd("DEL").arg(&key).query::<()>(&mut conn);
+            let _ = redis::cmd("DEL")
+                .arg(&key)
+                .query_async::<()>(&mut conn)
+                .await;
         }
     }
 
@@ -0,0 +1,483 @@
+use crate::AppState;
+use crate::logger;
+use crate::routes::access::CurrentUser;
+use crate::routes::auth;
+use crate::sql::User;
+use crate::totp;
+use axum::{Json, extract::State, http::StatusCode};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+/// How long a started enrollment waits for its first code
+const ENROLL_TTL_SECS: u64 = 10 * 60;
+/// How long the second login step stays open after the password
+const CHALLENGE_TTL_SECS: u64 = 5 * 60;
+/// Wrong codes a login challenge takes before it has to start over
+const CHALLENGE_ATTEMPTS: i64 = 5;
+const RECOVERY_CODE_COUNT: usize = 10;
+/// Used TOTP steps are remembered a little past the accepted skew
+const USED_STEP_TTL_SECS: u64 = 120;
+
+#[derive(Debug, Deserialize)]
+pub struct CodeRequest {
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DisableRequest {
+    pub password: String,
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SecurityPolicyRequest {
+    #[serde(rename = "requireAdmin2fa")]
+    pub require_admin_2fa: bool,
+}
+
+fn reply(status: StatusCode, message: &str) -> (StatusCode, Json<serde_json::Value>) {
+    (
+        status,
+        Json(serde_json::json!({
+            "success": status.is_success(),
+            "message": message
+        })),
+    )
+}
+
+/// Recovery codes are compared without case, dashes or spaces
+fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    hex::encode(Sha256::digest(normalized.as_bytes()))
+}
+
+/// Replace an account's recovery codes. Only the hashes are kept, the codes
+/// themselves are shown once.
+async fn issue_recovery_codes(
+    db: &sqlx::SqlitePool,
+    user_id: &str,
+) -> Result<Vec<String>, sqlx::Error> {
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let raw = hex::encode(rand::random::<[u8; 5]>());
+            format!("{}-{}", &raw[..5], &raw[5..])
+        })
+        .collect();
+
+    let mut tx = db.begin().await?;
+    sqlx::query("DELETE FROM recovery_codes WHERE userId = ?")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    for code in &codes {
+        sqlx::query("INSERT INTO recovery_codes (userId, codeHash, createdAt) VALUES (?, ?, ?)")
+            .bind(user_id)
+            .bind(hash_recovery_code(code))
+            .bind(chrono::Utc::now())
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok(codes)
+}
+
+async fn recovery_codes_left(db: &sqlx::SqlitePool, user_id: &str) -> i64 {
+    sqlx::query_scalar("SELECT COUNT(*) FROM recovery_codes WHERE userId = ? AND usedAt IS NULL")
+        .bind(user_id)
+        .fetch_one(db)
+        .await
+        .unwrap_or(0)
+}
+
+/// Check an authenticator code, refusing one that was already used
+async fn verify_totp(redis: &redis::Client, user_id: &str, secret: &str, code: &str) -> bool {
+    let Some(step) = totp::verify(secret, code, chrono::Utc::now().timestamp()) else {
+        return false;
+    };
+    // Without Redis a replay can't be ruled out, so the code is refused
+    let Ok(mut conn) = redis.get_multiplexed_async_connection().await else {
+        return false;
+    };
+    let first_use: Option<String> = redis::cmd("SET")
+        .arg(format!("totp_used:{}:{}", user_id, step))
+        .arg(1)
+        .arg("NX")
+        .arg("EX")
+        .arg(USED_STEP_TTL_SECS)
+        .query_async(&mut conn)
+        .await
+        .unwrap_or(None);
+    first_use.is_some()
+}
+
+/// Check the second factor of an account with 2FA on: an authenticator code,
+/// or a recovery code, which is used up
+pub async fn verify_second_factor(state: &Arc<AppState>, user: &User, code: &str) -> bool {
+    let Some(secret) = user.totp_secret.as_deref().filter(|_| user.totp_enabled) else {
+        return false;
+    };
+    if verify_totp(&state.redis, &user.id, secret, code).await {
+        return true;
+    }
+
+    let used = sqlx::query(
+        "UPDATE recovery_codes SET usedAt = ? WHERE userId = ? AND codeHash = ? AND usedAt IS NULL",
+    )
+    .bind(chrono::Utc::now())
+    .bind(&user.id)
+    .bind(hash_recovery_code(code))
+    .execute(&state.db)
+    .await
+    .map(|r| r.rows_affected() > 0)
+    .unwrap_or(false);
+    if used {
+        logger::info(
+            "AUTH",
+            &format!("{} signed in with a recovery code", user.phone_number),
+        );
+    }
+    used
+}
+
+/// Whether admin accounts must have 2FA before they can sign in
+pub async fn admin_2fa_required(db: &sqlx::SqlitePool) -> bool {
+    sqlx::query_scalar::<_, String>(
+        "SELECT value FROM system_settings WHERE name = 'requireAdmin2fa'",
+    )
+    .fetch_optional(db)
+    .await
+    .ok()
+    .flatten()
+    .is_some_and(|v| v == "true")
+}
+
+// ========== Login challenges ==========
+
+/// Open the second login step for an account whose password checked out.
+/// Returns the challenge id the code is sent back with.
+pub async fn start_challenge(redis: &redis::Client, user_id: &str) -> Option<String> {
+    let mut conn = redis.get_multiplexed_async_connection().await.ok()?;
+    let challenge_id = hex::encode(rand::random::<[u8; 16]>());
+    redis::cmd("SETEX")
+        .arg(format!("login_2fa:{}", challenge_id))
+        .arg(CHALLENGE_TTL_SECS)
+        .arg(user_id)
+        .query_async::<()>(&mut conn)
+        .await
+        .ok()?;
+    Some(challenge_id)
+}
+
+/// The account a login challenge belongs to
+pub async fn challenge_user(redis: &redis::Client, challenge_id: &str) -> Option<String> {
+    let mut conn = redis.get_multiplexed_async_connection().await.ok()?;
+    redis::cmd("GET")
+        .arg(format!("login_2fa:{}", challenge_id))
+        .query_async(&mut conn)
+        .await
+        .ok()?
+}
+
+/// Count a wrong code. After too many the challenge is dropped and the
+/// password has to be entered again.
+pub async fn challenge_failed(redis: &redis::Client, challenge_id: &str) {
+    let Ok(mut conn) = redis.get_multiplexed_async_connection().await else {
+        return;
+    };
+    let key = format!("login_2fa_attempts:{}", challenge_id);
+    let (attempts,): (i64,) = redis::pipe()
+        .atomic()
+        .incr(&key, 1)
+        .expire(&key, CHALLENGE_TTL_SECS as i64)
+        .ignore()
+        .query_async(&mut conn)
+        .await
+        .unwrap_or((CHALLENGE_ATTEMPTS,));
+    if attempts >= CHALLENGE_ATTEMPTS {
+        end_challenge(redis, challenge_id).await;
+    }
+}
+
+/// Close a challenge once it has been answered
+pub async fn end_challenge(redis: &redis::Client, challenge_id: &str) {
+    if let Ok(mut conn) = redis.get_multiplexed_async_connection().await {
+        let _: () = redis::pipe()
+            .del(format!("login_2fa:{}", challenge_id))
+            .del(format!("login_2fa_attempts:{}", challenge_id))
+            .query_async(&mut conn)
+            .await
+            .unwrap_or(());
+    }
+}
+
+// ========== Account settings ==========
+
+/// 2FA status of the account
+pub async fn get_two_factor(
+    State(state): State<Arc<AppState>>,
+    CurrentUser { user }: CurrentUser,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let required = user.is_admin && admin_2fa_required(&state.db).await;
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "success": true,
+            "enabled": user.totp_enabled,
+            "recoveryCodesLeft": recovery_codes_left(&state.db, &user.id).await,
+            "required": required
+        })),
+    )
+}
+
+/// Start enrollment with a fresh secret. It only takes effect once a code
+/// from the authenticator app confirms it.
+pub async fn enroll_two_factor(
+    State(state): State<Arc<AppState>>,
+    CurrentUser { user }: CurrentUser,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if user.totp_enabled {
+        return reply(
+            StatusCode::CONFLICT,
+            "Two-factor authentication is already on",
+        );
+    }
+
+    let secret = totp::generate_secret();
+    let stored = match state.redis.get_multiplexed_async_connection().await {
+        Ok(mut conn) => redis::cmd("SETEX")
+            .arg(format!("totp_enroll:{}", user.id))
+            .arg(ENROLL_TTL_SECS)
+            .arg(&secret)
+            .query_async::<()>(&mut conn)
+            .await
+            .ok(),
+        Err(_) => None,
+    };
+    if stored.is_none() {
+        return reply(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Could not start enrollment, try again",
+        );
+    }
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "success": true,
+            "secret": secret,
+            "otpauthUri": totp::otpauth_uri(&auth::get_rp_name(), &user.phone_number, &secret),
+            "expiresIn": ENROLL_TTL_SECS
+        })),
+    )
+}
+
+/// Confirm enrollment with a code from the app, which turns 2FA on and
+/// hands out the recovery codes
+pub async fn confirm_two_factor(
+    State(state): State<Arc<AppState>>,
+    CurrentUser { user }: CurrentUser,
+    Json(payload): Json<CodeRequest>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let key = format!("totp_enroll:{}", user.id);
+    let pending: Option<String> = match state.redis.get_multiplexed_async_connection().await {
+        Ok(mut conn) => redis::cmd("GET")
+            .arg(&key)
+            .query_async(&mut conn)
+            .await
+            .unwrap_or(None),
+        Err(_) => None,
+    };
+    let Some(secret) = pending else {
+        return reply(
+            StatusCode::BAD_REQUEST,
+            "No enrollment in progress, start again",
+        );
+    };
+    if !verify_totp(&state.redis, &user.id, &secret, &payload.code).await {
+        return reply(StatusCode::BAD_REQUEST, "Invalid code");
+    }
+
+    let enabled = sqlx::query(
+        "UPDATE users SET totpSecret = ?, totpEnabled = TRUE, updatedAt = ? WHERE id = ?",
+    )
+    .bind(&secret)
+    .bind(chrono::Utc::now())
+    .bind(&user.id)
+    .execute(&state.db)
+    .await;
+    if let Err(e) = enabled {
+        return reply(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to enable two-factor authentication: {}", e),
+        );
+    }
+    if let Ok(mut conn) = state.redis.get_multiplexed_async_connection().await {
+        let _: () = redis::cmd("DEL")
+            .arg(&key)
+            .query_async(&mut conn)
+            .await
+            .unwrap_or(());
+    }
+
+    let codes = match issue_recovery_codes(&state.db, &user.id).await {
+        Ok(codes) => codes,
+        Err(e) => {
+            return reply(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &format!("Failed to create recovery codes: {}", e),
+            );
+        }
+    };
+    logger::info(
+        "AUTH",
+        &format!("{} turned on two-factor authentication", user.phone_number),
+    );
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "success": true,
+            "message": "Two-factor authentication is on",
+            "recoveryCodes": codes
+        })),
+    )
+}
+
+/// Turn 2FA off. Takes the password and a current code, so a stolen session
+/// alone can't do it.
+pub async fn disable_two_factor(
+    State(state): State<Arc<AppState>>,
+    CurrentUser { user }: CurrentUser,
+    Json(payload): Json<DisableRequest>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if !user.totp_enabled {
+        return reply(StatusCode::BAD_REQUEST, "Two-factor authentication is off");
+    }
+    if user.is_admin && admin_2fa_required(&state.db).await {
+        return reply(
+            StatusCode::FORBIDDEN,
+            "Admin accounts are required to keep two-factor authentication on",
+        );
+    }
+    if !auth::verify_password(&payload.password, &user.password_hash)
+        || !verify_second_factor(&state, &user, &payload.code).await
+    {
+        return reply(StatusCode::UNAUTHORIZED, "Invalid password or code");
+    }
+
+    let result = sqlx::query(
+        "UPDATE users SET totpSecret = NULL, totpEnabled = FALSE, updatedAt = ? WHERE id = ?",
+    )
+    .bind(chrono::Utc::now())
+    .bind(&user.id)
+    .execute(&state.db)
+    .await;
+    if let Err(e) = result {
+        return reply(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to disable two-factor authentication: {}", e),
+        );
+    }
+    let _ = sqlx::query("DELETE FROM recovery_codes WHERE userId = ?")
+        .bind(&user.id)
+        .execute(&state.db)
+        .await;
+    logger::info(
+        "AUTH",
+        &format!("{} turned off two-factor authentication", user.phone_number),
+    );
+
+    reply(StatusCode::OK, "Two-factor authentication is off")
+}
+
+/// Replace the recovery codes, the old ones stop working
+pub async fn regenerate_recovery_codes(
+    State(state): State<Arc<AppState>>,
+    CurrentUser { user }: CurrentUser,
+    Json(payload): Json<CodeRequest>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if !user.totp_enabled {
+        return reply(StatusCode::BAD_REQUEST, "Two-factor authentication is off");
+    }
+    if !verify_second_factor(&state, &user, &payload.code).await {
+        return reply(StatusCode::UNAUTHORIZED, "Invalid code");
+    }
+
+    match issue_recovery_codes(&state.db, &user.id).await {
+        Ok(codes) => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "success": true,
+                "recoveryCodes": codes
+            })),
+        ),
+        Err(e) => reply(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to create recovery codes: {}", e),
+        ),
+    }
+}
+
+// ========== Admin policy ==========
+
+/// Security settings for staff accounts
+pub async fn get_security_policy(
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let without_2fa: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM users WHERE isAdmin = TRUE AND totpEnabled = FALSE",
+    )
+    .fetch_one(&state.db)
+    .await
+    .unwrap_or(0);
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "success": true,
+            "requireAdmin2fa": admin_2fa_required(&state.db).await,
+            "adminsWithout2fa": without_2fa
+        })),
+    )
+}
+
+/// Require 2FA for admin accounts, or stop requiring it. Admins without it
+/// can't sign in to the admin workspace until they turn it on.
+pub async fn set_security_policy(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<SecurityPolicyRequest>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let result = sqlx::query(
+        "INSERT INTO system_settings (name, value, updatedAt) VALUES ('requireAdmin2fa', ?, ?)
+         ON CONFLICT(name) DO UPDATE SET value = excluded.value, updatedAt = excluded.updatedAt",
+    )
+    .bind(payload.require_admin_2fa.to_string())
+    .bind(chrono::Utc::now())
+    .execute(&state.db)
+    .await;
+
+    match result {
+        Ok(_) => {
+            logger::info(
+                "ADMIN",
+                &format!(
+                    "Two-factor authentication for admins is now {}",
+                    if payload.require_admin_2fa {
+                        "required"
+                    } else {
+                        "optional"
+                    }
+                ),
+            );
+            reply(StatusCode::OK, "Security policy updated")
+        }
+        Err(e) => reply(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to update security policy: {}", e),
+        ),
+    }
+}
@@ -1,5 +1,6 @@
 use crate::AppState;
-use crate::sql::{CreditTransaction, SupportRequest, UsageLog, User};
+use crate::routes::access::CurrentUser;
+use crate::sql::{CreditTransaction, LoginRecord, SupportRequest, UsageLog, User};
 use axum::{
     Json,
     extract::{Path, State},
@@ -36,28 +37,8 @@ pub struct UserInstanceInfo {
 /// Get user dashboard data
 pub async fn get_user_dashboard(
     State(state): State<Arc<AppState>>,
-    Path(crypto_hash): Path<String>,
+    CurrentUser { user }: CurrentUser,
 ) -> (StatusCode, Json<serde_json::Value>) {
-    // Find user by crypto hash
-    let user: Option<User> = sqlx::query_as("SELECT * FROM users WHERE cryptoHash = ?")
-        .bind(&crypto_hash)
-        .fetch_optional(&state.db)
-        .await
-        .unwrap_or(None);
-
-    let user = match user {
-        Some(u) => u,
-        None => {
-            return (
-                StatusCode::UNAUTHORIZED,
-                Json(serde_json::json!({
-                    "success": false,
-                    "message": "Invalid crypto hash"
-                })),
-            );
-        }
-    };
-
     // Get user's instances
     let instances: Vec<UserInstanceInfo> = sqlx::query_as::<
         _,
@@ -136,27 +117,8 @@ pub async fn get_user_dashboard(
 /// Get user's instances
 pub async fn get_user_instances(
     State(state): State<Arc<AppState>>,
-    Path(crypto_hash): Path<String>,
+    CurrentUser { user }: CurrentUser,
 ) -> (StatusCode, Json<serde_json::Value>) {
-    let user: Option<User> = sqlx::query_as("SELECT * FROM users WHERE cryptoHash = ?")
-        .bind(&crypto_hash)
-        .fetch_optional(&state.db)
-        .await
-        .unwrap_or(None);
-
-    let user = match user {
-        Some(u) => u,
-        None => {
-            return (
-                StatusCode::UNAUTHORIZED,
-                Json(serde_json::json!({
-                    "success": false,
-                    "message": "Invalid crypto hash"
-                })),
-            );
-        }
-    };
-
     let instances: Vec<UserInstanceInfo> = sqlx::query_as::<
         _,
         (
@@ -196,27 +158,8 @@ pub async fn get_user_instances(
 /// Get user's credit balance and transactions
 pub async fn get_user_credits(
     State(state): State<Arc<AppState>>,
-    Path(crypto_hash): Path<String>,
+    CurrentUser { user }: CurrentUser,
 ) -> (StatusCode, Json<serde_json::Value>) {
-    let user: Option<User> = sqlx::query_as("SELECT * FROM users WHERE cryptoHash = ?")
-        .bind(&crypto_hash)
-        .fetch_optional(&state.db)
-        .await
-        .unwrap_or(None);
-
-    let user = match user {
-        Some(u) => u,
-        None => {
-            return (
-                StatusCode::UNAUTHORIZED,
-                Json(serde_json::json!({
-                    "success": false,
-                    "message": "Invalid crypto hash"
-                })),
-            );
-        }
-    };
-
     let transactions: Vec<CreditTransaction> = sqlx::query_as(
         "SELECT * FROM credit_transactions WHERE userId = ? ORDER BY createdAt DESC LIMIT 50",
     )
@@ -244,28 +187,9 @@ pub struct AddCreditsRequest {
 /// Add credits to user account (admin or payment gateway callback)
 pub async fn add_credits(
     State(state): State<Arc<AppState>>,
-    Path(crypto_hash): Path<String>,
+    CurrentUser { user }: CurrentUser,
     Json(payload): Json<AddCreditsRequest>,
 ) -> (StatusCode, Json<serde_json::Value>) {
-    let user: Option<User> = sqlx::query_as("SELECT * FROM users WHERE cryptoHash = ?")
-        .bind(&crypto_hash)
-        .fetch_optional(&state.db)
-        .await
-        .unwrap_or(None);
-
-    let user = match user {
-        Some(u) => u,
-        None => {
-            return (
-                StatusCode::UNAUTHORIZED,
-                Json(serde_json::json!({
-                    "success": false,
-                    "message": "Invalid crypto hash"
-                })),
-            );
-        }
-    };
-
     if payload.amount <= 0.0 {
         return (
             StatusCode::BAD_REQUEST,
@@ -316,27 +240,8 @@ pub async fn add_credits(
 /// Get user's usage history
 pub async fn get_usage_history(
     State(state): State<Arc<AppState>>,
-    Path(crypto_hash): Path<String>,
+    CurrentUser { user }: CurrentUser,
 ) -> (StatusCode, Json<serde_json::Value>) {
-    let user: Option<User> = sqlx::query_as("SELECT * FROM users WHERE cryptoHash = ?")
-        .bind(&crypto_hash)
-        .fetch_optional(&state.db)
-        .await
-        .unwrap_or(None);
-
-    let user = match user {
-        Some(u) => u,
-        None => {
-            return (
-                StatusCode::UNAUTHORIZED,
-                Json(serde_json::json!({
-                    "success": false,
-                    "message": "Invalid crypto hash"
-                })),
-            );
-        }
-    };
-
     let usage: Vec<UsageLog> = sqlx::query_as(
         "SELECT * FROM usage_logs WHERE userId = ? ORDER BY startTime DESC LIMIT 100",
     )
@@ -371,6 +276,28 @@ pub async fn get_usage_history(
     )
 }
 
+/// Get the account's recent sign-in attempts
+pub async fn get_login_history(
+    State(state): State<Arc<AppState>>,
+    CurrentUser { user }: CurrentUser,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let logins: Vec<LoginRecord> = sqlx::query_as(
+        "SELECT * FROM login_history WHERE userId = ? ORDER BY createdAt DESC LIMIT 50",
+    )
+    .bind(&user.id)
+    .fetch_all(&state.db)
+    .await
+    .unwrap_or_default();
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "success": true,
+            "logins": logins
+        })),
+    )
+}
+
 #[derive(Debug, Deserialize)]
 pub struct SupportRequestPayload {
     pub email: String,
@@ -381,28 +308,9 @@ pub struct SupportRequestPayload {
 /// Submit a support request
 pub async fn submit_support_request(
     State(state): State<Arc<AppState>>,
-    Path(crypto_hash): Path<String>,
+    CurrentUser { user }: CurrentUser,
     Json(payload): Json<SupportRequestPayload>,
 ) -> (StatusCode, Json<serde_json::Value>) {
-    let user: Option<User> = sqlx::query_as("SELECT * FROM users WHERE cryptoHash = ?")
-        .bind(&crypto_hash)
-        .fetch_optional(&state.db)
-        .await
-        .unwrap_or(None);
-
-    let user = match user {
-        Some(u) => u,
-        None => {
-            return (
-                StatusCode::UNAUTHORIZED,
-                Json(serde_json::json!({
-                    "success": false,
-                    "message": "Invalid crypto hash"
-                })),
-            );
-        }
-    };
-
     if payload.email.is_empty() || payload.subject.is_empty() || payload.message.is_empty() {
         return (
             StatusCode::BAD_REQUEST,
@@ -450,27 +358,8 @@ pub async fn submit_support_request(
 /// Get user's support requests
 pub async fn get_support_requests(
     State(state): State<Arc<AppState>>,
-    Path(crypto_hash): Path<String>,
+    CurrentUser { user }: CurrentUser,
 ) -> (StatusCode, Json<serde_json::Value>) {
-    let user: Option<User> = sqlx::query_as("SELECT * FROM users WHERE cryptoHash = ?")
-        .bind(&crypto_hash)
-        .fetch_optional(&state.db)
-        .await
-        .unwrap_or(None);
-
-    let user = match user {
-        Some(u) => u,
-        None => {
-            return (
-                StatusCode::UNAUTHORIZED,
-                Json(serde_json::json!({
-                    "success": false,
-                    "message": "Invalid crypto hash"
-                })),
-            );
-        }
-    };
-
     let requests: Vec<SupportRequest> =
         sqlx::query_as("SELECT * FROM support_requests WHERE userId = ? ORDER BY createdAt DESC")
             .bind(&user.id)
@@ -497,29 +386,9 @@ pub struct CreateInstanceRequest {
 /// Create a new instance for user (max 1 per phone number)
 pub async fn create_user_instance(
     State(state): State<Arc<AppState>>,
-    Path(crypto_hash): Path<String>,
+    CurrentUser { user }: CurrentUser,
     Json(payload): Json<CreateInstanceRequest>,
 ) -> (StatusCode, Json<serde_json::Value>) {
-    // Find user by crypto hash
-    let user: Option<User> = sqlx::query_as("SELECT * FROM users WHERE cryptoHash = ?")
-        .bind(&crypto_hash)
-        .fetch_optional(&state.db)
-        .await
-        .unwrap_or(None);
-
-    let user = match user {
-        Some(u) => u,
-        None => {
-            return (
-                StatusCode::UNAUTHORIZED,
-                Json(serde_json::json!({
-                    "success": false,
-                    "message": "Invalid crypto hash"
-                })),
-            );
-        }
-    };
-
     // Check if user is limited from creating instances
     if user.instance_limit == 0 {
         return (
@@ -613,7 +482,7 @@ pub async fn create_user_instance(
     .bind(payload.name.as_deref().unwrap_or("New Instance"))
     .bind("starting")
     .bind(&clean_phone)
-    .bind(&crypto_hash)
+    .bind(&user.crypto_hash)
     .bind(now)
     .bind(now)
     .execute(&state.db)
@@ -671,38 +540,11 @@ pub async fn create_user_instance(
 /// Get pairing code for a specific session
 pub async fn get_instance_pairing_code(
     State(state): State<Arc<AppState>>,
-    Path((crypto_hash, session_id)): Path<(String, String)>,
+    Path((_, session_id)): Path<(String, String)>,
+    current: CurrentUser,
 ) -> (StatusCode, Json<serde_json::Value>) {
-    // Verify user owns this instance
-    let user: Option<User> = sqlx::query_as("SELECT * FROM users WHERE cryptoHash = ?")
-        .bind(&crypto_hash)
-        .fetch_optional(&state.db)
-        .await
-        .unwrap_or(None);
-
-    let user = match user {
-        Some(u) => u,
-        None => {
-            return (
-                StatusCode::UNAUTHORIZED,
-                Json(serde_json::json!({
-                    "success": false,
-                    "message": "Invalid crypto hash"
-                })),
-            );
-        }
-    };
-
     // Check if user owns this session
-    let owns_session: Option<(String,)> =
-        sqlx::query_as("SELECT sessionId FROM user_instances WHERE userId = ? AND sessionId = ?")
-            .bind(&user.id)
-            .bind(&session_id)
-            .fetch_optional(&state.db)
-            .await
-            .unwrap_or(None);
-
-    if owns_session.is_none() {
+    if !current.owns_instance(&state, &session_id).await {
         return (
             StatusCode::FORBIDDEN,
             Json(serde_json::json!({
@@ -0,0 +1,402 @@
+use crate::AppState;
+use crate::manager::webhooks;
+use crate::routes::access::CurrentUser;
+use crate::sql::{User, Webhook, WebhookAttempt, WebhookDelivery};
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhookRequest {
+    pub url: String,
+    /// Limit the subscription to one instance, all owned instances otherwise
+    pub instance: Option<String>,
+    /// Event filters, e.g. `message.received`, `message.*` or `*`. Empty means all.
+    pub events: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateWebhookRequest {
+    pub url: Option<String>,
+    pub events: Option<Vec<String>>,
+    pub active: Option<bool>,
+    #[serde(rename = "rotateSecret")]
+    pub rotate_secret: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeliveriesQuery {
+    /// pending, delivered or dead (the dead-letter list)
+    pub status: Option<String>,
+    #[serde(rename = "webhookId")]
+    pub webhook_id: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+type ApiResponse = (StatusCode, Json<serde_json::Value>);
+
+fn error(status: StatusCode, message: &str) -> ApiResponse {
+    (
+        status,
+        Json(serde_json::json!({
+            "success": false,
+            "message": message
+        })),
+    )
+}
+
+async fn find_webhook(
+    state: &Arc<AppState>,
+    user: &User,
+    webhook_id: i64,
+) -> Result<Webhook, ApiResponse> {
+    sqlx::query_as("SELECT * FROM webhooks WHERE id = ? AND userId = ?")
+        .bind(webhook_id)
+        .bind(&user.id)
+        .fetch_optional(&state.db)
+        .await
+        .unwrap_or(None)
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "Webhook not found"))
+}
+
+async fn validate_url(raw: &str) -> Result<(), ApiResponse> {
+    webhooks::check_target(raw)
+        .await
+        .map(|_| ())
+        .map_err(|e| error(StatusCode::BAD_REQUEST, &e))
+}
+
+fn validate_events(events: &[String]) -> Result<String, ApiResponse> {
+    if let Some(bad) = events.iter().find(|e| !webhooks::is_valid_filter(e)) {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            &format!(
+                "Unknown event '{}', expected one of: {}",
+                bad,
+                webhooks::EVENTS.join(", ")
+            ),
+        ));
+    }
+    Ok(serde_json::to_string(events).unwrap_or_else(|_| "[]".to_string()))
+}
+
+/// List the user's webhook subscriptions
+pub async fn list_webhooks(
+    State(state): State<Arc<AppState>>,
+    CurrentUser { user }: CurrentUser,
+) -> ApiResponse {
+    let hooks: Vec<Webhook> =
+        sqlx::query_as("SELECT * FROM webhooks WHERE userId = ? ORDER BY createdAt DESC")
+            .bind(&user.id)
+            .fetch_all(&state.db)
+            .await
+            .unwrap_or_default();
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "success": true,
+            "webhooks": hooks,
+            "events": webhooks::EVENTS
+        })),
+    )
+}
+
+/// Create a webhook subscription. The signing secret is only returned here and on rotation.
+pub async fn create_webhook(
+    State(state): State<Arc<AppState>>,
+    CurrentUser { user }: CurrentUser,
+    Json(payload): Json<CreateWebhookRequest>,
+) -> ApiResponse {
+    if let Err(e) = validate_url(&payload.url).await {
+        return e;
+    }
+    let events = match validate_events(&payload.events.unwrap_or_default()) {
+        Ok(e) => e,
+        Err(e) => return e,
+    };
+
+    if let Some(ref instance) = payload.instance {
+        let owned: bool = sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM user_instances WHERE userId = ? AND sessionId = ?)",
+        )
+        .bind(&user.id)
+        .bind(instance)
+        .fetch_one(&state.db)
+        .await
+        .unwrap_or(false);
+
+        if !owned {
+            return error(
+                StatusCode::FORBIDDEN,
+                "You don't have access to this instance",
+            );
+        }
+    }
+
+    let secret = webhooks::generate_secret();
+    let now = chrono::Utc::now();
+    let result = sqlx::query(
+        "INSERT INTO webhooks (userId, sessionId, url, secret, events, active, createdAt, updatedAt)
+         VALUES (?, ?, ?, ?, ?, 1, ?, ?)",
+    )
+    .bind(&user.id)
+    .bind(&payload.instance)
+    .bind(&payload.url)
+    .bind(&secret)
+    .bind(&events)
+    .bind(now)
+    .bind(now)
+    .execute(&state.db)
+    .await;
+
+    match result {
+        Ok(r) => {
+            let hook = find_webhook(&state, &user, r.last_insert_rowid())
+                .await
+                .ok();
+            (
+                StatusCode::CREATED,
+                Json(serde_json::json!({
+                    "success": true,
+                    "webhook": hook,
+                    "secret": secret
+                })),
+            )
+        }
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to create webhook: {}", e),
+        ),
+    }
+}
+
+/// Update a webhook's URL, filters or active flag, optionally rotating its secret
+pub async fn update_webhook(
+    State(state): State<Arc<AppState>>,
+    CurrentUser { user }: CurrentUser,
+    Path((_, webhook_id)): Path<(String, i64)>,
+    Json(payload): Json<UpdateWebhookRequest>,
+) -> ApiResponse {
+    let hook = match find_webhook(&state, &user, webhook_id).await {
+        Ok(h) => h,
+        Err(e) => return e,
+    };
+
+    let url = payload.url.unwrap_or(hook.url);
+    if let Err(e) = validate_url(&url).await {
+        return e;
+    }
+    let events = match payload.events {
+        Some(ref events) => match validate_events(events) {
+            Ok(e) => e,
+            Err(e) => return e,
+        },
+        None => hook.events,
+    };
+    let rotated = payload
+        .rotate_secret
+        .unwrap_or(false)
+        .then(webhooks::generate_secret);
+    let secret = rotated.clone().unwrap_or(hook.secret);
+
+    let result = sqlx::query(
+        "UPDATE webhooks SET url = ?, events = ?, active = ?, secret = ?, updatedAt = ? WHERE id = ?",
+    )
+    .bind(&url)
+    .bind(&events)
+    .bind(payload.active.unwrap_or(hook.active))
+    .bind(&secret)
+    .bind(chrono::Utc::now())
+    .bind(webhook_id)
+    .execute(&state.db)
+    .await;
+
+    match result {
+        Ok(_) => {
+            let hook = find_webhook(&state, &user, webhook_id).await.ok();
+            let mut body = serde_json::json!({
+                "success": true,
+                "webhook": hook
+            });
+            if let Some(secret) = rotated {
+                body["secret"] = serde_json::json!(secret);
+            }
+            (StatusCode::OK, Json(body))
+        }
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to update webhook: {}", e),
+        ),
+    }
+}
+
+/// Delete a webhook subscription together with its delivery log
+pub async fn delete_webhook(
+    State(state): State<Arc<AppState>>,
+    CurrentUser { user }: CurrentUser,
+    Path((_, webhook_id)): Path<(String, i64)>,
+) -> ApiResponse {
+    if let Err(e) = find_webhook(&state, &user, webhook_id).await {
+        return e;
+    }
+
+    match sqlx::query("DELETE FROM webhooks WHERE id = ?")
+        .bind(webhook_id)
+        .execute(&state.db)
+        .await
+    {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "success": true,
+                "message": "Webhook deleted"
+            })),
+        ),
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to delete webhook: {}", e),
+        ),
+    }
+}
+
+/// Queue a `ping` delivery to check the receiver and signature setup
+pub async fn test_webhook(
+    State(state): State<Arc<AppState>>,
+    CurrentUser { user }: CurrentUser,
+    Path((_, webhook_id)): Path<(String, i64)>,
+) -> ApiResponse {
+    let hook = match find_webhook(&state, &user, webhook_id).await {
+        Ok(h) => h,
+        Err(e) => return e,
+    };
+
+    let data = serde_json::json!({ "webhookId": hook.id, "url": hook.url });
+    match webhooks::enqueue(
+        &state.db,
+        hook.id,
+        hook.session_id.as_deref(),
+        "ping",
+        &data,
+    )
+    .await
+    {
+        Ok(id) => (
+            StatusCode::ACCEPTED,
+            Json(serde_json::json!({
+                "success": true,
+                "deliveryId": id
+            })),
+        ),
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to queue test delivery: {}", e),
+        ),
+    }
+}
+
+/// Delivery log across the user's webhooks; `status=dead` lists dead letters
+pub async fn list_deliveries(
+    State(state): State<Arc<AppState>>,
+    CurrentUser { user }: CurrentUser,
+    Query(query): Query<DeliveriesQuery>,
+) -> ApiResponse {
+    let deliveries: Vec<WebhookDelivery> = sqlx::query_as(
+        "SELECT d.* FROM webhook_deliveries d JOIN webhooks w ON w.id = d.webhookId
+         WHERE w.userId = ? AND (? IS NULL OR d.status = ?) AND (? IS NULL OR d.webhookId = ?)
+         ORDER BY d.createdAt DESC LIMIT ?",
+    )
+    .bind(&user.id)
+    .bind(&query.status)
+    .bind(&query.status)
+    .bind(query.webhook_id)
+    .bind(query.webhook_id)
+    .bind(query.limit.unwrap_or(100).clamp(1, 500))
+    .fetch_all(&state.db)
+    .await
+    .unwrap_or_default();
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "success": true,
+            "deliveries": deliveries
+        })),
+    )
+}
+
+async fn find_delivery(
+    state: &Arc<AppState>,
+    user: &User,
+    delivery_id: &str,
+) -> Result<WebhookDelivery, ApiResponse> {
+    sqlx::query_as(
+        "SELECT d.* FROM webhook_deliveries d JOIN webhooks w ON w.id = d.webhookId
+         WHERE d.id = ? AND w.userId = ?",
+    )
+    .bind(delivery_id)
+    .bind(&user.id)
+    .fetch_optional(&state.db)
+    .await
+    .unwrap_or(None)
+    .ok_or_else(|| error(StatusCode::NOT_FOUND, "Delivery not found"))
+}
+
+/// A single delivery with every attempt made so far
+pub async fn get_delivery(
+    State(state): State<Arc<AppState>>,
+    CurrentUser { user }: CurrentUser,
+    Path((_, delivery_id)): Path<(String, String)>,
+) -> ApiResponse {
+    let delivery = match find_delivery(&state, &user, &delivery_id).await {
+        Ok(d) => d,
+        Err(e) => return e,
+    };
+
+    let attempts: Vec<WebhookAttempt> = sqlx::query_as(
+        "SELECT * FROM webhook_attempts WHERE deliveryId = ? ORDER BY createdAt ASC, id ASC",
+    )
+    .bind(&delivery.id)
+    .fetch_all(&state.db)
+    .await
+    .unwrap_or_default();
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "success": true,
+            "delivery": delivery,
+            "attempts": attempts
+        })),
+    )
+}
+
+/// Manually send a delivery again, resetting its retry budget
+pub async fn redeliver(
+    State(state): State<Arc<AppState>>,
+    CurrentUser { user }: CurrentUser,
+    Path((_, delivery_id)): Path<(String, String)>,
+) -> ApiResponse {
+    let delivery = match find_delivery(&state, &user, &delivery_id).await {
+        Ok(d) => d,
+        Err(e) => return e,
+    };
+
+    match webhooks::redeliver(&state.db, &delivery.id).await {
+        Ok(_) => (
+            StatusCode::ACCEPTED,
+            Json(serde_json::json!({
+                "success": true,
+                "message": "Delivery queued"
+            })),
+        ),
+        Err(e) => error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Failed to queue delivery: {}", e),
+        ),
+    }
+}
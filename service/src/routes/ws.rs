@@ -0,0 +1,328 @@
+use crate::AppState;
+use crate::manager::InstanceEvent;
+use crate::manager::outbound::{self, SendSource};
+use crate::routes::access::can_access_instance;
+use crate::routes::messages::SendMessageRequest;
+use crate::security::{Claims, is_revoked, verify_session_token};
+use axum::{
+    Extension, Json,
+    extract::{
+        Query, State,
+        ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade},
+    },
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use futures::{SinkExt, StreamExt};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::sync::{broadcast, mpsc};
+
+/// Close code sent when the token used to open the socket expires
+const CLOSE_TOKEN_EXPIRED: u16 = 4001;
+/// Close code sent when the token or its session is revoked while the socket is open
+const CLOSE_TOKEN_REVOKED: u16 = 4003;
+/// How often an open socket re-checks its token against the revocation list
+const REVOCATION_CHECK_SECS: u64 = 30;
+
+#[derive(Debug, Deserialize)]
+pub struct WsQuery {
+    /// Browsers can't set headers on a WebSocket handshake, so the JWT may come as `?token=`
+    pub token: Option<String>,
+}
+
+/// A client frame. `id` is echoed back on the matching response.
+#[derive(Debug, Deserialize)]
+struct ClientFrame {
+    id: Option<serde_json::Value>,
+    #[serde(flatten)]
+    action: Action,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "camelCase")]
+enum Action {
+    /// Start receiving events of these instances (`*` for every instance, admin only)
+    Subscribe {
+        instances: Vec<String>,
+    },
+    Unsubscribe {
+        instances: Vec<String>,
+    },
+    Send {
+        instance: String,
+        message: Box<SendMessageRequest>,
+    },
+    Pause {
+        instance: String,
+    },
+    Resume {
+        instance: String,
+    },
+    Ping,
+}
+
+/// Result of a command, sent back as a `response` frame
+type Reply = Result<serde_json::Value, (&'static str, String)>;
+
+/// Realtime API: instance events and commands over a single authenticated socket
+pub async fn ws_handler(
+    ws: WebSocketUpgrade,
+    State(state): State<Arc<AppState>>,
+    claims: Option<Extension<Claims>>,
+    Query(query): Query<WsQuery>,
+) -> Response {
+    let claims = match claims {
+        Some(Extension(c)) => Some(c),
+        None => match query.token.as_deref() {
+            Some(t) => verify_session_token(&state.redis, t, "access").await,
+            None => None,
+        },
+    };
+    let claims = match claims {
+        Some(c) => c,
+        None => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(serde_json::json!({
+                    "success": false,
+                    "message": "A valid token is required"
+                })),
+            )
+                .into_response();
+        }
+    };
+
+    ws.on_upgrade(move |socket| handle_socket(socket, state, claims))
+}
+
+/// Subscriptions held by a single socket
+#[derive(Default)]
+struct Subscriptions {
+    all: bool,
+    instances: HashSet<String>,
+}
+
+impl Subscriptions {
+    fn wants(&self, event: &InstanceEvent) -> bool {
+        self.all || self.instances.contains(&event.instance)
+    }
+}
+
+async fn handle_socket(socket: WebSocket, state: Arc<AppState>, claims: Claims) {
+    let (mut sink, mut stream) = socket.split();
+
+    // Commands run in their own tasks, so every write goes through one channel
+    let (out_tx, mut out_rx) = mpsc::unbounded_channel::<Message>();
+    let writer = tokio::spawn(async move {
+        while let Some(message) = out_rx.recv().await {
+            let closing = matches!(message, Message::Close(_));
+            if sink.send(message).await.is_err() || closing {
+                break;
+            }
+        }
+    });
+
+    let mut events = state.sm.events.subscribe();
+    let mut subscriptions = Subscriptions::default();
+
+    let ttl = (claims.exp - chrono::Utc::now().timestamp()).max(0) as u64;
+    let expiry = tokio::time::sleep(std::time::Duration::from_secs(ttl));
+    tokio::pin!(expiry);
+
+    let period = std::time::Duration::from_secs(REVOCATION_CHECK_SECS);
+    let mut revocation_check =
+        tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+
+    loop {
+        tokio::select! {
+            frame = stream.next() => match frame {
+                Some(Ok(Message::Text(text))) => {
+                    handle_frame(&text, &state, &claims, &mut subscriptions, &out_tx).await;
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+            event = events.recv() => match event {
+                Ok(event) if subscriptions.wants(&event) => {
+                    let mut frame = serde_json::to_value(&event).unwrap_or_default();
+                    frame["type"] = serde_json::json!("event");
+                    let _ = out_tx.send(Message::Text(frame.to_string()));
+                }
+                Ok(_) => {}
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    let frame = serde_json::json!({ "type": "lagged", "missed": missed });
+                    let _ = out_tx.send(Message::Text(frame.to_string()));
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            _ = &mut expiry => {
+                let _ = out_tx.send(Message::Close(Some(CloseFrame {
+                    code: CLOSE_TOKEN_EXPIRED,
+                    reason: "Token expired".into(),
+                })));
+                break;
+            }
+            _ = revocation_check.tick() => {
+                if is_revoked(&state.redis, &claims).await {
+                    let _ = out_tx.send(Message::Close(Some(CloseFrame {
+                        code: CLOSE_TOKEN_REVOKED,
+                        reason: "Token revoked".into(),
+                    })));
+                    break;
+                }
+            }
+        }
+    }
+
+    // Let queued frames (like the close frame) flush before tearing down
+    drop(out_tx);
+    let abort = writer.abort_handle();
+    if tokio::time::timeout(std::time::Duration::from_secs(1), writer)
+        .await
+        .is_err()
+    {
+        abort.abort();
+    }
+}
+
+fn respond(out_tx: &mpsc::UnboundedSender<Message>, id: Option<serde_json::Value>, reply: Reply) {
+    let frame = match reply {
+        Ok(data) => serde_json::json!({
+            "type": "response",
+            "id": id,
+            "success": true,
+            "data": data
+        }),
+        Err((error, message)) => serde_json::json!({
+            "type": "response",
+            "id": id,
+            "success": false,
+            "error": error,
+            "message": message
+        }),
+    };
+    let _ = out_tx.send(Message::Text(frame.to_string()));
+}
+
+async fn handle_frame(
+    text: &str,
+    state: &Arc<AppState>,
+    claims: &Claims,
+    subscriptions: &mut Subscriptions,
+    out_tx: &mpsc::UnboundedSender<Message>,
+) {
+    let frame: ClientFrame = match serde_json::from_str(text) {
+        Ok(f) => f,
+        Err(e) => {
+            // Still try to correlate the error with the request
+            let id = serde_json::from_str::<serde_json::Value>(text)
+                .ok()
+                .and_then(|v| v.get("id").cloned());
+            respond(out_tx, id, Err(("invalid_frame", e.to_string())));
+            return;
+        }
+    };
+
+    match frame.action {
+        Action::Subscribe { instances } => {
+            let mut granted = Vec::new();
+            let mut denied = Vec::new();
+            for instance in instances {
+                if instance == "*" {
+                    if claims.role == "admin" {
+                        subscriptions.all = true;
+                        granted.push(instance);
+                    } else {
+                        denied.push(instance);
+                    }
+                } else if can_access_instance(state, Some(claims), &instance).await {
+                    subscriptions.instances.insert(instance.clone());
+                    granted.push(instance);
+                } else {
+                    denied.push(instance);
+                }
+            }
+            respond(
+                out_tx,
+                frame.id,
+                Ok(serde_json::json!({ "subscribed": granted, "denied": denied })),
+            );
+        }
+        Action::Unsubscribe { instances } => {
+            for instance in &instances {
+                if instance == "*" {
+                    subscriptions.all = false;
+                }
+                subscriptions.instances.remove(instance);
+            }
+            respond(
+                out_tx,
+                frame.id,
+                Ok(serde_json::json!({ "unsubscribed": instances })),
+            );
+        }
+        Action::Ping => respond(out_tx, frame.id, Ok(serde_json::json!("pong"))),
+        Action::Send { instance, message } => {
+            let (state, claims, out_tx) = (state.clone(), claims.clone(), out_tx.clone());
+            // Sends are paced by the governor, don't hold up the event stream
+            tokio::spawn(async move {
+                let reply = send(&state, &claims, &instance, *message).await;
+                respond(&out_tx, frame.id, reply);
+            });
+        }
+        Action::Pause { instance } => {
+            let reply = set_paused(state, claims, &instance, true).await;
+            respond(out_tx, frame.id, reply);
+        }
+        Action::Resume { instance } => {
+            let reply = set_paused(state, claims, &instance, false).await;
+            respond(out_tx, frame.id, reply);
+        }
+    }
+}
+
+fn forbidden() -> (&'static str, String) {
+    (
+        "forbidden",
+        "You don't have access to this instance".to_string(),
+    )
+}
+
+async fn send(
+    state: &Arc<AppState>,
+    claims: &Claims,
+    instance: &str,
+    message: SendMessageRequest,
+) -> Reply {
+    if !can_access_instance(state, Some(claims), instance).await {
+        return Err(forbidden());
+    }
+    let message = message
+        .into_message(&state.db, instance)
+        .await
+        .map_err(|e| ("invalid_message", e))?;
+
+    match outbound::send(state, instance, SendSource::WebSocket, message).await {
+        Ok(result) => Ok(serde_json::from_str(&result.data).unwrap_or_default()),
+        Err(e) => Err((e.code(), e.to_string())),
+    }
+}
+
+async fn set_paused(state: &Arc<AppState>, claims: &Claims, instance: &str, pause: bool) -> Reply {
+    if !can_access_instance(state, Some(claims), instance).await {
+        return Err(forbidden());
+    }
+    if !state.sm.workers.read().await.contains_key(instance) {
+        return Err(("not_found", "Instance not found".to_string()));
+    }
+
+    let status = if pause {
+        state.sm.pause_instance(instance, true).await;
+        "paused"
+    } else {
+        state.sm.resume_instance(instance, state.clone()).await
+    };
+    Ok(serde_json::json!({ "status": status, "phone": instance }))
+}
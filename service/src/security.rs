@@ -1,7 +1,12 @@
+use crate::AppState;
+use crate::logger;
+use crate::manager::api_keys;
+use crate::rbac::Role;
 use axum::{
     Json,
     body::Body,
-    http::{Request, StatusCode, header},
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, Request, StatusCode, header},
     middleware::Next,
     response::{IntoResponse, Response},
 };
@@ -9,6 +14,8 @@ use hmac::{Hmac, Mac};
 use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation, decode, encode};
 use serde::{Deserialize, Serialize};
 use sha2::Sha256;
+use std::net::SocketAddr;
+use std::sync::Arc;
 
 // ========== JWT Token Types ==========
 
@@ -19,7 +26,9 @@ pub struct Claims {
     pub iat: i64,     // Issued at
     pub exp: i64,     // Expiration
     pub jti: String,  // JWT ID (unique token identifier)
-    pub sid: String,  // Session ID
+    pub sid: String,  // Session ID, shared by every token of one sign-in
+    #[serde(default)]
+    pub typ: String, // "access" or "refresh"
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -67,6 +76,7 @@ pub mod response_codes {
     pub const SESSION_CREATED: u32 = 0xE702;
     pub const OPERATION_OK: u32 = 0xF811;
     pub const PASSKEY_OK: u32 = 0x0920;
+    pub const TWO_FACTOR_REQUIRED: u32 = 0x1A31;
 }
 
 // ========== Security Configuration ==========
@@ -87,7 +97,7 @@ pub fn get_jwt_secret() -> String {
     }
 }
 
-/// Get the API secret key for request signing
+/// Get the secret responses are signed with
 /// WARNING: In production, API_SECRET_KEY MUST be set
 pub fn get_api_secret() -> String {
     match std::env::var("API_SECRET_KEY") {
@@ -111,23 +121,28 @@ pub fn get_allowed_origins() -> Vec<String> {
 
 // ========== JWT Token Functions ==========
 
-/// Generate a new access token
-pub fn generate_access_token(
+/// Access tokens live for an hour, refresh tokens for a week
+const ACCESS_TOKEN_TTL: i64 = 3600;
+const REFRESH_TOKEN_TTL: i64 = 7 * 24 * 3600;
+
+fn generate_token(
     user_id: &str,
     role: &str,
+    session_id: &str,
+    typ: &str,
+    ttl: i64,
 ) -> Result<String, jsonwebtoken::errors::Error> {
     let now = chrono::Utc::now();
-    let exp = now + chrono::Duration::hours(1); // 1 hour expiry
-    let jti = uuid::Uuid::new_v4().to_string();
-    let sid = uuid::Uuid::new_v4().to_string();
+    let exp = now + chrono::Duration::seconds(ttl);
 
     let claims = Claims {
         sub: user_id.to_string(),
         role: role.to_string(),
         iat: now.timestamp(),
         exp: exp.timestamp(),
-        jti,
-        sid,
+        jti: uuid::Uuid::new_v4().to_string(),
+        sid: session_id.to_string(),
+        typ: typ.to_string(),
     };
 
     let secret = get_jwt_secret();
@@ -138,51 +153,33 @@ pub fn generate_access_token(
     )
 }
 
-/// Generate a refresh token (longer expiry)
-pub fn generate_refresh_token(
+/// Generate both access and refresh tokens for a new session
+pub fn generate_token_pair(
     user_id: &str,
     role: &str,
-) -> Result<String, jsonwebtoken::errors::Error> {
-    let now = chrono::Utc::now();
-    let exp = now + chrono::Duration::days(7); // 7 days expiry
-    let jti = uuid::Uuid::new_v4().to_string();
-    let sid = uuid::Uuid::new_v4().to_string();
-
-    let claims = Claims {
-        sub: user_id.to_string(),
-        role: role.to_string(),
-        iat: now.timestamp(),
-        exp: exp.timestamp(),
-        jti,
-        sid,
-    };
-
-    let secret = get_jwt_secret();
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_bytes()),
-    )
+) -> Result<TokenPair, jsonwebtoken::errors::Error> {
+    generate_session_tokens(user_id, role, &uuid::Uuid::new_v4().to_string())
 }
 
-/// Generate both access and refresh tokens
-pub fn generate_token_pair(
+/// Generate access and refresh tokens within an existing session
+pub fn generate_session_tokens(
     user_id: &str,
     role: &str,
+    session_id: &str,
 ) -> Result<TokenPair, jsonwebtoken::errors::Error> {
-    let access_token = generate_access_token(user_id, role)?;
-    let refresh_token = generate_refresh_token(user_id, role)?;
+    let access_token = generate_token(user_id, role, session_id, "access", ACCESS_TOKEN_TTL)?;
+    let refresh_token = generate_token(user_id, role, session_id, "refresh", REFRESH_TOKEN_TTL)?;
 
     Ok(TokenPair {
         access_token,
         refresh_token,
         token_type: "Bearer".to_string(),
-        expires_in: 3600, // 1 hour in seconds
+        expires_in: ACCESS_TOKEN_TTL,
     })
 }
 
 /// Verify and decode a JWT token
-pub fn verify_token(token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
+fn verify_token(token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
     let secret = get_jwt_secret();
     let token_data = decode::<Claims>(
         token,
@@ -192,11 +189,211 @@ pub fn verify_token(token: &str) -> Result<Claims, jsonwebtoken::errors::Error>
     Ok(token_data.claims)
 }
 
+// ========== Token Revocation ==========
+
+/// Revoke a single token until it would have expired anyway
+pub async fn revoke_token(redis: &redis::Client, claims: &Claims) {
+    let ttl = claims.exp - chrono::Utc::now().timestamp();
+    if ttl <= 0 {
+        return;
+    }
+    if let Ok(mut conn) = redis.get_multiplexed_async_connection().await {
+        let _: () = redis::cmd("SETEX")
+            .arg(format!("revoked_token:{}", claims.jti))
+            .arg(ttl)
+            .arg(1)
+            .query_async(&mut conn)
+            .await
+            .unwrap_or(());
+    }
+}
+
+/// Revoke every token issued in a session, refreshed ones included
+pub async fn revoke_session(redis: &redis::Client, session_id: &str) {
+    if let Ok(mut conn) = redis.get_multiplexed_async_connection().await {
+        let _: () = redis::cmd("SETEX")
+            .arg(format!("revoked_session:{}", session_id))
+            .arg(REFRESH_TOKEN_TTL)
+            .arg(1)
+            .query_async(&mut conn)
+            .await
+            .unwrap_or(());
+    }
+}
+
+/// Whether a token or its session was revoked. Fails closed: when Redis
+/// can't answer, the token is treated as revoked.
+pub async fn is_revoked(redis: &redis::Client, claims: &Claims) -> bool {
+    let mut conn = match redis.get_multiplexed_async_connection().await {
+        Ok(conn) => conn,
+        Err(e) => {
+            logger::error("AUTH", &format!("Revocation check unavailable: {}", e));
+            return true;
+        }
+    };
+    match redis::cmd("EXISTS")
+        .arg(format!("revoked_token:{}", claims.jti))
+        .arg(format!("revoked_session:{}", claims.sid))
+        .query_async::<i64>(&mut conn)
+        .await
+    {
+        Ok(n) => n > 0,
+        Err(e) => {
+            logger::error("AUTH", &format!("Revocation check failed: {}", e));
+            true
+        }
+    }
+}
+
+/// Mark a refresh token as used. Returns false when it already was, which
+/// means it leaked: both the thief and the owner hold a copy.
+pub async fn consume_refresh_token(redis: &redis::Client, claims: &Claims) -> bool {
+    let Ok(mut conn) = redis.get_multiplexed_async_connection().await else {
+        return false;
+    };
+    let ttl = (claims.exp - chrono::Utc::now().timestamp()).max(1);
+    redis::cmd("SET")
+        .arg(format!("used_refresh_token:{}", claims.jti))
+        .arg(1)
+        .arg("NX")
+        .arg("EX")
+        .arg(ttl)
+        .query_async::<Option<String>>(&mut conn)
+        .await
+        .is_ok_and(|r| r.is_some())
+}
+
+/// Claims of a valid, unrevoked token of the given type
+pub async fn verify_session_token(redis: &redis::Client, token: &str, typ: &str) -> Option<Claims> {
+    let claims = verify_token(token).ok()?;
+    // Tokens from before token types were introduced are access tokens
+    let claims_typ = if claims.typ.is_empty() {
+        "access"
+    } else {
+        claims.typ.as_str()
+    };
+    if claims_typ != typ || is_revoked(redis, &claims).await {
+        return None;
+    }
+    Some(claims)
+}
+
+// ========== Admin Sessions ==========
+
+/// Admin sessions last 30 minutes
+pub const ADMIN_SESSION_TTL: i64 = 30 * 60;
+
+/// The admin account behind a request on an admin route
+#[derive(Debug, Clone)]
+pub struct AdminSession {
+    pub user_id: String,
+    pub role: Role,
+}
+
+/// Sessions are stored under a hash of their token, so the Redis data
+/// alone can't be replayed as a cookie
+fn admin_session_key(token: &str) -> String {
+    use sha2::Digest;
+    format!(
+        "admin_session:{}",
+        hex::encode(Sha256::digest(token.as_bytes()))
+    )
+}
+
+/// Start an admin session and return its cookie token
+pub async fn create_admin_session(redis: &redis::Client, user_id: &str) -> Option<String> {
+    let token = hex::encode(rand::random::<[u8; 32]>());
+    let key = admin_session_key(&token);
+    let index = format!("admin_sessions:{}", user_id);
+    let mut conn = redis.get_multiplexed_async_connection().await.ok()?;
+    redis::pipe()
+        .atomic()
+        .cmd("SETEX")
+        .arg(&key)
+        .arg(ADMIN_SESSION_TTL)
+        .arg(user_id)
+        .cmd("SADD")
+        .arg(&index)
+        .arg(&key)
+        .cmd("EXPIRE")
+        .arg(&index)
+        .arg(ADMIN_SESSION_TTL)
+        .query_async::<()>(&mut conn)
+        .await
+        .ok()?;
+    Some(token)
+}
+
+/// Resolve an admin session cookie. The account must still be staff in good
+/// standing, and its current role applies, so demoting or suspending it takes
+/// effect on the spot.
+pub async fn admin_session(state: &Arc<AppState>, token: &str) -> Option<AdminSession> {
+    let user_id: String = {
+        let mut conn = state.redis.get_multiplexed_async_connection().await.ok()?;
+        redis::cmd("GET")
+            .arg(admin_session_key(token))
+            .query_async::<Option<String>>(&mut conn)
+            .await
+            .ok()??
+    };
+    let role: Option<String> = sqlx::query_scalar(
+        "SELECT role FROM users WHERE id = ? AND isAdmin = TRUE AND suspended = FALSE",
+    )
+    .bind(&user_id)
+    .fetch_optional(&state.db)
+    .await
+    .ok()??;
+    Some(AdminSession {
+        user_id,
+        role: Role::of(role.as_deref()),
+    })
+}
+
+/// End one admin session
+pub async fn end_admin_session(redis: &redis::Client, token: &str) {
+    let key = admin_session_key(token);
+    if let Ok(mut conn) = redis.get_multiplexed_async_connection().await {
+        let user_id: Option<String> = redis::cmd("GET")
+            .arg(&key)
+            .query_async(&mut conn)
+            .await
+            .unwrap_or(None);
+        let mut pipe = redis::pipe();
+        pipe.del(&key);
+        if let Some(user_id) = user_id {
+            pipe.srem(format!("admin_sessions:{}", user_id), &key);
+        }
+        let _: () = pipe.query_async(&mut conn).await.unwrap_or(());
+    }
+}
+
+/// End every admin session of an account
+pub async fn revoke_admin_sessions(redis: &redis::Client, user_id: &str) {
+    let index = format!("admin_sessions:{}", user_id);
+    if let Ok(mut conn) = redis.get_multiplexed_async_connection().await {
+        let keys: Vec<String> = redis::cmd("SMEMBERS")
+            .arg(&index)
+            .query_async(&mut conn)
+            .await
+            .unwrap_or_default();
+        let mut pipe = redis::pipe();
+        for key in &keys {
+            pipe.del(key);
+        }
+        pipe.del(&index);
+        let _: () = pipe.query_async(&mut conn).await.unwrap_or(());
+    }
+}
+
 // ========== Response Signing ==========
 
 /// Sign response data with HMAC
 pub fn sign_response(data: &str) -> String {
-    let secret = get_api_secret();
+    sign_with_secret(&get_api_secret(), data)
+}
+
+/// Hex encoded HMAC-SHA256 of `data` under an arbitrary secret
+pub fn sign_with_secret(secret: &str, data: &str) -> String {
     type HmacSha256 = Hmac<Sha256>;
     let mut mac =
         HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC can take key of any size");
@@ -240,30 +437,30 @@ pub fn create_secure_response(
 
 /// Extract bearer token from Authorization header
 fn extract_bearer_token(auth_header: &str) -> Option<&str> {
-    if auth_header.starts_with("Bearer ") {
-        Some(&auth_header[7..])
-    } else {
-        None
-    }
+    auth_header.strip_prefix("Bearer ")
 }
 
 /// JWT Authentication middleware
-pub async fn jwt_auth_middleware(mut request: Request<Body>, next: Next) -> Response {
+pub async fn jwt_auth_middleware(
+    State(state): State<Arc<AppState>>,
+    mut request: Request<Body>,
+    next: Next,
+) -> Response {
     // Skip auth for public routes
     let path = request.uri().path();
 
-    // All auth routes should be public (no JWT required)
+    // All auth routes should be public (no JWT required), the ones acting on
+    // an account still get the caller's claims when a valid token is sent
     if path.starts_with("/api/auth/") {
+        if let Some(token) = request_token(request.headers())
+            && let Some(claims) = verify_session_token(&state.redis, &token, "access").await
+        {
+            request.extensions_mut().insert(claims);
+        }
         return next.run(request).await;
     }
 
-    let public_routes = [
-        "/util/whatsapp-news",
-        // SSE stream endpoints (real-time data feeds)
-        "/api/system/stream",
-        "/api/instances/stream",
-        "/api/logs/stream",
-    ];
+    let public_routes = ["/util/whatsapp-news"];
 
     if public_routes.iter().any(|r| path.starts_with(r)) {
         return next.run(request).await;
@@ -274,119 +471,96 @@ pub async fn jwt_auth_middleware(mut request: Request<Body>, next: Next) -> Resp
         return next.run(request).await;
     }
 
-    // Admin dashboard routes that can be accessed by admin session cookie
-    // These routes are for the hidden admin technical workspace
-    let admin_routes = [
-        "/api/instances",
-        "/api/settings",
-        "/api/tools",
-        "/api/logs",
-        "/api/system",
-        "/api/dashboard/user/cryptooooooohash", // Admin can get user crypto hashes
-    ];
-
-    // Check for admin session cookie first (for dashboard routes)
-    let cookie_header = request.headers().get(header::COOKIE);
-    let admin_session = cookie_header
-        .and_then(|h| h.to_str().ok())
-        .and_then(|cookies| {
-            cookies.split(';').find_map(|cookie| {
-                let parts: Vec<&str> = cookie.trim().splitn(2, '=').collect();
-                if parts.len() == 2 && parts[0] == "admin_session" {
-                    Some(parts[1].to_string())
-                } else {
-                    None
-                }
-            })
-        });
-
-    // If there's a valid admin session cookie, allow admin routes
-    if let Some(ref session) = admin_session
-        && is_valid_admin_session(session)
-    {
-        // Admin routes are allowed without JWT
-        if admin_routes.iter().any(|r| path.starts_with(r)) {
-            return next.run(request).await;
-        }
-    }
-
     // User portal routes - require JWT token from user authentication
-    let user_routes = ["/api/user/"];
-
-    let is_user_route = user_routes.iter().any(|r| path.starts_with(r));
+    let is_user_route = path.starts_with("/api/user/");
 
-    // Check for Authorization header
-    let auth_header = request
-        .headers()
-        .get(header::AUTHORIZATION)
-        .and_then(|h| h.to_str().ok());
+    // Staff signed in to the admin workspace. Which routes they reach is up
+    // to the permission each route declares (see rbac).
+    let session = match cookie_value(request.headers(), "admin_session") {
+        Some(token) => admin_session(&state, &token).await,
+        None => None,
+    };
 
-    let token = match auth_header {
-        Some(h) => extract_bearer_token(h).map(|t| t.to_string()),
-        None => {
-            // Check for token in cookie
-            cookie_header
-                .and_then(|h| h.to_str().ok())
-                .and_then(|cookies| {
-                    cookies.split(';').find_map(|cookie| {
-                        let parts: Vec<&str> = cookie.trim().splitn(2, '=').collect();
-                        if parts.len() == 2 && parts[0] == "whatsaly_token" {
-                            Some(parts[1].to_string())
-                        } else {
-                            None
-                        }
-                    })
-                })
-        }
+    let mut claims = match request_token(request.headers()) {
+        Some(token) => verify_session_token(&state.redis, &token, "access").await,
+        None => None,
     };
 
-    // If we have a token, verify it
-    if let Some(token) = token {
-        match verify_token(&token) {
-            Ok(claims) => {
-                // Add claims to request extensions for later use
-                request.extensions_mut().insert(claims);
-                return next.run(request).await;
-            }
-            Err(_e) => {
-                // Token invalid, continue to check other auth methods
-            }
+    // Integrations sign in with one of the user's API keys instead. A key
+    // that is presented has to be valid for this request.
+    if claims.is_none()
+        && let Some(key) = api_keys::presented(request.headers())
+    {
+        let peer = request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|c| c.0);
+        match api_keys::authenticate(
+            &state,
+            &key,
+            request.headers(),
+            peer,
+            request.method(),
+            request.uri().path(),
+        )
+        .await
+        {
+            Ok(key_claims) => claims = Some(key_claims),
+            Err(rejection) => return rejection,
         }
     }
 
-    // For user routes, require JWT
-    if is_user_route {
+    // For user routes, require JWT or an API key
+    if is_user_route && claims.is_none() {
         return create_error_response(response_codes::TOKEN_INVALID);
     }
 
-    // For admin routes, if we get here, no valid auth was found
-    if admin_routes.iter().any(|r| path.starts_with(r)) {
-        // If admin session cookie exists but is invalid, return error
-        if admin_session.is_some() {
-            return create_error_response(response_codes::TOKEN_EXPIRED);
+    match session {
+        // Outside the user portal an admin session acts as staff, not as the
+        // user the browser may also be signed in as
+        Some(session) if !is_user_route => {
+            request.extensions_mut().insert(session);
+        }
+        session => {
+            if let Some(session) = session {
+                request.extensions_mut().insert(session);
+            }
+            if let Some(claims) = claims {
+                // Add claims to request extensions for later use
+                request.extensions_mut().insert(claims);
+            }
         }
-        return create_error_response(response_codes::ACCESS_DENIED);
     }
 
-    // Allow other routes (for development/testing)
-    // In production, you might want to deny all unknown routes
     next.run(request).await
 }
 
-/// Check if admin session is valid (token:expiry format)
-fn is_valid_admin_session(session: &str) -> bool {
-    let parts: Vec<&str> = session.split(':').collect();
-    if parts.len() != 2 {
-        return false;
+/// Bearer token from the Authorization header, or the auth cookie
+fn request_token(headers: &HeaderMap) -> Option<String> {
+    match headers
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+    {
+        Some(h) => extract_bearer_token(h).map(|t| t.to_string()),
+        None => cookie_value(headers, "whatsaly_token"),
     }
+}
 
-    let expiry: i64 = match parts[1].parse() {
-        Ok(e) => e,
-        Err(_) => return false,
-    };
-
-    // Check if session has expired (30 minute sessions)
-    chrono::Utc::now().timestamp() <= expiry
+/// Value of a request cookie
+pub fn cookie_value(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get(header::COOKIE)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|cookies| {
+            cookies.split(';').find_map(|cookie| {
+                let parts: Vec<&str> = cookie.trim().splitn(2, '=').collect();
+                if parts.len() == 2 && parts[0] == name {
+                    Some(parts[1].to_string())
+                } else {
+                    None
+                }
+            })
+        })
 }
 
 /// Origin validation middleware
@@ -425,7 +599,7 @@ pub async fn origin_validation_middleware(request: Request<Body>, next: Next) ->
     match request_origin {
         Some(o) => {
             // Use exact matching to prevent bypass attacks (e.g., localhost.evil.com)
-            let origin_matches = allowed_origins.iter().any(|allowed| o == *allowed);
+            let origin_matches = allowed_origins.contains(&o);
 
             if origin_matches {
                 next.run(request).await
@@ -449,69 +623,6 @@ pub async fn origin_validation_middleware(request: Request<Body>, next: Next) ->
     }
 }
 
-/// API key validation middleware
-pub async fn api_key_middleware(request: Request<Body>, next: Next) -> Response {
-    let path = request.uri().path();
-
-    // Skip for non-API routes
-    if !path.starts_with("/api/") {
-        return next.run(request).await;
-    }
-
-    // All auth routes should be public (no API key required)
-    if path.starts_with("/api/auth/") {
-        return next.run(request).await;
-    }
-
-    // Public routes don't need API key
-    let public_routes = [
-        // SSE stream endpoints (real-time data feeds)
-        "/api/system/stream",
-        "/api/instances/stream",
-        "/api/logs/stream",
-    ];
-
-    if public_routes.iter().any(|r| path.starts_with(r)) {
-        return next.run(request).await;
-    }
-
-    // Check for X-API-Key header
-    let api_key = request
-        .headers()
-        .get("X-API-Key")
-        .and_then(|h| h.to_str().ok());
-
-    let expected_key = get_api_secret();
-
-    match api_key {
-        Some(key) if constant_time_compare(key, &expected_key) => next.run(request).await,
-        _ => {
-            // Allow if API key checking is not strictly enforced
-            if std::env::var("REQUIRE_API_KEY").unwrap_or_default() == "true" {
-                create_error_response(response_codes::ACCESS_DENIED)
-            } else {
-                next.run(request).await
-            }
-        }
-    }
-}
-
-/// Constant-time string comparison
-fn constant_time_compare(a: &str, b: &str) -> bool {
-    let a_bytes = a.as_bytes();
-    let b_bytes = b.as_bytes();
-
-    if a_bytes.len() != b_bytes.len() {
-        return false;
-    }
-
-    let mut result = 0u8;
-    for (x, y) in a_bytes.iter().zip(b_bytes.iter()) {
-        result |= x ^ y;
-    }
-    result == 0
-}
-
 /// Create an error response
 fn create_error_response(code: u32) -> Response {
     let response = create_secure_response(code, false, None);
@@ -535,8 +646,26 @@ pub fn create_auth_cookie(token: &str, is_production: bool) -> String {
     cookie
 }
 
+/// Create a secure cookie for the refresh token, only sent to the auth routes
+pub fn create_refresh_cookie(token: &str, is_production: bool) -> String {
+    let mut cookie = format!(
+        "whatsaly_refresh={}; Path=/api/auth; Max-Age={}; HttpOnly; SameSite=Strict",
+        token, REFRESH_TOKEN_TTL
+    );
+
+    if is_production {
+        cookie.push_str("; Secure");
+    }
+
+    cookie
+}
+
 /// Create a cookie to clear the auth token
-#[allow(dead_code)]
 pub fn create_logout_cookie() -> String {
     "whatsaly_token=; Path=/; Max-Age=0; HttpOnly; SameSite=Strict".to_string()
 }
+
+/// Create a cookie to clear the refresh token
+pub fn create_refresh_logout_cookie() -> String {
+    "whatsaly_refresh=; Path=/api/auth; Max-Age=0; HttpOnly; SameSite=Strict".to_string()
+}
@@ -46,11 +46,22 @@ pub struct User {
     #[sqlx(rename = "isAdmin")]
     #[serde(rename = "isAdmin")]
     pub is_admin: bool,
+    /// Staff role of admin accounts: owner, admin, support or billing
+    pub role: Option<String>,
+    /// Base32 TOTP secret, set once two-factor enrollment is confirmed
+    #[sqlx(rename = "totpSecret")]
+    #[serde(skip_serializing)]
+    pub totp_secret: Option<String>,
+    #[sqlx(rename = "totpEnabled")]
+    #[serde(rename = "totpEnabled")]
+    pub totp_enabled: bool,
     pub credits: f64,
     pub suspended: bool,
     #[sqlx(rename = "instanceLimit")]
     #[serde(rename = "instanceLimit")]
     pub instance_limit: i32,
+    /// Billing plan, drives the outbound governor defaults
+    pub plan: String,
     #[sqlx(rename = "createdAt")]
     #[serde(rename = "createdAt")]
     pub created_at: DateTime<Utc>,
@@ -134,6 +145,579 @@ pub struct SupportRequest {
     pub updated_at: DateTime<Utc>,
 }
 
+/// Sign-in attempt - maps to login_history table
+#[derive(Debug, FromRow, Serialize, Clone)]
+pub struct LoginRecord {
+    pub id: i64,
+    #[sqlx(rename = "userId")]
+    #[serde(rename = "userId")]
+    pub user_id: String,
+    pub method: String,
+    pub outcome: String,
+    pub ip: String,
+    #[sqlx(rename = "userAgent")]
+    #[serde(rename = "userAgent")]
+    pub user_agent: Option<String>,
+    #[sqlx(rename = "createdAt")]
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+}
+
+/// Admin override of an instance's outbound limits - maps to outbound_limits table
+#[derive(Debug, FromRow, Serialize, Clone)]
+pub struct OutboundLimitOverride {
+    #[sqlx(rename = "sessionId")]
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    #[sqlx(rename = "perMinute")]
+    #[serde(rename = "perMinute")]
+    pub per_minute: Option<i64>,
+    #[sqlx(rename = "perDay")]
+    #[serde(rename = "perDay")]
+    pub per_day: Option<i64>,
+    #[sqlx(rename = "warmupDays")]
+    #[serde(rename = "warmupDays")]
+    pub warmup_days: Option<i64>,
+    #[sqlx(rename = "minDelayMs")]
+    #[serde(rename = "minDelayMs")]
+    pub min_delay_ms: Option<i64>,
+    #[sqlx(rename = "maxDelayMs")]
+    #[serde(rename = "maxDelayMs")]
+    pub max_delay_ms: Option<i64>,
+    #[sqlx(rename = "updatedAt")]
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Normalized message - maps to messages table (raw messageContent excluded)
+#[derive(Debug, FromRow, Serialize, Clone)]
+pub struct StoredMessage {
+    #[serde(skip_serializing)]
+    pub rowid: i64,
+    #[sqlx(rename = "messageId")]
+    #[serde(rename = "messageId")]
+    pub message_id: String,
+    pub chat: String,
+    pub sender: Option<String>,
+    pub device: Option<String>,
+    pub mtype: Option<String>,
+    pub text: Option<String>,
+    #[sqlx(rename = "isGroup")]
+    #[serde(rename = "isGroup")]
+    pub is_group: bool,
+    /// JSON encoded quoted message
+    #[serde(serialize_with = "serialize_json_text")]
+    pub quoted: Option<String>,
+    #[sqlx(rename = "fromMe")]
+    #[serde(rename = "fromMe")]
+    pub from_me: bool,
+    #[sqlx(rename = "sentAt")]
+    #[serde(rename = "sentAt")]
+    pub sent_at: DateTime<Utc>,
+}
+
+/// Chat summary built from the messages table
+#[derive(Debug, FromRow, Serialize, Clone)]
+pub struct ChatSummary {
+    pub chat: String,
+    #[sqlx(rename = "isGroup")]
+    #[serde(rename = "isGroup")]
+    pub is_group: bool,
+    #[sqlx(rename = "messageCount")]
+    #[serde(rename = "messageCount")]
+    pub message_count: i64,
+    #[sqlx(rename = "lastText")]
+    #[serde(rename = "lastText")]
+    pub last_text: Option<String>,
+    #[sqlx(rename = "lastAt")]
+    #[serde(rename = "lastAt")]
+    pub last_at: DateTime<Utc>,
+}
+
+/// Outgoing webhook subscription - maps to webhooks table
+#[derive(Debug, FromRow, Serialize, Clone)]
+pub struct Webhook {
+    pub id: i64,
+    #[sqlx(rename = "userId")]
+    #[serde(rename = "userId")]
+    pub user_id: String,
+    /// Instance the subscription is scoped to, `None` for all of the user's instances
+    #[sqlx(rename = "sessionId")]
+    #[serde(rename = "instance")]
+    pub session_id: Option<String>,
+    pub url: String,
+    #[serde(skip_serializing)]
+    pub secret: String,
+    /// JSON encoded list of event filters
+    #[serde(serialize_with = "serialize_json_text_required")]
+    pub events: String,
+    pub active: bool,
+    #[sqlx(rename = "createdAt")]
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[sqlx(rename = "updatedAt")]
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Per-user API key - maps to api_keys table. Only a hash of the key is
+/// stored, and it isn't loaded here.
+#[derive(Debug, FromRow, Serialize, Clone)]
+pub struct ApiKey {
+    pub id: String,
+    #[sqlx(rename = "userId")]
+    #[serde(skip_serializing)]
+    pub user_id: String,
+    pub name: String,
+    /// First characters of the key, to tell keys apart
+    #[sqlx(rename = "keyPrefix")]
+    #[serde(rename = "prefix")]
+    pub key_prefix: String,
+    /// JSON encoded list of scopes
+    #[serde(serialize_with = "serialize_json_text_required")]
+    pub scopes: String,
+    /// JSON encoded list of instances the key is limited to, `None` for all
+    #[serde(serialize_with = "serialize_json_text")]
+    pub instances: Option<String>,
+    /// JSON encoded list of addresses or CIDR ranges, `None` for any
+    #[sqlx(rename = "allowedIps")]
+    #[serde(rename = "allowedIps", serialize_with = "serialize_json_text")]
+    pub allowed_ips: Option<String>,
+    #[sqlx(rename = "expiresAt")]
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[sqlx(rename = "lastUsedAt")]
+    #[serde(rename = "lastUsedAt")]
+    pub last_used_at: Option<DateTime<Utc>>,
+    #[sqlx(rename = "lastUsedIp")]
+    #[serde(rename = "lastUsedIp")]
+    pub last_used_ip: Option<String>,
+    #[sqlx(rename = "createdAt")]
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+}
+
+/// Queued webhook event - maps to webhook_deliveries table
+#[derive(Debug, FromRow, Serialize, Clone)]
+pub struct WebhookDelivery {
+    pub id: String,
+    #[sqlx(rename = "webhookId")]
+    #[serde(rename = "webhookId")]
+    pub webhook_id: i64,
+    #[sqlx(rename = "sessionId")]
+    #[serde(rename = "instance")]
+    pub session_id: Option<String>,
+    pub event: String,
+    #[serde(serialize_with = "serialize_json_text_required")]
+    pub payload: String,
+    pub status: String,
+    pub attempts: i64,
+    #[sqlx(rename = "nextAttemptAt")]
+    #[serde(rename = "nextAttemptAt")]
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    #[sqlx(rename = "lastStatusCode")]
+    #[serde(rename = "lastStatusCode")]
+    pub last_status_code: Option<i64>,
+    #[sqlx(rename = "lastError")]
+    #[serde(rename = "lastError")]
+    pub last_error: Option<String>,
+    #[sqlx(rename = "deliveredAt")]
+    #[serde(rename = "deliveredAt")]
+    pub delivered_at: Option<DateTime<Utc>>,
+    #[sqlx(rename = "createdAt")]
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+}
+
+/// Single HTTP attempt of a webhook delivery - maps to webhook_attempts table
+#[derive(Debug, FromRow, Serialize, Clone)]
+pub struct WebhookAttempt {
+    pub attempt: i64,
+    #[sqlx(rename = "statusCode")]
+    #[serde(rename = "statusCode")]
+    pub status_code: Option<i64>,
+    pub error: Option<String>,
+    #[sqlx(rename = "durationMs")]
+    #[serde(rename = "durationMs")]
+    pub duration_ms: i64,
+    #[sqlx(rename = "createdAt")]
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+}
+
+/// Auto-reply rule - maps to auto_reply_rules table
+#[derive(Debug, FromRow, Serialize, Clone)]
+pub struct AutoReplyRule {
+    pub id: i64,
+    #[sqlx(rename = "sessionId")]
+    #[serde(rename = "instance")]
+    pub session_id: String,
+    pub name: String,
+    #[sqlx(rename = "matchType")]
+    #[serde(rename = "matchType")]
+    pub match_type: String,
+    pub pattern: Option<String>,
+    #[sqlx(rename = "caseSensitive")]
+    #[serde(rename = "caseSensitive")]
+    pub case_sensitive: bool,
+    pub scope: String,
+    /// JSON encoded list of chat JIDs, used by the `chats` scope
+    #[serde(serialize_with = "serialize_json_text_required")]
+    pub chats: String,
+    #[sqlx(rename = "cooldownSeconds")]
+    #[serde(rename = "cooldownSeconds")]
+    pub cooldown_seconds: i64,
+    #[sqlx(rename = "responseType")]
+    #[serde(rename = "responseType")]
+    pub response_type: String,
+    #[sqlx(rename = "responseText")]
+    #[serde(rename = "responseText")]
+    pub response_text: Option<String>,
+    #[sqlx(rename = "mediaUrl")]
+    #[serde(rename = "mediaUrl")]
+    pub media_url: Option<String>,
+    #[sqlx(rename = "mediaType")]
+    #[serde(rename = "mediaType")]
+    pub media_type: Option<String>,
+    #[sqlx(rename = "fileName")]
+    #[serde(rename = "fileName")]
+    pub file_name: Option<String>,
+    /// Stored message template sent by `template` responses
+    #[sqlx(rename = "templateId")]
+    #[serde(rename = "templateId")]
+    pub template_id: Option<i64>,
+    pub priority: i64,
+    pub enabled: bool,
+    pub hits: i64,
+    #[sqlx(rename = "lastHitAt")]
+    #[serde(rename = "lastHitAt")]
+    pub last_hit_at: Option<DateTime<Utc>>,
+    #[sqlx(rename = "createdAt")]
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[sqlx(rename = "updatedAt")]
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Business hours and away message settings - maps to business_hours table
+#[derive(Debug, FromRow, Serialize, Clone)]
+pub struct BusinessHours {
+    #[sqlx(rename = "sessionId")]
+    #[serde(rename = "instance")]
+    pub session_id: String,
+    pub enabled: bool,
+    /// IANA timezone the hours are expressed in
+    pub timezone: String,
+    /// JSON encoded weekday to opening windows map
+    #[serde(serialize_with = "serialize_json_text_required")]
+    pub hours: String,
+    /// JSON encoded list of closed dates
+    #[serde(serialize_with = "serialize_json_text_required")]
+    pub holidays: String,
+    /// Sent to first-time contacts
+    pub greeting: Option<String>,
+    /// Sent outside business hours
+    #[sqlx(rename = "awayMessage")]
+    #[serde(rename = "awayMessage")]
+    pub away_message: Option<String>,
+    #[sqlx(rename = "awayCooldownHours")]
+    #[serde(rename = "awayCooldownHours")]
+    pub away_cooldown_hours: i64,
+    #[sqlx(rename = "updatedAt")]
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Conversational flow - maps to flows table
+#[derive(Debug, FromRow, Serialize, Clone)]
+pub struct Flow {
+    pub id: i64,
+    #[sqlx(rename = "sessionId")]
+    #[serde(rename = "instance")]
+    pub session_id: String,
+    pub name: String,
+    pub enabled: bool,
+    #[sqlx(rename = "activeVersion")]
+    #[serde(rename = "activeVersion")]
+    pub active_version: i64,
+    #[sqlx(rename = "createdAt")]
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[sqlx(rename = "updatedAt")]
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Uploaded flow definition - maps to flow_versions table
+#[derive(Debug, FromRow, Serialize, Clone)]
+pub struct FlowVersion {
+    #[sqlx(rename = "flowId")]
+    #[serde(rename = "flowId")]
+    pub flow_id: i64,
+    pub version: i64,
+    #[serde(serialize_with = "serialize_json_text_required")]
+    pub definition: String,
+    #[sqlx(rename = "createdAt")]
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+}
+
+/// Reusable message content - maps to message_templates table
+#[derive(Debug, FromRow, Serialize, Clone)]
+pub struct MessageTemplate {
+    pub id: i64,
+    #[sqlx(rename = "userId")]
+    #[serde(rename = "userId")]
+    pub user_id: String,
+    pub name: String,
+    pub description: Option<String>,
+    #[sqlx(rename = "defaultLanguage")]
+    #[serde(rename = "defaultLanguage")]
+    pub default_language: String,
+    /// JSON encoded list of placeholder declarations
+    #[serde(serialize_with = "serialize_json_text_required")]
+    pub variables: String,
+    /// JSON encoded language to body and media map
+    #[serde(serialize_with = "serialize_json_text_required")]
+    pub variants: String,
+    #[sqlx(rename = "createdAt")]
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[sqlx(rename = "updatedAt")]
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Contact with its tags - maps to contacts joined with contact_tags
+#[derive(Debug, FromRow, Serialize, Clone)]
+pub struct Contact {
+    #[sqlx(rename = "contactPn")]
+    #[serde(rename = "jid")]
+    pub contact_pn: String,
+    #[sqlx(rename = "contactLid")]
+    #[serde(rename = "lid")]
+    pub contact_lid: Option<String>,
+    pub name: Option<String>,
+    /// `name`, falling back to the phone number
+    #[sqlx(rename = "displayName")]
+    #[serde(rename = "displayName")]
+    pub display_name: String,
+    /// JSON encoded list of tags
+    #[serde(serialize_with = "serialize_json_text_required")]
+    pub tags: String,
+    /// JSON encoded map of user-defined fields
+    #[sqlx(rename = "customFields")]
+    #[serde(
+        rename = "customFields",
+        serialize_with = "serialize_json_text_required"
+    )]
+    pub custom_fields: String,
+    #[sqlx(rename = "addedAt")]
+    #[serde(rename = "addedAt")]
+    pub added_at: DateTime<Utc>,
+}
+
+/// Bulk send to a tag audience - maps to broadcasts table
+#[derive(Debug, FromRow, Serialize, Clone)]
+pub struct Broadcast {
+    pub id: i64,
+    #[sqlx(rename = "sessionId")]
+    #[serde(rename = "instance")]
+    pub session_id: String,
+    pub name: Option<String>,
+    /// JSON encoded list of audience tags
+    #[serde(serialize_with = "serialize_json_text_required")]
+    pub tags: String,
+    /// Recipients need every tag rather than any of them
+    #[sqlx(rename = "matchAll")]
+    #[serde(rename = "matchAll")]
+    pub match_all: bool,
+    /// JSON encoded send payload
+    #[serde(serialize_with = "serialize_json_text_required")]
+    pub message: String,
+    /// running, paused, completed or cancelled
+    pub status: String,
+    pub total: i64,
+    pub sent: i64,
+    pub failed: i64,
+    #[sqlx(rename = "lastError")]
+    #[serde(rename = "lastError")]
+    pub last_error: Option<String>,
+    #[sqlx(rename = "createdAt")]
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[sqlx(rename = "updatedAt")]
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
+    #[sqlx(rename = "finishedAt")]
+    #[serde(rename = "finishedAt")]
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// Bulk WhatsApp existence lookup - maps to number_checks table
+#[derive(Debug, FromRow, Serialize, Clone)]
+pub struct NumberCheck {
+    pub id: i64,
+    #[sqlx(rename = "sessionId")]
+    #[serde(rename = "instance")]
+    pub session_id: String,
+    /// running, completed or failed
+    pub status: String,
+    pub total: i64,
+    pub checked: i64,
+    /// Numbers with a WhatsApp account so far
+    pub found: i64,
+    #[sqlx(rename = "lastError")]
+    #[serde(rename = "lastError")]
+    pub last_error: Option<String>,
+    #[sqlx(rename = "createdAt")]
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[sqlx(rename = "finishedAt")]
+    #[serde(rename = "finishedAt")]
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// One number of a check - maps to number_check_results table
+#[derive(Debug, FromRow, Serialize, Clone)]
+pub struct NumberCheckResult {
+    /// E.164 with the leading `+`
+    pub number: String,
+    /// None until the number has been checked
+    #[sqlx(rename = "onWhatsApp")]
+    #[serde(rename = "onWhatsApp")]
+    pub on_whatsapp: Option<bool>,
+    pub jid: Option<String>,
+    pub lid: Option<String>,
+    #[sqlx(rename = "checkedAt")]
+    #[serde(rename = "checkedAt")]
+    pub checked_at: Option<DateTime<Utc>>,
+}
+
+/// Opt-out keyword settings - maps to opt_out_settings table
+#[derive(Debug, FromRow, Serialize, Clone)]
+pub struct OptOutSettings {
+    #[sqlx(rename = "sessionId")]
+    #[serde(rename = "instance")]
+    pub session_id: String,
+    pub enabled: bool,
+    /// JSON encoded list of opt-out keywords
+    #[serde(serialize_with = "serialize_json_text_required")]
+    pub keywords: String,
+    /// JSON encoded list of keywords that lift a contact's opt-out
+    #[sqlx(rename = "optInKeywords")]
+    #[serde(
+        rename = "optInKeywords",
+        serialize_with = "serialize_json_text_required"
+    )]
+    pub opt_in_keywords: String,
+    #[sqlx(rename = "updatedAt")]
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Suppressed recipient - maps to opt_outs table
+#[derive(Debug, FromRow, Serialize, Clone)]
+pub struct OptOut {
+    pub jid: String,
+    /// keyword, manual or import
+    pub source: String,
+    pub reason: Option<String>,
+    #[sqlx(rename = "createdAt")]
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+}
+
+/// Opt-out audit record - maps to opt_out_events table
+#[derive(Debug, FromRow, Serialize, Clone)]
+pub struct OptOutEvent {
+    pub id: i64,
+    pub jid: String,
+    /// opt_out or opt_in
+    pub action: String,
+    /// keyword, manual or import
+    pub source: String,
+    /// Matched keyword or the reason given
+    pub detail: Option<String>,
+    /// Who made a manual change, `contact` for keywords
+    pub actor: Option<String>,
+    /// Incoming message that carried the keyword
+    #[sqlx(rename = "messageId")]
+    #[serde(rename = "messageId")]
+    pub message_id: Option<String>,
+    #[sqlx(rename = "createdAt")]
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+}
+
+/// Status (story) update - maps to statuses table
+#[derive(Debug, FromRow, Serialize, Clone)]
+pub struct StatusUpdate {
+    pub id: i64,
+    #[sqlx(rename = "sessionId")]
+    #[serde(rename = "instance")]
+    pub session_id: String,
+    /// text, image or video
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub text: Option<String>,
+    #[sqlx(rename = "mediaUrl")]
+    #[serde(rename = "mediaUrl")]
+    pub media_url: Option<String>,
+    #[sqlx(rename = "backgroundColor")]
+    #[serde(rename = "backgroundColor")]
+    pub background_color: Option<String>,
+    pub font: Option<i64>,
+    /// all, list or exclude
+    pub audience: String,
+    /// JSON encoded list the audience mode applies to
+    #[serde(serialize_with = "serialize_json_text_required")]
+    pub contacts: String,
+    /// Contacts the status was shown to
+    pub recipients: i64,
+    /// scheduled, posting, posted, failed or cancelled
+    pub status: String,
+    #[sqlx(rename = "scheduledAt")]
+    #[serde(rename = "scheduledAt")]
+    pub scheduled_at: DateTime<Utc>,
+    #[sqlx(rename = "postedAt")]
+    #[serde(rename = "postedAt")]
+    pub posted_at: Option<DateTime<Utc>>,
+    #[sqlx(rename = "messageId")]
+    #[serde(rename = "messageId")]
+    pub message_id: Option<String>,
+    #[sqlx(rename = "lastError")]
+    #[serde(rename = "lastError")]
+    pub last_error: Option<String>,
+    #[sqlx(rename = "createdAt")]
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    /// Only filled in by queries that count views
+    #[sqlx(default)]
+    pub views: i64,
+}
+
+/// Emit a column holding JSON text as the JSON value itself
+fn serialize_json_text<S: serde::Serializer>(
+    value: &Option<String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    value
+        .as_deref()
+        .and_then(|v| serde_json::from_str::<serde_json::Value>(v).ok())
+        .serialize(serializer)
+}
+
+fn serialize_json_text_required<S: serde::Serializer>(
+    value: &str,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serde_json::from_str::<serde_json::Value>(value)
+        .unwrap_or(serde_json::Value::Null)
+        .serialize(serializer)
+}
+
 #[derive(Debug, Deserialize)]
 pub struct RegisterRequest {
     #[serde(rename = "phoneNumber")]
@@ -146,8 +730,22 @@ pub struct LoginRequest {
     #[serde(rename = "phoneNumber")]
     pub phone_number: String,
     pub password: String,
-    #[serde(rename = "cryptoHash")]
-    pub crypto_hash: String,
+}
+
+/// Second login step for accounts with 2FA on
+#[derive(Debug, Deserialize)]
+pub struct TwoFactorLoginRequest {
+    /// Returned by the password step
+    #[serde(rename = "challengeId")]
+    pub challenge_id: String,
+    /// Authenticator or recovery code
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RefreshRequest {
+    #[serde(rename = "refreshToken")]
+    pub refresh_token: String,
 }
 
 /// Legacy auth response (deprecated - use SecureAuthResponse instead)
@@ -201,7 +799,7 @@ pub struct PasskeyCredential {
     #[sqlx(rename = "publicKey")]
     #[serde(rename = "publicKey")]
     pub public_key: String,
-    pub counter: i32,
+    pub counter: i64,
     #[sqlx(rename = "deviceName")]
     #[serde(rename = "deviceName")]
     pub device_name: Option<String>,
@@ -217,10 +815,11 @@ pub struct PasskeyCredential {
 pub struct PasskeyRegisterRequest {
     #[serde(rename = "userId")]
     pub user_id: String,
-    #[serde(rename = "credentialId")]
-    pub credential_id: String,
-    #[serde(rename = "publicKey")]
-    pub public_key: String,
+    /// CBOR attestation object, the credential and its key are read from it
+    #[serde(rename = "attestationObject")]
+    pub attestation_object: String,
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
     #[serde(rename = "deviceName")]
     pub device_name: Option<String>,
 }
@@ -228,18 +827,18 @@ pub struct PasskeyRegisterRequest {
 /// Passkey login request - contains WebAuthn assertion data
 #[derive(Debug, Deserialize)]
 pub struct PasskeyLoginRequest {
+    /// Returned by the login challenge endpoint
+    #[serde(rename = "challengeId")]
+    pub challenge_id: String,
     #[serde(rename = "credentialId")]
     pub credential_id: String,
     /// WebAuthn authenticator data (verified server-side)
     #[serde(rename = "authenticatorData")]
-    #[allow(dead_code)]
     pub authenticator_data: String,
     /// Client data JSON (verified server-side for challenge)
     #[serde(rename = "clientDataJSON")]
-    #[allow(dead_code)]
     pub client_data_json: String,
     /// Digital signature from authenticator (verified server-side)
-    #[allow(dead_code)]
     pub signature: String,
 }
 
@@ -248,6 +847,30 @@ use std::fs;
 use std::path::Path;
 use std::str::FromStr;
 
+/// Columns added after a table was first released. `CREATE TABLE IF NOT EXISTS`
+/// leaves existing databases untouched, so these are applied one by one. Errors
+/// (fresh database, column already present) are ignored since main.sql already
+/// declares every column.
+const COLUMN_UPGRADES: &[(&str, &str)] = &[
+    ("sessions", "pairedAt TIMESTAMP"),
+    ("users", "plan TEXT NOT NULL DEFAULT 'free'"),
+    ("users", "role TEXT"),
+    ("users", "totpSecret TEXT"),
+    ("users", "totpEnabled BOOLEAN NOT NULL DEFAULT FALSE"),
+    ("messages", "chat TEXT"),
+    ("messages", "sender TEXT"),
+    ("messages", "device TEXT"),
+    ("messages", "mtype TEXT"),
+    ("messages", "text TEXT"),
+    ("messages", "isGroup BOOLEAN NOT NULL DEFAULT FALSE"),
+    ("messages", "quoted TEXT"),
+    ("messages", "fromMe BOOLEAN NOT NULL DEFAULT FALSE"),
+    ("messages", "sentAt TIMESTAMP"),
+    ("auto_reply_rules", "templateId INTEGER"),
+    ("contacts", "name TEXT"),
+    ("contacts", "customFields TEXT NOT NULL DEFAULT '{}'"),
+];
+
 pub async fn sync_db() -> SqlitePool {
     let database_url = "sqlite://database.db";
 
@@ -264,12 +887,35 @@ pub async fn sync_db() -> SqlitePool {
         .await
         .expect("Failed to initialize SQLite database");
 
+    // Upgrade existing tables before main.sql so its indexes can reference new columns
+    for (table, column) in COLUMN_UPGRADES {
+        let _ = sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {}", table, column))
+            .execute(&pool)
+            .await;
+    }
+    let rekey_messages = messages_keyed_by_id(&pool).await;
+    if rekey_messages {
+        let _ = sqlx::query("ALTER TABLE messages RENAME TO messages_by_id")
+            .execute(&pool)
+            .await;
+    }
+
+    apply_schema(&pool).await;
+
+    if rekey_messages {
+        rekey_messages_table(&pool).await;
+    }
+
+    pool
+}
+
+async fn apply_schema(pool: &SqlitePool) {
     let schema_path = "service/store/main.sql";
 
     if Path::new(schema_path).exists() {
         match fs::read_to_string(schema_path) {
             Ok(schema) => {
-                if let Err(e) = sqlx::query(&schema).execute(&pool).await {
+                if let Err(e) = sqlx::query(&schema).execute(pool).await {
                     eprintln!("⚠️ Warning: Failed to execute schema from main.sql: {}", e);
                 }
             }
@@ -281,6 +927,40 @@ pub async fn sync_db() -> SqlitePool {
             schema_path
         );
     }
+}
 
-    pool
+/// Older databases key `messages` by `messageId` alone, which lets one
+/// instance's message overwrite another's with the same id
+async fn messages_keyed_by_id(pool: &SqlitePool) -> bool {
+    let keys: Vec<String> =
+        sqlx::query_scalar("SELECT name FROM pragma_table_info('messages') WHERE pk > 0")
+            .fetch_all(pool)
+            .await
+            .unwrap_or_default();
+    keys == ["messageId"]
+}
+
+/// Move rows from the renamed old table into the `(sessionId, messageId)`
+/// keyed one main.sql just created, then recreate the indexes and triggers
+/// that were dropped with the old table
+async fn rekey_messages_table(pool: &SqlitePool) {
+    const COLUMNS: &str = "sessionId, messageId, messageContent, chat, sender, device, mtype, text, isGroup, quoted, fromMe, sentAt, createdAt";
+    let copied = sqlx::query(&format!(
+        "INSERT OR IGNORE INTO messages ({cols}) SELECT {cols} FROM messages_by_id",
+        cols = COLUMNS
+    ))
+    .execute(pool)
+    .await;
+    if let Err(e) = copied {
+        eprintln!(
+            "❌ Failed to rekey messages, old rows kept in messages_by_id: {}",
+            e
+        );
+        return;
+    }
+    let _ = sqlx::query("DROP TABLE messages_by_id").execute(pool).await;
+    apply_schema(pool).await;
+    let _ = sqlx::query("INSERT INTO messages_fts (messages_fts) VALUES ('rebuild')")
+        .execute(pool)
+        .await;
 }
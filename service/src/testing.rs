@@ -0,0 +1,67 @@
+//! Shared fixtures for handler tests: an in-memory database with the full
+//! schema and a Redis client that is only dialed when a test needs it.
+
+use crate::AppState;
+use crate::manager::SessionManager;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub async fn state() -> Arc<AppState> {
+    // One connection, each new one would open a separate empty database
+    let db = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    sqlx::query(include_str!("../store/main.sql"))
+        .execute(&db)
+        .await
+        .unwrap();
+
+    let (tx, _) = tokio::sync::broadcast::channel(16);
+    let (events, _) = tokio::sync::broadcast::channel(16);
+    let (log_tx, _) = tokio::sync::broadcast::channel(16);
+    Arc::new(AppState {
+        db,
+        redis: redis::Client::open("redis://127.0.0.1/").unwrap(),
+        sm: SessionManager {
+            workers: Arc::new(tokio::sync::RwLock::new(HashMap::new())),
+            tx,
+            events,
+            links: Arc::new(tokio::sync::RwLock::new(HashMap::new())),
+            pending: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
+        },
+        log_tx,
+    })
+}
+
+/// Insert an account and return its id; the crypto hash is `hash-<phone>`
+pub async fn user(state: &AppState, phone: &str, is_admin: bool, role: Option<&str>) -> String {
+    let id = format!("user-{}", phone);
+    sqlx::query(
+        "INSERT INTO users (id, phoneNumber, passwordHash, passwordSalt, cryptoHash, isAdmin, role)
+         VALUES (?, ?, '', '', ?, ?, ?)",
+    )
+    .bind(&id)
+    .bind(phone)
+    .bind(format!("hash-{}", phone))
+    .bind(is_admin)
+    .bind(role)
+    .execute(&state.db)
+    .await
+    .unwrap();
+    id
+}
+
+/// Access token claims for an account made by `user`
+pub fn claims(phone: &str, role: &str) -> crate::security::Claims {
+    crate::security::Claims {
+        sub: format!("hash-{}", phone),
+        role: role.to_string(),
+        iat: 0,
+        exp: i64::MAX,
+        jti: "jti".to_string(),
+        sid: "sid".to_string(),
+        typ: "access".to_string(),
+    }
+}
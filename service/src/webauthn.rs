@@ -0,0 +1,578 @@
+//! Server-side checks of WebAuthn responses: client data, authenticator data,
+//! attestations and assertion signatures. Credential public keys are stored as
+//! base64url SubjectPublicKeyInfo DER, converted from the COSE key in the
+//! attestation at registration.
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use serde::Deserialize;
+use serde_cbor::Value;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+/// User present
+const FLAG_UP: u8 = 0x01;
+/// User verified
+const FLAG_UV: u8 = 0x04;
+/// Attested credential data included
+const FLAG_AT: u8 = 0x40;
+
+/// COSE algorithm identifiers we accept
+const COSE_ES256: i128 = -7;
+const COSE_RS256: i128 = -257;
+
+/// SubjectPublicKeyInfo algorithms of attestation certificates
+const OID_EC_PUBLIC_KEY: &str = "1.2.840.10045.2.1";
+const OID_RSA_ENCRYPTION: &str = "1.2.840.113549.1.1.1";
+
+/// rpIdHash, flags and signCount
+const AUTH_DATA_MIN_LEN: usize = 37;
+
+#[derive(Debug, Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    kind: String,
+    challenge: String,
+    origin: String,
+    #[serde(default, rename = "crossOrigin")]
+    cross_origin: bool,
+}
+
+/// The fixed part of authenticator data
+#[derive(Debug)]
+pub struct AuthenticatorData<'a> {
+    pub rp_id_hash: &'a [u8],
+    pub flags: u8,
+    pub sign_count: u32,
+    /// Attested credential data and extensions, when present
+    pub rest: &'a [u8],
+}
+
+impl<'a> AuthenticatorData<'a> {
+    pub fn parse(raw: &'a [u8]) -> Result<Self, String> {
+        if raw.len() < AUTH_DATA_MIN_LEN {
+            return Err("Authenticator data is too short".to_string());
+        }
+        Ok(Self {
+            rp_id_hash: &raw[..32],
+            flags: raw[32],
+            sign_count: u32::from_be_bytes([raw[33], raw[34], raw[35], raw[36]]),
+            rest: &raw[AUTH_DATA_MIN_LEN..],
+        })
+    }
+
+    /// rpIdHash must match our relying party, the user must have been present
+    /// and, when required, verified
+    pub fn check(&self, rp_id: &str) -> Result<(), String> {
+        if self.rp_id_hash != Sha256::digest(rp_id.as_bytes()).as_slice() {
+            return Err("rpIdHash does not match the relying party".to_string());
+        }
+        if self.flags & FLAG_UP == 0 {
+            return Err("User presence flag not set".to_string());
+        }
+        if user_verification() == "required" && self.flags & FLAG_UV == 0 {
+            return Err("User verification flag not set".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// `preferred` unless WEBAUTHN_USER_VERIFICATION is `required`
+pub fn user_verification() -> &'static str {
+    match std::env::var("WEBAUTHN_USER_VERIFICATION").as_deref() {
+        Ok("required") => "required",
+        _ => "preferred",
+    }
+}
+
+pub fn decode(value: &str) -> Result<Vec<u8>, String> {
+    URL_SAFE_NO_PAD
+        .decode(value.trim_end_matches('='))
+        .map_err(|_| "Invalid base64url value".to_string())
+}
+
+/// Origins passkeys may be used from, WEBAUTHN_ORIGINS or else ALLOWED_ORIGINS
+pub fn allowed_origins() -> Vec<String> {
+    match std::env::var("WEBAUTHN_ORIGINS") {
+        Ok(origins) => origins
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect(),
+        Err(_) => crate::security::get_allowed_origins(),
+    }
+}
+
+/// An origin is ours when it is one of the configured origins, its host is
+/// the RP ID or one of its subdomains, and it is served over HTTPS (plain
+/// HTTP only for localhost)
+fn is_valid_origin(origin: &str, rp_id: &str, allowed: &[String]) -> bool {
+    let Ok(url) = url::Url::parse(origin) else {
+        return false;
+    };
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+    let secure = match url.scheme() {
+        "https" => true,
+        "http" => host == "localhost" || host == "127.0.0.1",
+        _ => false,
+    };
+    let serialized = url.origin().ascii_serialization();
+    let configured = allowed
+        .iter()
+        .any(|a| url::Url::parse(a).is_ok_and(|a| a.origin().ascii_serialization() == serialized));
+    secure && configured && (host == rp_id || host.ends_with(&format!(".{}", rp_id)))
+}
+
+/// Check the ceremony type, the challenge we issued and the origin
+pub fn verify_client_data(
+    raw: &[u8],
+    expected_type: &str,
+    challenge: &str,
+    rp_id: &str,
+    origins: &[String],
+) -> Result<(), String> {
+    let data: ClientData =
+        serde_json::from_slice(raw).map_err(|e| format!("Invalid clientDataJSON: {}", e))?;
+    if data.kind != expected_type {
+        return Err(format!("Unexpected client data type '{}'", data.kind));
+    }
+    if data.challenge.trim_end_matches('=') != challenge.trim_end_matches('=') {
+        return Err("Challenge mismatch".to_string());
+    }
+    if data.cross_origin {
+        return Err("Cross-origin requests are not accepted".to_string());
+    }
+    if !is_valid_origin(&data.origin, rp_id, origins) {
+        return Err(format!("Origin '{}' is not allowed", data.origin));
+    }
+    Ok(())
+}
+
+/// Verify an assertion signature over `authenticatorData || SHA-256(clientDataJSON)`
+/// with an ES256 or RS256 public key
+pub fn verify_signature(
+    public_key_der: &[u8],
+    auth_data: &[u8],
+    client_data: &[u8],
+    signature: &[u8],
+) -> Result<(), String> {
+    use p256::ecdsa::signature::Verifier;
+    use p256::pkcs8::DecodePublicKey;
+
+    let mut signed = auth_data.to_vec();
+    signed.extend_from_slice(&Sha256::digest(client_data));
+
+    if let Ok(key) = p256::ecdsa::VerifyingKey::from_public_key_der(public_key_der) {
+        let sig = p256::ecdsa::Signature::from_der(signature)
+            .map_err(|_| "Malformed ES256 signature".to_string())?;
+        return key
+            .verify(&signed, &sig)
+            .map_err(|_| "Invalid signature".to_string());
+    }
+    if let Ok(key) = rsa::RsaPublicKey::from_public_key_der(public_key_der) {
+        let key = rsa::pkcs1v15::VerifyingKey::<Sha256>::new(key);
+        let sig = rsa::pkcs1v15::Signature::try_from(signature)
+            .map_err(|_| "Malformed RS256 signature".to_string())?;
+        return key
+            .verify(&signed, &sig)
+            .map_err(|_| "Invalid signature".to_string());
+    }
+    Err("Unsupported public key".to_string())
+}
+
+/// Authenticators that count signatures must move forward on every use,
+/// a counter that stays put or goes back points at a cloned authenticator.
+/// Both being zero means the authenticator doesn't count.
+pub fn check_sign_count(stored: i64, received: u32) -> Result<(), String> {
+    if (stored != 0 || received != 0) && i64::from(received) <= stored {
+        return Err(format!(
+            "Signature counter went from {} to {}",
+            stored, received
+        ));
+    }
+    Ok(())
+}
+
+/// A credential taken from a verified attestation
+#[derive(Debug)]
+pub struct NewCredential {
+    pub credential_id: Vec<u8>,
+    /// SubjectPublicKeyInfo DER
+    pub public_key: Vec<u8>,
+    pub sign_count: u32,
+}
+
+fn field<'a>(map: &'a BTreeMap<Value, Value>, key: &str) -> Option<&'a Value> {
+    map.get(&Value::Text(key.to_string()))
+}
+
+fn cose_field(map: &BTreeMap<Value, Value>, key: i128) -> Option<&Value> {
+    map.get(&Value::Integer(key))
+}
+
+fn cose_bytes(map: &BTreeMap<Value, Value>, key: i128) -> Result<&[u8], String> {
+    match cose_field(map, key) {
+        Some(Value::Bytes(b)) => Ok(b),
+        _ => Err(format!("COSE key is missing parameter {}", key)),
+    }
+}
+
+/// Convert a COSE_Key into SubjectPublicKeyInfo DER, returning its algorithm too
+fn cose_to_spki(key: &Value) -> Result<(i128, Vec<u8>), String> {
+    use p256::pkcs8::EncodePublicKey;
+
+    let Value::Map(map) = key else {
+        return Err("COSE key is not a map".to_string());
+    };
+    let Some(Value::Integer(alg)) = cose_field(map, 3) else {
+        return Err("COSE key has no algorithm".to_string());
+    };
+    let der = match (*alg, cose_field(map, 1)) {
+        // EC2 on P-256
+        (COSE_ES256, Some(Value::Integer(2))) => {
+            if cose_field(map, -1) != Some(&Value::Integer(1)) {
+                return Err("Only the P-256 curve is supported".to_string());
+            }
+            let mut point = vec![0x04];
+            point.extend_from_slice(cose_bytes(map, -2)?);
+            point.extend_from_slice(cose_bytes(map, -3)?);
+            p256::PublicKey::from_sec1_bytes(&point)
+                .map_err(|_| "Invalid P-256 public key".to_string())?
+                .to_public_key_der()
+        }
+        // RSA
+        (COSE_RS256, Some(Value::Integer(3))) => {
+            let n = rsa::BigUint::from_bytes_be(cose_bytes(map, -1)?);
+            let e = rsa::BigUint::from_bytes_be(cose_bytes(map, -2)?);
+            rsa::RsaPublicKey::new(n, e)
+                .map_err(|_| "Invalid RSA public key".to_string())?
+                .to_public_key_der()
+        }
+        _ => return Err(format!("Unsupported COSE algorithm {}", alg)),
+    };
+    der.map(|d| (*alg, d.as_bytes().to_vec()))
+        .map_err(|_| "Failed to encode public key".to_string())
+}
+
+/// Check a `packed` attestation statement. With `x5c` the signature comes from
+/// the attestation certificate (the chain itself isn't checked against any
+/// vendor roots), without it the credential signed its own attestation.
+fn verify_packed(
+    statement: &BTreeMap<Value, Value>,
+    credential_alg: i128,
+    credential_key: &[u8],
+    auth_data: &[u8],
+    client_data: &[u8],
+) -> Result<(), String> {
+    use x509_cert::der::{Decode, Encode};
+
+    let Some(Value::Integer(alg)) = field(statement, "alg") else {
+        return Err("Packed attestation has no alg".to_string());
+    };
+    let Some(Value::Bytes(sig)) = field(statement, "sig") else {
+        return Err("Packed attestation has no sig".to_string());
+    };
+    match field(statement, "x5c") {
+        Some(Value::Array(chain)) => {
+            let Some(Value::Bytes(cert)) = chain.first() else {
+                return Err("Packed attestation has an empty x5c".to_string());
+            };
+            let cert = x509_cert::Certificate::from_der(cert)
+                .map_err(|_| "Invalid attestation certificate".to_string())?;
+            let spki = &cert.tbs_certificate.subject_public_key_info;
+            let key_alg = match spki.algorithm.oid.to_string().as_str() {
+                OID_EC_PUBLIC_KEY => COSE_ES256,
+                OID_RSA_ENCRYPTION => COSE_RS256,
+                _ => return Err("Unsupported attestation certificate key".to_string()),
+            };
+            if *alg != key_alg {
+                return Err("Attestation algorithm doesn't match the certificate key".to_string());
+            }
+            let key = spki
+                .to_der()
+                .map_err(|_| "Invalid attestation certificate key".to_string())?;
+            verify_signature(&key, auth_data, client_data, sig)
+        }
+        None => {
+            if *alg != credential_alg {
+                return Err("Self attestation algorithm doesn't match the credential".to_string());
+            }
+            verify_signature(credential_key, auth_data, client_data, sig)
+        }
+        Some(_) => Err("Invalid x5c".to_string()),
+    }
+}
+
+/// Verify an attestation object (`none` or `packed`) and pull the new
+/// credential out of its authenticator data
+pub fn verify_attestation(
+    attestation_object: &[u8],
+    client_data: &[u8],
+    rp_id: &str,
+) -> Result<NewCredential, String> {
+    let Ok(Value::Map(object)) = serde_cbor::from_slice::<Value>(attestation_object) else {
+        return Err("Invalid attestation object".to_string());
+    };
+    let Some(Value::Text(fmt)) = field(&object, "fmt") else {
+        return Err("Attestation object has no fmt".to_string());
+    };
+    let Some(Value::Bytes(auth_data_raw)) = field(&object, "authData") else {
+        return Err("Attestation object has no authData".to_string());
+    };
+    let Some(Value::Map(statement)) = field(&object, "attStmt") else {
+        return Err("Attestation object has no attStmt".to_string());
+    };
+
+    let auth_data = AuthenticatorData::parse(auth_data_raw)?;
+    auth_data.check(rp_id)?;
+    if auth_data.flags & FLAG_AT == 0 {
+        return Err("Authenticator data has no attested credential".to_string());
+    }
+
+    // aaguid (16), credential ID length (2), credential ID, COSE key
+    let rest = auth_data.rest;
+    if rest.len() < 18 {
+        return Err("Attested credential data is too short".to_string());
+    }
+    let id_len = u16::from_be_bytes([rest[16], rest[17]]) as usize;
+    let Some(credential_id) = rest.get(18..18 + id_len) else {
+        return Err("Attested credential data is too short".to_string());
+    };
+    // Extensions may follow the key, so read just the one CBOR item
+    let mut de = serde_cbor::Deserializer::from_slice(&rest[18 + id_len..]);
+    let cose_key =
+        Value::deserialize(&mut de).map_err(|_| "Invalid credential public key".to_string())?;
+    let (alg, public_key) = cose_to_spki(&cose_key)?;
+
+    match fmt.as_str() {
+        "none" => {}
+        "packed" => verify_packed(statement, alg, &public_key, auth_data_raw, client_data)?,
+        other => return Err(format!("Unsupported attestation format '{}'", other)),
+    }
+
+    Ok(NewCredential {
+        credential_id: credential_id.to_vec(),
+        public_key,
+        sign_count: auth_data.sign_count,
+    })
+}
+
+pub fn encode(data: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use p256::ecdsa::{SigningKey, signature::Signer};
+
+    const RP_ID: &str = "example.com";
+
+    fn signing_key() -> SigningKey {
+        SigningKey::from_slice(&[7u8; 32]).unwrap()
+    }
+
+    fn cose_key(key: &SigningKey) -> Value {
+        let point = key.verifying_key().to_encoded_point(false);
+        Value::Map(BTreeMap::from([
+            (Value::Integer(1), Value::Integer(2)),
+            (Value::Integer(3), Value::Integer(COSE_ES256)),
+            (Value::Integer(-1), Value::Integer(1)),
+            (
+                Value::Integer(-2),
+                Value::Bytes(point.x().unwrap().to_vec()),
+            ),
+            (
+                Value::Integer(-3),
+                Value::Bytes(point.y().unwrap().to_vec()),
+            ),
+        ]))
+    }
+
+    fn auth_data(flags: u8, sign_count: u32, credential: Option<(&[u8], &Value)>) -> Vec<u8> {
+        let mut data = Sha256::digest(RP_ID.as_bytes()).to_vec();
+        data.push(flags);
+        data.extend_from_slice(&sign_count.to_be_bytes());
+        if let Some((id, key)) = credential {
+            data.extend_from_slice(&[0u8; 16]);
+            data.extend_from_slice(&(id.len() as u16).to_be_bytes());
+            data.extend_from_slice(id);
+            data.extend_from_slice(&serde_cbor::to_vec(key).unwrap());
+        }
+        data
+    }
+
+    fn attestation(fmt: &str, auth_data: &[u8], statement: Vec<(&str, Value)>) -> Vec<u8> {
+        let statement = statement
+            .into_iter()
+            .map(|(k, v)| (Value::Text(k.to_string()), v))
+            .collect();
+        serde_cbor::to_vec(&Value::Map(BTreeMap::from([
+            (Value::Text("fmt".into()), Value::Text(fmt.into())),
+            (
+                Value::Text("authData".into()),
+                Value::Bytes(auth_data.to_vec()),
+            ),
+            (Value::Text("attStmt".into()), Value::Map(statement)),
+        ])))
+        .unwrap()
+    }
+
+    fn sign(key: &SigningKey, auth_data: &[u8], client_data: &[u8]) -> Vec<u8> {
+        let mut signed = auth_data.to_vec();
+        signed.extend_from_slice(&Sha256::digest(client_data));
+        let sig: p256::ecdsa::Signature = key.sign(&signed);
+        sig.to_der().as_bytes().to_vec()
+    }
+
+    #[test]
+    fn authenticator_data_is_parsed_and_checked() {
+        let raw = auth_data(FLAG_UP | FLAG_UV, 42, None);
+        let parsed = AuthenticatorData::parse(&raw).unwrap();
+        assert_eq!(parsed.flags, FLAG_UP | FLAG_UV);
+        assert_eq!(parsed.sign_count, 42);
+        assert!(parsed.rest.is_empty());
+        assert!(parsed.check(RP_ID).is_ok());
+        assert!(parsed.check("evil.com").is_err());
+
+        let absent = auth_data(FLAG_UV, 0, None);
+        assert!(
+            AuthenticatorData::parse(&absent)
+                .unwrap()
+                .check(RP_ID)
+                .is_err()
+        );
+        assert!(AuthenticatorData::parse(&raw[..36]).is_err());
+    }
+
+    #[test]
+    fn none_attestation_yields_a_usable_credential() {
+        let key = signing_key();
+        let raw = auth_data(
+            FLAG_UP | FLAG_UV | FLAG_AT,
+            1,
+            Some((b"cred", &cose_key(&key))),
+        );
+        let client_data = br#"{"type":"webauthn.create"}"#;
+        let credential =
+            verify_attestation(&attestation("none", &raw, vec![]), client_data, RP_ID).unwrap();
+        assert_eq!(credential.credential_id, b"cred");
+        assert_eq!(credential.sign_count, 1);
+
+        // An assertion signed by the same key verifies against the stored key
+        let assertion = auth_data(FLAG_UP | FLAG_UV, 2, None);
+        let sig = sign(&key, &assertion, client_data);
+        assert!(verify_signature(&credential.public_key, &assertion, client_data, &sig).is_ok());
+        let other = b"{}";
+        assert!(verify_signature(&credential.public_key, &assertion, other, &sig).is_err());
+    }
+
+    #[test]
+    fn packed_self_attestation_must_match_the_credential() {
+        let key = signing_key();
+        let raw = auth_data(
+            FLAG_UP | FLAG_UV | FLAG_AT,
+            0,
+            Some((b"cred", &cose_key(&key))),
+        );
+        let client_data = br#"{"type":"webauthn.create"}"#;
+        let sig = Value::Bytes(sign(&key, &raw, client_data));
+
+        let good = attestation(
+            "packed",
+            &raw,
+            vec![("alg", Value::Integer(COSE_ES256)), ("sig", sig.clone())],
+        );
+        assert!(verify_attestation(&good, client_data, RP_ID).is_ok());
+
+        let wrong_alg = attestation(
+            "packed",
+            &raw,
+            vec![("alg", Value::Integer(COSE_RS256)), ("sig", sig.clone())],
+        );
+        assert!(verify_attestation(&wrong_alg, client_data, RP_ID).is_err());
+
+        let tampered = attestation(
+            "packed",
+            &raw,
+            vec![("alg", Value::Integer(COSE_ES256)), ("sig", sig)],
+        );
+        assert!(verify_attestation(&tampered, b"{}", RP_ID).is_err());
+    }
+
+    #[test]
+    fn attestation_without_a_credential_is_rejected() {
+        let raw = auth_data(FLAG_UP | FLAG_UV, 0, None);
+        assert!(verify_attestation(&attestation("none", &raw, vec![]), b"{}", RP_ID).is_err());
+        let raw = auth_data(FLAG_UP | FLAG_UV | FLAG_AT, 0, None);
+        assert!(verify_attestation(&attestation("none", &raw, vec![]), b"{}", RP_ID).is_err());
+        assert!(verify_attestation(b"not cbor", b"{}", RP_ID).is_err());
+    }
+
+    #[test]
+    fn origins_must_be_configured_secure_and_under_the_rp_id() {
+        let allowed = vec![
+            "https://example.com".to_string(),
+            "https://app.example.com".to_string(),
+            "http://localhost:3000".to_string(),
+        ];
+        assert!(is_valid_origin("https://example.com", RP_ID, &allowed));
+        assert!(is_valid_origin("https://app.example.com", RP_ID, &allowed));
+        assert!(is_valid_origin(
+            "http://localhost:3000",
+            "localhost",
+            &allowed
+        ));
+        // Under the RP ID but not configured
+        assert!(!is_valid_origin(
+            "https://evil.example.com",
+            RP_ID,
+            &allowed
+        ));
+        // Configured but outside the RP ID
+        assert!(!is_valid_origin(
+            "https://example.com",
+            "other.com",
+            &allowed
+        ));
+        assert!(!is_valid_origin("http://example.com", RP_ID, &allowed));
+        assert!(!is_valid_origin(
+            "https://example.com:8443",
+            RP_ID,
+            &allowed
+        ));
+        assert!(!is_valid_origin("not a url", RP_ID, &allowed));
+    }
+
+    #[test]
+    fn client_data_must_match_the_ceremony() {
+        let origins = vec!["https://example.com".to_string()];
+        let data = |kind: &str, challenge: &str, cross: bool| {
+            serde_json::json!({
+                "type": kind,
+                "challenge": challenge,
+                "origin": "https://example.com",
+                "crossOrigin": cross,
+            })
+            .to_string()
+        };
+        let check = |raw: String, kind: &str| {
+            verify_client_data(raw.as_bytes(), kind, "abc", RP_ID, &origins)
+        };
+        assert!(check(data("webauthn.get", "abc", false), "webauthn.get").is_ok());
+        assert!(check(data("webauthn.get", "abc==", false), "webauthn.get").is_ok());
+        assert!(check(data("webauthn.create", "abc", false), "webauthn.get").is_err());
+        assert!(check(data("webauthn.get", "xyz", false), "webauthn.get").is_err());
+        assert!(check(data("webauthn.get", "abc", true), "webauthn.get").is_err());
+    }
+
+    #[test]
+    fn sign_counts_must_move_forward() {
+        assert!(check_sign_count(0, 0).is_ok());
+        assert!(check_sign_count(5, 6).is_ok());
+        assert!(check_sign_count(5, 5).is_err());
+        assert!(check_sign_count(5, 0).is_err());
+    }
+}
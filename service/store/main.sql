@@ -9,6 +9,7 @@ CREATE TABLE
         phoneNumber TEXT,
         ownerCryptoHash TEXT,
         isBusinessAccount BOOLEAN NOT NULL DEFAULT FALSE,
+        pairedAt TIMESTAMP,
         createdAt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
         updatedAt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
     );
@@ -44,6 +45,8 @@ CREATE TABLE
         sessionId TEXT NOT NULL,
         contactPn TEXT NOT NULL,
         contactLid TEXT,
+        name TEXT,
+        customFields TEXT NOT NULL DEFAULT '{}',
         addedAt TIMESTAMP NOT NULL,
         createdAt TIMESTAMP NOT NULL,
         PRIMARY KEY (sessionId, contactPn),
@@ -52,15 +55,61 @@ CREATE TABLE
 
 CREATE INDEX IF NOT EXISTS idx_contacts_sessionId ON contacts (sessionId);
 
+-- User-defined contact labels, also used as broadcast audiences
+CREATE TABLE
+    IF NOT EXISTS contact_tags (
+        sessionId TEXT NOT NULL,
+        contactPn TEXT NOT NULL,
+        tag TEXT NOT NULL,
+        PRIMARY KEY (sessionId, contactPn, tag),
+        FOREIGN KEY (sessionId, contactPn) REFERENCES contacts (sessionId, contactPn) ON DELETE CASCADE
+    );
+
+CREATE INDEX IF NOT EXISTS idx_contact_tags_tag ON contact_tags (sessionId, tag);
+
+-- messageContent holds the raw message written by the worker, the remaining
+-- columns are the normalized event persisted by the service
 CREATE TABLE
     IF NOT EXISTS messages (
         sessionId TEXT NOT NULL,
-        messageId TEXT PRIMARY KEY,
+        messageId TEXT NOT NULL,
         messageContent TEXT,
+        chat TEXT,
+        sender TEXT,
+        device TEXT,
+        mtype TEXT,
+        text TEXT,
+        isGroup BOOLEAN NOT NULL DEFAULT FALSE,
+        quoted TEXT,
+        fromMe BOOLEAN NOT NULL DEFAULT FALSE,
+        sentAt TIMESTAMP,
         createdAt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        PRIMARY KEY (sessionId, messageId),
         FOREIGN KEY (sessionId) REFERENCES sessions (id) ON DELETE CASCADE
     );
 
+CREATE INDEX IF NOT EXISTS idx_messages_chat ON messages (sessionId, chat, sentAt);
+
+-- Full-text index over normalized message text, kept in sync by triggers
+CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts USING fts5 (
+    text,
+    content = 'messages',
+    content_rowid = 'rowid'
+);
+
+CREATE TRIGGER IF NOT EXISTS messages_fts_insert AFTER INSERT ON messages BEGIN
+    INSERT INTO messages_fts (rowid, text) VALUES (new.rowid, new.text);
+END;
+
+CREATE TRIGGER IF NOT EXISTS messages_fts_delete AFTER DELETE ON messages BEGIN
+    INSERT INTO messages_fts (messages_fts, rowid, text) VALUES ('delete', old.rowid, old.text);
+END;
+
+CREATE TRIGGER IF NOT EXISTS messages_fts_update AFTER UPDATE OF text ON messages BEGIN
+    INSERT INTO messages_fts (messages_fts, rowid, text) VALUES ('delete', old.rowid, old.text);
+    INSERT INTO messages_fts (rowid, text) VALUES (new.rowid, new.text);
+END;
+
 CREATE TABLE
     IF NOT EXISTS configurations (
         sessionId TEXT PRIMARY KEY,
@@ -89,9 +138,13 @@ CREATE TABLE
         passwordSalt TEXT NOT NULL,
         cryptoHash TEXT UNIQUE NOT NULL,
         isAdmin BOOLEAN NOT NULL DEFAULT FALSE,
+        role TEXT,
+        totpSecret TEXT,
+        totpEnabled BOOLEAN NOT NULL DEFAULT FALSE,
         credits REAL NOT NULL DEFAULT 0.0,
         suspended BOOLEAN NOT NULL DEFAULT FALSE,
         instanceLimit INTEGER NOT NULL DEFAULT 10,
+        plan TEXT NOT NULL DEFAULT 'free',
         createdAt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
         updatedAt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
     );
@@ -175,3 +228,364 @@ CREATE TABLE
 
 CREATE INDEX IF NOT EXISTS idx_support_requests_user ON support_requests (userId);
 CREATE INDEX IF NOT EXISTS idx_support_requests_status ON support_requests (status);
+
+-- Admin overrides for the outbound governor (NULL falls back to the owner's plan)
+CREATE TABLE
+    IF NOT EXISTS outbound_limits (
+        sessionId TEXT PRIMARY KEY,
+        perMinute INTEGER,
+        perDay INTEGER,
+        warmupDays INTEGER,
+        minDelayMs INTEGER,
+        maxDelayMs INTEGER,
+        updatedAt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (sessionId) REFERENCES sessions (id) ON DELETE CASCADE
+    );
+
+-- Outgoing webhook subscriptions, scoped to one instance or (sessionId NULL)
+-- every instance the user owns. events is a JSON array of event filters.
+CREATE TABLE
+    IF NOT EXISTS webhooks (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        userId TEXT NOT NULL,
+        sessionId TEXT,
+        url TEXT NOT NULL,
+        secret TEXT NOT NULL,
+        events TEXT NOT NULL DEFAULT '[]',
+        active BOOLEAN NOT NULL DEFAULT TRUE,
+        createdAt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        updatedAt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (userId) REFERENCES users (id) ON DELETE CASCADE,
+        FOREIGN KEY (sessionId) REFERENCES sessions (id) ON DELETE CASCADE
+    );
+
+CREATE INDEX IF NOT EXISTS idx_webhooks_user ON webhooks (userId);
+CREATE INDEX IF NOT EXISTS idx_webhooks_session ON webhooks (sessionId);
+
+-- One row per event per subscription; status is pending, delivered or dead
+CREATE TABLE
+    IF NOT EXISTS webhook_deliveries (
+        id TEXT PRIMARY KEY,
+        webhookId INTEGER NOT NULL,
+        sessionId TEXT,
+        event TEXT NOT NULL,
+        payload TEXT NOT NULL,
+        status TEXT NOT NULL DEFAULT 'pending',
+        attempts INTEGER NOT NULL DEFAULT 0,
+        nextAttemptAt TIMESTAMP,
+        lastStatusCode INTEGER,
+        lastError TEXT,
+        deliveredAt TIMESTAMP,
+        createdAt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        updatedAt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (webhookId) REFERENCES webhooks (id) ON DELETE CASCADE
+    );
+
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_due ON webhook_deliveries (status, nextAttemptAt);
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_webhook ON webhook_deliveries (webhookId, createdAt);
+
+-- Delivery log, one row per HTTP attempt
+CREATE TABLE
+    IF NOT EXISTS webhook_attempts (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        deliveryId TEXT NOT NULL,
+        attempt INTEGER NOT NULL,
+        statusCode INTEGER,
+        error TEXT,
+        durationMs INTEGER NOT NULL DEFAULT 0,
+        createdAt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (deliveryId) REFERENCES webhook_deliveries (id) ON DELETE CASCADE
+    );
+
+CREATE INDEX IF NOT EXISTS idx_webhook_attempts_delivery ON webhook_attempts (deliveryId);
+
+-- Declarative auto-reply rules, evaluated on incoming messages by priority.
+-- matchType: exact, contains, regex or first_message; scope: all, dm, group or
+-- chats (JSON list of JIDs in chats); responseType: text, media or template
+CREATE TABLE
+    IF NOT EXISTS auto_reply_rules (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        sessionId TEXT NOT NULL,
+        name TEXT NOT NULL,
+        matchType TEXT NOT NULL,
+        pattern TEXT,
+        caseSensitive BOOLEAN NOT NULL DEFAULT FALSE,
+        scope TEXT NOT NULL DEFAULT 'all',
+        chats TEXT NOT NULL DEFAULT '[]',
+        cooldownSeconds INTEGER NOT NULL DEFAULT 10,
+        responseType TEXT NOT NULL DEFAULT 'text',
+        responseText TEXT,
+        mediaUrl TEXT,
+        mediaType TEXT,
+        fileName TEXT,
+        templateId INTEGER,
+        priority INTEGER NOT NULL DEFAULT 0,
+        enabled BOOLEAN NOT NULL DEFAULT TRUE,
+        hits INTEGER NOT NULL DEFAULT 0,
+        lastHitAt TIMESTAMP,
+        createdAt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        updatedAt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (sessionId) REFERENCES sessions (id) ON DELETE CASCADE
+    );
+
+CREATE INDEX IF NOT EXISTS idx_auto_reply_rules_session ON auto_reply_rules (sessionId, enabled, priority);
+
+-- Business hours and away messages, one row per instance. hours is a JSON
+-- object of weekday (mon..sun) to [{open, close}] windows in timezone,
+-- holidays a JSON list of YYYY-MM-DD dates that count as closed.
+CREATE TABLE
+    IF NOT EXISTS business_hours (
+        sessionId TEXT PRIMARY KEY,
+        enabled BOOLEAN NOT NULL DEFAULT FALSE,
+        timezone TEXT NOT NULL DEFAULT 'UTC',
+        hours TEXT NOT NULL DEFAULT '{}',
+        holidays TEXT NOT NULL DEFAULT '[]',
+        greeting TEXT,
+        awayMessage TEXT,
+        awayCooldownHours INTEGER NOT NULL DEFAULT 24,
+        updatedAt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (sessionId) REFERENCES sessions (id) ON DELETE CASCADE
+    );
+
+-- Conversational flows per instance. Every upload adds a version, the active
+-- one is used for new conversations; running ones finish on their own version.
+CREATE TABLE
+    IF NOT EXISTS flows (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        sessionId TEXT NOT NULL,
+        name TEXT NOT NULL,
+        enabled BOOLEAN NOT NULL DEFAULT TRUE,
+        activeVersion INTEGER NOT NULL DEFAULT 1,
+        createdAt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        updatedAt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (sessionId) REFERENCES sessions (id) ON DELETE CASCADE
+    );
+
+CREATE INDEX IF NOT EXISTS idx_flows_session ON flows (sessionId);
+
+CREATE TABLE
+    IF NOT EXISTS flow_versions (
+        flowId INTEGER NOT NULL,
+        version INTEGER NOT NULL,
+        definition TEXT NOT NULL,
+        createdAt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        PRIMARY KEY (flowId, version),
+        FOREIGN KEY (flowId) REFERENCES flows (id) ON DELETE CASCADE
+    );
+
+-- Reusable message content per user. variables is a JSON list of
+-- placeholder declarations, variants maps a language to its body and media.
+CREATE TABLE
+    IF NOT EXISTS message_templates (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        userId TEXT NOT NULL,
+        name TEXT NOT NULL,
+        description TEXT,
+        defaultLanguage TEXT NOT NULL DEFAULT 'en',
+        variables TEXT NOT NULL DEFAULT '[]',
+        variants TEXT NOT NULL DEFAULT '{}',
+        createdAt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        updatedAt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        UNIQUE (userId, name),
+        FOREIGN KEY (userId) REFERENCES users (id) ON DELETE CASCADE
+    );
+
+-- Bulk sends to a tag audience; message is the send payload without `to`
+CREATE TABLE
+    IF NOT EXISTS broadcasts (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        sessionId TEXT NOT NULL,
+        name TEXT,
+        tags TEXT NOT NULL DEFAULT '[]',
+        matchAll BOOLEAN NOT NULL DEFAULT FALSE,
+        message TEXT NOT NULL,
+        status TEXT NOT NULL DEFAULT 'running',
+        total INTEGER NOT NULL DEFAULT 0,
+        sent INTEGER NOT NULL DEFAULT 0,
+        failed INTEGER NOT NULL DEFAULT 0,
+        lastError TEXT,
+        createdAt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        updatedAt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        finishedAt TIMESTAMP,
+        FOREIGN KEY (sessionId) REFERENCES sessions (id) ON DELETE CASCADE
+    );
+
+CREATE INDEX IF NOT EXISTS idx_broadcasts_session ON broadcasts (sessionId, createdAt);
+
+-- Audience snapshot taken when a broadcast starts; status is pending, sent or failed
+CREATE TABLE
+    IF NOT EXISTS broadcast_recipients (
+        broadcastId INTEGER NOT NULL,
+        jid TEXT NOT NULL,
+        status TEXT NOT NULL DEFAULT 'pending',
+        error TEXT,
+        sentAt TIMESTAMP,
+        PRIMARY KEY (broadcastId, jid),
+        FOREIGN KEY (broadcastId) REFERENCES broadcasts (id) ON DELETE CASCADE
+    );
+
+-- Bulk "is on WhatsApp" lookups; status is running, completed or failed
+CREATE TABLE
+    IF NOT EXISTS number_checks (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        sessionId TEXT NOT NULL,
+        status TEXT NOT NULL DEFAULT 'running',
+        total INTEGER NOT NULL DEFAULT 0,
+        checked INTEGER NOT NULL DEFAULT 0,
+        found INTEGER NOT NULL DEFAULT 0,
+        lastError TEXT,
+        createdAt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        finishedAt TIMESTAMP,
+        FOREIGN KEY (sessionId) REFERENCES sessions (id) ON DELETE CASCADE
+    );
+
+CREATE INDEX IF NOT EXISTS idx_number_checks_session ON number_checks (sessionId, createdAt);
+
+-- One row per distinct E.164 number; onWhatsApp stays NULL until checked
+CREATE TABLE
+    IF NOT EXISTS number_check_results (
+        checkId INTEGER NOT NULL,
+        number TEXT NOT NULL,
+        onWhatsApp BOOLEAN,
+        jid TEXT,
+        lid TEXT,
+        checkedAt TIMESTAMP,
+        PRIMARY KEY (checkId, number),
+        FOREIGN KEY (checkId) REFERENCES number_checks (id) ON DELETE CASCADE
+    );
+
+-- Opt-out keywords per instance; keywords and optInKeywords are JSON lists
+-- matched case-insensitively against the whole message
+CREATE TABLE
+    IF NOT EXISTS opt_out_settings (
+        sessionId TEXT PRIMARY KEY,
+        enabled BOOLEAN NOT NULL DEFAULT TRUE,
+        keywords TEXT NOT NULL DEFAULT '[]',
+        optInKeywords TEXT NOT NULL DEFAULT '[]',
+        updatedAt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (sessionId) REFERENCES sessions (id) ON DELETE CASCADE
+    );
+
+-- Recipients excluded from bulk sends; source is keyword, manual or import
+CREATE TABLE
+    IF NOT EXISTS opt_outs (
+        sessionId TEXT NOT NULL,
+        jid TEXT NOT NULL,
+        source TEXT NOT NULL,
+        reason TEXT,
+        createdAt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        PRIMARY KEY (sessionId, jid),
+        FOREIGN KEY (sessionId) REFERENCES sessions (id) ON DELETE CASCADE
+    );
+
+-- Append-only history of opt-outs and opt-ins, kept after an opt-out is removed
+CREATE TABLE
+    IF NOT EXISTS opt_out_events (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        sessionId TEXT NOT NULL,
+        jid TEXT NOT NULL,
+        action TEXT NOT NULL,
+        source TEXT NOT NULL,
+        detail TEXT,
+        actor TEXT,
+        messageId TEXT,
+        createdAt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (sessionId) REFERENCES sessions (id) ON DELETE CASCADE
+    );
+
+CREATE INDEX IF NOT EXISTS idx_opt_out_events_session ON opt_out_events (sessionId, jid, createdAt);
+
+-- Status (story) updates per instance. kind is text, image or video; audience
+-- is all, list or exclude, applied to contacts (a JSON list) when posting.
+-- status is scheduled, posting, posted, failed or cancelled.
+CREATE TABLE
+    IF NOT EXISTS statuses (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        sessionId TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        text TEXT,
+        mediaUrl TEXT,
+        backgroundColor TEXT,
+        font INTEGER,
+        audience TEXT NOT NULL DEFAULT 'all',
+        contacts TEXT NOT NULL DEFAULT '[]',
+        recipients INTEGER NOT NULL DEFAULT 0,
+        status TEXT NOT NULL DEFAULT 'scheduled',
+        scheduledAt TIMESTAMP NOT NULL,
+        postedAt TIMESTAMP,
+        messageId TEXT,
+        lastError TEXT,
+        createdAt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (sessionId) REFERENCES sessions (id) ON DELETE CASCADE
+    );
+
+CREATE INDEX IF NOT EXISTS idx_statuses_due ON statuses (status, scheduledAt);
+
+CREATE INDEX IF NOT EXISTS idx_statuses_message ON statuses (sessionId, messageId);
+
+-- Who viewed a posted status, from the worker's read receipts
+CREATE TABLE
+    IF NOT EXISTS status_views (
+        statusId INTEGER NOT NULL,
+        viewer TEXT NOT NULL,
+        viewedAt TIMESTAMP NOT NULL,
+        PRIMARY KEY (statusId, viewer),
+        FOREIGN KEY (statusId) REFERENCES statuses (id) ON DELETE CASCADE
+    );
+
+-- Sign-in attempts per account. method is password, passkey, admin or
+-- register; outcome is success, failed or locked.
+CREATE TABLE
+    IF NOT EXISTS login_history (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        userId TEXT NOT NULL,
+        method TEXT NOT NULL,
+        outcome TEXT NOT NULL,
+        ip TEXT NOT NULL,
+        userAgent TEXT,
+        createdAt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (userId) REFERENCES users (id) ON DELETE CASCADE
+    );
+
+CREATE INDEX IF NOT EXISTS idx_login_history_user ON login_history (userId, createdAt);
+
+-- One-time 2FA recovery codes, stored as SHA-256 hashes
+CREATE TABLE
+    IF NOT EXISTS recovery_codes (
+        userId TEXT NOT NULL,
+        codeHash TEXT NOT NULL,
+        usedAt TIMESTAMP,
+        createdAt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        PRIMARY KEY (userId, codeHash),
+        FOREIGN KEY (userId) REFERENCES users (id) ON DELETE CASCADE
+    );
+
+-- Service-wide settings changed from the admin workspace
+CREATE TABLE
+    IF NOT EXISTS system_settings (
+        name TEXT PRIMARY KEY,
+        value TEXT NOT NULL,
+        updatedAt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+    );
+
+-- Per-user API keys for backend integrations, stored as SHA-256 hashes.
+-- scopes, instances and allowedIps are JSON lists; NULL instances or
+-- allowedIps mean no restriction.
+CREATE TABLE
+    IF NOT EXISTS api_keys (
+        id TEXT PRIMARY KEY,
+        userId TEXT NOT NULL,
+        name TEXT NOT NULL,
+        keyPrefix TEXT NOT NULL,
+        keyHash TEXT NOT NULL UNIQUE,
+        scopes TEXT NOT NULL DEFAULT '[]',
+        instances TEXT,
+        allowedIps TEXT,
+        expiresAt TIMESTAMP,
+        lastUsedAt TIMESTAMP,
+        lastUsedIp TEXT,
+        createdAt TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        FOREIGN KEY (userId) REFERENCES users (id) ON DELETE CASCADE
+    );
+
+CREATE INDEX IF NOT EXISTS idx_api_keys_user ON api_keys (userId);
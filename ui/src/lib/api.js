@@ -20,7 +20,8 @@ export const ResponseCodes = {
 	INTERNAL_ERROR: 0xD6F3,
 	SESSION_CREATED: 0xE702,
 	OPERATION_OK: 0xF811,
-	PASSKEY_OK: 0x0920
+	PASSKEY_OK: 0x0920,
+	TWO_FACTOR_REQUIRED: 0x1A31
 };
 
 /**
@@ -73,6 +74,12 @@ export function parseSecureResponse(response) {
 		case ResponseCodes.INTERNAL_ERROR:
 			errorHint = 'System error';
 			break;
+		case ResponseCodes.RATE_LIMITED:
+			errorHint = 'Too many attempts, try again shortly';
+			break;
+		case ResponseCodes.TWO_FACTOR_REQUIRED:
+			errorHint = 'Verification code required';
+			break;
 		default:
 			errorHint = isSuccess ? '' : 'Unknown error';
 	}
@@ -131,7 +138,7 @@ export async function secureRequest(url, options = {}) {
 		'Content-Type': 'application/json'
 	};
 	
-	const response = await fetch(url, {
+	const send = () => fetch(url, {
 		...options,
 		headers: {
 			...defaultHeaders,
@@ -139,7 +146,46 @@ export async function secureRequest(url, options = {}) {
 		},
 		credentials: 'include' // Include cookies
 	});
-	
+
+	let response = await send();
+	// The access token expired: rotate it with the refresh cookie and retry once
+	if (response.status === 401 && await refreshSession()) {
+		response = await send();
+	}
+
 	const data = await response.json();
 	return parseSecureResponse(data);
 }
+
+/**
+ * Rotate the session tokens using the HttpOnly refresh cookie
+ * @returns {Promise<boolean>} True if new tokens were issued
+ */
+export async function refreshSession() {
+	try {
+		const response = await fetch('/api/auth/refresh', {
+			method: 'POST',
+			credentials: 'include'
+		});
+		const data = parseSecureResponse(await response.json());
+		if (data.success) {
+			storeAuthTokens(data.tokens);
+			return true;
+		}
+	} catch {
+		// Treat network errors like a failed refresh
+	}
+	clearAuthTokens();
+	return false;
+}
+
+/**
+ * Sign out and revoke the session server-side
+ */
+export async function logout() {
+	try {
+		await fetch('/api/auth/logout', { method: 'POST', credentials: 'include' });
+	} finally {
+		clearAuthTokens();
+	}
+}
@@ -3,6 +3,9 @@ import { writable } from 'svelte/store';
 // Admin authentication state
 export const isAdminAuthenticated = writable(false);
 
+// Permissions granted by the admin's role, e.g. 'users:delete'
+export const adminPermissions = writable([]);
+
 // Store admin session in browser
 export function setAdminAuthenticated(value) {
 	isAdminAuthenticated.set(value);
@@ -35,6 +38,7 @@ export async function checkAdminAuth() {
 				const data = await response.json();
 				if (data.valid) {
 					isAdminAuthenticated.set(true);
+					adminPermissions.set(data.permissions || []);
 					sessionStorage.setItem('adminAuth', 'true');
 					return true;
 				}
@@ -58,11 +62,16 @@ export async function checkAdminAuth() {
 }
 
 // Logout admin - clear local state (cookie will expire or be cleared by server)
-export function logoutAdmin() {
+export async function logoutAdmin() {
 	isAdminAuthenticated.set(false);
+	adminPermissions.set([]);
 	if (typeof window !== 'undefined') {
 		sessionStorage.removeItem('adminAuth');
-		// Clear admin_session cookie by setting expired date
-		document.cookie = 'admin_session=; Path=/; Max-Age=0; SameSite=Strict';
+		// The session cookie is HttpOnly, the server ends it and clears it
+		try {
+			await fetch('/api/auth/admin/logout', { method: 'POST', credentials: 'include' });
+		} catch {
+			// Session expires on its own
+		}
 	}
 }